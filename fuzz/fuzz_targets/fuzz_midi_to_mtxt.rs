@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Malformed MIDI files must be rejected with an error, never panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(file) = mtxt::midi::convert_midi_to_mtxt(data) {
+        let _ = file.to_string();
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The parser must reject invalid input with an error, never panic
+fuzz_target!(|data: &[u8]| {
+    let content = std::str::from_utf8(data).unwrap_or("");
+    let _ = mtxt::parse_mtxt(content);
+});
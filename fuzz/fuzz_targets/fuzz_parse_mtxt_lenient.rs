@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mtxt::record_parser::parse_mtxt_line;
+
+// Invalid UTF-8 is replaced instead of discarded, every line is parsed on its own
+// so later lines are reached even after an error, and the records that parse are
+// processed and written back like a consumer of the library would do
+fuzz_target!(|data: &[u8]| {
+    let content = String::from_utf8_lossy(data);
+    for line in content.lines() {
+        let _ = parse_mtxt_line(line);
+    }
+
+    if let Ok(file) = mtxt::parse_mtxt(&content) {
+        let _ = file.get_output_records();
+        let _ = file.to_string();
+    }
+});
@@ -0,0 +1,732 @@
+//! Compact binary encoding of MTXT records.
+//!
+//! Every record is written as a 1 byte type, a `u32` payload length and the payload.
+//! Numbers are big endian: channels `u16`, velocities, BPM and other values `f32`.
+//! Beat times are the whole beats as `u32` followed by the fraction as `u32` in 1/2^32 beats,
+//! so they round-trip exactly. Notes are the pitch class (`u8`), octave (`i8`) and cents (`f32`)
+//! to keep their spelling. Strings and byte arrays are prefixed by their `u32` length,
+//! optional fields by a presence byte.
+
+use crate::types::note::NoteTarget;
+use crate::types::record::{AliasDefinition, MtxtRecord, VoiceList};
+use crate::{BeatTime, Note, PitchClass, TimeSignature, Version};
+use anyhow::{Result, bail};
+use std::rc::Rc;
+
+/// Start of an [`MtxtFile`](crate::MtxtFile) in binary form, followed by [`BINARY_VERSION`]
+pub const BINARY_MAGIC: [u8; 4] = *b"MTXT";
+pub const BINARY_VERSION: u8 = 1;
+
+mod tag {
+    pub const HEADER: u8 = 0x01;
+    pub const GLOBAL_META: u8 = 0x02;
+    pub const META: u8 = 0x03;
+    pub const DURATION_DIRECTIVE: u8 = 0x10;
+    pub const CHANNEL_DIRECTIVE: u8 = 0x11;
+    pub const VELOCITY_DIRECTIVE: u8 = 0x12;
+    pub const OFF_VELOCITY_DIRECTIVE: u8 = 0x13;
+    pub const TRANSITION_CURVE_DIRECTIVE: u8 = 0x14;
+    pub const TRANSITION_INTERVAL_DIRECTIVE: u8 = 0x15;
+    pub const ALIAS_DEF: u8 = 0x20;
+    pub const NOTE: u8 = 0x30;
+    pub const NOTE_ON: u8 = 0x31;
+    pub const NOTE_OFF: u8 = 0x32;
+    pub const CONTROL_CHANGE: u8 = 0x40;
+    pub const VOICE: u8 = 0x41;
+    pub const TEMPO: u8 = 0x50;
+    pub const TIME_SIGNATURE: u8 = 0x51;
+    pub const TUNING: u8 = 0x52;
+    pub const RESET: u8 = 0x53;
+    pub const SYSEX: u8 = 0x54;
+    pub const EMPTY_LINE: u8 = 0x60;
+    /// Inline comment of the previous record, only used in files
+    pub const COMMENT: u8 = 0xFE;
+}
+
+// Pitch classes by their binary value
+const PITCH_CLASSES: [PitchClass; 35] = [
+    PitchClass::Cb,
+    PitchClass::C,
+    PitchClass::CSharp,
+    PitchClass::Db,
+    PitchClass::D,
+    PitchClass::DSharp,
+    PitchClass::Eb,
+    PitchClass::E,
+    PitchClass::ESharp,
+    PitchClass::Fb,
+    PitchClass::F,
+    PitchClass::FSharp,
+    PitchClass::Gb,
+    PitchClass::G,
+    PitchClass::GSharp,
+    PitchClass::Ab,
+    PitchClass::A,
+    PitchClass::ASharp,
+    PitchClass::Bb,
+    PitchClass::B,
+    PitchClass::BSharp,
+    PitchClass::Cbb,
+    PitchClass::CDoubleSharp,
+    PitchClass::Dbb,
+    PitchClass::DDoubleSharp,
+    PitchClass::Ebb,
+    PitchClass::EDoubleSharp,
+    PitchClass::Fbb,
+    PitchClass::FDoubleSharp,
+    PitchClass::Gbb,
+    PitchClass::GDoubleSharp,
+    PitchClass::Abb,
+    PitchClass::ADoubleSharp,
+    PitchClass::Bbb,
+    PitchClass::BDoubleSharp,
+];
+
+// Target of a note record
+const TARGET_NOTE: u8 = 0;
+const TARGET_ALIAS_KEY: u8 = 1;
+const TARGET_ALIAS: u8 = 2;
+const TARGET_RANGE: u8 = 3;
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.len(value.len());
+        self.data.extend_from_slice(value);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn time(&mut self, time: BeatTime) {
+        let units = time.as_fixed_point();
+        self.u32((units >> 32) as u32);
+        self.u32(units as u32);
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                write(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn note(&mut self, note: &Note) {
+        let pitch_class = PITCH_CLASSES
+            .iter()
+            .position(|p| *p == note.pitch_class)
+            .unwrap_or_default();
+        self.u8(pitch_class as u8);
+        self.u8(note.octave as u8);
+        self.f32(note.cents);
+    }
+
+    fn notes(&mut self, notes: &[Note]) {
+        self.len(notes.len());
+        for note in notes {
+            self.note(note);
+        }
+    }
+
+    fn target(&mut self, target: &NoteTarget) {
+        match target {
+            NoteTarget::Note(note) => {
+                self.u8(TARGET_NOTE);
+                self.note(note);
+            }
+            NoteTarget::AliasKey(name) => {
+                self.u8(TARGET_ALIAS_KEY);
+                self.str(name);
+            }
+            NoteTarget::Alias(def) => {
+                self.u8(TARGET_ALIAS);
+                self.str(&def.name);
+                self.notes(&def.notes);
+            }
+            NoteTarget::Range { low, high } => {
+                self.u8(TARGET_RANGE);
+                self.note(low);
+                self.note(high);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self
+            .pos
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.pos..end))
+        else {
+            bail!("Unexpected end of binary data");
+        };
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.len()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn str(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.bytes()?)?)
+    }
+
+    fn time(&mut self) -> Result<BeatTime> {
+        let beats = self.u32()? as u64;
+        let fraction = self.u32()? as u64;
+        Ok(BeatTime::from_fixed_point(beats << 32 | fraction))
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(read(self)?)),
+            flag => bail!("Invalid option flag: {}", flag),
+        }
+    }
+
+    fn note(&mut self) -> Result<Note> {
+        let index = self.u8()?;
+        let Some(pitch_class) = PITCH_CLASSES.get(index as usize) else {
+            bail!("Invalid pitch class: {}", index);
+        };
+        Ok(Note {
+            pitch_class: *pitch_class,
+            octave: self.u8()? as i8,
+            cents: self.f32()?,
+        })
+    }
+
+    fn notes(&mut self) -> Result<Vec<Note>> {
+        let count = self.len()?;
+        // no preallocation, a corrupt count fails on the missing data
+        let mut notes = Vec::new();
+        for _ in 0..count {
+            notes.push(self.note()?);
+        }
+        Ok(notes)
+    }
+
+    fn target(&mut self) -> Result<NoteTarget> {
+        match self.u8()? {
+            TARGET_NOTE => Ok(NoteTarget::Note(self.note()?)),
+            TARGET_ALIAS_KEY => Ok(NoteTarget::AliasKey(self.str()?)),
+            TARGET_ALIAS => Ok(NoteTarget::Alias(Rc::new(AliasDefinition {
+                name: self.str()?,
+                notes: self.notes()?,
+            }))),
+            TARGET_RANGE => Ok(NoteTarget::Range {
+                low: self.note()?,
+                high: self.note()?,
+            }),
+            target => bail!("Invalid note target: {}", target),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+fn write_payload(record: &MtxtRecord, w: &mut Writer) -> u8 {
+    match record {
+        MtxtRecord::Header { version } => {
+            w.u16(version.major);
+            w.u16(version.minor);
+            tag::HEADER
+        }
+        MtxtRecord::GlobalMeta { meta_type, value } => {
+            w.str(meta_type);
+            w.str(value);
+            tag::GLOBAL_META
+        }
+        MtxtRecord::Meta {
+            time,
+            channel,
+            meta_type,
+            value,
+        } => {
+            w.option(*time, Writer::time);
+            w.option(*channel, Writer::u16);
+            w.str(meta_type);
+            w.str(value);
+            tag::META
+        }
+        MtxtRecord::DurationDirective { duration } => {
+            w.time(*duration);
+            tag::DURATION_DIRECTIVE
+        }
+        MtxtRecord::ChannelDirective { channel } => {
+            w.u16(*channel);
+            tag::CHANNEL_DIRECTIVE
+        }
+        MtxtRecord::VelocityDirective { velocity } => {
+            w.f32(*velocity);
+            tag::VELOCITY_DIRECTIVE
+        }
+        MtxtRecord::OffVelocityDirective { off_velocity } => {
+            w.f32(*off_velocity);
+            tag::OFF_VELOCITY_DIRECTIVE
+        }
+        MtxtRecord::TransitionCurveDirective { curve } => {
+            w.f32(*curve);
+            tag::TRANSITION_CURVE_DIRECTIVE
+        }
+        MtxtRecord::TransitionIntervalDirective { interval } => {
+            w.f32(*interval);
+            tag::TRANSITION_INTERVAL_DIRECTIVE
+        }
+        MtxtRecord::AliasDef { value } => {
+            w.str(&value.name);
+            w.notes(&value.notes);
+            tag::ALIAS_DEF
+        }
+        MtxtRecord::Note {
+            time,
+            note,
+            duration,
+            velocity,
+            off_velocity,
+            channel,
+        } => {
+            w.time(*time);
+            w.target(note);
+            w.option(*duration, Writer::time);
+            w.option(*velocity, Writer::f32);
+            w.option(*off_velocity, Writer::f32);
+            w.option(*channel, Writer::u16);
+            tag::NOTE
+        }
+        MtxtRecord::NoteOn {
+            time,
+            note,
+            velocity,
+            channel,
+        } => {
+            w.time(*time);
+            w.target(note);
+            w.option(*velocity, Writer::f32);
+            w.option(*channel, Writer::u16);
+            tag::NOTE_ON
+        }
+        MtxtRecord::NoteOff {
+            time,
+            note,
+            off_velocity,
+            channel,
+        } => {
+            w.time(*time);
+            w.target(note);
+            w.option(*off_velocity, Writer::f32);
+            w.option(*channel, Writer::u16);
+            tag::NOTE_OFF
+        }
+        MtxtRecord::ControlChange {
+            time,
+            note,
+            controller,
+            value,
+            channel,
+            transition_curve,
+            transition_time,
+            transition_interval,
+        } => {
+            w.time(*time);
+            w.option(note.as_ref(), Writer::target);
+            w.str(controller);
+            w.f32(*value);
+            w.option(*channel, Writer::u16);
+            w.option(*transition_curve, Writer::f32);
+            w.option(*transition_time, Writer::time);
+            w.option(*transition_interval, Writer::f32);
+            tag::CONTROL_CHANGE
+        }
+        MtxtRecord::Voice {
+            time,
+            voices,
+            channel,
+        } => {
+            w.time(*time);
+            w.len(voices.voices.len());
+            for voice in &voices.voices {
+                w.str(voice);
+            }
+            w.option(*channel, Writer::u16);
+            tag::VOICE
+        }
+        MtxtRecord::Tempo {
+            time,
+            bpm,
+            transition_curve,
+            transition_time,
+            transition_interval,
+        } => {
+            w.time(*time);
+            w.f32(*bpm);
+            w.option(*transition_curve, Writer::f32);
+            w.option(*transition_time, Writer::time);
+            w.option(*transition_interval, Writer::f32);
+            tag::TEMPO
+        }
+        MtxtRecord::TimeSignature { time, signature } => {
+            w.time(*time);
+            w.u8(signature.numerator);
+            w.u8(signature.denominator);
+            tag::TIME_SIGNATURE
+        }
+        MtxtRecord::Tuning {
+            time,
+            target,
+            cents,
+        } => {
+            w.time(*time);
+            w.str(target);
+            w.f32(*cents);
+            tag::TUNING
+        }
+        MtxtRecord::Reset { time, target } => {
+            w.time(*time);
+            w.str(target);
+            tag::RESET
+        }
+        MtxtRecord::SysEx { time, data } => {
+            w.time(*time);
+            w.bytes(data);
+            tag::SYSEX
+        }
+        MtxtRecord::EmptyLine => tag::EMPTY_LINE,
+    }
+}
+
+fn read_payload(tag: u8, r: &mut Reader) -> Result<MtxtRecord> {
+    let record = match tag {
+        tag::HEADER => MtxtRecord::Header {
+            version: Version {
+                major: r.u16()?,
+                minor: r.u16()?,
+            },
+        },
+        tag::GLOBAL_META => MtxtRecord::GlobalMeta {
+            meta_type: r.str()?,
+            value: r.str()?,
+        },
+        tag::META => MtxtRecord::Meta {
+            time: r.option(Reader::time)?,
+            channel: r.option(Reader::u16)?,
+            meta_type: r.str()?,
+            value: r.str()?,
+        },
+        tag::DURATION_DIRECTIVE => MtxtRecord::DurationDirective {
+            duration: r.time()?,
+        },
+        tag::CHANNEL_DIRECTIVE => MtxtRecord::ChannelDirective { channel: r.u16()? },
+        tag::VELOCITY_DIRECTIVE => MtxtRecord::VelocityDirective { velocity: r.f32()? },
+        tag::OFF_VELOCITY_DIRECTIVE => MtxtRecord::OffVelocityDirective {
+            off_velocity: r.f32()?,
+        },
+        tag::TRANSITION_CURVE_DIRECTIVE => MtxtRecord::TransitionCurveDirective { curve: r.f32()? },
+        tag::TRANSITION_INTERVAL_DIRECTIVE => {
+            MtxtRecord::TransitionIntervalDirective { interval: r.f32()? }
+        }
+        tag::ALIAS_DEF => MtxtRecord::AliasDef {
+            value: Rc::new(AliasDefinition {
+                name: r.str()?,
+                notes: r.notes()?,
+            }),
+        },
+        tag::NOTE => MtxtRecord::Note {
+            time: r.time()?,
+            note: r.target()?,
+            duration: r.option(Reader::time)?,
+            velocity: r.option(Reader::f32)?,
+            off_velocity: r.option(Reader::f32)?,
+            channel: r.option(Reader::u16)?,
+        },
+        tag::NOTE_ON => MtxtRecord::NoteOn {
+            time: r.time()?,
+            note: r.target()?,
+            velocity: r.option(Reader::f32)?,
+            channel: r.option(Reader::u16)?,
+        },
+        tag::NOTE_OFF => MtxtRecord::NoteOff {
+            time: r.time()?,
+            note: r.target()?,
+            off_velocity: r.option(Reader::f32)?,
+            channel: r.option(Reader::u16)?,
+        },
+        tag::CONTROL_CHANGE => MtxtRecord::ControlChange {
+            time: r.time()?,
+            note: r.option(Reader::target)?,
+            controller: r.str()?,
+            value: r.f32()?,
+            channel: r.option(Reader::u16)?,
+            transition_curve: r.option(Reader::f32)?,
+            transition_time: r.option(Reader::time)?,
+            transition_interval: r.option(Reader::f32)?,
+        },
+        tag::VOICE => {
+            let time = r.time()?;
+            let count = r.len()?;
+            let mut voices = Vec::new();
+            for _ in 0..count {
+                voices.push(r.str()?);
+            }
+            MtxtRecord::Voice {
+                time,
+                voices: VoiceList { voices },
+                channel: r.option(Reader::u16)?,
+            }
+        }
+        tag::TEMPO => MtxtRecord::Tempo {
+            time: r.time()?,
+            bpm: r.f32()?,
+            transition_curve: r.option(Reader::f32)?,
+            transition_time: r.option(Reader::time)?,
+            transition_interval: r.option(Reader::f32)?,
+        },
+        tag::TIME_SIGNATURE => MtxtRecord::TimeSignature {
+            time: r.time()?,
+            signature: TimeSignature {
+                numerator: r.u8()?,
+                denominator: r.u8()?,
+            },
+        },
+        tag::TUNING => MtxtRecord::Tuning {
+            time: r.time()?,
+            target: r.str()?,
+            cents: r.f32()?,
+        },
+        tag::RESET => MtxtRecord::Reset {
+            time: r.time()?,
+            target: r.str()?,
+        },
+        tag::SYSEX => MtxtRecord::SysEx {
+            time: r.time()?,
+            data: r.bytes()?,
+        },
+        tag::EMPTY_LINE => MtxtRecord::EmptyLine,
+        tag => bail!("Unknown record type: 0x{:02X}", tag),
+    };
+    if !r.is_empty() {
+        bail!("Invalid length of record type 0x{:02X}", tag);
+    }
+    Ok(record)
+}
+
+// Splits the type, payload and total size of the next entry
+fn read_entry(data: &[u8]) -> Result<(u8, &[u8], usize)> {
+    let mut r = Reader::new(data);
+    let tag = r.u8()?;
+    let len = r.len()?;
+    let payload = r.take(len)?;
+    Ok((tag, payload, r.pos))
+}
+
+fn write_entry(tag: u8, payload: &[u8], data: &mut Vec<u8>) {
+    data.push(tag);
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(payload);
+}
+
+impl MtxtRecord {
+    /// The record in the binary encoding, see [`crate::binary`]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = Writer::default();
+        let tag = write_payload(self, &mut payload);
+        let mut data = Vec::with_capacity(payload.data.len() + 5);
+        write_entry(tag, &payload.data, &mut data);
+        data
+    }
+
+    /// Decodes the record at the start of `data`, with the number of bytes it used
+    pub fn from_bytes(data: &[u8]) -> Result<(MtxtRecord, usize)> {
+        let (tag, payload, size) = read_entry(data)?;
+        let record = read_payload(tag, &mut Reader::new(payload))?;
+        Ok((record, size))
+    }
+}
+
+pub(crate) fn write_comment(comment: &str, data: &mut Vec<u8>) {
+    write_entry(tag::COMMENT, comment.as_bytes(), data);
+}
+
+/// The comment at the start of `data` with its size, `None` if the next entry is a record
+pub(crate) fn read_comment(data: &[u8]) -> Result<Option<(String, usize)>> {
+    let (tag, payload, size) = read_entry(data)?;
+    if tag != tag::COMMENT {
+        return Ok(None);
+    }
+    Ok(Some((String::from_utf8(payload.to_vec())?, size)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MtxtFile, parse_mtxt};
+
+    const ALL_RECORDS: &str = r#"mtxt 1.0
+meta global title Binary "test"
+meta composer Someone
+
+dur=0.5
+ch=2
+vel=0.8
+offvel=0.25
+transition_curve=0.5
+transition_interval=10
+alias Cmaj C4,E4,G4
+0.0 tempo 120 // start
+0.0 timesig 7/8
+0.0 voice piano, strings
+0.0 meta ch=3 name Lead
+1.0 note Cmaj
+1.333333 note Db4-F#4 dur=0.25 vel=0.7 offvel=0.1 ch=1
+2.0 on B#3+25.5 vel=0.9
+2.5 off B#3+25.5 offvel=0.2 ch=0
+3.0 cc C4 pitch 0.5 ch=4 transition_time=1.5
+3.5 cc volume 1.0 transition_curve=-1 transition_interval=5
+4.0 tempo 90 transition_time=2 transition_curve=0.5
+4.0 tuning A 12.5
+4.0 reset all
+4.0 sysex 7e 7f 09 01
+"#;
+
+    #[test]
+    fn test_record_round_trip() {
+        let file = parse_mtxt(ALL_RECORDS).unwrap();
+        for line in &file.records {
+            let bytes = line.record.to_bytes();
+            let (record, size) = MtxtRecord::from_bytes(&bytes).unwrap();
+            assert_eq!(record, line.record);
+            assert_eq!(size, bytes.len());
+        }
+
+        let note = MtxtRecord::NoteOn {
+            time: BeatTime::from_parts(1, 0.5),
+            note: NoteTarget::Alias(Rc::new(AliasDefinition {
+                name: "Dyad".to_string(),
+                notes: vec![
+                    "Cb0".parse().unwrap(),
+                    "Fx5".parse().unwrap(),
+                    "G9".parse().unwrap(),
+                ],
+            })),
+            velocity: None,
+            channel: Some(15),
+        };
+        let mut bytes = note.to_bytes();
+        bytes.extend_from_slice(&[tag::EMPTY_LINE, 0, 0, 0, 0]);
+        let (record, size) = MtxtRecord::from_bytes(&bytes).unwrap();
+        assert_eq!(record, note);
+        assert_eq!(
+            MtxtRecord::from_bytes(&bytes[size..]).unwrap(),
+            (MtxtRecord::EmptyLine, 5)
+        );
+    }
+
+    #[test]
+    fn test_channel_directive_encoding() {
+        let bytes = MtxtRecord::ChannelDirective { channel: 0x1234 }.to_bytes();
+        assert_eq!(bytes, [tag::CHANNEL_DIRECTIVE, 0, 0, 0, 2, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let file = parse_mtxt(ALL_RECORDS).unwrap();
+        let binary = file.to_binary();
+        assert_eq!(binary[..4], BINARY_MAGIC);
+        assert_eq!(binary[4], BINARY_VERSION);
+        let decoded = MtxtFile::from_binary(&binary).unwrap();
+        assert_eq!(decoded.records, file.records);
+        assert_eq!(decoded.to_string(), file.to_string());
+        assert!(binary.len() < ALL_RECORDS.len() * 2);
+    }
+
+    #[test]
+    fn test_invalid_binary() {
+        let binary = parse_mtxt(ALL_RECORDS).unwrap().to_binary();
+        for len in 0..5 {
+            assert!(MtxtFile::from_binary(&binary[..len]).is_err());
+        }
+        assert!(
+            MtxtFile::from_binary(&binary[..5])
+                .unwrap()
+                .records
+                .is_empty()
+        );
+        assert!(MtxtFile::from_binary(&binary[..binary.len() - 1]).is_err());
+        assert!(MtxtFile::from_binary(b"MTXX\x01").is_err());
+        assert!(MtxtFile::from_binary(b"MTXT\x02").is_err());
+        assert!(MtxtRecord::from_bytes(&[0x99, 0, 0, 0, 0]).is_err());
+        // payload longer than the record
+        assert!(MtxtRecord::from_bytes(&[tag::EMPTY_LINE, 0, 0, 0, 1, 0]).is_err());
+        // comment without a record
+        assert!(MtxtFile::from_binary(b"MTXT\x01\xFE\x00\x00\x00\x00").is_err());
+    }
+}
@@ -1,19 +1,64 @@
+use crate::binary::{self, BINARY_MAGIC, BINARY_VERSION};
+use crate::piano_roll::{self, PianoRollNote, PianoRollOptions};
 use crate::process::process_records;
-use crate::types::beat_time::BeatTime;
+use crate::transforms::channels::record_channel;
+use crate::transforms::swing::SwingReport;
+use crate::transforms::{
+    TransformDescriptor, apply, apply_transform_chain, include, remap, sort, swing, velocity,
+};
+use crate::transitions::apply_transition_curve;
+use crate::types::beat_time::{BeatTime, BeatTimeFormat};
+use crate::types::chords::{NO_CHORD, chord_root, match_chord};
+use crate::types::key::{ScaleMode, find_key};
+use crate::types::note::NoteTarget;
 use crate::types::output_record::MtxtOutputRecord;
 use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use crate::types::time_signature::{TimeSignature, TimeSignatureMap};
 use crate::types::version::Version;
+use crate::{BeatFraction, Note, PitchClass, TempoMap};
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::path::Path;
 
 pub struct MtxtFileFormatter<'a> {
     file: &'a MtxtFile,
     timestamp_width: Option<usize>,
+    time_sig: Option<TimeSignature>,
+    time_format: BeatTimeFormat,
+}
+
+impl MtxtFileFormatter<'_> {
+    /// Writes the times, durations and transition times with the given format
+    pub fn with_time_format(mut self, time_format: BeatTimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
 }
 
 impl<'a> fmt::Display for MtxtFileFormatter<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let measure_map = self.time_sig.as_ref().map(|sig| {
+            let pickup = self.file.pickup_duration().unwrap_or_default();
+            TimeSignatureMap::new(sig, &self.file.records, pickup)
+        });
+        let mut last_measure: Option<u32> = None;
+
         for line in &self.file.records {
             let record = &line.record;
+
+            if let (Some(map), Some(time)) = (&measure_map, record.time()) {
+                let measure = map.bar_at(time);
+                if last_measure.is_none_or(|last| measure > last) {
+                    if measure == 0 {
+                        writeln!(f, "// pickup")?;
+                    } else {
+                        writeln!(f, "// measure: {}", measure)?;
+                    }
+                    last_measure = Some(measure);
+                }
+            }
+
             match record {
                 // File-level records don't have timestamps
                 MtxtRecord::Header { .. } | MtxtRecord::GlobalMeta { .. } => {
@@ -27,25 +72,28 @@ impl<'a> fmt::Display for MtxtFileFormatter<'a> {
                 }
                 // Timed or directive records: print with timestamp
                 _ => {
+                    let time_format = self.time_format;
+                    let record_display = record.display_with(time_format);
                     match record.time() {
                         Some(time) => {
+                            let time = time.display_with(time_format);
                             if let Some(width) = self.timestamp_width {
-                                write!(f, "{:<width$} {}", time, record, width = width)?;
+                                write!(f, "{:<width$} {}", time, record_display, width = width)?;
                             } else {
-                                write!(f, "{} {}", time, record)?;
+                                write!(f, "{} {}", time, record_display)?;
                             }
                         }
                         None => {
-                            write!(f, "{}", record)?;
+                            write!(f, "{}", record_display)?;
                         }
                     };
                 }
             }
 
-            if record != &MtxtRecord::EmptyLine {
-                if let Some(comment) = &line.comment {
-                    write!(f, " // {}", comment)?;
-                }
+            if record != &MtxtRecord::EmptyLine
+                && let Some(comment) = &line.comment
+            {
+                write!(f, " // {}", comment)?;
             }
             writeln!(f)?;
         }
@@ -53,6 +101,148 @@ impl<'a> fmt::Display for MtxtFileFormatter<'a> {
     }
 }
 
+struct NoteSpan {
+    // index of the note or note on record
+    idx: usize,
+    channel: u16,
+    start: BeatTime,
+    end: BeatTime,
+    notes: Vec<Note>,
+}
+
+fn sounding_notes(spans: &[NoteSpan], beat: BeatTime) -> impl Iterator<Item = &Note> {
+    spans
+        .iter()
+        .filter(move |span| span.start <= beat && beat < span.end)
+        .flat_map(|span| span.notes.iter())
+}
+
+// Multiples of `resolution` until the last note ends
+fn grid_points(spans: &[NoteSpan], resolution: BeatFraction) -> impl Iterator<Item = BeatTime> {
+    let step = resolution.as_beat_time();
+    let last_end = spans.iter().map(|span| span.end).max();
+    let count = match last_end {
+        Some(end) if step > BeatTime::zero() => end.as_f64().div_euclid(step.as_f64()) as u64 + 1,
+        _ => 0,
+    };
+    (0..count)
+        .map(move |i| step.mul_ratio(i, 1))
+        .take_while(move |beat| last_end.is_some_and(|end| *beat < end))
+}
+
+/// Snapshot of the note spans of a file, it is not updated when the records change
+pub struct ActiveNotesIndex {
+    // sorted by onset
+    spans: Vec<NoteSpan>,
+}
+
+impl ActiveNotesIndex {
+    /// Same as [`MtxtFile::get_active_notes_at`]
+    pub fn at(&self, beat: BeatTime) -> Vec<(usize, u8)> {
+        let started = self.spans.partition_point(|span| span.start <= beat);
+        self.spans[..started]
+            .iter()
+            .filter(|span| beat < span.end)
+            .flat_map(|span| {
+                span.notes
+                    .iter()
+                    .map(|note| (span.idx, note.to_midi_note()))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceLeadingErrorKind {
+    /// Both voices move in the same direction from a fifth to a fifth
+    ParallelFifths,
+    /// Both voices move in the same direction from an octave or unison to an octave or unison
+    ParallelOctaves,
+    /// Both voices move in the same direction into a fifth from another interval
+    DirectFifths,
+    /// Both voices move in the same direction into an octave or unison from another interval
+    DirectOctaves,
+    /// The upper voice moves below the lower voice, reported once until they uncross
+    VoiceCrossing,
+}
+
+/// Counterpoint error between two voices, found by [`MtxtFile::compute_voice_leading_errors`].
+/// `note_a` and `note_b` are the MIDI pitches of the upper and lower voice where it occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoiceLeadingError {
+    pub at_beat: BeatTime,
+    pub kind: VoiceLeadingErrorKind,
+    pub channel_a: u16,
+    pub channel_b: u16,
+    pub note_a: u8,
+    pub note_b: u8,
+}
+
+// Melody of a channel as (start, end, MIDI pitch) sorted by start,
+// the highest or lowest note of chords
+fn melodic_line(spans: &[NoteSpan], channel: u16, highest: bool) -> Vec<(BeatTime, BeatTime, u8)> {
+    let mut line: BTreeMap<BeatTime, (BeatTime, u8)> = BTreeMap::new();
+    for span in spans
+        .iter()
+        .filter(|span| span.channel == channel && span.start < span.end)
+    {
+        for note in &span.notes {
+            let pitch = note.to_midi_note();
+            let entry = line.entry(span.start).or_insert((span.end, pitch));
+            if (highest && pitch > entry.1) || (!highest && pitch < entry.1) {
+                *entry = (span.end, pitch);
+            }
+        }
+    }
+    line.into_iter()
+        .map(|(start, (end, pitch))| (start, end, pitch))
+        .collect()
+}
+
+// Pitch of a melodic line sounding at `beat`, the last note started at or before it
+fn line_pitch_at(line: &[(BeatTime, BeatTime, u8)], beat: BeatTime) -> Option<u8> {
+    let started = line.partition_point(|(start, _, _)| *start <= beat);
+    line[..started]
+        .last()
+        .filter(|(_, end, _)| beat < *end)
+        .map(|(_, _, pitch)| *pitch)
+}
+
+// Every pair of notes sounding at the same time as ((channel, MIDI pitch), (channel, MIDI pitch)),
+// counted once when the later of the two starts. The first note of a pair starts no later.
+fn simultaneous_pairs(spans: &[NoteSpan]) -> Vec<((u16, u8), (u16, u8))> {
+    let mut notes: Vec<(BeatTime, BeatTime, u16, u8)> = spans
+        .iter()
+        .filter(|span| span.start < span.end)
+        .flat_map(|span| {
+            span.notes
+                .iter()
+                .map(|note| (span.start, span.end, span.channel, note.to_midi_note()))
+        })
+        .collect();
+    notes.sort_by_key(|(start, ..)| *start);
+
+    let mut pairs = Vec::new();
+    for (i, (_, end, channel, pitch)) in notes.iter().enumerate() {
+        for (start_b, _, channel_b, pitch_b) in &notes[i + 1..] {
+            if start_b >= end {
+                break;
+            }
+            pairs.push(((*channel, *pitch), (*channel_b, *pitch_b)));
+        }
+    }
+    pairs
+}
+
+/// Chord of a measure in [`MtxtFile::chord_chart`], `N.C.` if no known chord sounds in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordMeasure {
+    /// 1-based, 0 for a pickup
+    pub measure: u32,
+    pub start_beat: BeatTime,
+    pub symbol: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct MtxtFile {
     pub records: Vec<MtxtRecordLine>,
@@ -80,6 +270,31 @@ impl MtxtFile {
         self.records.iter().map(|line| &line.record).collect()
     }
 
+    /// Records of a channel and the records without one, like tempo or global CCs.
+    /// Notes and voices without a channel belong to the channel of the last `ch=` directive.
+    pub fn iter_channel(&self, channel: u16) -> impl Iterator<Item = &MtxtRecord> + '_ {
+        let mut current_channel: u16 = 0;
+        self.records
+            .iter()
+            .map(|line| &line.record)
+            .filter(move |record| {
+                if let MtxtRecord::ChannelDirective { channel } = record {
+                    current_channel = *channel;
+                }
+                record_channel(record, Some(current_channel)).is_none_or(|ch| ch == channel)
+            })
+    }
+
+    /// Note, note on and note off records of a channel
+    pub fn iter_channel_notes(&self, channel: u16) -> impl Iterator<Item = &MtxtRecord> + '_ {
+        self.iter_channel(channel).filter(|record| {
+            matches!(
+                record,
+                MtxtRecord::Note { .. } | MtxtRecord::NoteOn { .. } | MtxtRecord::NoteOff { .. }
+            )
+        })
+    }
+
     /// Get the version from the records
     pub fn get_version(&self) -> Option<&Version> {
         self.records.iter().find_map(|line| match &line.record {
@@ -111,6 +326,42 @@ impl MtxtFile {
         })
     }
 
+    pub fn title(&self) -> Option<&str> {
+        self.get_global_meta_value("title")
+    }
+
+    pub fn copyright(&self) -> Option<&str> {
+        self.get_global_meta_value("copyright")
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.get_global_meta_value("key")
+    }
+
+    /// BPM of the earliest tempo record
+    pub fn initial_bpm(&self) -> Option<f32> {
+        self.records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Tempo { time, bpm, .. } => Some((*time, *bpm)),
+                _ => None,
+            })
+            .min_by_key(|(time, _)| *time)
+            .map(|(_, bpm)| bpm)
+    }
+
+    /// Signature of the earliest time signature record
+    pub fn initial_time_signature(&self) -> Option<TimeSignature> {
+        self.records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::TimeSignature { time, signature } => Some((*time, signature)),
+                _ => None,
+            })
+            .min_by_key(|(time, _)| *time)
+            .map(|(_, signature)| signature.clone())
+    }
+
     pub fn duration(&self) -> Option<BeatTime> {
         self.records
             .iter()
@@ -122,6 +373,745 @@ impl MtxtFile {
             })
     }
 
+    // Every note of the file with its sounding range, chords resolved to their notes
+    fn note_spans(&self) -> Vec<NoteSpan> {
+        let mut current_channel: u16 = 0;
+        let mut current_duration = BeatTime::from_parts(1, 0.0);
+        let mut aliases: HashMap<&str, &[Note]> = HashMap::new();
+        // spans of note on events, ended by the matching note off
+        let mut open_notes: HashMap<(u16, String), VecDeque<NoteSpan>> = HashMap::new();
+        let mut spans = Vec::new();
+
+        let resolve = |target: &NoteTarget, aliases: &HashMap<&str, &[Note]>| match target {
+            NoteTarget::Note(note) => vec![note.clone()],
+            NoteTarget::AliasKey(name) => aliases
+                .get(name.as_str())
+                .map(|notes| notes.to_vec())
+                .unwrap_or_default(),
+            NoteTarget::Alias(def) => def.notes.clone(),
+            NoteTarget::Range { low, high } => Note::chromatic_range(low, high),
+        };
+
+        for (idx, line) in self.records.iter().enumerate() {
+            match &line.record {
+                MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+                MtxtRecord::DurationDirective { duration } => current_duration = *duration,
+                MtxtRecord::AliasDef { value } => {
+                    aliases.insert(value.name.as_str(), &value.notes);
+                }
+                MtxtRecord::Note {
+                    time,
+                    note,
+                    duration,
+                    channel,
+                    ..
+                } => spans.push(NoteSpan {
+                    idx,
+                    channel: channel.unwrap_or(current_channel),
+                    start: *time,
+                    end: *time + duration.unwrap_or(current_duration),
+                    notes: resolve(note, &aliases),
+                }),
+                MtxtRecord::NoteOn {
+                    time,
+                    note,
+                    channel,
+                    ..
+                } => {
+                    let channel = channel.unwrap_or(current_channel);
+                    let key = (channel, note.to_string());
+                    open_notes.entry(key).or_default().push_back(NoteSpan {
+                        idx,
+                        channel,
+                        start: *time,
+                        end: *time,
+                        notes: resolve(note, &aliases),
+                    });
+                }
+                MtxtRecord::NoteOff {
+                    time,
+                    note,
+                    channel,
+                    ..
+                } => {
+                    let key = (channel.unwrap_or(current_channel), note.to_string());
+                    if let Some(mut span) =
+                        open_notes.get_mut(&key).and_then(|queue| queue.pop_front())
+                    {
+                        span.end = *time;
+                        spans.push(span);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        spans
+    }
+
+    /// Number of notes sounding at `beat` (started at or before it and not yet ended)
+    pub fn polyphony_at_beat(&self, beat: BeatTime) -> u32 {
+        let spans = self.note_spans();
+        sounding_notes(&spans, beat).count() as u32
+    }
+
+    /// Notes sounding at `beat` as (record index, MIDI pitch) pairs, ordered by onset.
+    /// Note on records are paired with their note off, aliases and ranges give one pair per note.
+    /// Build an [`ActiveNotesIndex`] instead to query many times of an unchanged file.
+    pub fn get_active_notes_at(&self, beat: BeatTime) -> Vec<(usize, u8)> {
+        self.active_notes_index().at(beat)
+    }
+
+    /// Note spans sorted by onset, for repeated `get_active_notes_at` queries
+    pub fn active_notes_index(&self) -> ActiveNotesIndex {
+        let mut spans = self.note_spans();
+        spans.sort_by_key(|span| (span.start, span.idx));
+        ActiveNotesIndex { spans }
+    }
+
+    /// Highest number of notes sounding at the same time
+    pub fn max_polyphony(&self) -> u32 {
+        // note ends sort before note starts at the same time
+        let mut events: Vec<(BeatTime, bool, u32)> = self
+            .note_spans()
+            .into_iter()
+            .flat_map(|span| {
+                let voices = span.notes.len() as u32;
+                [(span.start, true, voices), (span.end, false, voices)]
+            })
+            .collect();
+        events.sort_by_key(|(time, is_start, _)| (*time, *is_start));
+
+        let mut current: u32 = 0;
+        let mut max: u32 = 0;
+        for (_, is_start, voices) in events {
+            if is_start {
+                current += voices;
+                max = max.max(current);
+            } else {
+                current = current.saturating_sub(voices);
+            }
+        }
+        max
+    }
+
+    /// MIDI pitches sounding after every note start or end, sorted by time, one entry per time.
+    /// Unlike sampling on a grid no change is missed, and sparse parts give few entries.
+    /// A pitch played twice at once (e.g. on two channels) is listed once, until both end.
+    pub fn to_pitch_timeline(&self) -> Vec<(BeatTime, Vec<u8>)> {
+        // note ends sort before note starts at the same time, so repeated notes stay active
+        let mut events: Vec<(BeatTime, bool, u8)> = self
+            .note_spans()
+            .into_iter()
+            .filter(|span| span.start < span.end)
+            .flat_map(|span| {
+                span.notes.into_iter().flat_map(move |note| {
+                    let pitch = note.to_midi_note();
+                    [(span.start, true, pitch), (span.end, false, pitch)]
+                })
+            })
+            .collect();
+        events.sort_by_key(|(time, is_start, _)| (*time, *is_start));
+
+        let mut active: BTreeMap<u8, u32> = BTreeMap::new();
+        let mut timeline: Vec<(BeatTime, Vec<u8>)> = Vec::new();
+        for (idx, (time, is_start, pitch)) in events.iter().enumerate() {
+            if *is_start {
+                *active.entry(*pitch).or_default() += 1;
+            } else if let Some(count) = active.get_mut(pitch) {
+                *count -= 1;
+                if *count == 0 {
+                    active.remove(pitch);
+                }
+            }
+            if events.get(idx + 1).is_none_or(|(next, _, _)| next != time) {
+                timeline.push((*time, active.keys().copied().collect()));
+            }
+        }
+        timeline
+    }
+
+    /// Polyphony sampled at every multiple of `resolution` until the last note ends
+    pub fn polyphony_timeline(&self, resolution: BeatFraction) -> Vec<(BeatTime, u32)> {
+        let spans = self.note_spans();
+        grid_points(&spans, resolution)
+            .map(|beat| (beat, sounding_notes(&spans, beat).count() as u32))
+            .collect()
+    }
+
+    /// Counterpoint errors between the melodies of two channels, in time order.
+    /// Chords are reduced to their highest note in the upper channel and their lowest note
+    /// in the lower channel. Motion is checked at every note start of either channel while both
+    /// voices sound, a rest in either voice ends the progression. Intervals are compared
+    /// modulo the octave, so a unison counts as an octave and a twelfth as a fifth.
+    pub fn compute_voice_leading_errors(
+        &self,
+        upper_channel: u16,
+        lower_channel: u16,
+    ) -> Vec<VoiceLeadingError> {
+        let spans = self.note_spans();
+        let upper = melodic_line(&spans, upper_channel, true);
+        let lower = melodic_line(&spans, lower_channel, false);
+        let onsets: BTreeSet<BeatTime> = upper
+            .iter()
+            .chain(lower.iter())
+            .map(|(start, _, _)| *start)
+            .collect();
+
+        let mut errors = Vec::new();
+        let mut previous: Option<(u8, u8)> = None;
+        for beat in onsets {
+            let (Some(a), Some(b)) = (line_pitch_at(&upper, beat), line_pitch_at(&lower, beat))
+            else {
+                previous = None;
+                continue;
+            };
+            let mut report = |kind| {
+                errors.push(VoiceLeadingError {
+                    at_beat: beat,
+                    kind,
+                    channel_a: upper_channel,
+                    channel_b: lower_channel,
+                    note_a: a,
+                    note_b: b,
+                })
+            };
+
+            let crossed = a < b;
+            if crossed && previous.is_none_or(|(prev_a, prev_b)| prev_a >= prev_b) {
+                report(VoiceLeadingErrorKind::VoiceCrossing);
+            }
+
+            if let Some((prev_a, prev_b)) = previous {
+                let motion_a = (a as i16 - prev_a as i16).signum();
+                let motion_b = (b as i16 - prev_b as i16).signum();
+                let interval = a.abs_diff(b) % 12;
+                let prev_interval = prev_a.abs_diff(prev_b) % 12;
+                if motion_a != 0 && motion_a == motion_b {
+                    let kind = match (interval, interval == prev_interval) {
+                        (7, true) => Some(VoiceLeadingErrorKind::ParallelFifths),
+                        (0, true) => Some(VoiceLeadingErrorKind::ParallelOctaves),
+                        (7, false) => Some(VoiceLeadingErrorKind::DirectFifths),
+                        (0, false) => Some(VoiceLeadingErrorKind::DirectOctaves),
+                        _ => None,
+                    };
+                    if let Some(kind) = kind {
+                        report(kind);
+                    }
+                }
+            }
+            previous = Some((a, b));
+        }
+        errors
+    }
+
+    /// Counts of the intervals in semitones between the notes of two channels sounding at the
+    /// same time, positive if the note of `channel_a` is above the note of `channel_b`.
+    /// Each pair of notes is counted once, at the onset of the later one. With the same
+    /// channel twice, every pair is counted in both directions.
+    pub fn compute_interval_histogram(&self, channel_a: u16, channel_b: u16) -> HashMap<i8, u32> {
+        let mut histogram = HashMap::new();
+        for (first, second) in simultaneous_pairs(&self.note_spans()) {
+            for ((ch_a, a), (ch_b, b)) in [(first, second), (second, first)] {
+                if ch_a == channel_a && ch_b == channel_b {
+                    *histogram.entry(a as i8 - b as i8).or_default() += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    /// Counts of the intervals in semitones (always positive or zero) between any two notes
+    /// sounding at the same time, on any channel. Each pair is counted once, at the onset of
+    /// the later note.
+    pub fn compute_all_intervals_histogram(&self) -> HashMap<i8, u32> {
+        let mut histogram = HashMap::new();
+        for ((_, a), (_, b)) in simultaneous_pairs(&self.note_spans()) {
+            *histogram.entry(a.abs_diff(b) as i8).or_default() += 1;
+        }
+        histogram
+    }
+
+    /// Total sounding duration in beats of every pitch class (C = 0)
+    pub fn pitch_class_histogram(&self) -> [f32; 12] {
+        let mut histogram = [0.0; 12];
+        for span in self.note_spans() {
+            let length = (span.end - span.start).as_f64() as f32;
+            for note in &span.notes {
+                histogram[note.pitch_class.to_semitone() as usize % 12] += length;
+            }
+        }
+        histogram
+    }
+
+    /// Most likely key of the file (Krumhansl-Schmuckler)
+    pub fn detect_key(&self) -> Option<(Note, ScaleMode)> {
+        self.detect_key_confidence()
+            .map(|(tonic, mode, _)| (tonic, mode))
+    }
+
+    /// Most likely key with the correlation of its key profile as a confidence score.
+    /// `None` if fewer than 4 distinct pitch classes are played.
+    pub fn detect_key_confidence(&self) -> Option<(Note, ScaleMode, f32)> {
+        let histogram = self.pitch_class_histogram();
+        if histogram.iter().filter(|weight| **weight > 0.0).count() < 4 {
+            return None;
+        }
+        let (tonic, mode, score) = find_key(&histogram);
+        let tonic = Note {
+            pitch_class: PitchClass::from_semitone(tonic, false).to_canonical(),
+            octave: 4,
+            cents: 0.0,
+        };
+        Some((tonic, mode, score))
+    }
+
+    /// Chord symbol (e.g. `Cmaj7`, `Fm`, `G7`) of the notes sounding at every multiple of
+    /// `resolution`, or `N.C.` if they don't form a known chord
+    pub fn detect_chords(&self, resolution: BeatFraction) -> Vec<(BeatTime, String)> {
+        let spans = self.note_spans();
+        grid_points(&spans, resolution)
+            .map(|beat| {
+                let mut pitch_classes = [false; 12];
+                for note in sounding_notes(&spans, beat) {
+                    pitch_classes[note.pitch_class.to_semitone() as usize % 12] = true;
+                }
+                let symbol = match_chord(&pitch_classes)
+                    .map_or_else(|| NO_CHORD.to_string(), |chord| chord.to_string());
+                (beat, symbol)
+            })
+            .collect()
+    }
+
+    /// One chord symbol per measure for a lead sheet: of the chords found every quarter beat by
+    /// [`detect_chords`](Self::detect_chords), the one sounding the longest in the measure.
+    /// Measures follow the time signatures of the file, 4/4 if there are none.
+    pub fn chord_chart(&self) -> Vec<ChordMeasure> {
+        let initial = self.initial_time_signature().unwrap_or_default();
+        let pickup = self.pickup_duration().unwrap_or_default();
+        let measure_map = TimeSignatureMap::new(&initial, &self.records, pickup);
+
+        // chords of each measure with the number of samples, in order of appearance
+        let mut measures: BTreeMap<u32, Vec<(String, u32)>> = BTreeMap::new();
+        for (beat, symbol) in self.detect_chords(BeatFraction {
+            numerator: 1,
+            denominator: 4,
+        }) {
+            let chords = measures.entry(measure_map.bar_at(beat)).or_default();
+            if symbol == "N.C." {
+                continue;
+            }
+            match chords.iter_mut().find(|(chord, _)| *chord == symbol) {
+                Some((_, count)) => *count += 1,
+                None => chords.push((symbol, 1)),
+            }
+        }
+
+        measures
+            .into_iter()
+            .map(|(measure, chords)| {
+                let symbol = chords
+                    .into_iter()
+                    .fold(
+                        None,
+                        |best: Option<(String, u32)>, (chord, count)| match best {
+                            Some(best) if best.1 >= count => Some(best),
+                            _ => Some((chord, count)),
+                        },
+                    )
+                    .map_or_else(|| "N.C.".to_string(), |(chord, _)| chord);
+                ChordMeasure {
+                    measure,
+                    start_beat: measure_map.bar_start(measure),
+                    symbol,
+                }
+            })
+            .collect()
+    }
+
+    /// Skeleton of the file with a note on the root of each chord of the
+    /// [`chord_chart`](Self::chord_chart), in octave 3 on channel 0 with velocity 0.8.
+    /// A chord lasting several measures is a single note, measures without a chord are rests.
+    /// The tempo and time signature records of the file are kept.
+    pub fn to_chord_progression_mtxt(&self) -> Result<MtxtFile> {
+        let mut records = vec![MtxtRecordLine::new(MtxtRecord::Header {
+            version: self
+                .get_version()
+                .cloned()
+                .unwrap_or(Version { major: 1, minor: 0 }),
+        })];
+        records.extend(apply::transform(&self.records).into_iter().filter(|line| {
+            matches!(
+                line.record,
+                MtxtRecord::Tempo { .. } | MtxtRecord::TimeSignature { .. }
+            )
+        }));
+
+        let chart = self.chord_chart();
+        let initial = self.initial_time_signature().unwrap_or_default();
+        let pickup = self.pickup_duration().unwrap_or_default();
+        let measure_map = TimeSignatureMap::new(&initial, &self.records, pickup);
+        for (idx, measure) in chart.iter().enumerate() {
+            let Some(root) = chord_root(&measure.symbol) else {
+                continue;
+            };
+            if idx > 0 && chart[idx - 1].symbol == measure.symbol {
+                continue;
+            }
+            // the chord lasts until the next measure with another symbol
+            let end_measure = chart[idx..]
+                .iter()
+                .find(|next| next.symbol != measure.symbol)
+                .map_or(chart[chart.len() - 1].measure + 1, |next| next.measure);
+            records.push(MtxtRecordLine::new(MtxtRecord::Note {
+                time: measure.start_beat,
+                note: NoteTarget::Note(Note::new(
+                    PitchClass::from_semitone(root, false).to_canonical(),
+                    3,
+                    0.0,
+                )?),
+                duration: Some(measure_map.bar_start(end_measure) - measure.start_beat),
+                velocity: Some(0.8),
+                off_velocity: None,
+                channel: Some(0),
+            }));
+        }
+
+        let mut file = MtxtFile::from_records(records);
+        file.ensure_sorted();
+        Ok(file)
+    }
+
+    /// Value of a controller on a channel at the beat, as it is played back: the value of the
+    /// last change at or before the beat, or part way into a change whose `transition_time`
+    /// ramp (ending at the change) covers the beat, shaped by its transition curve.
+    /// Changes addressing single notes are ignored. 0.0 before the first change.
+    pub fn cc_value_at_beat(&self, channel: u16, controller: &str, beat: BeatTime) -> f32 {
+        let mut current_channel: u16 = 0;
+        let mut current_curve = 0.0;
+        // (time, value, transition time, curve) in file order
+        let mut changes: Vec<(BeatTime, f32, BeatTime, f32)> = Vec::new();
+        for line in &self.records {
+            match &line.record {
+                MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+                MtxtRecord::TransitionCurveDirective { curve } => current_curve = *curve,
+                MtxtRecord::ControlChange {
+                    time,
+                    note: None,
+                    controller: name,
+                    value,
+                    channel: cc_channel,
+                    transition_curve,
+                    transition_time,
+                    ..
+                } if name == controller && cc_channel.unwrap_or(current_channel) == channel => {
+                    changes.push((
+                        *time,
+                        *value,
+                        transition_time.unwrap_or_default(),
+                        transition_curve.unwrap_or(current_curve),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        changes.sort_by_key(|(time, ..)| *time);
+
+        let mut value = 0.0;
+        for (time, target, transition_time, curve) in changes {
+            if time <= beat {
+                value = target;
+                continue;
+            }
+            let start = time - transition_time;
+            if start <= beat {
+                let pos = (beat - start).as_f64() / transition_time.as_f64();
+                value = apply_transition_curve(value, target, pos as f32, curve);
+            }
+            break;
+        }
+        value
+    }
+
+    /// End of the last event, including the release of the last note
+    pub fn total_duration_beats(&self) -> BeatTime {
+        let last_event = self.duration().unwrap_or_default();
+        self.note_spans()
+            .iter()
+            .map(|span| span.end)
+            .fold(last_event, BeatTime::max)
+    }
+
+    /// Wall-clock length of [`total_duration_beats`](Self::total_duration_beats) in seconds
+    pub fn total_duration_seconds(&self) -> f64 {
+        self.tempo_map().seconds_at(self.total_duration_beats())
+    }
+
+    /// Conversion between beats and wall-clock time for the tempo changes of the file
+    pub fn tempo_map(&self) -> TempoMap {
+        let records: Vec<MtxtRecord> = self
+            .records
+            .iter()
+            .map(|line| line.record.clone())
+            .collect();
+        TempoMap::new(&records)
+    }
+
+    /// Extends the file to at least `target` beats with a `marker` meta event,
+    /// e.g. to make a loop end on a measure line
+    pub fn pad_to_duration(&mut self, target: BeatTime) {
+        if self.total_duration_beats() >= target {
+            return;
+        }
+        self.records.push(MtxtRecordLine::new(MtxtRecord::Meta {
+            time: Some(target),
+            channel: None,
+            meta_type: "marker".to_string(),
+            value: "end".to_string(),
+        }));
+    }
+
+    /// Copy of the file with every descriptor of `chain` applied in turn, each one working on
+    /// the output of the previous. Unlike setting several options on one descriptor, where the
+    /// order of the transforms is fixed, this allows pipelines like quantizing channels 0-1,
+    /// then transposing only channel 2, then merging everything to channel 0.
+    /// The channel filters of a step only select what that step works on, see
+    /// [`apply_transform_chain`](crate::transforms::apply_transform_chain).
+    pub fn apply_transform_chain(&self, chain: &[TransformDescriptor]) -> Result<MtxtFile> {
+        Ok(MtxtFile::from_records(apply_transform_chain(
+            &self.records,
+            chain,
+        )?))
+    }
+
+    /// Estimated swing of the off-beat notes on a grid (e.g. 8 for eighth notes),
+    /// `None` if no note is near an off-beat
+    pub fn analyze_swing(&self, grid: u32) -> Option<SwingReport> {
+        swing::estimate(&self.records, grid)
+    }
+
+    /// Copy of the file without the silence before the first note and after the end of the last one.
+    /// Silences shorter than `threshold` are kept. Events inside the leading silence move to beat 0,
+    /// events after the last note ends are removed. Gaps between notes are not affected.
+    pub fn remove_silence(&self, threshold: BeatTime) -> MtxtFile {
+        let spans = self.note_spans();
+        let first_start = spans.iter().map(|span| span.start).min();
+        let last_end = spans.iter().map(|span| span.end).max();
+        let (Some(first_start), Some(last_end)) = (first_start, last_end) else {
+            return self.clone();
+        };
+
+        let shift = if first_start >= threshold {
+            first_start
+        } else {
+            BeatTime::zero()
+        };
+        let trailing = self.total_duration_beats() - last_end;
+        let trim_end = trailing > BeatTime::zero() && trailing >= threshold;
+
+        let records = self
+            .records
+            .iter()
+            .filter(|line| !trim_end || line.record.time().is_none_or(|time| time <= last_end))
+            .map(|line| {
+                let mut line = line.clone();
+                if let Some(time) = line.record.time() {
+                    line.record.set_time(time - shift);
+                }
+                line
+            })
+            .collect();
+        MtxtFile::from_records(records)
+    }
+
+    /// Shifts every timed record later by `offset`, e.g. to insert the file into another timeline.
+    /// With `exclude_zero_time` records at beat 0 (like the initial tempo) stay in place.
+    /// Nothing is changed if a shifted time would be out of range.
+    pub fn offset_all(&mut self, offset: BeatTime, exclude_zero_time: bool) -> Result<()> {
+        let mut shifted = Vec::new();
+        for (idx, line) in self.records.iter().enumerate() {
+            let Some(time) = line.record.time() else {
+                continue;
+            };
+            if exclude_zero_time && time == BeatTime::zero() {
+                continue;
+            }
+            let Some(new_time) = time.checked_add(offset) else {
+                bail!("Offsetting {} by {} is out of range", time, offset);
+            };
+            shifted.push((idx, new_time));
+        }
+
+        for (idx, new_time) in shifted {
+            self.records[idx].record.set_time(new_time);
+        }
+        Ok(())
+    }
+
+    /// Moves every record of channel `from` to channel `into`
+    pub fn merge_channels(&mut self, from: u16, into: u16) {
+        if from == into {
+            return;
+        }
+        self.records = remap::transform(&self.records, &HashMap::from([(from, into)]));
+        if from == 0 {
+            self.move_default_channel(into);
+        }
+    }
+
+    /// Moves every record to channel `target`
+    pub fn merge_all_channels_to(&mut self, target: u16) {
+        let channel_map: HashMap<u16, u16> = self
+            .used_channels()
+            .into_iter()
+            .map(|channel| (channel, target))
+            .collect();
+        self.records = remap::transform(&self.records, &channel_map);
+        self.move_default_channel(target);
+    }
+
+    /// Remaps the note velocities from their observed range onto `target_min..=target_max`,
+    /// written inline on every note, see `--velocity-normalize`. A single velocity becomes
+    /// the middle of the target range, results are kept within 0.001 to 1.0 so no note turns
+    /// silent. Files without notes are left unchanged.
+    pub fn normalize_velocity(&mut self, target_min: f32, target_max: f32) {
+        let mut records =
+            velocity::transform(&self.records, Some((target_min, target_max)), None, false);
+        let mut has_notes = false;
+        for line in &mut records {
+            if let MtxtRecord::Note {
+                velocity: Some(velocity),
+                ..
+            }
+            | MtxtRecord::NoteOn {
+                velocity: Some(velocity),
+                ..
+            } = &mut line.record
+            {
+                *velocity = velocity.clamp(0.001, 1.0);
+                has_notes = true;
+            }
+        }
+        if has_notes {
+            self.records = records;
+        }
+    }
+
+    /// Combines files playing at the same time into one, e.g. tracks edited separately.
+    /// The channels of every file are moved above the highest channel of the previous one,
+    /// so channels 0-1 of the first file and 0-2 of the second become 0-1 and 2-4.
+    /// The header, global meta, tempo and time signature changes are taken from the first file,
+    /// the aliases of every file are kept, so their names should be unique.
+    /// Directives are resolved into the records, events starting together keep the file order.
+    pub fn merge_parallel(files: &[MtxtFile]) -> Result<MtxtFile> {
+        let mut untimed = Vec::new();
+        let mut timed = Vec::new();
+        let mut offset: u16 = 0;
+
+        for (file_idx, file) in files.iter().enumerate() {
+            let max_channel = file.used_channels().last().copied().unwrap_or(0);
+            let Some(next_offset) = offset
+                .checked_add(max_channel)
+                .and_then(|channel| channel.checked_add(1))
+            else {
+                bail!("Too many channels to merge file {}", file_idx + 1);
+            };
+
+            let mut current_channel: u16 = 0;
+            let mut resolved = Vec::with_capacity(file.records.len());
+            for line in &file.records {
+                let mut line = line.clone();
+                match &mut line.record {
+                    MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+                    MtxtRecord::Note { channel, .. }
+                    | MtxtRecord::NoteOn { channel, .. }
+                    | MtxtRecord::NoteOff { channel, .. }
+                    | MtxtRecord::ControlChange { channel, .. }
+                    | MtxtRecord::Voice { channel, .. }
+                    | MtxtRecord::Meta { channel, .. } => {
+                        let new_channel = channel.unwrap_or(current_channel) + offset;
+                        if channel.is_some() || new_channel != 0 {
+                            *channel = Some(new_channel);
+                        }
+                    }
+                    _ => {}
+                }
+                resolved.push(line);
+            }
+
+            for line in apply::transform(&resolved) {
+                match &line.record {
+                    MtxtRecord::Header { .. }
+                    | MtxtRecord::GlobalMeta { .. }
+                    | MtxtRecord::Tempo { .. }
+                    | MtxtRecord::TimeSignature { .. }
+                        if file_idx > 0 => {}
+                    record if record.time().is_some() => timed.push(line),
+                    _ => untimed.push(line),
+                }
+            }
+            offset = next_offset;
+        }
+
+        // stable, so the file order is kept for events at the same time
+        timed.sort_by_key(|line| line.record.time());
+        untimed.extend(timed);
+        Ok(MtxtFile::from_records(untimed))
+    }
+
+    /// Copy of the file with only the records of `channel` and the records without a channel
+    pub fn channel_solo(&self, channel: u16) -> MtxtFile {
+        MtxtFile::from_records(include::transform(&self.records, &HashSet::from([channel])))
+    }
+
+    // Channels referenced by the file, including the default channel 0
+    fn used_channels(&self) -> BTreeSet<u16> {
+        let mut channels = BTreeSet::from([0]);
+        for line in &self.records {
+            match &line.record {
+                MtxtRecord::ChannelDirective { channel } => {
+                    channels.insert(*channel);
+                }
+                MtxtRecord::Note { channel, .. }
+                | MtxtRecord::NoteOn { channel, .. }
+                | MtxtRecord::NoteOff { channel, .. }
+                | MtxtRecord::ControlChange { channel, .. }
+                | MtxtRecord::Voice { channel, .. }
+                | MtxtRecord::Meta { channel, .. } => channels.extend(*channel),
+                _ => {}
+            }
+        }
+        channels
+    }
+
+    // Records before the first `ch=` directive without their own channel play on channel 0,
+    // this adds a directive in front of them to move them to `channel`
+    fn move_default_channel(&mut self, channel: u16) {
+        if channel == 0 {
+            return;
+        }
+        let first_implicit = self
+            .records
+            .iter()
+            .take_while(|line| !matches!(line.record, MtxtRecord::ChannelDirective { .. }))
+            .position(|line| {
+                matches!(
+                    line.record,
+                    MtxtRecord::Note { channel: None, .. }
+                        | MtxtRecord::NoteOn { channel: None, .. }
+                        | MtxtRecord::NoteOff { channel: None, .. }
+                        | MtxtRecord::ControlChange { channel: None, .. }
+                        | MtxtRecord::Voice { channel: None, .. }
+                )
+            });
+        if let Some(idx) = first_implicit {
+            self.records.insert(
+                idx,
+                MtxtRecordLine::new(MtxtRecord::ChannelDirective { channel }),
+            );
+        }
+    }
+
     pub fn add_global_meta(&mut self, meta_type: String, value: String) {
         self.records
             .push(MtxtRecordLine::new(MtxtRecord::GlobalMeta {
@@ -130,11 +1120,70 @@ impl MtxtFile {
             }));
     }
 
-    pub fn calculate_auto_timestamp_width(&self) -> usize {
-        let max_time = self.duration().unwrap_or(BeatTime::zero());
-        let digits = max_time.whole_beats().to_string().len();
-        // digits + 1 (dot) + 5 (fractional digits)
-        digits + 1 + 5
+    /// Starts the file with an incomplete measure of `beats`: every timed record moves later
+    /// by `beats` (less the previous pickup), so the first full measure starts at `beats`.
+    /// Tempo, time signature, voice, CC and meta events at beat 0 stay there to set up
+    /// the pickup. The length is stored in a `pickup` global meta, a zero length removes it.
+    pub fn set_pickup(&mut self, beats: BeatTime) {
+        let previous = self.pickup_duration().unwrap_or_default();
+        self.records.retain(|line| {
+            !matches!(&line.record, MtxtRecord::GlobalMeta { meta_type, .. } if meta_type == "pickup")
+        });
+
+        for line in &mut self.records {
+            let Some(time) = line.record.time() else {
+                continue;
+            };
+            let is_note = matches!(
+                line.record,
+                MtxtRecord::Note { .. } | MtxtRecord::NoteOn { .. } | MtxtRecord::NoteOff { .. }
+            );
+            if time == BeatTime::zero() && !is_note {
+                continue;
+            }
+            line.record.set_time(time - previous + beats);
+        }
+
+        if beats > BeatTime::zero() {
+            // with the other file-level records at the start
+            let idx = self
+                .records
+                .iter()
+                .position(|line| {
+                    !matches!(
+                        line.record,
+                        MtxtRecord::Header { .. } | MtxtRecord::GlobalMeta { .. }
+                    )
+                })
+                .unwrap_or(self.records.len());
+            self.records.insert(
+                idx,
+                MtxtRecordLine::new(MtxtRecord::GlobalMeta {
+                    meta_type: "pickup".to_string(),
+                    value: beats.to_string(),
+                }),
+            );
+        }
+    }
+
+    /// Length of the pickup measure stored by [`set_pickup`](Self::set_pickup)
+    pub fn pickup_duration(&self) -> Option<BeatTime> {
+        self.get_global_meta_value("pickup")?.parse().ok()
+    }
+
+    /// Minimum column width needed to align all timestamps when printing,
+    /// or `None` if the file has no timed records.
+    pub fn auto_padding_width(&self) -> Option<usize> {
+        self.auto_padding_width_with(BeatTimeFormat::default())
+    }
+
+    /// Width of the longest timestamp written with the given format
+    pub fn auto_padding_width_with(&self, time_format: BeatTimeFormat) -> Option<usize> {
+        self.records
+            .iter()
+            .filter_map(|line| line.record.time())
+            .map(|time| time.display_with(time_format).to_string().len())
+            .max()
     }
 
     pub fn get_output_records(&self) -> Vec<MtxtOutputRecord> {
@@ -143,22 +1192,1183 @@ impl MtxtFile {
             .iter()
             .map(|line| line.record.clone())
             .collect();
-        process_records(&records)
+        let output = process_records(&records);
+        // the records may be in any order, the output must not
+        #[cfg(debug_assertions)]
+        if let Some(pair) = output
+            .windows(2)
+            .find(|pair| pair[0].time() > pair[1].time())
+        {
+            panic!(
+                "Output records out of order: {} at {}us after {} at {}us",
+                pair[1],
+                pair[1].time(),
+                pair[0],
+                pair[0].time()
+            );
+        }
+        output
     }
 
+    /// Whether the timed records are in non-decreasing time order.
+    /// Records without a time (header, meta, directives) are not checked.
+    pub fn is_sorted(&self) -> bool {
+        self.records
+            .iter()
+            .filter_map(|line| line.record.time())
+            .is_sorted()
+    }
+
+    /// Sorts the records by time unless they are sorted already.
+    /// Directives are resolved into the records first so they keep applying to the same events,
+    /// the header, global meta and aliases come first, then the meta without a time.
+    pub fn ensure_sorted(&mut self) {
+        if self.is_sorted() {
+            return;
+        }
+        // CCs take their channel from the directives, which are dropped
+        let mut records = apply::transform(&apply::resolve_cc_channels(&self.records));
+        records.sort_by_key(|line| sort::sort_key(&line.record));
+        self.records = records;
+    }
+
+    /// The output records as a JSON array, see [`MtxtOutputRecord::to_json`]
+    pub fn get_output_records_json(&self) -> String {
+        let records: Vec<String> = self
+            .get_output_records()
+            .iter()
+            .map(MtxtOutputRecord::to_json)
+            .collect();
+        format!("[{}]", records.join(","))
+    }
+
+    /// The output records as CSV with a header row, see [`MtxtOutputRecord::to_csv`]
+    pub fn get_output_records_csv(&self) -> String {
+        let mut csv = format!("{}\n", MtxtOutputRecord::CSV_HEADER);
+        for record in self.get_output_records() {
+            csv.push_str(&record.to_csv());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Standalone HTML page with an SVG piano roll of the notes: beats left to right, MIDI pitch
+    /// bottom to top, notes colored by channel and a keyboard with pitch names on the left
+    pub fn to_html_piano_roll(&self, options: PianoRollOptions) -> String {
+        let notes: Vec<PianoRollNote> = self
+            .note_spans()
+            .into_iter()
+            .flat_map(|span| {
+                span.notes.into_iter().map(move |note| PianoRollNote {
+                    channel: span.channel,
+                    start: span.start.as_f64(),
+                    end: span.end.as_f64(),
+                    pitch: note.to_midi_note(),
+                    label: note.to_string(),
+                })
+            })
+            .collect();
+        piano_roll::render(&notes, self.title().unwrap_or("MTXT"), &options)
+    }
+
+    /// The file in the binary encoding: [`BINARY_MAGIC`], [`BINARY_VERSION`] and the records,
+    /// each inline comment right after its record
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut data = BINARY_MAGIC.to_vec();
+        data.push(BINARY_VERSION);
+        for line in &self.records {
+            data.extend_from_slice(&line.record.to_bytes());
+            if let Some(comment) = &line.comment {
+                binary::write_comment(comment, &mut data);
+            }
+        }
+        data
+    }
+
+    /// Reads a file written by [`MtxtFile::to_binary`]
+    pub fn from_binary(data: &[u8]) -> Result<MtxtFile> {
+        let Some(rest) = data.strip_prefix(&BINARY_MAGIC) else {
+            bail!("Not an MTXT binary file");
+        };
+        let Some((&version, mut rest)) = rest.split_first() else {
+            bail!("Missing MTXT binary version");
+        };
+        if version != BINARY_VERSION {
+            bail!("Unsupported MTXT binary version: {}", version);
+        }
+
+        let mut records: Vec<MtxtRecordLine> = Vec::new();
+        while !rest.is_empty() {
+            if let Some((comment, size)) = binary::read_comment(rest)? {
+                let Some(line) = records.last_mut() else {
+                    bail!("Comment without a record");
+                };
+                line.comment = Some(comment);
+                rest = &rest[size..];
+                continue;
+            }
+            let (record, size) = MtxtRecord::from_bytes(rest)?;
+            records.push(MtxtRecordLine::new(record));
+            rest = &rest[size..];
+        }
+        Ok(MtxtFile { records })
+    }
+
+    /// Writes the file in the format of the extension of `path`: `.mid`, `.midi` or `.smf` for
+    /// MIDI, `.mtxt` for MTXT text, and `.json` or `.csv` for the output records
+    pub fn export_to_path(&self, path: &str) -> Result<()> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .ok_or_else(|| anyhow::anyhow!("File has no extension: {}", path))?;
+
+        let data = match extension.as_str() {
+            "mid" | "midi" | "smf" => {
+                #[cfg(feature = "midi")]
+                {
+                    crate::midi::convert_mtxt_to_midi(self)?
+                }
+                #[cfg(not(feature = "midi"))]
+                {
+                    bail!("MIDI support is not enabled. Compile with --features midi");
+                }
+            }
+            "mtxt" => self.to_string().into_bytes(),
+            "json" => self.get_output_records_json().into_bytes(),
+            "csv" => self.get_output_records_csv().into_bytes(),
+            _ => bail!("Unsupported file extension: .{}", extension),
+        };
+        std::fs::write(path, data).with_context(|| format!("Failed to write file: {}", path))
+    }
+
+    /// Formats the file with optional timestamp padding.
+    /// If `time_sig` is set, a `// measure: N` comment is inserted before the first
+    /// event of each measure, following the time signature changes of the file.
+    /// Events of a pickup (see [`MtxtFile::set_pickup`]) get a `// pickup` comment.
     pub fn display_with_formatting<'a>(
         &'a self,
         timestamp_width: Option<usize>,
+        time_sig: Option<TimeSignature>,
     ) -> MtxtFileFormatter<'a> {
         MtxtFileFormatter {
             file: self,
             timestamp_width,
+            time_sig,
+            time_format: BeatTimeFormat::default(),
         }
     }
+    /// Formats the file with all timestamps padded to the same width.
+    pub fn to_string_aligned(&self) -> String {
+        self.display_with_formatting(self.auto_padding_width(), None)
+            .to_string()
+    }
+
+    /// Formats the file without timestamp padding.
+    pub fn to_string_compact(&self) -> String {
+        self.display_with_formatting(None, None).to_string()
+    }
 }
 
 impl fmt::Display for MtxtFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.display_with_formatting(None))
+        write!(f, "{}", self.display_with_formatting(None, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VoiceLeadingError, VoiceLeadingErrorKind};
+    use crate::parse_mtxt;
+    use crate::transforms::TransformDescriptor;
+    use crate::{
+        BeatFraction, BeatTime, BeatTimeFormat, MtxtFile, MtxtOutputRecord, ScaleMode,
+        TimeSignature,
+    };
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn test_auto_padding_width() {
+        let file = parse_mtxt("mtxt 1.0\n0.0 note C4\n12.25 note D4\n").unwrap();
+        assert_eq!(file.auto_padding_width(), Some(5));
+        assert_eq!(
+            file.to_string_aligned(),
+            "mtxt 1.0\n0.0   note C4\n12.25 note D4\n"
+        );
+        assert_eq!(
+            file.to_string_compact(),
+            "mtxt 1.0\n0.0 note C4\n12.25 note D4\n"
+        );
+
+        let empty = parse_mtxt("mtxt 1.0\nmeta global title Empty\n").unwrap();
+        assert_eq!(empty.auto_padding_width(), None);
+    }
+
+    #[test]
+    fn test_metadata_accessors() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+meta global title My Song
+meta global key A minor
+4.0 tempo 90
+0.0 tempo 120
+0.0 timesig 6/8
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(file.title(), Some("My Song"));
+        assert_eq!(file.copyright(), None);
+        assert_eq!(file.key(), Some("A minor"));
+        assert_eq!(file.initial_bpm(), Some(120.0));
+        assert_eq!(
+            file.initial_time_signature(),
+            Some("6/8".parse::<TimeSignature>().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_time_format() {
+        let file = parse_mtxt(
+            "mtxt 1.0\n0.33333 note C4 dur=0.66667\n12.0 cc volume 0.5 transition_time=1.5\n",
+        )
+        .unwrap();
+        let format = BeatTimeFormat {
+            max_decimals: 2,
+            always_decimal_point: false,
+            snap_tolerance: 0.0,
+        };
+        assert_eq!(file.auto_padding_width_with(format), Some(4));
+        assert_eq!(
+            file.display_with_formatting(file.auto_padding_width_with(format), None)
+                .with_time_format(format)
+                .to_string(),
+            "mtxt 1.0\n0.33 note C4 dur=0.67\n12   cc volume 0.5 transition_time=1.5\n"
+        );
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.33333 note C4 dur=0.66667\n12.0 cc volume 0.5 transition_time=1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_measure_comments() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+0.0 note C4
+2.0 note D4
+4.0 note E4
+9.0 note F4
+12.0 timesig 3/4
+12.0 note G4
+15.0 note A4
+"#,
+        )
+        .unwrap();
+
+        let sig: TimeSignature = "4/4".parse().unwrap();
+        assert_eq!(
+            file.display_with_formatting(None, Some(sig)).to_string(),
+            r#"mtxt 1.0
+// measure: 1
+0.0 note C4
+2.0 note D4
+// measure: 2
+4.0 note E4
+// measure: 3
+9.0 note F4
+// measure: 4
+12.0 timesig 3/4
+12.0 note G4
+// measure: 5
+15.0 note A4
+"#
+        );
+    }
+
+    #[test]
+    fn test_set_pickup() {
+        let mut file = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Anacrusis
+0.0 tempo 100
+0.0 note C4
+4.0 note D4
+"#,
+        )
+        .unwrap();
+        assert_eq!(file.pickup_duration(), None);
+
+        file.set_pickup(BeatTime::from_parts(1, 0.0));
+        let pickup_note = parse_mtxt("mtxt 1.0\n0.0 note G3\n").unwrap().records.pop();
+        file.records.insert(4, pickup_note.unwrap());
+        assert_eq!(file.pickup_duration(), Some(BeatTime::from_parts(1, 0.0)));
+
+        let sig: TimeSignature = "4/4".parse().unwrap();
+        assert_eq!(
+            file.display_with_formatting(None, Some(sig)).to_string(),
+            r#"mtxt 1.0
+meta global title Anacrusis
+meta global pickup 1.0
+// pickup
+0.0 tempo 100.0
+0.0 note G3
+// measure: 1
+1.0 note C4
+// measure: 2
+5.0 note D4
+"#
+        );
+
+        // a shorter pickup moves the notes back
+        file.set_pickup(BeatTime::from_parts(0, 0.5));
+        assert_eq!(file.pickup_duration(), Some(BeatTime::from_parts(0, 0.5)));
+        assert_eq!(
+            file.records[5].record.time(),
+            Some(BeatTime::from_parts(0, 0.5))
+        );
+        file.set_pickup(BeatTime::zero());
+        assert_eq!(file.pickup_duration(), None);
+        assert_eq!(file.records[4].record.time(), Some(BeatTime::zero()));
+    }
+
+    #[test]
+    fn test_export_to_path() {
+        let file = parse_mtxt("mtxt 1.0\n0.0 note C4 dur=1.0\n").unwrap();
+        let dir = std::env::temp_dir().join(format!("mtxt-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        file.export_to_path(&path("song.mtxt")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("song.mtxt")).unwrap(),
+            file.to_string()
+        );
+        file.export_to_path(&path("song.JSON")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("song.JSON")).unwrap(),
+            file.get_output_records_json()
+        );
+        file.export_to_path(&path("song.csv")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("song.csv")).unwrap(),
+            file.get_output_records_csv()
+        );
+        #[cfg(feature = "midi")]
+        {
+            file.export_to_path(&path("song.mid")).unwrap();
+            let midi = std::fs::read(path("song.mid")).unwrap();
+            assert_eq!(&midi[..4], b"MThd");
+        }
+
+        assert!(file.export_to_path(&path("song.abc")).is_err());
+        assert!(file.export_to_path(&path("song.wav")).is_err());
+        assert!(file.export_to_path(&path("song")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_polyphony() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+alias Cmaj C4,E4,G4
+dur=2
+0.0 note C3
+1.0 note Cmaj dur=1
+2.0 note D3
+2.5 on A4 ch=1
+3.5 off A4 ch=1
+"#,
+        )
+        .unwrap();
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+
+        assert_eq!(file.polyphony_at_beat(beat("0.0")), 1);
+        assert_eq!(file.polyphony_at_beat(beat("1.5")), 4);
+        assert_eq!(file.polyphony_at_beat(beat("2.0")), 1);
+        assert_eq!(file.polyphony_at_beat(beat("3.0")), 2);
+        assert_eq!(file.polyphony_at_beat(beat("4.0")), 0);
+        assert_eq!(file.max_polyphony(), 4);
+
+        let timeline = file.polyphony_timeline(BeatFraction::new(1, 1).unwrap());
+        let counts: Vec<u32> = timeline.iter().map(|(_, count)| *count).collect();
+        assert_eq!(counts, vec![1, 4, 1, 2]);
+        assert_eq!(timeline[3].0, beat("3.0"));
+    }
+
+    #[test]
+    fn test_to_pitch_timeline() {
+        let input = r#"mtxt 1.0
+alias Dyad C4,E4
+0.0 note Dyad dur=2
+1.0 note G4 dur=0.5
+1.0 note C4 dur=2 ch=1
+2.0 on C5
+2.0 note E4 dur=0
+2.5 off C5
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+        assert_eq!(
+            file.to_pitch_timeline(),
+            vec![
+                (beat("0.0"), vec![60, 64]),
+                (beat("1.0"), vec![60, 64, 67]),
+                (beat("1.5"), vec![60, 64]),
+                (beat("2.0"), vec![60, 72]),
+                (beat("2.5"), vec![60]),
+                (beat("3.0"), vec![]),
+            ]
+        );
+        assert!(MtxtFile::new().to_pitch_timeline().is_empty());
+    }
+
+    #[test]
+    fn test_compute_voice_leading_errors() {
+        let input = r#"mtxt 1.0
+dur=1
+ch=1
+0.0 note E4
+0.0 note G4
+1.0 note A4
+2.0 note F4
+3.0 note A4
+4.0 note B4
+5.0 note D5
+6.0 note C5
+7.0 note D4
+8.0 note C5
+9.0 note D5
+10.0 note G5
+ch=2
+0.0 note C4
+0.0 note E4
+1.0 note D4
+2.0 note D4
+3.0 note A3
+4.0 note B3
+5.0 note G4
+6.0 note E4 dur=2
+9.0 note F4
+10.0 note G4
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let error = |beat: u32, kind, note_a, note_b| VoiceLeadingError {
+            at_beat: BeatTime::from_parts(beat, 0.0),
+            kind,
+            channel_a: 1,
+            channel_b: 2,
+            note_a,
+            note_b,
+        };
+        // contrary motion into the octave at 3 and the rest at 8 are not errors
+        assert_eq!(
+            file.compute_voice_leading_errors(1, 2),
+            vec![
+                error(1, VoiceLeadingErrorKind::ParallelFifths, 69, 62),
+                error(4, VoiceLeadingErrorKind::ParallelOctaves, 71, 59),
+                error(5, VoiceLeadingErrorKind::DirectFifths, 74, 67),
+                error(7, VoiceLeadingErrorKind::VoiceCrossing, 62, 64),
+                error(10, VoiceLeadingErrorKind::DirectOctaves, 79, 67),
+            ]
+        );
+        assert!(file.compute_voice_leading_errors(1, 3).is_empty());
+    }
+
+    #[test]
+    fn test_interval_histogram() {
+        let input = r#"mtxt 1.0
+ch=1
+0.0 note C5 dur=2
+2.0 note G4 dur=1
+3.0 note D5 dur=1
+ch=2
+0.0 note C4 dur=1
+1.0 note E4 dur=2
+3.5 note D5 dur=1
+4.0 note A4 dur=1
+"#;
+        let file = parse_mtxt(input).unwrap();
+        // the held C5 is paired with both notes below it, the A4 starts when the D5 has ended
+        assert_eq!(
+            file.compute_interval_histogram(1, 2),
+            HashMap::from([(12, 1), (8, 1), (3, 1), (0, 1)])
+        );
+        assert_eq!(
+            file.compute_interval_histogram(2, 1),
+            HashMap::from([(-12, 1), (-8, 1), (-3, 1), (0, 1)])
+        );
+        assert!(file.compute_interval_histogram(1, 3).is_empty());
+        // within channel 2 the D5 and the A4 overlap
+        assert_eq!(
+            file.compute_all_intervals_histogram(),
+            HashMap::from([(12, 1), (8, 1), (3, 1), (0, 1), (5, 1)])
+        );
+    }
+
+    #[test]
+    fn test_detect_key() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+0.0 note A3 dur=2
+2.0 note B3
+3.0 note C4
+4.0 note D4
+5.0 note E4 dur=2
+7.0 note F4
+8.0 note G#4
+9.0 note A4 dur=3
+"#,
+        )
+        .unwrap();
+        let (tonic, mode, confidence) = file.detect_key_confidence().unwrap();
+        assert_eq!(tonic.to_string(), "A4");
+        assert_eq!(mode, ScaleMode::Minor);
+        assert!(confidence > 0.5);
+
+        let sparse = parse_mtxt("mtxt 1.0\n0.0 note C4\n1.0 note E4\n2.0 note G4\n").unwrap();
+        assert_eq!(sparse.detect_key(), None);
+    }
+
+    #[test]
+    fn test_detect_chords() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+alias Cmaj7 C4,E4,G4,B4
+0.0 note Cmaj7 dur=2
+2.0 note F3 dur=1
+2.0 note Ab3 dur=1
+2.0 note C4 dur=1
+3.0 note G2 dur=1
+3.0 note B3 dur=1
+3.0 note D4 dur=1
+3.0 note F4 dur=1
+4.0 note C4 dur=1
+"#,
+        )
+        .unwrap();
+        let chords: Vec<String> = file
+            .detect_chords(BeatFraction::new(1, 1).unwrap())
+            .into_iter()
+            .map(|(_, chord)| chord)
+            .collect();
+        assert_eq!(chords, vec!["Cmaj7", "Cmaj7", "Fm", "G7", "N.C."]);
+    }
+
+    #[test]
+    fn test_chord_chart() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+0.0 timesig 3/4
+0.0 note C4 dur=3
+0.0 note E4 dur=3
+0.0 note G4 dur=2
+2.0 note A4 dur=1
+3.0 note D4 dur=0.5
+3.0 note F4 dur=0.5
+3.0 note A4 dur=0.5
+6.0 timesig 4/4
+6.0 note G3 dur=3
+6.0 note B3 dur=3
+6.0 note D4 dur=3
+6.0 note F4 dur=3
+"#,
+        )
+        .unwrap();
+        let chart: Vec<(u32, String, String)> = file
+            .chord_chart()
+            .into_iter()
+            .map(|chord| (chord.measure, chord.start_beat.to_string(), chord.symbol))
+            .collect();
+        let expected = [(1, "0.0", "C"), (2, "3.0", "Dm"), (3, "6.0", "G7")];
+        assert_eq!(
+            chart,
+            expected
+                .iter()
+                .map(|(measure, start, symbol)| (*measure, start.to_string(), symbol.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        let silent = parse_mtxt("mtxt 1.0\n0.0 note C4 dur=4\n4.0 note D4 dur=2\n").unwrap();
+        let symbols: Vec<String> = silent
+            .chord_chart()
+            .into_iter()
+            .map(|chord| chord.symbol)
+            .collect();
+        assert_eq!(symbols, vec!["N.C.", "N.C."]);
+    }
+
+    #[test]
+    fn test_to_chord_progression_mtxt() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Song
+alias Cmaj C4,E4,G4
+0.0 tempo 100
+0.0 timesig 3/4
+ch=2
+0.0 note Cmaj dur=6
+6.0 note Cmaj dur=3
+9.0 note D4 dur=3
+12.0 note G3 dur=3
+12.0 note B3 dur=3
+12.0 note D4 dur=3
+12.0 note F4 dur=3
+12.0 tempo 80 transition_time=2
+"#,
+        )
+        .unwrap();
+        let skeleton = file.to_chord_progression_mtxt().unwrap();
+        assert_eq!(
+            skeleton.to_string(),
+            r#"mtxt 1.0
+0.0 tempo 100.0
+0.0 timesig 3/4
+0.0 note C3 dur=9.0 vel=0.8 ch=0
+12.0 tempo 80.0 transition_time=2.0
+12.0 note G3 dur=3.0 vel=0.8 ch=0
+"#
+        );
+    }
+
+    #[test]
+    fn test_total_duration_seconds() {
+        let file = parse_mtxt(
+            "mtxt 1.0\n0.0 tempo 60\n0.0 note C4 dur=2\n2.0 tempo 120\n3.0 note D4 dur=1\n",
+        )
+        .unwrap();
+        assert_eq!(file.total_duration_beats(), BeatTime::from_parts(4, 0.0));
+        assert_eq!(file.total_duration_seconds(), 3.0);
+        assert_eq!(
+            file.tempo_map().beat_at_seconds(2.5),
+            BeatTime::from_parts(3, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_cc_value_at_beat() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+ch=1
+1.0 cc volume 0.5
+4.0 cc volume 1.0 transition_time=2
+0.0 cc volume 0.9 ch=2
+6.0 cc volume 0.0 transition_time=2 transition_curve=1.0
+7.0 cc volume 0.2 ch=2
+"#,
+        )
+        .unwrap();
+        let value =
+            |channel, beat: &str| file.cc_value_at_beat(channel, "volume", beat.parse().unwrap());
+        assert_eq!(value(1, "0.5"), 0.0);
+        assert_eq!(value(1, "1.0"), 0.5);
+        assert_eq!(value(1, "2.0"), 0.5);
+        assert_eq!(value(1, "3.0"), 0.75);
+        assert_eq!(value(1, "4.0"), 1.0);
+        // ease-in: slower than linear at the middle
+        assert!(value(1, "5.0") > 0.5);
+        assert_eq!(value(1, "6.0"), 0.0);
+        assert_eq!(value(2, "6.5"), 0.9);
+        assert_eq!(value(2, "7.0"), 0.2);
+        assert_eq!(file.cc_value_at_beat(1, "pan", "5.0".parse().unwrap()), 0.0);
+    }
+
+    #[test]
+    fn test_iter_channel() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+0.0 tempo 120
+0.0 note C4
+ch=1
+0.0 note D4
+0.0 cc volume 0.5
+1.0 on E4 ch=2
+1.5 off E4 ch=2
+2.0 note F4
+"#,
+        )
+        .unwrap();
+        let lines = |records: Vec<&crate::MtxtRecord>| -> Vec<String> {
+            records
+                .iter()
+                .map(|record| match record.time() {
+                    Some(time) => format!("{} {}", time, record),
+                    None => record.to_string(),
+                })
+                .collect()
+        };
+        assert_eq!(
+            lines(file.iter_channel(1).collect()),
+            [
+                "mtxt 1.0",
+                "0.0 tempo 120.0",
+                "ch=1",
+                "0.0 note D4",
+                "0.0 cc volume 0.5",
+                "2.0 note F4"
+            ]
+        );
+        assert_eq!(
+            lines(file.iter_channel_notes(2).collect()),
+            ["1.0 on E4 ch=2", "1.5 off E4 ch=2"]
+        );
+        assert_eq!(lines(file.iter_channel_notes(0).collect()), ["0.0 note C4"]);
+    }
+
+    #[test]
+    fn test_pad_to_duration() {
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+        let mut file = parse_mtxt("mtxt 1.0\n0.0 note C4\n5.0 note D4 dur=2\n").unwrap();
+        assert_eq!(file.total_duration_beats(), beat("7.0"));
+
+        file.pad_to_duration(beat("6.0"));
+        assert_eq!(file.records.len(), 3);
+
+        file.pad_to_duration(beat("8.0"));
+        assert_eq!(file.total_duration_beats(), beat("8.0"));
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 note C4\n5.0 note D4 dur=2.0\n8.0 meta marker end\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_silence() {
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+        let input = r#"mtxt 1.0
+meta global title Song
+0.0 tempo 100
+1.0 cc C4 volume 0.5
+4.0 note C4
+6.0 note D4 dur=2
+9.0 cc sustain 0
+12.0 meta marker end
+"#;
+        let file = parse_mtxt(input).unwrap();
+        assert_eq!(
+            file.remove_silence(beat("1.0")).to_string(),
+            r#"mtxt 1.0
+meta global title Song
+0.0 tempo 100.0
+0.0 cc C4 volume 0.5
+0.0 note C4
+2.0 note D4 dur=2.0
+"#
+        );
+
+        // both silences are shorter than the threshold
+        let untouched = file.remove_silence(beat("5.0"));
+        assert_eq!(untouched.to_string(), file.to_string());
+        assert_eq!(
+            file.remove_silence(beat("4.0")).total_duration_beats(),
+            beat("4.0")
+        );
+    }
+
+    #[test]
+    fn test_merge_channels() {
+        let input = r#"mtxt 1.0
+0.0 tempo 120.0
+0.0 note C4
+ch=1
+1.0 note D4
+1.0 note E4 ch=2
+ch=3
+2.0 cc volume 0.5
+"#;
+        let mut file = parse_mtxt(input).unwrap();
+        file.merge_channels(1, 2);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 tempo 120.0\n0.0 note C4\nch=2\n1.0 note D4\n1.0 note E4 ch=2\nch=3\n2.0 cc volume 0.5\n"
+        );
+
+        let mut file = parse_mtxt(input).unwrap();
+        file.merge_all_channels_to(5);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 tempo 120.0\nch=5\n0.0 note C4\nch=5\n1.0 note D4\n1.0 note E4 ch=5\nch=5\n2.0 cc volume 0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_velocity() {
+        let mut file = parse_mtxt(
+            r#"mtxt 1.0
+vel=0.2
+0.0 note C4
+1.0 note D4 vel=0.6
+2.0 note E4 vel=1.0
+"#,
+        )
+        .unwrap();
+        file.normalize_velocity(0.0, 0.5);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 note C4 vel=0.001\n1.0 note D4 vel=0.25\n2.0 note E4 vel=0.5\n"
+        );
+
+        let mut file = parse_mtxt("mtxt 1.0\n0.0 note C4 vel=0.3\n1.0 note D4 vel=0.3\n").unwrap();
+        file.normalize_velocity(0.4, 0.8);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 note C4 vel=0.6\n1.0 note D4 vel=0.6\n"
+        );
+
+        // notes without a velocity are at full velocity
+        let mut file = parse_mtxt("mtxt 1.0\n0.0 note C4\n1.0 note D4\n").unwrap();
+        file.normalize_velocity(0.4, 0.8);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 note C4 vel=0.6\n1.0 note D4 vel=0.6\n"
+        );
+        let mut file = parse_mtxt("mtxt 1.0\n0.0 note C4 vel=0.5\n1.0 note D4\n").unwrap();
+        file.normalize_velocity(0.0, 0.5);
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n0.0 note C4 vel=0.001\n1.0 note D4 vel=0.5\n"
+        );
+
+        let input = "mtxt 1.0\nvel=0.3\n0.0 cc volume 0.5\n";
+        let mut file = parse_mtxt(input).unwrap();
+        file.normalize_velocity(0.4, 0.8);
+        assert_eq!(file.to_string(), input);
+    }
+
+    #[test]
+    fn test_merge_parallel() {
+        let drums = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Drums
+0.0 tempo 120.0
+ch=9
+dur=0.5
+0.0 note C2
+1.0 note D2
+"#,
+        )
+        .unwrap();
+        let bass = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Bass
+0.0 tempo 90.0
+alias Low E1
+0.0 meta name Bass
+0.5 note Low
+ch=1
+1.0 cc volume 0.5
+"#,
+        )
+        .unwrap();
+        let lead = parse_mtxt("mtxt 1.0\n0.0 note C5 vel=0.7\n").unwrap();
+
+        let merged = MtxtFile::merge_parallel(&[drums, bass, lead]).unwrap();
+        assert_eq!(merged.title(), Some("Drums"));
+        assert_eq!(
+            merged.to_string(),
+            "mtxt 1.0\nmeta global title Drums\nalias Low E1\n0.0 tempo 120.0\n0.0 note C2 dur=0.5 ch=9\n0.0 meta ch=10 name Bass\n0.0 note C5 vel=0.7 ch=12\n0.5 note Low ch=10\n1.0 note D2 dur=0.5 ch=9\n1.0 cc volume 0.5 ch=11\n"
+        );
+        assert!(MtxtFile::merge_parallel(&[]).unwrap().records.is_empty());
+    }
+
+    #[test]
+    fn test_get_output_records_json() {
+        let input = r#"mtxt 1.0
+meta global title "Quoted"
+0.0 tempo 120.0
+ch=1
+0.0 voice piano, strings
+0.5 note C4 dur=0.5 vel=0.8
+0.5 cc pan 0.25 ch=2
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let expected = [
+            r#"{"type":"Beat","time_micros":0,"beat":0}"#,
+            r#"{"type":"GlobalMeta","time_micros":0,"meta_type":"title","value":"\"Quoted\""}"#,
+            r#"{"type":"Tempo","time_micros":0,"bpm":120}"#,
+            r#"{"type":"Voice","time_micros":0,"channel":1,"voices":["piano","strings"]}"#,
+            r#"{"type":"ControlChange","time_micros":250000,"channel":2,"note":null,"controller":"pan","value":0.25}"#,
+            r#"{"type":"NoteOn","time_micros":250000,"channel":1,"note":"C4","midi_note":60,"velocity":0.8}"#,
+            r#"{"type":"NoteOff","time_micros":500000,"channel":1,"note":"C4","midi_note":60,"off_velocity":0}"#,
+            r#"{"type":"Beat","time_micros":500000,"beat":1}"#,
+        ];
+        assert_eq!(
+            file.get_output_records_json(),
+            format!("[{}]", expected.join(","))
+        );
+        assert_eq!(MtxtFile::new().get_output_records_json(), "[]");
+    }
+
+    #[test]
+    fn test_get_output_records_csv() {
+        let input = r#"mtxt 1.0
+meta global title "Quoted, with comma"
+0.0 tempo 120.0
+0.0 timesig 3/4
+ch=1
+0.0 voice piano, strings
+0.5 note C4 dur=0.5 vel=0.8
+0.5 cc pan 0.25 ch=2
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let expected = [
+            "type,time_micros,channel,note,midi_note,name,value",
+            "Beat,0,,,,,0",
+            r#"GlobalMeta,0,,,,title,"""Quoted, with comma""""#,
+            "Tempo,0,,,,,120",
+            "TimeSignature,0,,,,,3/4",
+            r#"Voice,0,1,,,"piano, strings","#,
+            "ControlChange,250000,2,,,pan,0.25",
+            "NoteOn,250000,1,C4,60,,0.8",
+            "NoteOff,500000,1,C4,60,,0",
+            "Beat,500000,,,,,1",
+        ];
+        assert_eq!(
+            file.get_output_records_csv(),
+            format!("{}\n", expected.join("\n"))
+        );
+        assert_eq!(
+            MtxtFile::new().get_output_records_csv(),
+            "type,time_micros,channel,note,midi_note,name,value\n"
+        );
+    }
+
+    #[test]
+    fn test_ensure_sorted() {
+        let input = r#"mtxt 1.0
+meta global title Song
+ch=1
+2.0 note C4
+ch=2
+1.0 note E4
+meta text untimed
+0.5 tempo 100
+"#;
+        let mut file = parse_mtxt(input).unwrap();
+        assert!(!file.is_sorted());
+        file.ensure_sorted();
+        assert!(file.is_sorted());
+        let expected = r#"mtxt 1.0
+meta global title Song
+meta text untimed
+0.5 tempo 100.0
+1.0 note E4 ch=2
+2.0 note C4 ch=1
+"#;
+        assert_eq!(file.to_string(), expected);
+
+        // directives stay in place when the file is sorted already
+        let sorted = "mtxt 1.0\nch=1\n1.0 note C4\n1.0 note E4\n";
+        let mut file = parse_mtxt(sorted).unwrap();
+        assert!(file.is_sorted());
+        file.ensure_sorted();
+        assert_eq!(file.to_string(), sorted);
+
+        // CCs keep the channel of the directive before them
+        let mut file = parse_mtxt("mtxt 1.0\nch=2\n2.0 cc volume 0.5\n1.0 note C4\n").unwrap();
+        file.ensure_sorted();
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\n1.0 note C4 ch=2\n2.0 cc volume 0.5 ch=2\n"
+        );
+        assert_eq!(
+            file.cc_value_at_beat(2, "volume", "3.0".parse().unwrap()),
+            0.5
+        );
+    }
+
+    #[test]
+    fn test_output_record_beat_time() {
+        let input = r#"mtxt 1.0
+0.0 tempo 120
+2.0 tempo 60
+1.5 note C4 dur=0.5
+3.0 note E4 dur=0.25
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let tempo_map = [
+            (BeatTime::zero(), 120.0),
+            (BeatTime::from_parts(2, 0.0), 60.0),
+        ];
+        let notes: Vec<_> = file
+            .get_output_records()
+            .into_iter()
+            .filter(|record| matches!(record, MtxtOutputRecord::NoteOn { .. }))
+            .collect();
+        assert_eq!(notes[1].time(), 2_000_000);
+        assert_eq!(
+            notes[1].time_as_beat_time(120.0),
+            BeatTime::from_parts(4, 0.0)
+        );
+        assert_eq!(
+            notes[0].time_as_beat_time_with_map(&tempo_map),
+            BeatTime::from_parts(1, 0.5)
+        );
+        assert_eq!(
+            notes[1].time_as_beat_time_with_map(&tempo_map),
+            BeatTime::from_parts(3, 0.0)
+        );
+        // 120 BPM before the first change
+        assert_eq!(
+            notes[1].time_as_beat_time_with_map(&[]),
+            BeatTime::from_parts(4, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_channel_solo() {
+        let input = r#"mtxt 1.0
+meta global title Song
+0.0 tempo 120.0
+ch=1
+0.0 voice piano
+1.0 note D4
+1.0 note E4 ch=2
+ch=2
+2.0 note F4
+2.0 note G4 ch=1
+"#;
+        let file = parse_mtxt(input).unwrap();
+        assert_eq!(
+            file.channel_solo(1).to_string(),
+            "mtxt 1.0\nmeta global title Song\n0.0 tempo 120.0\nch=1\n0.0 voice piano\n1.0 note D4\n2.0 note G4 ch=1\n"
+        );
+    }
+
+    #[test]
+    fn test_offset_all() {
+        let input = r#"mtxt 1.0
+meta global title Song
+0.0 tempo 120.0
+0.0 note C4
+meta text untimed
+2.5 note D4
+"#;
+        let mut file = parse_mtxt(input).unwrap();
+        file.offset_all(BeatTime::from_parts(4, 0.0), true).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\nmeta global title Song\n0.0 tempo 120.0\n0.0 note C4\nmeta text untimed\n6.5 note D4\n"
+        );
+
+        let mut file = parse_mtxt(input).unwrap();
+        file.offset_all(BeatTime::from_parts(4, 0.0), false)
+            .unwrap();
+        assert_eq!(
+            file.to_string(),
+            "mtxt 1.0\nmeta global title Song\n4.0 tempo 120.0\n4.0 note C4\nmeta text untimed\n6.5 note D4\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_transform_chain() {
+        let input = r#"mtxt 1.0
+0.0 tempo 120.0
+0.1 note C4
+1.1 note D4 ch=1
+2.1 note E4 ch=2
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let chain = [
+            TransformDescriptor {
+                quantize_grid: 4,
+                include_channels: HashSet::from([0, 1]),
+                ..Default::default()
+            },
+            TransformDescriptor {
+                transpose_amount: 12,
+                include_channels: HashSet::from([2]),
+                ..Default::default()
+            },
+            TransformDescriptor {
+                channel_map: HashMap::from([(1, 0), (2, 0)]),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            file.apply_transform_chain(&chain).unwrap().to_string(),
+            "mtxt 1.0\n0.0 tempo 120.0\n0.0 note C4\n1.0 note D4 ch=0\n2.1 note E5 ch=0\n"
+        );
+
+        // a single descriptor drops the channels outside of its filter
+        let single = crate::transforms::apply_transforms(&file.records, &chain[1]).unwrap();
+        assert_eq!(
+            crate::MtxtFile::from_records(single).to_string(),
+            "mtxt 1.0\n0.0 tempo 120.0\n2.1 note E5 ch=2\n"
+        );
+
+        // CCs under a channel directive belong to that channel
+        let file =
+            parse_mtxt("mtxt 1.0\n0.0 cc volume 0.2\nch=2\n1.0 cc volume 0.5\n1.0 note C4\n")
+                .unwrap();
+        let chain = [TransformDescriptor {
+            channel_map: HashMap::from([(2, 5)]),
+            include_channels: HashSet::from([2]),
+            ..Default::default()
+        }];
+        assert_eq!(
+            file.apply_transform_chain(&chain).unwrap().to_string(),
+            "mtxt 1.0\n0.0 cc volume 0.2\n1.0 cc volume 0.5 ch=5\n1.0 note C4 ch=5\n"
+        );
+    }
+
+    #[test]
+    fn test_transform_descriptor_validate() {
+        assert!(TransformDescriptor::default().validate().is_ok());
+        let valid = TransformDescriptor {
+            merge_notes: true,
+            sort_by_time: true,
+            quantize_grid: 16,
+            quantize_swing: 0.5,
+            include_channels: HashSet::from([1, 2]),
+            exclude_channels: HashSet::from([3]),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let invalid = TransformDescriptor {
+            merge_notes: true,
+            quantize_grid: 12,
+            include_channels: HashSet::from([1, 2, 3]),
+            exclude_channels: HashSet::from([3, 2]),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid.validate().unwrap_err().to_string(),
+            "Invalid transform settings: merging notes requires sorting by time; \
+             quantize grid must be a power of 2, got 12; \
+             channels both included and excluded: 2, 3"
+        );
+        let swing_only = TransformDescriptor {
+            quantize_swing: 0.3,
+            ..Default::default()
+        };
+        let file = parse_mtxt("mtxt 1.0\n").unwrap();
+        assert!(file.apply_transform_chain(&[swing_only]).is_err());
+    }
+
+    #[test]
+    fn test_get_active_notes_at() {
+        let input = r#"mtxt 1.0
+alias Cmaj C4,E4,G4
+0.0 note C3 dur=4
+1.0 note Cmaj dur=1
+1.5 on D5
+2.5 off D5
+3.0 note E3
+"#;
+        let file = parse_mtxt(input).unwrap();
+        let at = |beat: &str| file.get_active_notes_at(beat.parse::<BeatTime>().unwrap());
+        assert_eq!(at("0.0"), [(2, 48)]);
+        assert_eq!(at("1.0"), [(2, 48), (3, 60), (3, 64), (3, 67)]);
+        assert_eq!(at("1.5"), [(2, 48), (3, 60), (3, 64), (3, 67), (4, 74)]);
+        // ends are exclusive
+        assert_eq!(at("2.0"), [(2, 48), (4, 74)]);
+        assert_eq!(at("2.5"), [(2, 48)]);
+        assert_eq!(at("3.5"), [(2, 48), (6, 52)]);
+        assert!(at("4.0").is_empty());
+
+        let index = file.active_notes_index();
+        assert_eq!(index.at(BeatTime::from_parts(3, 0.0)), at("3.0"));
     }
 }
@@ -3,8 +3,12 @@
 //! This library provides functionality for working with MTXT (Musical Text) format,
 //! a human-readable text format for representing musical data.
 
+pub mod binary;
 pub mod file;
+#[doc(hidden)]
+pub mod macros;
 pub mod parser;
+pub mod piano_roll;
 pub mod process;
 pub mod record_parser;
 pub mod transforms;
@@ -16,16 +20,25 @@ pub mod util;
 pub mod midi;
 
 // Re-export commonly used types
-pub use file::MtxtFile;
+pub use file::{ChordMeasure, MtxtFile};
 pub use parser::parse_mtxt;
-pub use types::beat_time::BeatTime;
+pub use piano_roll::PianoRollOptions;
+pub use types::beat_expression::{BeatExpression, BeatExpressionError};
+pub use types::beat_fraction::BeatFraction;
+pub use types::beat_time::{BeatTime, BeatTimeFormat};
+pub use types::chords::Chord;
+pub use types::interval::Interval;
+pub use types::key::ScaleMode;
 pub use types::note::Note;
 pub use types::note::NoteTarget;
+pub use types::note::SpellingPreference;
 pub use types::output_record::MtxtOutputRecord;
 pub use types::pitch::PitchClass;
 pub use types::record::MtxtRecord;
 pub use types::record::MtxtRecordLine;
-pub use types::time_signature::TimeSignature;
+pub use types::scale::ScaleType;
+pub use types::tempo_map::TempoMap;
+pub use types::time_signature::{TimeSignature, TimeSignatureMap};
 pub use types::version::Version;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
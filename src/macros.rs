@@ -0,0 +1,204 @@
+//! The [`mtxt!`](crate::mtxt) macro for building files in code, mostly for tests and examples.
+
+use crate::BeatTime;
+use crate::types::note::NoteTarget;
+
+/// Builds an [`MtxtFile`](crate::MtxtFile), one record per statement.
+///
+/// Times and durations are beats as floats. Notes, controllers and voices are identifiers
+/// or string literals (`C4`, `"C#4"`, `volume`). `vel:` and `ch:` are optional and come
+/// in this order, `vel:` is the off velocity for `noteoff`. Invalid notes panic.
+///
+/// ```
+/// use mtxt::mtxt;
+///
+/// let file = mtxt! {
+///     header(1, 0);
+///     globalmeta(title, "Scale");
+///     tempo(0.0, 120.0);
+///     time_sig(0.0, 3, 4);
+///     voice(0.0, piano, ch: 0);
+///     cc(0.0, volume, 0.8, ch: 0);
+///     note(0.0, C4, 0.25, vel: 0.8, ch: 0);
+///     note(0.25, "D#4", 0.25);
+///     noteon(0.5, E4, vel: 0.5);
+///     noteoff(1.0, E4);
+///     meta(1.0, text, "end", ch: 0);
+/// };
+/// assert_eq!(file.records.len(), 11);
+/// ```
+#[macro_export]
+macro_rules! mtxt {
+    ($($kind:ident ( $($args:tt)* ));* $(;)?) => {
+        $crate::MtxtFile::from_records(vec![
+            $($crate::types::record::MtxtRecordLine::new(
+                $crate::__mtxt_record!($kind($($args)*))
+            )),*
+        ])
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __mtxt_record {
+    (header($major:expr, $minor:expr)) => {
+        $crate::MtxtRecord::Header {
+            version: $crate::Version {
+                major: $major,
+                minor: $minor,
+            },
+        }
+    };
+    (globalmeta($meta_type:tt, $value:tt)) => {
+        $crate::MtxtRecord::GlobalMeta {
+            meta_type: $crate::__mtxt_str!($meta_type),
+            value: $crate::__mtxt_str!($value),
+        }
+    };
+    (meta($time:expr, $meta_type:tt, $value:tt $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::Meta {
+            time: Some($crate::macros::beat($time)),
+            channel: $crate::__mtxt_opt!($($ch)?),
+            meta_type: $crate::__mtxt_str!($meta_type),
+            value: $crate::__mtxt_str!($value),
+        }
+    };
+    (tempo($time:expr, $bpm:expr)) => {
+        $crate::MtxtRecord::Tempo {
+            time: $crate::macros::beat($time),
+            bpm: $bpm,
+            transition_curve: None,
+            transition_time: None,
+            transition_interval: None,
+        }
+    };
+    (time_sig($time:expr, $numerator:expr, $denominator:expr)) => {
+        $crate::MtxtRecord::TimeSignature {
+            time: $crate::macros::beat($time),
+            signature: $crate::TimeSignature {
+                numerator: $numerator,
+                denominator: $denominator,
+            },
+        }
+    };
+    (note($time:expr, $note:tt, $duration:expr $(, vel: $vel:expr)? $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::Note {
+            time: $crate::macros::beat($time),
+            note: $crate::macros::note_target(&$crate::__mtxt_str!($note)),
+            duration: Some($crate::macros::beat($duration)),
+            velocity: $crate::__mtxt_opt!($($vel)?),
+            off_velocity: None,
+            channel: $crate::__mtxt_opt!($($ch)?),
+        }
+    };
+    (noteon($time:expr, $note:tt $(, vel: $vel:expr)? $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::NoteOn {
+            time: $crate::macros::beat($time),
+            note: $crate::macros::note_target(&$crate::__mtxt_str!($note)),
+            velocity: $crate::__mtxt_opt!($($vel)?),
+            channel: $crate::__mtxt_opt!($($ch)?),
+        }
+    };
+    (noteoff($time:expr, $note:tt $(, vel: $vel:expr)? $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::NoteOff {
+            time: $crate::macros::beat($time),
+            note: $crate::macros::note_target(&$crate::__mtxt_str!($note)),
+            off_velocity: $crate::__mtxt_opt!($($vel)?),
+            channel: $crate::__mtxt_opt!($($ch)?),
+        }
+    };
+    (cc($time:expr, $controller:tt, $value:expr $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::ControlChange {
+            time: $crate::macros::beat($time),
+            note: None,
+            controller: $crate::__mtxt_str!($controller),
+            value: $value,
+            channel: $crate::__mtxt_opt!($($ch)?),
+            transition_curve: None,
+            transition_time: None,
+            transition_interval: None,
+        }
+    };
+    (voice($time:expr, $voice:tt $(, ch: $ch:expr)?)) => {
+        $crate::MtxtRecord::Voice {
+            time: $crate::macros::beat($time),
+            voices: $crate::types::record::VoiceList {
+                voices: vec![$crate::__mtxt_str!($voice)],
+            },
+            channel: $crate::__mtxt_opt!($($ch)?),
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __mtxt_str {
+    ($value:literal) => {
+        $value.to_string()
+    };
+    ($value:ident) => {
+        stringify!($value).to_string()
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __mtxt_opt {
+    () => {
+        None
+    };
+    ($value:expr) => {
+        Some($value)
+    };
+}
+
+#[doc(hidden)]
+pub fn beat(value: f64) -> BeatTime {
+    assert!(value >= 0.0, "negative beat time: {}", value);
+    BeatTime::from_parts(value.trunc() as u32, value.fract() as f32)
+}
+
+#[doc(hidden)]
+pub fn note_target(value: &str) -> NoteTarget {
+    value
+        .parse()
+        .unwrap_or_else(|err| panic!("invalid note {}: {}", value, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_mtxt;
+
+    #[test]
+    fn test_mtxt_macro() {
+        let file = mtxt! {
+            header(1, 0);
+            globalmeta(title, "Test song");
+            tempo(0.0, 120.0);
+            time_sig(0.0, 6, 8);
+            voice(0.0, piano, ch: 1);
+            cc(0.0, volume, 0.5, ch: 1);
+            note(0.0, C4, 0.25, vel: 0.8, ch: 0);
+            note(0.25, "F#4", 0.5);
+            noteon(1.0, E4, vel: 0.7, ch: 2);
+            noteoff(1.5, E4, ch: 2);
+            meta(2.0, text, "Coda");
+        };
+        let expected = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Test song
+0.0 tempo 120
+0.0 timesig 6/8
+0.0 voice ch=1 piano
+0.0 cc volume 0.5 ch=1
+0.0 note C4 dur=0.25 vel=0.8 ch=0
+0.25 note F#4 dur=0.5
+1.0 on E4 vel=0.7 ch=2
+1.5 off E4 ch=2
+2.0 meta text Coda
+"#,
+        )
+        .unwrap();
+        assert_eq!(file.records, expected.records);
+    }
+}
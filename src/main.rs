@@ -30,9 +30,26 @@ fn detect_file_format(file_path: &str) -> Result<FileFormat> {
     }
 }
 
+// Semitones (e.g. "-12") or an interval name (e.g. "P5")
+fn parse_transpose(value: &str) -> Result<(i32, Option<mtxt::Interval>)> {
+    if let Ok(semitones) = value.parse::<i32>() {
+        return Ok((semitones, None));
+    }
+    Ok((0, Some(value.parse()?)))
+}
+
+// Root and scale name (e.g. "D dorian")
+fn parse_scale(value: &str) -> Result<(mtxt::PitchClass, mtxt::ScaleType)> {
+    let (root, scale) = value
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| anyhow::anyhow!("Expected a root and a scale (e.g. \"D dorian\")"))?;
+    Ok((root.parse()?, scale.trim().parse()?))
+}
+
 fn main() -> Result<()> {
     println!("MTXT Converter v{}", env!("CARGO_PKG_VERSION"));
-    println!("");
+    println!();
 
     let matches = Command::new("mtxt")
         .version(env!("CARGO_PKG_VERSION"))
@@ -60,12 +77,80 @@ fn main() -> Result<()> {
         )
         .arg(
             Arg::new("transpose")
-                .help("Transpose by semitones (e.g. +1, -12)")
+                .help("Transpose by semitones (e.g. +1, -12) or by an interval (e.g. P5, -M2, +2oct)")
                 .long("transpose")
                 .allow_hyphen_values(true)
-                .value_name("SEMITONES")
+                .value_name("SEMITONES|INTERVAL")
+                .value_parser(parse_transpose),
+        )
+        .arg(
+            Arg::new("transpose-octaves")
+                .help("Transpose by octaves, added to --transpose")
+                .long("transpose-octaves")
+                .allow_hyphen_values(true)
+                .value_name("OCTAVES")
                 .value_parser(clap::value_parser!(i32)),
         )
+        .arg(
+            Arg::new("diatonic-transpose")
+                .help("Transpose by scale degrees, staying in the key (e.g. 1, -2)")
+                .long("diatonic-transpose")
+                .allow_hyphen_values(true)
+                .value_name("DEGREES")
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            Arg::new("diatonic-key")
+                .help("Key of --diatonic-transpose (e.g. \"D major\"), defaults to the key meta")
+                .long("diatonic-key")
+                .value_name("KEY")
+                .value_parser(clap::value_parser!(mtxt::types::key::KeySignature)),
+        )
+        .arg(
+            Arg::new("transpose-preserve-channels")
+                .help("Channels left untouched by --transpose and --diatonic-transpose (comma-separated, e.g. 9)")
+                .long("transpose-preserve-channels")
+                .value_name("CHANNELS")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("snap-to-scale")
+                .help("Move notes outside of a scale to the nearest scale tone (e.g. \"D dorian\")")
+                .long("snap-to-scale")
+                .value_name("SCALE")
+                .value_parser(parse_scale),
+        )
+        .arg(
+            Arg::new("snap-tie")
+                .help("Direction of --snap-to-scale for notes exactly between two scale tones")
+                .long("snap-tie")
+                .value_name("DIRECTION")
+                .value_parser(["down", "up"])
+                .default_value("down"),
+        )
+        .arg(
+            Arg::new("invert")
+                .help("Invert pitches around a note (e.g. C4)")
+                .long("invert")
+                .value_name("NOTE")
+                .value_parser(clap::value_parser!(mtxt::Note)),
+        )
+        .arg(
+            Arg::new("invert-drop")
+                .help("Drop inverted notes outside the MIDI range instead of clamping them")
+                .long("invert-drop")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("invert-exempt-channels")
+                .help("Channels left untouched by --invert (comma-separated, default 9)")
+                .long("invert-exempt-channels")
+                .value_name("CHANNELS")
+                .value_delimiter(',')
+                .default_value("9")
+                .value_parser(clap::value_parser!(u16)),
+        )
         .arg(
             Arg::new("offset")
                 .help("Offset all events by beats (e.g. 1.5, -0.5)")
@@ -74,6 +159,14 @@ fn main() -> Result<()> {
                 .value_name("BEATS")
                 .value_parser(clap::value_parser!(f32)),
         )
+        .arg(
+            Arg::new("channel-offset")
+                .help("Offset the events of a channel by beats (e.g. 2:0.05, 3:-0.02), can be repeated")
+                .long("channel-offset")
+                .allow_hyphen_values(true)
+                .value_name("CH:BEATS")
+                .action(clap::ArgAction::Append),
+        )
         .arg(
             Arg::new("include-channels")
                 .help("Include only specific channels (comma-separated, e.g. 1,2,10)")
@@ -90,6 +183,31 @@ fn main() -> Result<()> {
                 .value_delimiter(',')
                 .value_parser(clap::value_parser!(u16)),
         )
+        .arg(
+            Arg::new("remove-conductor")
+                .help("Remove tempo, time signature and global meta events, which channel filters keep")
+                .long("remove-conductor")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("remove-empty-channels")
+                .help("Remove the voice, CC and meta events of channels without notes")
+                .long("remove-empty-channels")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("compact-channels")
+                .help("Renumber the channels in use to 1..n, the mapping is printed with --verbose")
+                .long("compact-channels")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("map-channel")
+                .help("Remap a channel to another one (e.g. 3:1), can be repeated")
+                .long("map-channel")
+                .value_name("FROM:TO")
+                .action(clap::ArgAction::Append),
+        )
         .arg(
             Arg::new("apply-directives")
                 .help("Apply directives to events")
@@ -116,17 +234,222 @@ fn main() -> Result<()> {
         )
         .arg(
             Arg::new("merge-notes")
-                .help("Merge note on / off pairs into note shorthand events with durations")
+                .help(
+                    "Merge note on / off pairs into note shorthand events with durations, \
+                     sorts by time as well",
+                )
                 .long("merge-notes")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("split-notes")
+                .help("Split notes into note on / off pairs, the inverse of --merge-notes")
+                .long("split-notes")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("legato")
+                .help("Extend notes to the next onset on their channel, plus an overlap in beats (negative leaves a gap)")
+                .long("legato")
+                .allow_hyphen_values(true)
+                .value_name("OVERLAP")
+                .num_args(0..=1)
+                .default_missing_value("0")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("note-length")
+                .help("Set note durations to a fraction of the space until the next note (0.5 = staccato, 1.0 = legato)")
+                .long("note-length")
+                .value_name("PERCENT")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("extract-line")
+                .help("Keep only the highest (top) or lowest (bottom) note at every onset per channel")
+                .long("extract-line")
+                .value_name("MODE")
+                .value_parser(["top", "bottom"]),
+        )
+        .arg(
+            Arg::new("extract-line-drop")
+                .help("Drop notes interrupted by the extracted line instead of shortening them")
+                .long("extract-line-drop")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("arpeggiate")
+                .help("Stagger the notes of chords by an interval in beats")
+                .long("arpeggiate")
+                .value_name("BEATS")
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("arpeggio-pattern")
+                .help("Order of the arpeggiated notes")
+                .long("arpeggio-pattern")
+                .value_name("PATTERN")
+                .value_parser(["up", "down", "updown", "random"])
+                .default_value("up"),
+        )
+        .arg(
+            Arg::new("arpeggio-seed")
+                .help("Seed of the random arpeggio pattern")
+                .long("arpeggio-seed")
+                .value_name("SEED")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("arpeggio-gate")
+                .help("Hold arpeggiated notes until the chord ends, or until the next note starts")
+                .long("arpeggio-gate")
+                .value_name("GATE")
+                .value_parser(["chord", "next"])
+                .default_value("chord"),
+        )
+        .arg(
+            Arg::new("arpeggio-wrap")
+                .help("Restart the pattern when a chord has more notes than fit in its duration, instead of dropping them")
+                .long("arpeggio-wrap")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("strum")
+                .help("Offset the onsets of chord notes by a spread in beats or milliseconds (e.g. 0.05 or 10ms)")
+                .long("strum")
+                .value_name("SPREAD"),
+        )
+        .arg(
+            Arg::new("strum-direction")
+                .help("Strum from the lowest note (down) or from the highest note (up)")
+                .long("strum-direction")
+                .value_name("DIRECTION")
+                .value_parser(["down", "up"])
+                .default_value("down"),
+        )
+        .arg(
+            Arg::new("strum-velocity-ramp")
+                .help("Velocity change of the last strummed note relative to the first (e.g. -0.2)")
+                .long("strum-velocity-ramp")
+                .value_name("AMOUNT")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(f32))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("monophonic")
+                .help("Trim overlapping notes on channels so only one note sounds at a time (comma-separated, e.g. 1,2)")
+                .long("monophonic")
+                .value_name("CHANNELS")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("monophonic-gap")
+                .help("Gap in beats left before the next note on monophonic channels")
+                .long("monophonic-gap")
+                .value_name("BEATS")
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("monophonic-keep")
+                .help("Note kept when several notes start together on a monophonic channel")
+                .long("monophonic-keep")
+                .value_name("POLICY")
+                .value_parser(["highest", "last"])
+                .default_value("highest"),
+        )
+        .arg(
+            Arg::new("echo")
+                .help("Repeat every note after a delay in beats")
+                .long("echo")
+                .value_name("DELAY")
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("echo-repeats")
+                .help("Number of echoes per note")
+                .long("echo-repeats")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("echo-decay")
+                .help("Velocity multiplier applied on every echo")
+                .long("echo-decay")
+                .value_name("FACTOR")
+                .value_parser(clap::value_parser!(f32))
+                .default_value("0.5"),
+        )
+        .arg(
+            Arg::new("echo-channel")
+                .help("Put the echoes on a different channel")
+                .long("echo-channel")
+                .value_name("CHANNEL")
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("remove-swing")
+                .help("Detect the swing on a grid (e.g. 8 for eighth notes) and straighten it")
+                .long("remove-swing")
+                .value_name("GRID")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("remove-cc")
+                .help("Remove all CC events of a controller (name or number, e.g. sustain, cc64, or all), can be repeated")
+                .long("remove-cc")
+                .value_name("CONTROLLER")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("dedup-cc")
+                .help("Remove CC and voice events repeating the previous value")
+                .long("dedup-cc")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dedup-voices")
+                .help("Remove voice events repeating the previous voice of the channel")
+                .long("dedup-voices")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hoist-voices")
+                .help("Move the first voice of each channel to beat 0 if no note plays before it")
+                .long("hoist-voices")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("simplify-tempo")
+                .help("Remove tempo changes smaller than a tolerance in BPM and repeated time signatures")
+                .long("simplify-tempo")
+                .value_name("BPM")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("cc-thin-interval")
+                .help("Drop CC events closer than this many beats to the last kept one (see --cc-thin-min-delta)")
+                .long("cc-thin-interval")
+                .value_name("BEATS")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("cc-thin-min-delta")
+                .help("Drop CC events whose value changed less than this since the last kept one")
+                .long("cc-thin-min-delta")
+                .value_name("DELTA")
+                .value_parser(clap::value_parser!(f32)),
+        )
         .arg(
             Arg::new("quantize")
-                .help("Quantize grid (e.g. 4 for quarter notes, 16 for 16th notes)")
+                .help("Quantize grid, steps per beat (e.g. 4, 16) or a fraction of a beat (e.g. 1/6 for triplets, 3/4 dotted)")
                 .long("quantize")
                 .short('q')
                 .value_name("GRID")
-                .value_parser(clap::value_parser!(u32)),
+                .value_parser(mtxt::transforms::pipeline::parse_grid),
         )
         .arg(
             Arg::new("swing")
@@ -142,6 +465,167 @@ fn main() -> Result<()> {
                 .value_name("AMOUNT")
                 .value_parser(clap::value_parser!(f32)),
         )
+        .arg(
+            Arg::new("humanize-velocity")
+                .help("Largest random change of note velocities (e.g. 0.1)")
+                .long("humanize-velocity")
+                .value_name("AMOUNT")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("humanize-seed")
+                .help("Seed of the humanization, makes the output reproducible")
+                .long("humanize-seed")
+                .value_name("SEED")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("humanize-distribution")
+                .help("Distribution of the humanization")
+                .long("humanize-distribution")
+                .value_name("DISTRIBUTION")
+                .value_parser(["uniform", "gaussian"])
+                .default_value("uniform"),
+        )
+        .arg(
+            Arg::new("retrograde")
+                .help("Reverse the file in time")
+                .long("retrograde")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("velocity-min")
+                .help("Drop notes with a velocity below this value")
+                .long("velocity-min")
+                .value_name("VELOCITY")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("velocity-max")
+                .help("Drop notes with a velocity above this value")
+                .long("velocity-max")
+                .value_name("VELOCITY")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("velocity-normalize")
+                .help("Map note velocities onto a target range (e.g. 0.2,0.9)")
+                .long("velocity-normalize")
+                .value_name("MIN,MAX")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("velocity-compress")
+                .help("Compress velocities above a threshold by a ratio (e.g. 0.6,2)")
+                .long("velocity-compress")
+                .value_name("THRESHOLD,RATIO")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("velocity-per-channel")
+                .help("Compute velocity normalization per channel")
+                .long("velocity-per-channel")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("crop")
+                .help("Keep only events within a beat range (e.g. 64,96)")
+                .long("crop")
+                .value_name("START,END")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("crop-drop-partial")
+                .help("Drop notes crossing the crop boundaries instead of shortening them")
+                .long("crop-drop-partial")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("crop-rebase")
+                .help("Shift cropped events so the crop start becomes beat 0")
+                .long("crop-rebase")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("align-to-zero")
+                .help("Move everything earlier so the first note starts at beat 0")
+                .long("align-to-zero")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("loop-region")
+                .help("Repeat the events within a beat range right after it, later events move back (e.g. 16,24)")
+                .long("loop-region")
+                .value_name("START,END")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("loop-count")
+                .help("Number of copies added by --loop-region")
+                .long("loop-count")
+                .value_name("COUNT")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("loop-tempo")
+                .help("Also repeat the tempo and time signature changes inside --loop-region")
+                .long("loop-tempo")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("time-scale")
+                .help("Multiply all times and durations by a factor (e.g. 0.5, 1.5)")
+                .long("time-scale")
+                .value_name("FACTOR")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("time-scale-region")
+                .help("Only stretch events within a beat range, shifting later events")
+                .long("time-scale-region")
+                .value_name("START,END")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(mtxt::BeatTime)),
+        )
+        .arg(
+            Arg::new("fit-to-bars")
+                .help("Stretch or squash the notes to exactly this many bars of the current meter")
+                .long("fit-to-bars")
+                .value_name("BARS")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("bake-tempo")
+                .help("Re-time the file for a constant tempo so it sounds the same as with the original tempo changes")
+                .long("bake-tempo")
+                .value_name("BPM")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("preserve-wallclock")
+                .help("Scale tempos so that the stretched file sounds identical (--time-scale, --fit-to-bars)")
+                .long("preserve-wallclock")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("transform")
+                .help("Apply a transform after the ones above, in the given order (e.g. quantize=16, offset=0.5), can be repeated")
+                .long("transform")
+                .value_name("STEP")
+                .value_parser(|value: &str| value.parse::<mtxt::transforms::TransformStep>())
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("preset")
+                .help("Apply the transforms of a TOML or JSON preset file before the options above")
+                .long("preset")
+                .value_name("FILE"),
+        )
         .arg(
             Arg::new("indent")
                 .help("Enable timestamp padding")
@@ -154,17 +638,234 @@ fn main() -> Result<()> {
     let output_file = matches.get_one::<String>("output").unwrap();
     let verbose = matches.get_flag("verbose");
     let apply_directives = matches.get_flag("apply-directives");
-    let sort_by_time = matches.get_flag("sort");
     let merge_notes = matches.get_flag("merge-notes");
+    // merging pairs notes in time order, so it sorts as well
+    let sort_by_time = matches.get_flag("sort") || merge_notes;
+    let split_notes = matches.get_flag("split-notes");
+    let legato = matches.get_one::<f32>("legato").copied();
+    let note_length_percent = matches.get_one::<f32>("note-length").copied();
+    let extract_line = matches
+        .get_one::<String>("extract-line")
+        .map(|mode| match mode.as_str() {
+            "top" => mtxt::transforms::line::LineMode::Top,
+            _ => mtxt::transforms::line::LineMode::Bottom,
+        });
+    let extract_line_drop_overlapping = matches.get_flag("extract-line-drop");
+    let arpeggiate = matches
+        .get_one::<mtxt::BeatTime>("arpeggiate")
+        .map(|interval| {
+            use mtxt::transforms::arpeggio::{ArpeggioConfig, ArpeggioGate, ArpeggioPattern};
+            let seed = *matches.get_one::<u64>("arpeggio-seed").unwrap();
+            ArpeggioConfig {
+                interval: *interval,
+                pattern: match matches
+                    .get_one::<String>("arpeggio-pattern")
+                    .unwrap()
+                    .as_str()
+                {
+                    "down" => ArpeggioPattern::Down,
+                    "updown" => ArpeggioPattern::UpDown,
+                    "random" => ArpeggioPattern::Random(seed),
+                    _ => ArpeggioPattern::Up,
+                },
+                gate: match matches.get_one::<String>("arpeggio-gate").unwrap().as_str() {
+                    "next" => ArpeggioGate::NextNote,
+                    _ => ArpeggioGate::ChordEnd,
+                },
+                wrap: matches.get_flag("arpeggio-wrap"),
+            }
+        });
+    let strum = match matches.get_one::<String>("strum") {
+        Some(spread) => {
+            use mtxt::transforms::strum::{StrumConfig, StrumDirection, StrumSpread};
+            let spread = match spread.strip_suffix("ms") {
+                Some(millis) => StrumSpread::Millis(
+                    millis
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid strum spread: {}", spread))?,
+                ),
+                None => StrumSpread::Beats(spread.parse()?),
+            };
+            Some(StrumConfig {
+                direction: match matches
+                    .get_one::<String>("strum-direction")
+                    .unwrap()
+                    .as_str()
+                {
+                    "up" => StrumDirection::Up,
+                    _ => StrumDirection::Down,
+                },
+                spread,
+                velocity_ramp: *matches.get_one::<f32>("strum-velocity-ramp").unwrap(),
+            })
+        }
+        None => None,
+    };
+    let monophonic_channels: Vec<u16> = matches
+        .get_many::<u16>("monophonic")
+        .unwrap_or_default()
+        .copied()
+        .collect();
+    let monophonic_gap = matches
+        .get_one::<mtxt::BeatTime>("monophonic-gap")
+        .copied()
+        .unwrap_or_default();
+    let monophonic_policy = match matches
+        .get_one::<String>("monophonic-keep")
+        .unwrap()
+        .as_str()
+    {
+        "last" => mtxt::transforms::monophonic::MonophonicPolicy::Last,
+        _ => mtxt::transforms::monophonic::MonophonicPolicy::Highest,
+    };
+    let echo =
+        matches
+            .get_one::<mtxt::BeatTime>("echo")
+            .map(|delay| mtxt::transforms::echo::EchoConfig {
+                delay: *delay,
+                repeats: *matches.get_one::<u32>("echo-repeats").unwrap(),
+                decay: *matches.get_one::<f32>("echo-decay").unwrap(),
+                channel: matches.get_one::<u16>("echo-channel").copied(),
+            });
+    let remove_swing = matches.get_one::<u32>("remove-swing").copied();
+    let remove_controllers: Vec<String> = matches
+        .get_many::<String>("remove-cc")
+        .map(|names| names.cloned().collect())
+        .unwrap_or_default();
+    let dedup_cc = matches.get_flag("dedup-cc");
+    let dedup_voices = matches.get_flag("dedup-voices");
+    let hoist_voices = matches.get_flag("hoist-voices");
+    let simplify_tempo = matches.get_one::<f32>("simplify-tempo").copied();
+    let cc_thin_interval = matches
+        .get_one::<f32>("cc-thin-interval")
+        .copied()
+        .unwrap_or(0.0);
+    let cc_thin_min_delta = matches
+        .get_one::<f32>("cc-thin-min-delta")
+        .copied()
+        .unwrap_or(0.0);
     let extract_directives = matches.get_flag("extract-directives");
     let group_channels = matches.get_flag("group-channels");
 
-    let transpose_amount = matches.get_one::<i32>("transpose").copied().unwrap_or(0);
+    let (transpose_amount, transpose_interval) = matches
+        .get_one::<(i32, Option<mtxt::Interval>)>("transpose")
+        .copied()
+        .unwrap_or((0, None));
+    let transpose_octaves = matches
+        .get_one::<i32>("transpose-octaves")
+        .copied()
+        .unwrap_or(0);
+    let diatonic_transpose = matches.get_one::<i32>("diatonic-transpose").map(|degrees| {
+        mtxt::transforms::diatonic::DiatonicTranspose {
+            degrees: *degrees,
+            key: matches
+                .get_one::<mtxt::types::key::KeySignature>("diatonic-key")
+                .copied(),
+        }
+    });
+    let transpose_preserve_channels: std::collections::HashSet<u16> = matches
+        .get_many::<u16>("transpose-preserve-channels")
+        .unwrap_or_default()
+        .copied()
+        .collect();
+    let snap_to_scale = matches
+        .get_one::<(mtxt::PitchClass, mtxt::ScaleType)>("snap-to-scale")
+        .map(|(root, scale)| {
+            use mtxt::transforms::snap::{ScaleSnap, SnapTie};
+            ScaleSnap {
+                root: *root,
+                scale: *scale,
+                tie: match matches.get_one::<String>("snap-tie").unwrap().as_str() {
+                    "up" => SnapTie::Up,
+                    _ => SnapTie::Down,
+                },
+            }
+        });
+    let invert_around = matches.get_one::<mtxt::Note>("invert").cloned();
+    let invert_drop_out_of_range = matches.get_flag("invert-drop");
+    let invert_exempt_channels: std::collections::HashSet<u16> = matches
+        .get_many::<u16>("invert-exempt-channels")
+        .unwrap_or_default()
+        .copied()
+        .collect();
     let offset_amount = matches.get_one::<f32>("offset").copied().unwrap_or(0.0);
-    let quantize_grid = matches.get_one::<u32>("quantize").copied().unwrap_or(0);
+    let mut channel_offset = std::collections::HashMap::new();
+    for entry in matches
+        .get_many::<String>("channel-offset")
+        .unwrap_or_default()
+    {
+        let (channel, offset) = entry
+            .split_once(':')
+            .and_then(|(channel, offset)| {
+                Some((channel.parse::<u16>().ok()?, offset.parse::<f32>().ok()?))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Invalid channel offset: {}", entry))?;
+        channel_offset.insert(channel, offset);
+    }
+    let quantize_step = matches.get_one::<mtxt::BeatFraction>("quantize").copied();
     let quantize_swing = matches.get_one::<f32>("swing").copied().unwrap_or(0.0);
     let quantize_humanize = matches.get_one::<f32>("humanize").copied().unwrap_or(0.0);
+    let humanize_velocity = matches
+        .get_one::<f32>("humanize-velocity")
+        .copied()
+        .unwrap_or(0.0);
+    let humanize_seed = matches.get_one::<u64>("humanize-seed").copied();
+    let humanize_distribution = {
+        use mtxt::transforms::quantize::HumanizeDistribution;
+        match matches
+            .get_one::<String>("humanize-distribution")
+            .unwrap()
+            .as_str()
+        {
+            "gaussian" => HumanizeDistribution::Gaussian,
+            _ => HumanizeDistribution::Uniform,
+        }
+    };
     let indent = matches.get_flag("indent");
+    let steps: Vec<mtxt::transforms::TransformStep> = matches
+        .get_many::<mtxt::transforms::TransformStep>("transform")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let preset = match matches.get_one::<String>("preset") {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read preset file: {}", path))?;
+            let preset = if path.to_lowercase().ends_with(".json") {
+                mtxt::transforms::Preset::from_json(&content)
+            } else {
+                mtxt::transforms::Preset::from_toml(&content)
+            };
+            Some(preset.with_context(|| format!("Failed to load preset file: {}", path))?)
+        }
+        None => None,
+    };
+    let velocity_per_channel = matches.get_flag("velocity-per-channel");
+    let velocity_min = matches.get_one::<f32>("velocity-min").copied();
+    let velocity_max = matches.get_one::<f32>("velocity-max").copied();
+
+    let velocity_normalize = match matches
+        .get_many::<f32>("velocity-normalize")
+        .map(|values| values.copied().collect::<Vec<f32>>())
+        .as_deref()
+    {
+        Some([min, max]) => Some((*min, *max)),
+        Some(_) => anyhow::bail!("--velocity-normalize expects MIN,MAX"),
+        None => None,
+    };
+
+    let velocity_compress = match matches
+        .get_many::<f32>("velocity-compress")
+        .map(|values| values.copied().collect::<Vec<f32>>())
+        .as_deref()
+    {
+        Some([threshold, ratio]) => Some(mtxt::transforms::velocity::VelocityCompression {
+            threshold: *threshold,
+            ratio: *ratio,
+        }),
+        Some(_) => anyhow::bail!("--velocity-compress expects THRESHOLD,RATIO"),
+        None => None,
+    };
 
     let include_channels: std::collections::HashSet<u16> = matches
         .get_many::<u16>("include-channels")
@@ -178,19 +879,136 @@ fn main() -> Result<()> {
         .copied()
         .collect();
 
+    let crop_drop_partial = matches.get_flag("crop-drop-partial");
+    let crop_rebase = matches.get_flag("crop-rebase");
+    let align_to_zero = matches.get_flag("align-to-zero");
+
+    let crop = match matches
+        .get_many::<mtxt::BeatTime>("crop")
+        .map(|values| values.copied().collect::<Vec<mtxt::BeatTime>>())
+        .as_deref()
+    {
+        Some([start, end]) => Some((*start, *end)),
+        Some(_) => anyhow::bail!("--crop expects START,END"),
+        None => None,
+    };
+
+    let time_scale = matches.get_one::<f32>("time-scale").copied().unwrap_or(1.0);
+    let preserve_wallclock = matches.get_flag("preserve-wallclock");
+    let fit_to_bars = matches.get_one::<u32>("fit-to-bars").copied();
+    let bake_tempo = matches.get_one::<f32>("bake-tempo").copied();
+
+    let time_scale_region = match matches
+        .get_many::<mtxt::BeatTime>("time-scale-region")
+        .map(|values| values.copied().collect::<Vec<mtxt::BeatTime>>())
+        .as_deref()
+    {
+        Some([start, end]) => Some((*start, *end)),
+        Some(_) => anyhow::bail!("--time-scale-region expects START,END"),
+        None => None,
+    };
+
+    let loop_region = match matches
+        .get_many::<mtxt::BeatTime>("loop-region")
+        .map(|values| values.copied().collect::<Vec<mtxt::BeatTime>>())
+        .as_deref()
+    {
+        Some([start, end]) => Some(mtxt::transforms::loop_region::LoopRegion {
+            start: *start,
+            end: *end,
+            count: *matches.get_one::<u32>("loop-count").unwrap(),
+            repeat_tempo: matches.get_flag("loop-tempo"),
+        }),
+        Some(_) => anyhow::bail!("--loop-region expects START,END"),
+        None => None,
+    };
+
+    let keep_conductor = !matches.get_flag("remove-conductor");
+    let remove_empty_channels = matches.get_flag("remove-empty-channels");
+    let compact_channels = matches.get_flag("compact-channels");
+
+    let mut channel_map = std::collections::HashMap::new();
+    for mapping in matches
+        .get_many::<String>("map-channel")
+        .unwrap_or_default()
+    {
+        let (from, to) = mapping
+            .split_once(':')
+            .and_then(|(from, to)| Some((from.parse::<u16>().ok()?, to.parse::<u16>().ok()?)))
+            .ok_or_else(|| anyhow::anyhow!("Invalid channel mapping: {}", mapping))?;
+        channel_map.insert(from, to);
+    }
+
+    let retrograde = matches
+        .get_flag("retrograde")
+        .then_some(mtxt::transforms::retrograde::RetrogradeMode::Reposition);
+
     let transforms = mtxt::transforms::TransformDescriptor {
         apply_directives,
         extract_directives,
         sort_by_time,
         merge_notes,
-        quantize_grid,
+        split_notes,
+        quantize_grid: 0,
+        quantize_step,
         quantize_swing,
         quantize_humanize,
+        humanize_velocity,
+        humanize_seed,
+        humanize_distribution,
         transpose_amount,
+        transpose_interval,
+        transpose_octaves,
+        transpose_preserve_channels,
+        diatonic_transpose,
+        snap_to_scale,
+        invert_around,
+        invert_drop_out_of_range,
+        invert_exempt_channels,
         offset_amount,
+        channel_offset,
         include_channels,
         exclude_channels,
         group_channels,
+        keep_conductor,
+        velocity_normalize,
+        velocity_compress,
+        velocity_per_channel,
+        velocity_min,
+        velocity_max,
+        crop,
+        crop_drop_partial,
+        crop_rebase,
+        align_to_zero,
+        loop_region,
+        time_scale,
+        time_scale_region,
+        fit_to_bars,
+        preserve_wallclock,
+        bake_tempo,
+        channel_map,
+        remove_empty_channels,
+        // applied below to report the mapping
+        compact_channels: false,
+        retrograde,
+        legato,
+        note_length_percent,
+        remove_controllers,
+        dedup_cc,
+        dedup_voices,
+        hoist_voices,
+        simplify_tempo,
+        cc_thin_interval,
+        cc_thin_min_delta,
+        extract_line,
+        extract_line_drop_overlapping,
+        arpeggiate,
+        strum,
+        monophonic_channels,
+        monophonic_gap,
+        monophonic_policy,
+        echo,
+        remove_swing,
     };
 
     let input_format = detect_file_format(input_file)
@@ -235,8 +1053,36 @@ fn main() -> Result<()> {
 
     if verbose {
         println!("Applying transforms...");
+        if let Some(grid) = remove_swing
+            && let Some(report) = mtxt_file.analyze_swing(grid)
+        {
+            println!(
+                "Detected swing: {:.2} (ratio {:.3}, {} off-beat notes)",
+                report.swing, report.ratio, report.off_beat_notes
+            );
+        }
+        if let Some(bars) = fit_to_bars
+            && let Some(report) = mtxt::transforms::fit::estimate(&mtxt_file.records, bars)
+        {
+            println!(
+                "Fitting {} beats to {} bars ({} beats): factor {:.6}",
+                report.length, bars, report.target_length, report.factor
+            );
+        }
+    }
+    if let Some(preset) = &preset {
+        mtxt_file.records = preset.apply(&mtxt_file.records)?;
+    }
+    mtxt_file.records = mtxt::transforms::apply_transforms(&mtxt_file.records, &transforms)?;
+    if compact_channels {
+        if verbose {
+            for (from, to) in mtxt::transforms::channels::compaction_map(&mtxt_file.records) {
+                println!("Channel {} -> {}", from, to);
+            }
+        }
+        mtxt_file.records = mtxt::transforms::channels::compact(&mtxt_file.records);
     }
-    mtxt_file.records = mtxt::transforms::apply_transforms(&mtxt_file.records, &transforms);
+    mtxt_file.records = mtxt::transforms::apply_pipeline(&mtxt_file.records, &steps);
 
     match output_format {
         FileFormat::Midi => {
@@ -259,12 +1105,11 @@ fn main() -> Result<()> {
             if verbose {
                 println!("Writing MTXT file: {}", output_file);
             }
-            let timestamp_width = if indent {
-                Some(mtxt_file.calculate_auto_timestamp_width())
+            let output_content = if indent {
+                mtxt_file.to_string_aligned()
             } else {
-                None
+                mtxt_file.to_string_compact()
             };
-            let output_content = format!("{}", mtxt_file.display_with_formatting(timestamp_width));
             std::fs::write(output_file, output_content)
                 .with_context(|| format!("Failed to write output file: {}", output_file))?;
         }
@@ -1,20 +1,24 @@
 use crate::file::MtxtFile;
 use crate::midi::drums;
-use crate::transforms::{extract, merge};
+use crate::transforms::{extract, merge, sort};
 use crate::types::beat_time::BeatTime;
-use crate::types::note::NoteTarget;
+use crate::types::key::KeySignature;
+use crate::types::note::{NoteTarget, SpellingPreference};
 use crate::types::record::{MtxtRecord, MtxtRecordLine, VoiceList};
 use crate::types::time_signature::TimeSignature;
 use crate::types::version::Version;
 use anyhow::{Result, bail};
-use midly::{Format, MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use midly::{Format, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
 
 use super::escape::escape_string;
-use super::shared::{midi_cc_to_name, midi_key_signature_to_string, midi_key_to_note};
+use super::shared::{
+    DEFAULT_PPQN, midi_cc_to_name, midi_key_signature_to_string, midi_key_to_note,
+};
 
 use super::drums::DRUMS;
 use super::instruments::INSTRUMENTS;
 use crate::types::record::AliasDefinition;
+use std::collections::HashSet;
 use std::rc::Rc;
 
 #[derive(Debug)]
@@ -23,25 +27,79 @@ struct MidiSingleTrackEvent {
     record: MtxtRecordLine,
 }
 
+/// How the tracks of a MIDI format 2 file are laid out in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format2Mode {
+    /// Each track is a pattern starting after the previous one ends
+    #[default]
+    Sequential,
+    /// All tracks start at beat 0, like in format 1 files
+    Parallel,
+}
+
+/// How imported notes are spelled, MIDI only gives their key number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MidiSpelling {
+    #[default]
+    Sharps,
+    Flats,
+    /// In the key of the key signature events of the file, with sharps before the first one
+    KeySignature,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MidiImportOptions {
+    pub format2_mode: Format2Mode,
+    pub spelling: MidiSpelling,
+}
+
 // It merges all events from all MIDI tracks into a single list of events
-fn get_midi_single_track_events(smf: &Smf) -> Result<Vec<MidiSingleTrackEvent>> {
+// Explicit TrackNumber meta event of a track
+fn track_number(track: &[TrackEvent]) -> Option<u16> {
+    track.iter().find_map(|event| match event.kind {
+        TrackEventKind::Meta(MetaMessage::TrackNumber(Some(number))) => Some(number),
+        _ => None,
+    })
+}
+
+// Tracks ordered by their TrackNumber meta event.
+// Tracks without one are ordered by their index in the file.
+fn ordered_tracks<'a, 'b>(smf: &'b Smf<'a>) -> Vec<&'b Vec<TrackEvent<'a>>> {
+    let mut tracks: Vec<(usize, &Vec<TrackEvent>)> = smf
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(idx, track)| (track_number(track).map_or(idx, usize::from), track))
+        .collect();
+    tracks.sort_by_key(|(order, _)| *order);
+    tracks.into_iter().map(|(_, track)| track).collect()
+}
+
+fn get_midi_single_track_events(
+    smf: &Smf,
+    options: &MidiImportOptions,
+) -> Result<Vec<MidiSingleTrackEvent>> {
     let mut all_events: Vec<MidiSingleTrackEvent> = Vec::new();
 
     // MIDI format 0 is a single track file
     // MIDI format 1 is a synchronous multi-track file (first track usually is the tempo track)
     // MIDI format 2 is an asynchronous multi-track file (each track has its own timing, no common time signature)
 
-    if smf.header.format == Format::Sequential {
-        bail!("MIDI format 2 files are not yet supported");
-    }
+    let sequential =
+        smf.header.format == Format::Sequential && options.format2_mode == Format2Mode::Sequential;
 
     let ppqn = match smf.header.timing {
         Timing::Metrical(ppqn) => ppqn.as_int() as u64,
         Timing::Timecode(_, _) => bail!("Timecode timing is not yet supported"),
     };
+    if ppqn == 0 {
+        bail!("Invalid MIDI header: 0 ticks per quarter note");
+    }
+
+    let mut track_start_ticks = 0u64;
 
-    for (_track_idx, track) in smf.tracks.iter().enumerate() {
-        let mut current_raw_ticks = 0u64;
+    for (_track_idx, track) in ordered_tracks(smf).into_iter().enumerate() {
+        let mut current_raw_ticks = track_start_ticks;
 
         // Heuristic: associate track with a channel (Type 1 MIDI)
         let mut guessed_track_channel: Option<u8> = None;
@@ -54,12 +112,12 @@ fn get_midi_single_track_events(smf: &Smf) -> Result<Vec<MidiSingleTrackEvent>>
             }
         }
 
+        // (channel, key) of the notes sounding in this track
+        let mut open_notes: HashSet<(u8, u8)> = HashSet::new();
+
         for event in track.iter() {
             current_raw_ticks += event.delta.as_int() as u64;
-            let whole_parts = current_raw_ticks / ppqn;
-            let frac_parts = current_raw_ticks % ppqn;
-            let beat_time =
-                BeatTime::from_parts(whole_parts as u32, frac_parts as f32 / ppqn as f32);
+            let beat_time = BeatTime::from_rational(current_raw_ticks as i64, ppqn)?;
 
             match &event.kind {
                 TrackEventKind::Midi { channel, message } => {
@@ -68,9 +126,32 @@ fn get_midi_single_track_events(smf: &Smf) -> Result<Vec<MidiSingleTrackEvent>>
                         channel.as_int() as u16,
                         beat_time,
                     )?;
+                    let line = match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            open_notes.insert((channel.as_int(), key.as_int()));
+                            MtxtRecordLine::new(record)
+                        }
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            if open_notes.remove(&(channel.as_int(), key.as_int())) {
+                                MtxtRecordLine::new(record)
+                            } else {
+                                // kept as an off record, flagged so it can be found in the output
+                                MtxtRecordLine::with_comment(
+                                    record,
+                                    format!(
+                                        "NoteOff for key={} channel={} at tick={} with no matching NoteOn",
+                                        key.as_int(),
+                                        channel.as_int(),
+                                        current_raw_ticks
+                                    ),
+                                )
+                            }
+                        }
+                        _ => MtxtRecordLine::new(record),
+                    };
                     all_events.push(MidiSingleTrackEvent {
                         tick: beat_time,
-                        record: MtxtRecordLine::new(record),
+                        record: line,
                     });
                 }
                 TrackEventKind::Meta(meta_msg) => {
@@ -109,18 +190,101 @@ fn get_midi_single_track_events(smf: &Smf) -> Result<Vec<MidiSingleTrackEvent>>
                 }
             }
         }
+
+        if sequential {
+            // the next pattern starts where this one ends
+            track_start_ticks = current_raw_ticks;
+        }
     }
 
     all_events.sort_by_key(|event| event.tick);
+    respell_notes(&mut all_events, options.spelling);
     Ok(all_events)
 }
 
+// Notes are converted with sharps, respelled here once the key signatures of all tracks are known
+fn respell_notes(events: &mut [MidiSingleTrackEvent], spelling: MidiSpelling) {
+    let key_changes: Vec<(BeatTime, KeySignature)> = events
+        .iter()
+        .filter_map(|event| match &event.record.record {
+            MtxtRecord::GlobalMeta { meta_type, value } if meta_type == "key" => {
+                Some((event.tick, value.parse().ok()?))
+            }
+            MtxtRecord::Meta {
+                meta_type, value, ..
+            } if meta_type == "keysignature" => Some((event.tick, value.parse().ok()?)),
+            _ => None,
+        })
+        .collect();
+
+    for event in events.iter_mut() {
+        let (MtxtRecord::NoteOn { note, .. } | MtxtRecord::NoteOff { note, .. }) =
+            &mut event.record.record
+        else {
+            continue;
+        };
+        let NoteTarget::Note(note) = note else {
+            continue;
+        };
+        let preference = match spelling {
+            MidiSpelling::Sharps => continue,
+            MidiSpelling::Flats => SpellingPreference::Flats,
+            MidiSpelling::KeySignature => {
+                let idx = key_changes.partition_point(|(tick, _)| *tick <= event.tick);
+                match idx.checked_sub(1) {
+                    Some(idx) => SpellingPreference::Key(key_changes[idx].1),
+                    None => SpellingPreference::Sharps,
+                }
+            }
+        };
+        *note = note.respell(preference);
+    }
+}
+
 pub fn convert_midi_to_mtxt(midi_bytes: &[u8]) -> Result<MtxtFile> {
+    convert_midi_to_mtxt_with_options(midi_bytes, &MidiImportOptions::default())
+}
+
+// The SMF data the MIDI parser reads: the whole file, or the `data` chunk of a RIFF RMID file
+fn smf_data(midi_bytes: &[u8]) -> &[u8] {
+    if !midi_bytes.starts_with(b"RIFF") || midi_bytes.get(8..12) != Some(b"RMID") {
+        return midi_bytes;
+    }
+    let mut chunks = &midi_bytes[12..];
+    while chunks.len() >= 8 {
+        let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+        let data = &chunks[8..len.saturating_add(8).min(chunks.len())];
+        if &chunks[..4] == b"data" {
+            return data;
+        }
+        // chunks are padded to an even length
+        chunks = &chunks[len.saturating_add(8 + len % 2).min(chunks.len())..];
+    }
+    &[]
+}
+
+pub fn convert_midi_to_mtxt_with_options(
+    midi_bytes: &[u8],
+    options: &MidiImportOptions,
+) -> Result<MtxtFile> {
+    // midly overflows while negating a SMPTE frame rate of -128, reject it before parsing.
+    // The file starts with the header, but every later chunk with a header id is read as one too
+    let mut chunks = smf_data(midi_bytes);
+    if !chunks.starts_with(b"MThd") {
+        chunks = &[];
+    }
+    while chunks.len() >= 8 {
+        let len = u32::from_be_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+        if &chunks[..4] == b"MThd" && chunks.get(12) == Some(&0x80) {
+            bail!("Invalid MIDI header: unsupported SMPTE frame rate");
+        }
+        chunks = &chunks[len.saturating_add(8).min(chunks.len())..];
+    }
     let smf = Smf::parse(midi_bytes)?;
-    convert_smf_to_mtxt(&smf)
+    convert_smf_to_mtxt(&smf, options)
 }
 
-fn convert_smf_to_mtxt(smf: &Smf) -> Result<MtxtFile> {
+fn convert_smf_to_mtxt(smf: &Smf, options: &MidiImportOptions) -> Result<MtxtFile> {
     let mut mtxt_file = MtxtFile::new();
     mtxt_file
         .records
@@ -128,10 +292,22 @@ fn convert_smf_to_mtxt(smf: &Smf) -> Result<MtxtFile> {
             version: Version { major: 1, minor: 0 },
         }));
 
-    let all_events = get_midi_single_track_events(smf)?;
+    // kept so exporting the file again doesn't round the times to another resolution
+    if let Timing::Metrical(ppqn) = smf.header.timing
+        && ppqn.as_int() != DEFAULT_PPQN
+    {
+        mtxt_file
+            .records
+            .push(MtxtRecordLine::new(MtxtRecord::GlobalMeta {
+                meta_type: "ppqn".to_string(),
+                value: ppqn.to_string(),
+            }));
+    }
+
+    let all_events = get_midi_single_track_events(smf, options)?;
 
     // Collect used drum aliases
-    let mut used_drum_aliases = std::collections::HashSet::new();
+    let mut used_drum_aliases = HashSet::new();
     for event in &all_events {
         match &event.record.record {
             MtxtRecord::NoteOn {
@@ -149,57 +325,24 @@ fn convert_smf_to_mtxt(smf: &Smf) -> Result<MtxtFile> {
     }
 
     for drum in DRUMS.iter() {
-        if used_drum_aliases.contains(drum.slug) {
-            if let Ok(note) = midi_key_to_note(drum.number.into()) {
-                mtxt_file
-                    .records
-                    .push(MtxtRecordLine::new(MtxtRecord::AliasDef {
-                        value: Rc::new(AliasDefinition {
-                            name: drum.slug.to_string(),
-                            notes: vec![note],
-                        }),
-                    }));
-            }
+        if used_drum_aliases.contains(drum.slug)
+            && let Ok(note) = midi_key_to_note(drum.number)
+        {
+            mtxt_file
+                .records
+                .push(MtxtRecordLine::new(MtxtRecord::AliasDef {
+                    value: Rc::new(AliasDefinition {
+                        name: drum.slug.to_string(),
+                        notes: vec![note],
+                    }),
+                }));
         }
     }
     let mut final_events: Vec<MtxtRecordLine> =
         all_events.into_iter().map(|event| event.record).collect();
 
     // Sort final events to ensure None/GlobalMeta come first
-    final_events.sort_by(|a_line, b_line| {
-        let a = &a_line.record;
-        let b = &b_line.record;
-
-        // Helper to get sort key: (order_group, time)
-        // order_group: 0=GlobalMeta, 1=Meta(None), 2=Other
-        fn get_sort_key(record: &MtxtRecord) -> (u8, BeatTime) {
-            match record {
-                MtxtRecord::GlobalMeta { .. } => (0, BeatTime::zero()),
-                MtxtRecord::AliasDef { .. } => (0, BeatTime::zero()),
-                MtxtRecord::Meta { time: None, .. } => (1, BeatTime::zero()),
-                MtxtRecord::Header { .. } => (0, BeatTime::zero()),
-                MtxtRecord::Meta { time: Some(t), .. } => (2, *t),
-                MtxtRecord::Note { time, .. }
-                | MtxtRecord::NoteOn { time, .. }
-                | MtxtRecord::NoteOff { time, .. }
-                | MtxtRecord::ControlChange { time, .. }
-                | MtxtRecord::Voice { time, .. }
-                | MtxtRecord::Tempo { time, .. }
-                | MtxtRecord::TimeSignature { time, .. }
-                | MtxtRecord::SysEx { time, .. } => (2, *time),
-                _ => (2, BeatTime::zero()),
-            }
-        }
-
-        let (group_a, time_a) = get_sort_key(a);
-        let (group_b, time_b) = get_sort_key(b);
-
-        if group_a != group_b {
-            return group_a.cmp(&group_b);
-        }
-
-        time_a.cmp(&time_b)
-    });
+    final_events.sort_by_key(|line| sort::sort_key(&line.record));
 
     final_events = extract::transform(&final_events);
     final_events = merge::transform(&final_events);
@@ -239,12 +382,12 @@ fn convert_midi_message_to_record(
             }
 
             let velocity = int_vel as f32 / 127.0;
-            return Ok(MtxtRecord::NoteOn {
+            Ok(MtxtRecord::NoteOn {
                 time: beat_time,
                 note: note_target,
                 velocity: Some(velocity),
                 channel: Some(channel),
-            });
+            })
         }
         MidiMessage::NoteOff { key, vel } => {
             let note_target = if channel == 9 {
@@ -259,18 +402,18 @@ fn convert_midi_message_to_record(
 
             let off_velocity = vel.as_int() as f32 / 127.0;
 
-            return Ok(MtxtRecord::NoteOff {
+            Ok(MtxtRecord::NoteOff {
                 time: beat_time,
                 note: note_target,
                 off_velocity: Some(off_velocity),
                 channel: Some(channel),
-            });
+            })
         }
         MidiMessage::Controller { controller, value } => {
             let controller_name = midi_cc_to_name(controller.as_int());
             let mtxt_value = value.as_int() as f32 / 127.0;
 
-            return Ok(MtxtRecord::ControlChange {
+            Ok(MtxtRecord::ControlChange {
                 time: beat_time,
                 note: None,
                 controller: controller_name,
@@ -279,7 +422,7 @@ fn convert_midi_message_to_record(
                 transition_curve: None,
                 transition_time: None,
                 transition_interval: None,
-            });
+            })
         }
         MidiMessage::ProgramChange { program } => {
             let prog_num = program.as_int();
@@ -292,18 +435,18 @@ fn convert_midi_message_to_record(
                 voice_names.push(prog_num.to_string());
             }
 
-            return Ok(MtxtRecord::Voice {
+            Ok(MtxtRecord::Voice {
                 time: beat_time,
                 voices: VoiceList {
                     voices: voice_names,
                 },
                 channel: Some(channel),
-            });
+            })
         }
         MidiMessage::PitchBend { bend } => {
             let bend_value = (bend.as_int() as f32 - 8192.0) / 8192.0 * 12.0;
 
-            return Ok(MtxtRecord::ControlChange {
+            Ok(MtxtRecord::ControlChange {
                 time: beat_time,
                 note: None,
                 controller: "pitch".to_string(),
@@ -312,11 +455,11 @@ fn convert_midi_message_to_record(
                 transition_curve: None,
                 transition_time: None,
                 transition_interval: None,
-            });
+            })
         }
         MidiMessage::Aftertouch { key: _, vel } | MidiMessage::ChannelAftertouch { vel } => {
             let value = vel.as_int() as f32 / 127.0;
-            return Ok(MtxtRecord::ControlChange {
+            Ok(MtxtRecord::ControlChange {
                 time: beat_time,
                 note: None,
                 controller: "aftertouch".to_string(),
@@ -325,7 +468,7 @@ fn convert_midi_message_to_record(
                 transition_curve: None,
                 transition_time: None,
                 transition_interval: None,
-            });
+            })
         }
     }
 }
@@ -349,9 +492,13 @@ fn convert_meta_message(
             }))
         }
         MetaMessage::TimeSignature(num, den, _clocks, _bb) => {
+            // the denominator is stored as a power of 2
+            let Some(denominator) = 1u8.checked_shl(*den as u32) else {
+                bail!("Invalid time signature denominator: 2^{}", den);
+            };
             let signature = TimeSignature {
                 numerator: *num,
-                denominator: 1 << den,
+                denominator,
             };
             Ok(Some(MtxtRecord::TimeSignature {
                 time: beat_time,
@@ -543,3 +690,246 @@ fn convert_meta_message(
         MetaMessage::EndOfTrack => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::Header;
+    use midly::num::{u4, u7, u15, u28};
+
+    fn note_track(key: u8, length: u32) -> Vec<TrackEvent<'static>> {
+        let note = |delta: u32, vel: u8| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                },
+            },
+        };
+        vec![
+            note(0, 100),
+            note(length, 0),
+            TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ]
+    }
+
+    fn note_times(file: &MtxtFile) -> Vec<String> {
+        file.records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note { time, .. } => Some(time.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_format2_modes() {
+        let smf = Smf {
+            header: Header::new(Format::Sequential, Timing::Metrical(u15::new(480))),
+            tracks: vec![note_track(60, 960), note_track(62, 480)],
+        };
+
+        let sequential = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+        assert_eq!(note_times(&sequential), vec!["0.0", "2.0"]);
+
+        let options = MidiImportOptions {
+            format2_mode: Format2Mode::Parallel,
+            ..Default::default()
+        };
+        let parallel = convert_smf_to_mtxt(&smf, &options).unwrap();
+        assert_eq!(note_times(&parallel), vec!["0.0", "0.0"]);
+    }
+
+    fn numbered_track(number: u16, key: u8, length: u32) -> Vec<TrackEvent<'static>> {
+        let mut track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::TrackNumber(Some(number))),
+        }];
+        track.extend(note_track(key, length));
+        track
+    }
+
+    fn note_names(file: &MtxtFile) -> Vec<String> {
+        file.records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note { note, .. } => Some(note.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tracks_ordered_by_track_number() {
+        let smf = Smf {
+            header: Header::new(Format::Sequential, Timing::Metrical(u15::new(480))),
+            tracks: vec![numbered_track(1, 60, 960), numbered_track(0, 62, 480)],
+        };
+        let file = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+        assert_eq!(note_times(&file), vec!["0.0", "1.0"]);
+        assert_eq!(note_names(&file), vec!["D4", "C4"]);
+
+        let smf = Smf {
+            header: Header::new(Format::Sequential, Timing::Metrical(u15::new(480))),
+            tracks: vec![note_track(60, 960), note_track(62, 480)],
+        };
+        let file = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+        assert_eq!(note_names(&file), vec!["C4", "D4"]);
+    }
+
+    #[test]
+    fn test_ppqn_round_trip() {
+        // an odd tick count at 960 PPQN falls between two ticks of the default resolution
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(960))),
+            tracks: vec![note_track(60, 1001)],
+        };
+        let file = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+        assert_eq!(file.get_global_meta_value("ppqn"), Some("960"));
+
+        let bytes = crate::midi::convert_mtxt_to_midi(&file).unwrap();
+        let exported = Smf::parse(&bytes).unwrap();
+        assert_eq!(exported.header.timing, Timing::Metrical(u15::new(960)));
+        let ticks: Vec<u32> = exported.tracks[0]
+            .iter()
+            .filter(|event| matches!(event.kind, TrackEventKind::Midi { .. }))
+            .map(|event| event.delta.as_int())
+            .collect();
+        assert_eq!(ticks, vec![0, 1001]);
+        // the resolution is not exported as a text event
+        assert!(
+            !exported.tracks[0]
+                .iter()
+                .any(|event| matches!(event.kind, TrackEventKind::Meta(MetaMessage::Text(_))))
+        );
+
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![note_track(60, 480)],
+        };
+        let file = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+        assert_eq!(file.get_global_meta_value("ppqn"), None);
+    }
+
+    #[test]
+    fn test_malformed_input_is_an_error() {
+        let header = |division: [u8; 2]| {
+            let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01".to_vec();
+            bytes.extend(division);
+            bytes.extend(b"MTrk\x00\x00\x00\x04\x00\xff\x2f\x00");
+            bytes
+        };
+        assert!(convert_midi_to_mtxt(&header([0x01, 0xe0])).is_ok());
+        assert!(convert_midi_to_mtxt(&[]).is_err());
+        assert!(convert_midi_to_mtxt(&header([0x01, 0xe0])[..10]).is_err());
+        assert!(convert_midi_to_mtxt(&header([0x00, 0x00])).is_err());
+        assert!(convert_midi_to_mtxt(&header([0x80, 0x00])).is_err());
+
+        // headers are found by their chunks, also inside a RIFF RMID file
+        let mut trailing = header([0x01, 0xe0]);
+        trailing.extend(header([0x80, 0x00]));
+        assert!(convert_midi_to_mtxt(&trailing).is_err());
+        let mut text = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x80\x00".to_vec();
+        text.splice(0..0, [0x00, 0xff, 0x01, 14]);
+        text.extend(b"\x00\xff\x2f\x00");
+        let mut in_text = header([0x01, 0xe0]);
+        in_text.extend(b"MTrk");
+        in_text.extend((text.len() as u32).to_be_bytes());
+        in_text.extend(text);
+        assert!(convert_midi_to_mtxt(&in_text).is_ok());
+        let rmid = |smf: Vec<u8>| {
+            let mut bytes = b"RIFF".to_vec();
+            bytes.extend((smf.len() as u32 + 12).to_le_bytes());
+            bytes.extend(b"RMIDdata");
+            bytes.extend((smf.len() as u32).to_le_bytes());
+            bytes.extend(smf);
+            bytes
+        };
+        assert!(convert_midi_to_mtxt(&rmid(header([0x01, 0xe0]))).is_ok());
+        assert!(convert_midi_to_mtxt(&rmid(header([0x80, 0x00]))).is_err());
+
+        // chunk lengths past the end of the file
+        let mut truncated = header([0x01, 0xe0]);
+        truncated.extend(b"MTrk\xff\xff\xff\xff");
+        let _ = convert_midi_to_mtxt(&truncated);
+        let riff = b"RIFF\x00\x00\x00\x00RMIDLIST\xff\xff\xff\xff\x00".to_vec();
+        assert!(smf_data(&riff).is_empty());
+
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![vec![TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::TimeSignature(4, 8, 24, 8)),
+            }]],
+        };
+        assert!(convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_unmatched_note_off() {
+        let mut track = note_track(60, 480);
+        // a second release of the same key, and one of a key that never sounded
+        let extra: Vec<TrackEvent> = note_track(60, 240)
+            .into_iter()
+            .chain(note_track(64, 0))
+            .filter(|event| {
+                matches!(event.kind, TrackEventKind::Midi { message: MidiMessage::NoteOn { vel, .. }, .. } if vel == 0)
+            })
+            .collect();
+        track.splice(2..2, extra);
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![track],
+        };
+        let file = convert_smf_to_mtxt(&smf, &MidiImportOptions::default()).unwrap();
+
+        assert_eq!(note_times(&file), vec!["0.0"]);
+        let comments: Vec<&str> = file
+            .records
+            .iter()
+            .filter(|line| matches!(line.record, MtxtRecord::NoteOff { .. }))
+            .filter_map(|line| line.comment.as_deref())
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                "NoteOff for key=60 channel=0 at tick=720 with no matching NoteOn",
+                "NoteOff for key=64 channel=0 at tick=720 with no matching NoteOn",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spelling() {
+        let key_signature = |delta: u32, sharps_flats: i8| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(MetaMessage::KeySignature(sharps_flats, false)),
+        };
+        // Eb major from the start, E major from beat 2
+        let mut track = vec![key_signature(0, -3)];
+        track.extend(note_track(63, 480));
+        track.insert(3, key_signature(480, 4));
+        track.splice(4..4, note_track(63, 480).into_iter().take(2));
+        let smf = Smf {
+            header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480))),
+            tracks: vec![track],
+        };
+        let spelled = |spelling: MidiSpelling| {
+            let options = MidiImportOptions {
+                spelling,
+                ..Default::default()
+            };
+            note_names(&convert_smf_to_mtxt(&smf, &options).unwrap())
+        };
+
+        assert_eq!(spelled(MidiSpelling::Sharps), vec!["D#4", "D#4"]);
+        assert_eq!(spelled(MidiSpelling::Flats), vec!["Eb4", "Eb4"]);
+        assert_eq!(spelled(MidiSpelling::KeySignature), vec!["Eb4", "D#4"]);
+    }
+}
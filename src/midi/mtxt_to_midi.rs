@@ -1,3 +1,4 @@
+use crate::TempoMap;
 use crate::file::MtxtFile;
 use crate::types::output_record::MtxtOutputRecord;
 use crate::types::record::VoiceList;
@@ -7,12 +8,19 @@ use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
 use super::escape::unescape_string;
 use super::instruments::INSTRUMENTS;
 use super::shared::{
-    MidiControllerEvent, controller_name_to_midi, note_to_midi_number, time_signature_to_midi,
+    DEFAULT_PPQN, MidiControllerEvent, controller_name_to_midi, note_to_midi_number,
+    time_signature_to_midi,
 };
 
 pub fn convert_mtxt_to_midi(mtxt_file: &MtxtFile) -> Result<Vec<u8>> {
+    // resolution of the imported MIDI file, if it wasn't the default
+    let ppqn = mtxt_file
+        .get_global_meta_value("ppqn")
+        .and_then(|value| value.parse::<u16>().ok())
+        .filter(|ppqn| (1..=midly::num::u15::max_value().as_int()).contains(ppqn))
+        .unwrap_or(DEFAULT_PPQN);
     let mut output_records = mtxt_file.get_output_records();
-    let smf = convert_output_records_to_midi(&mut output_records)?;
+    let smf = convert_output_records_to_midi(&mut output_records, ppqn)?;
 
     let mut buffer = Vec::new();
     smf.write(&mut buffer)
@@ -187,6 +195,8 @@ fn record_to_track_event(
             // For now, just skip it
             Ok(None)
         }
+        // stored resolution of the file, written to the header
+        MtxtOutputRecord::GlobalMeta { meta_type, .. } if meta_type == "ppqn" => Ok(None),
         MtxtOutputRecord::GlobalMeta {
             meta_type, value, ..
         }
@@ -220,25 +230,18 @@ fn record_to_track_event(
     }
 }
 
-fn convert_output_records_to_midi(records: &mut [MtxtOutputRecord]) -> Result<Smf<'_>> {
-    let ppqn = 480;
+fn convert_output_records_to_midi(records: &mut [MtxtOutputRecord], ppqn: u16) -> Result<Smf<'_>> {
     let timing = Timing::Metrical(midly::num::u15::new(ppqn));
 
     let mut track_events = Vec::new();
 
-    let mut current_bpm = 120.0;
-
-    let mut last_micros = 0u64;
-    let mut accumulated_delta_ticks = 0u64;
+    let tempo_map = TempoMap::from_output_records(records);
+    // tick of the last event written, the deltas are rounded from exact positions so they don't drift
+    let mut last_tick = 0u64;
 
     for record in records.iter_mut() {
-        let time_micros = record.time();
-        let delta_micros = time_micros.saturating_sub(last_micros);
-        last_micros = time_micros;
-
-        let micros_per_beat = 60_000_000.0 / current_bpm;
-        let delta_beats = delta_micros as f64 / micros_per_beat;
-        let mut delta_tick = accumulated_delta_ticks + ((delta_beats * ppqn as f64).round() as u64);
+        let tick = (tempo_map.beat_at_micros(record.time()).as_f64() * ppqn as f64).round() as u64;
+        let mut delta_tick = tick.saturating_sub(last_tick);
 
         while delta_tick > midly::num::u28::max_value().as_int() as u64 {
             track_events.push(TrackEvent {
@@ -246,20 +249,15 @@ fn convert_output_records_to_midi(records: &mut [MtxtOutputRecord]) -> Result<Sm
                 kind: TrackEventKind::Meta(MetaMessage::Text(b"long delta")),
             });
             delta_tick -= midly::num::u28::max_value().as_int() as u64;
-        }
-
-        if let MtxtOutputRecord::Tempo { bpm, .. } = record {
-            current_bpm = *bpm as f64;
+            last_tick += midly::num::u28::max_value().as_int() as u64;
         }
 
         let track_event = record_to_track_event(record, delta_tick as u32)?;
 
+        // records without an event leave their delta to the next one
         if let Some(event) = track_event {
             track_events.push(event);
-            accumulated_delta_ticks = 0;
-        } else {
-            // did not manage to consume deltas -> accumulate
-            accumulated_delta_ticks = delta_tick;
+            last_tick = tick;
         }
     }
 
@@ -1,35 +1,11 @@
+use crate::types::controller::MIDI_CC_MAPPINGS;
 use crate::types::note::Note;
 use crate::types::pitch::PitchClass;
 use anyhow::{Result, anyhow};
 
-const MIDI_CC_MAPPINGS: &[(u8, &str)] = &[
-    (1, "vibrato"),
-    (2, "breath"),
-    (4, "foot"),
-    (5, "portamento"),
-    (7, "volume"),
-    (8, "balance"),
-    (10, "pan"),
-    (11, "expression"),
-    (64, "sustain"),
-    (65, "portamento_switch"),
-    (66, "sostenuto"),
-    (67, "soft"),
-    (68, "legato"),
-    (70, "sound_variation"),
-    (71, "timbre"),
-    (73, "attack"),
-    (74, "cutoff"),
-    (75, "decay"),
-    (76, "vibrato_rate"),
-    (77, "vibrato_depth"),
-    (78, "vibrato_delay"),
-    (91, "reverb"),
-    (92, "tremolo"),
-    (93, "chorus"),
-    (94, "detune"),
-    (95, "phaser"),
-];
+/// Ticks per quarter note of exported files, imported files with another resolution
+/// keep theirs in a `ppqn` global meta
+pub const DEFAULT_PPQN: u16 = 480;
 
 pub fn midi_cc_to_name(cc: u8) -> String {
     MIDI_CC_MAPPINGS
@@ -47,23 +23,7 @@ fn midi_cc_name_to_number(name: &str) -> Option<u8> {
 }
 
 pub fn note_to_midi_number(note: &Note) -> Result<u8> {
-    let pitch_offset = match note.pitch_class {
-        PitchClass::C => 0,
-        PitchClass::CSharp | PitchClass::Db => 1,
-        PitchClass::D => 2,
-        PitchClass::DSharp | PitchClass::Eb => 3,
-        PitchClass::E => 4,
-        PitchClass::F => 5,
-        PitchClass::FSharp | PitchClass::Gb => 6,
-        PitchClass::G => 7,
-        PitchClass::GSharp | PitchClass::Ab => 8,
-        PitchClass::A => 9,
-        PitchClass::ASharp | PitchClass::Bb => 10,
-        PitchClass::B => 11,
-        _ => note.pitch_class.to_semitone(),
-    };
-
-    let midi_number = (note.octave as i32 + 1) * 12 + pitch_offset as i32;
+    let midi_number = note.absolute_semitone();
 
     if !(0..=127).contains(&midi_number) {
         anyhow::bail!("Note {} is outside MIDI range", note);
@@ -72,6 +32,19 @@ pub fn note_to_midi_number(note: &Note) -> Result<u8> {
     Ok(midi_number as u8)
 }
 
+/// Nearest MIDI key of the note with its cents, and the cents left between that key and the
+/// pitch (-50 to 50) to be played as pitch bend
+pub fn note_to_nearest_midi_key(note: &Note) -> Result<(u8, f32)> {
+    let pitch = note.absolute_semitone() as f32 * 100.0 + note.cents;
+    let key = (pitch / 100.0).round();
+
+    if !(0.0..=127.0).contains(&key) {
+        anyhow::bail!("Note {} is outside MIDI range", note);
+    }
+
+    Ok((key as u8, pitch - key * 100.0))
+}
+
 pub fn midi_key_to_note(key: u8) -> Result<Note> {
     let octave = (key / 12) as i8 - 1;
     let pitch_class = match key % 12 {
@@ -147,12 +120,13 @@ pub fn controller_name_to_midi(name: &str, value: f32) -> Result<MidiControllerE
 
             // Try parsing as a numeric CC number
             if let Ok(num) = name.parse::<u8>()
-                && num <= 127 {
-                    return Ok(MidiControllerEvent::CC {
-                        number: num,
-                        value: (value.clamp(0.0, 1.0) * 127.0) as u8,
-                    });
-                }
+                && num <= 127
+            {
+                return Ok(MidiControllerEvent::CC {
+                    number: num,
+                    value: (value.clamp(0.0, 1.0) * 127.0) as u8,
+                });
+            }
 
             Err(anyhow!("Unknown controller name: {}", name))
         }
@@ -208,3 +182,20 @@ pub fn midi_key_signature_to_string(sharps_flats: i8, minor: bool) -> String {
 
     format!("{} {}", key_name, if minor { "minor" } else { "major" })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_midi_key() {
+        let key = |name: &str| note_to_nearest_midi_key(&name.parse().unwrap()).unwrap();
+        assert_eq!(key("E4"), (64, 0.0));
+        assert_eq!(key("E4-14c"), (64, -14.0));
+        assert_eq!(key("E4+75"), (65, -25.0));
+        assert_eq!(key("E4-50"), (64, -50.0));
+        assert_eq!(key("B#3+60"), (61, -40.0));
+        assert!(note_to_nearest_midi_key(&"G9+60".parse().unwrap()).is_err());
+        assert_eq!(note_to_midi_number(&"E4+75".parse().unwrap()).unwrap(), 64);
+    }
+}
@@ -0,0 +1,235 @@
+//! Self-contained HTML piano roll, see [`MtxtFile::to_html_piano_roll`](crate::MtxtFile::to_html_piano_roll).
+
+use crate::PitchClass;
+use std::fmt::Write;
+
+/// Width of the keyboard drawn left of the notes
+const KEYBOARD_WIDTH: f32 = 48.0;
+/// Pitches shown around the notes, so the highest and lowest aren't on the edge
+const PITCH_MARGIN: u8 = 2;
+
+const DEFAULT_COLORS: [&str; 8] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PianoRollOptions {
+    pub pixels_per_beat: f32,
+    pub pixels_per_semitone: f32,
+    /// CSS colors of the notes by channel, repeating for channels past the end
+    pub channel_colors: Vec<String>,
+    /// Draw a line at every beat
+    pub show_grid: bool,
+}
+
+impl Default for PianoRollOptions {
+    fn default() -> Self {
+        Self {
+            pixels_per_beat: 40.0,
+            pixels_per_semitone: 10.0,
+            channel_colors: DEFAULT_COLORS
+                .iter()
+                .map(|color| color.to_string())
+                .collect(),
+            show_grid: true,
+        }
+    }
+}
+
+pub(crate) struct PianoRollNote {
+    pub channel: u16,
+    /// Start and end in beats
+    pub start: f64,
+    pub end: f64,
+    pub pitch: u8,
+    pub label: String,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Pixel coordinate without float noise
+fn px(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn is_black_key(pitch: u8) -> bool {
+    matches!(pitch % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+fn pitch_name(pitch: u8) -> String {
+    let octave = pitch as i32 / 12 - 1;
+    format!("{}{}", PitchClass::from_semitone(pitch % 12, false), octave)
+}
+
+pub(crate) fn render(notes: &[PianoRollNote], title: &str, options: &PianoRollOptions) -> String {
+    let beat_width = options.pixels_per_beat.max(1.0) as f64;
+    let row_height = options.pixels_per_semitone.max(1.0) as f64;
+    let keyboard_width = KEYBOARD_WIDTH as f64;
+
+    let (low, high) = notes
+        .iter()
+        .map(|note| note.pitch)
+        .fold(None, |range: Option<(u8, u8)>, pitch| match range {
+            Some((low, high)) => Some((low.min(pitch), high.max(pitch))),
+            None => Some((pitch, pitch)),
+        })
+        .unwrap_or((60, 72));
+    let low = low.saturating_sub(PITCH_MARGIN);
+    let high = high.saturating_add(PITCH_MARGIN).min(127);
+    let beats = notes.iter().map(|note| note.end).fold(4.0, f64::max).ceil() as u32;
+
+    let width = keyboard_width + beats as f64 * beat_width;
+    let height = (high - low + 1) as f64 * row_height;
+    let row_y = |pitch: u8| (high - pitch) as f64 * row_height;
+    let colors: Vec<&str> = if options.channel_colors.is_empty() {
+        DEFAULT_COLORS.to_vec()
+    } else {
+        options.channel_colors.iter().map(String::as_str).collect()
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        px(width),
+        px(height),
+        px(width),
+        px(height)
+    );
+
+    // rows, shaded for black keys, and the keyboard with pitch labels
+    let font_size = (row_height * 0.8).min(12.0);
+    for pitch in low..=high {
+        let y = px(row_y(pitch));
+        let (row, key) = if is_black_key(pitch) {
+            ("#eeeeee", "#333333")
+        } else {
+            ("#fafafa", "#ffffff")
+        };
+        let _ = writeln!(
+            svg,
+            r#"<rect class="row" x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            px(keyboard_width),
+            y,
+            px(width - keyboard_width),
+            px(row_height),
+            row
+        );
+        let _ = writeln!(
+            svg,
+            r##"<rect class="key" x="0" y="{}" width="{}" height="{}" fill="{}" stroke="#999999" stroke-width="0.5"/>"##,
+            y,
+            px(keyboard_width),
+            px(row_height),
+            key
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="2" y="{}" font-size="{}" fill="{}">{}</text>"#,
+            px(row_y(pitch) + row_height * 0.8),
+            px(font_size),
+            if is_black_key(pitch) {
+                "#ffffff"
+            } else {
+                "#333333"
+            },
+            pitch_name(pitch)
+        );
+    }
+
+    if options.show_grid {
+        for beat in 0..=beats {
+            let x = px(keyboard_width + beat as f64 * beat_width);
+            let _ = writeln!(
+                svg,
+                r##"<line class="grid" x1="{}" y1="0" x2="{}" y2="{}" stroke="#cccccc" stroke-width="1"/>"##,
+                x,
+                x,
+                px(height)
+            );
+        }
+    }
+
+    for note in notes {
+        let _ = writeln!(
+            svg,
+            r#"<rect class="note" x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="black" stroke-width="0.5"><title>{} ch={}</title></rect>"#,
+            px(keyboard_width + note.start * beat_width),
+            px(row_y(note.pitch)),
+            px(((note.end - note.start) * beat_width).max(1.0)),
+            px(row_height),
+            escape(colors[note.channel as usize % colors.len()]),
+            escape(&note.label),
+            note.channel
+        );
+    }
+    svg.push_str("</svg>\n");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>body {{ margin: 0; font-family: sans-serif; }}</style>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        escape(title),
+        svg
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+
+    #[test]
+    fn test_piano_roll() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+meta global title Tom & Jerry
+0.0 note C4 dur=1 ch=0
+1.0 note E4 dur=0.5 ch=1
+"#,
+        )
+        .unwrap();
+        let html = file.to_html_piano_roll(PianoRollOptions {
+            pixels_per_beat: 20.0,
+            pixels_per_semitone: 5.0,
+            channel_colors: vec!["red".to_string(), "blue".to_string()],
+            show_grid: true,
+        });
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Tom &amp; Jerry</title>"));
+        // C4 is two rows above the bottom row A#3, E4 is two below the top row F#4
+        assert!(
+            html.contains(r#"<rect class="note" x="48" y="30" width="20" height="5" fill="red""#)
+        );
+        assert!(
+            html.contains(r#"<rect class="note" x="68" y="10" width="10" height="5" fill="blue""#)
+        );
+        assert!(html.contains(">C4</text>"));
+        assert!(html.contains(">A#3</text>"));
+        assert_eq!(html.matches(r#"class="key""#).count(), 9);
+        // the roll is at least 4 beats wide
+        assert_eq!(html.matches(r#"class="grid""#).count(), 5);
+
+        let html = file.to_html_piano_roll(PianoRollOptions {
+            show_grid: false,
+            ..Default::default()
+        });
+        assert!(!html.contains(r#"class="grid""#));
+        assert!(html.contains(r##"fill="#4e79a7""##));
+    }
+}
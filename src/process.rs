@@ -49,12 +49,17 @@ pub struct IntermediateRecord {
 pub fn process_records(records: &[MtxtRecord]) -> Vec<MtxtOutputRecord> {
     let intermediate_output = create_intermediate_records(records);
     let mut transition_processor = TransitionProcessor::new(&intermediate_output);
-    transition_processor.process_all()
+    let mut output = transition_processor.process_all();
+    // stable, records of the same kind at the same time keep their order
+    output.sort_by_key(|record| (record.time(), record.order_priority()));
+    output
 }
 
 fn create_intermediate_records(records: &[MtxtRecord]) -> Vec<IntermediateRecord> {
     let mut state = ProcessState::new();
     let mut intermediate_output = Vec::new();
+    // CCs addressing a note range, resolved once all notes are known
+    let mut range_ccs: Vec<(NoteTarget, IntermediateRecord)> = Vec::new();
 
     for record in records {
         match record {
@@ -192,7 +197,25 @@ fn create_intermediate_records(records: &[MtxtRecord]) -> Vec<IntermediateRecord
                 let t_time = transition_time.unwrap_or(BeatTime::zero());
                 let t_interval = transition_interval.unwrap_or(state.transition_interval);
 
-                if let Some(target) = note {
+                if let Some(target @ NoteTarget::Range { .. }) = note {
+                    range_ccs.push((
+                        target.clone(),
+                        IntermediateRecord {
+                            start_beat_time: *time - t_time,
+                            end_beat_time: *time,
+                            record: MtxtOutputRecord::ControlChange {
+                                time: 0,
+                                note: None,
+                                controller: controller.clone(),
+                                value: *value,
+                                channel: ch,
+                            },
+                            transition_curve: t_curve,
+                            transition_time: t_time,
+                            transition_interval: t_interval,
+                        },
+                    ));
+                } else if let Some(target) = note {
                     let notes = resolve_note_target(target, &state.aliases);
                     for n in notes {
                         intermediate_output.push(IntermediateRecord {
@@ -363,10 +386,51 @@ fn create_intermediate_records(records: &[MtxtRecord]) -> Vec<IntermediateRecord
         }
     }
 
-    intermediate_output.sort_by(|a, b| a.end_beat_time.cmp(&b.end_beat_time));
+    for (target, cc) in range_ccs {
+        let MtxtOutputRecord::ControlChange { channel, .. } = &cc.record else {
+            continue;
+        };
+        let notes = active_notes(&intermediate_output, *channel, cc.end_beat_time);
+        for n in notes.into_iter().filter(|n| target.matches(n)) {
+            let mut record = cc.clone();
+            if let MtxtOutputRecord::ControlChange { note, .. } = &mut record.record {
+                *note = Some(n);
+            }
+            intermediate_output.push(record);
+        }
+    }
+
+    intermediate_output.sort_by_key(|a| a.end_beat_time);
     intermediate_output
 }
 
+// Notes sounding on `channel` at `time`: started at or before it and not yet released
+fn active_notes(records: &[IntermediateRecord], channel: u16, time: BeatTime) -> Vec<Note> {
+    let mut events: Vec<&IntermediateRecord> = records
+        .iter()
+        .filter(|record| record.end_beat_time <= time)
+        .collect();
+    events.sort_by_key(|record| record.end_beat_time);
+
+    let mut active: Vec<Note> = Vec::new();
+    for event in events {
+        match &event.record {
+            MtxtOutputRecord::NoteOn {
+                note, channel: ch, ..
+            } if *ch == channel => active.push(note.clone()),
+            MtxtOutputRecord::NoteOff {
+                note, channel: ch, ..
+            } if *ch == channel => {
+                if let Some(idx) = active.iter().position(|n| n == note) {
+                    active.remove(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    active
+}
+
 fn resolve_note_target(
     target: &NoteTarget,
     aliases: &HashMap<String, Rc<AliasDefinition>>,
@@ -381,5 +445,69 @@ fn resolve_note_target(
             }
         }
         NoteTarget::Alias(def) => def.notes.clone(),
+        NoteTarget::Range { low, high } => Note::chromatic_range(low, high),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_mtxt;
+    use crate::types::output_record::MtxtOutputRecord;
+
+    #[test]
+    fn test_cc_note_range() {
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+0.0 note C3 dur=4
+0.0 note G4 dur=4
+0.0 note C6 dur=4
+1.0 note E4 dur=0.5 ch=1
+2.0 note D5 dur=1
+1.0 cc C3-C5 pitch 0.5
+"#,
+        )
+        .unwrap();
+
+        let targets: Vec<String> = file
+            .get_output_records()
+            .iter()
+            .filter_map(|record| match record {
+                MtxtOutputRecord::ControlChange {
+                    note: Some(note), ..
+                } => Some(note.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(targets, ["C3", "G4"]);
+    }
+
+    #[test]
+    fn test_same_time_order() {
+        // the second note is written first, its note on must still follow the note off
+        let file = parse_mtxt(
+            r#"mtxt 1.0
+1.0 note C4 dur=1
+1.0 cc volume 0.5 ch=0
+0.0 note C4 dur=1
+1.0 tempo 90
+1.0 reset all
+"#,
+        )
+        .unwrap();
+
+        let kinds: Vec<&str> = file
+            .get_output_records()
+            .iter()
+            .filter(|record| record.time() == 500_000)
+            .filter_map(|record| match record {
+                MtxtOutputRecord::NoteOff { .. } => Some("off"),
+                MtxtOutputRecord::ControlChange { .. } => Some("cc"),
+                MtxtOutputRecord::Tempo { .. } => Some("tempo"),
+                MtxtOutputRecord::NoteOn { .. } => Some("on"),
+                MtxtOutputRecord::Reset { .. } => Some("reset"),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(kinds, ["off", "reset", "cc", "tempo", "on"]);
     }
 }
@@ -518,6 +518,15 @@ fn find_inline_comment_index(line: &str) -> Option<usize> {
     None
 }
 
+/// Parses a line and fails unless it holds a record of the given type
+pub(crate) fn parse_record_of_type(line: &str, type_str: &str) -> Result<MtxtRecord> {
+    let record = parse_mtxt_line(line)?.record;
+    if record.type_str() != type_str {
+        bail!("Expected \"{}\" record, got \"{}\"", type_str, line.trim());
+    }
+    Ok(record)
+}
+
 pub fn parse_mtxt_line(line: &str) -> Result<MtxtRecordLine, anyhow::Error> {
     let line = line.trim();
 
@@ -526,8 +535,8 @@ pub fn parse_mtxt_line(line: &str) -> Result<MtxtRecordLine, anyhow::Error> {
     }
 
     // Full-line comments (line starts with //)
-    if line.starts_with("//") {
-        let comment_text = line[2..].trim().to_string();
+    if let Some(comment_text) = line.strip_prefix("//") {
+        let comment_text = comment_text.trim().to_string();
         return Ok(MtxtRecordLine::with_comment(
             MtxtRecord::EmptyLine,
             comment_text,
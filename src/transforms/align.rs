@@ -0,0 +1,95 @@
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+/// Moves everything earlier so the first note starts at beat 0.
+/// Records before the first note (tempo, time signature, voice, CC setup) are moved to beat 0
+/// and keep their order in the file. Global meta and meta without a time are not changed.
+pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let first_note = records
+        .iter()
+        .filter_map(|line| match &line.record {
+            MtxtRecord::Note { time, .. } | MtxtRecord::NoteOn { time, .. } => Some(*time),
+            _ => None,
+        })
+        .min();
+    let Some(shift) = first_note else {
+        return records.to_vec();
+    };
+
+    records
+        .iter()
+        .map(|line| {
+            let mut new_line = line.clone();
+            if let Some(time) = new_line.record.time() {
+                // subtraction saturates at 0
+                new_line.record.set_time(time - shift);
+            }
+            new_line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_align_to_zero() {
+        let input = r#"
+mtxt 1.0
+meta global title Song
+0.0 tempo 100.0
+0.0 timesig 3/4
+meta text untimed
+ch=1
+30.0 voice piano
+36.5 cc volume 0.8
+37.0 cc pan 0.2
+37.25 note C4
+38.0 tempo 120.0
+38.25 on E4
+39.0 off E4
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global title Song
+0.0 tempo 100.0
+0.0 timesig 3/4
+meta text untimed
+ch=1
+0.0 voice piano
+0.0 cc volume 0.8
+0.0 cc pan 0.2
+0.0 note C4
+0.75 tempo 120.0
+1.0 on E4
+1.75 off E4
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+
+    #[test]
+    fn test_align_to_zero_first_note_is_note_on() {
+        let input = r#"
+mtxt 1.0
+2.0 cc volume 0.5
+4.5 on C4
+5.0 note E4
+6.0 off C4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc volume 0.5
+0.0 on C4
+0.5 note E4
+1.5 off C4
+"#;
+        assert_eq_records(input, transform, expected);
+        // nothing to align without notes
+        assert_eq_records(
+            "\nmtxt 1.0\n2.0 cc volume 0.5\n",
+            transform,
+            "\nmtxt 1.0\n2.0 cc volume 0.5\n",
+        );
+    }
+}
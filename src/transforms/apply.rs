@@ -166,6 +166,26 @@ pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
     new_records
 }
 
+/// Gives CCs without a channel the channel of the `ch=` directive before them, as playback
+/// does. The directives are kept, CCs before the first one stay global.
+pub(crate) fn resolve_cc_channels(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let mut current_channel: Option<u16> = None;
+    records
+        .iter()
+        .map(|line| {
+            let mut line = line.clone();
+            match &mut line.record {
+                MtxtRecord::ChannelDirective { channel } => current_channel = Some(*channel),
+                MtxtRecord::ControlChange { channel, .. } => {
+                    *channel = channel.or(current_channel);
+                }
+                _ => {}
+            }
+            line
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,4 +217,24 @@ mtxt 1.0
 
         assert_eq_records(input, transform, expected);
     }
+
+    #[test]
+    fn test_resolve_cc_channels() {
+        let input = r#"
+mtxt 1.0
+0.0 cc volume 0.5
+ch=2
+1.0 cc volume 0.6
+2.0 cc pan 0.1 ch=3
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc volume 0.5
+ch=2
+1.0 cc volume 0.6 ch=2
+2.0 cc pan 0.1 ch=3
+"#;
+
+        assert_eq_records(input, resolve_cc_channels, expected);
+    }
 }
@@ -0,0 +1,475 @@
+use super::DEFAULT_VELOCITY;
+use super::merge::pair_note_events;
+use crate::BeatTime;
+use crate::types::note::{Note, NoteTarget};
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::{BTreeMap, HashMap};
+
+/// Order in which the notes of a chord are played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ArpeggioPattern {
+    /// Lowest note first
+    Up,
+    /// Highest note first
+    Down,
+    /// Alternates between up and down on consecutive chords of a channel
+    UpDown,
+    /// Shuffled order, deterministic for a given seed
+    Random(u64),
+}
+
+/// Where the arpeggiated notes end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ArpeggioGate {
+    /// Every note is held until the original chord ended
+    #[default]
+    ChordEnd,
+    /// Every note ends where the next note of the arpeggio starts
+    NextNote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct ArpeggioConfig {
+    pub interval: BeatTime,
+    pub pattern: ArpeggioPattern,
+    pub gate: ArpeggioGate,
+    /// Notes that don't fit in the chord restart the pattern from the chord start
+    /// instead of being dropped
+    pub wrap: bool,
+}
+
+pub(super) struct ChordNote {
+    pub(super) idx: usize,
+    // position inside an expanded alias
+    pub(super) sub: usize,
+    pub(super) end: BeatTime,
+    pub(super) velocity: f32,
+    pub(super) note: Note,
+}
+
+pub(super) struct PlacedNote {
+    pub(super) time: BeatTime,
+    pub(super) duration: BeatTime,
+    pub(super) note: Note,
+    // None keeps the velocity of the record
+    pub(super) velocity: Option<f32>,
+}
+
+pub(super) fn pitch_value(note: &Note) -> f32 {
+    note.absolute_semitone() as f32 * 100.0 + note.cents
+}
+
+// Note records and paired note ons grouped by (channel, onset), aliases expanded to their notes
+pub(super) fn collect_chords(
+    records: &[MtxtRecordLine],
+) -> BTreeMap<(u16, BeatTime), Vec<ChordNote>> {
+    let pairs = pair_note_events(records);
+    let mut current_channel: u16 = 0;
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut current_velocity = DEFAULT_VELOCITY;
+    let mut aliases: HashMap<&str, &[Note]> = HashMap::new();
+    let mut chords: BTreeMap<(u16, BeatTime), Vec<ChordNote>> = BTreeMap::new();
+
+    for (idx, line) in records.iter().enumerate() {
+        let (time, note, end, velocity, channel) = match &line.record {
+            MtxtRecord::ChannelDirective { channel } => {
+                current_channel = *channel;
+                continue;
+            }
+            MtxtRecord::DurationDirective { duration } => {
+                current_duration = *duration;
+                continue;
+            }
+            MtxtRecord::VelocityDirective { velocity } => {
+                current_velocity = *velocity;
+                continue;
+            }
+            MtxtRecord::AliasDef { value } => {
+                aliases.insert(value.name.as_str(), &value.notes);
+                continue;
+            }
+            MtxtRecord::Note {
+                time,
+                note,
+                duration,
+                velocity,
+                channel,
+                ..
+            } => (
+                *time,
+                note,
+                *time + duration.unwrap_or(current_duration),
+                velocity,
+                channel,
+            ),
+            MtxtRecord::NoteOn {
+                time,
+                note,
+                velocity,
+                channel,
+            } => match pairs.get(&idx).and_then(|off| records[*off].record.time()) {
+                Some(end) => (*time, note, end, velocity, channel),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let notes: Vec<Note> = match note {
+            NoteTarget::Note(n) => vec![n.clone()],
+            NoteTarget::AliasKey(name) => aliases
+                .get(name.as_str())
+                .map(|notes| notes.to_vec())
+                .unwrap_or_default(),
+            NoteTarget::Alias(def) => def.notes.clone(),
+            NoteTarget::Range { low, high } => Note::chromatic_range(low, high),
+        };
+        let chord = chords
+            .entry((channel.unwrap_or(current_channel), time))
+            .or_default();
+        for (sub, note) in notes.into_iter().enumerate() {
+            chord.push(ChordNote {
+                idx,
+                sub,
+                end,
+                velocity: velocity.unwrap_or(current_velocity),
+                note,
+            });
+        }
+    }
+
+    chords
+}
+
+/// Staggers the notes of every chord (notes starting together on a channel)
+/// by `interval`, in the order given by the pattern.
+/// Paired note on / off events take part like notes, unpaired ones are kept as they are.
+/// Aliases played as chords are expanded into their notes.
+pub fn transform(records: &[MtxtRecordLine], config: &ArpeggioConfig) -> Vec<MtxtRecordLine> {
+    if config.interval == BeatTime::zero() {
+        return records.to_vec();
+    }
+
+    let mut rng = match config.pattern {
+        ArpeggioPattern::Random(seed) => Some(StdRng::seed_from_u64(seed)),
+        _ => None,
+    };
+    let mut chords_on_channel: HashMap<u16, usize> = HashMap::new();
+    // new position of every chord note, None if dropped
+    let mut placed: HashMap<(usize, usize), Option<PlacedNote>> = HashMap::new();
+
+    for ((channel, start), mut chord) in collect_chords(records) {
+        if chord.len() < 2 {
+            continue;
+        }
+
+        chord.sort_by(|a, b| pitch_value(&a.note).total_cmp(&pitch_value(&b.note)));
+        let chord_count = chords_on_channel.entry(channel).or_default();
+        match config.pattern {
+            ArpeggioPattern::Up => {}
+            ArpeggioPattern::Down => chord.reverse(),
+            ArpeggioPattern::UpDown => {
+                if *chord_count % 2 == 1 {
+                    chord.reverse();
+                }
+            }
+            ArpeggioPattern::Random(_) => {
+                if let Some(rng) = rng.as_mut() {
+                    chord.shuffle(rng);
+                }
+            }
+        }
+        *chord_count += 1;
+
+        let chord_end = chord.iter().map(|n| n.end).max().unwrap_or(start);
+        let chord_len = chord.len() as u64;
+        // number of onsets fitting inside the chord
+        let mut steps = 1;
+        while steps < chord_len && start + config.interval.mul_ratio(steps, 1) < chord_end {
+            steps += 1;
+        }
+
+        for (position, note) in chord.into_iter().enumerate() {
+            let position = position as u64;
+            if !config.wrap && position >= steps {
+                placed.insert((note.idx, note.sub), None);
+                continue;
+            }
+            let step = position % steps;
+            let onset = start + config.interval.mul_ratio(step, 1);
+            let is_last_step = step + 1 == steps || position + 1 == chord_len;
+            let end = match config.gate {
+                ArpeggioGate::NextNote if !is_last_step => onset + config.interval,
+                _ => chord_end,
+            };
+            placed.insert(
+                (note.idx, note.sub),
+                Some(PlacedNote {
+                    time: onset,
+                    duration: end - onset,
+                    note: note.note,
+                    velocity: None,
+                }),
+            );
+        }
+    }
+
+    place_notes(records, &placed)
+}
+
+// Replaces the chord notes with their new positions, aliases are written as one record
+// per note. Paired note ons are written as note ons, their note offs move to the new ends.
+pub(super) fn place_notes(
+    records: &[MtxtRecordLine],
+    placed: &HashMap<(usize, usize), Option<PlacedNote>>,
+) -> Vec<MtxtRecordLine> {
+    let pair_ends: HashMap<usize, usize> = pair_note_events(records)
+        .into_iter()
+        .map(|(on, off)| (off, on))
+        .collect();
+    let mut new_records = Vec::with_capacity(records.len());
+    for (idx, line) in records.iter().enumerate() {
+        // the note on of a note off, whose entries say where the offs go
+        let on_idx = pair_ends
+            .get(&idx)
+            .copied()
+            .filter(|on| placed.contains_key(&(*on, 0)));
+        if on_idx.is_none() && !placed.contains_key(&(idx, 0)) {
+            new_records.push(line.clone());
+            continue;
+        }
+
+        let mut comment = line.comment.clone();
+        let mut sub = 0;
+        while let Some(entry) = placed.get(&(on_idx.unwrap_or(idx), sub)) {
+            if let Some(placed_note) = entry {
+                let note = NoteTarget::Note(placed_note.note.clone());
+                let record = match &line.record {
+                    MtxtRecord::Note {
+                        velocity,
+                        off_velocity,
+                        channel,
+                        ..
+                    } => MtxtRecord::Note {
+                        time: placed_note.time,
+                        note,
+                        duration: Some(placed_note.duration),
+                        velocity: placed_note.velocity.or(*velocity),
+                        off_velocity: *off_velocity,
+                        channel: *channel,
+                    },
+                    MtxtRecord::NoteOn {
+                        velocity, channel, ..
+                    } => MtxtRecord::NoteOn {
+                        time: placed_note.time,
+                        note,
+                        velocity: placed_note.velocity.or(*velocity),
+                        channel: *channel,
+                    },
+                    MtxtRecord::NoteOff {
+                        off_velocity,
+                        channel,
+                        ..
+                    } => MtxtRecord::NoteOff {
+                        time: placed_note.time + placed_note.duration,
+                        note,
+                        off_velocity: *off_velocity,
+                        channel: *channel,
+                    },
+                    record => record.clone(),
+                };
+                new_records.push(MtxtRecordLine {
+                    record,
+                    comment: comment.take(),
+                });
+            }
+            sub += 1;
+        }
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use crate::util::assert_eq_records;
+
+    fn config(pattern: ArpeggioPattern, gate: ArpeggioGate, wrap: bool) -> ArpeggioConfig {
+        ArpeggioConfig {
+            interval: BeatTime::from_parts(0, 0.25),
+            pattern,
+            gate,
+            wrap,
+        }
+    }
+
+    const TRIADS: &str = r#"
+mtxt 1.0
+0.0 note E4
+0.0 note C4
+0.0 note G4
+1.0 note A3 dur=2
+1.0 note C4 dur=2
+1.0 note E4 dur=2
+3.0 note D4
+"#;
+
+    #[test]
+    fn test_arpeggiate_up() {
+        let expected = r#"
+mtxt 1.0
+0.25 note E4 dur=0.75
+0.0 note C4 dur=1.0
+0.5 note G4 dur=0.5
+1.0 note A3 dur=2.0
+1.25 note C4 dur=1.75
+1.5 note E4 dur=1.5
+3.0 note D4
+"#;
+        assert_eq_records(
+            TRIADS,
+            |r| {
+                transform(
+                    r,
+                    &config(ArpeggioPattern::Up, ArpeggioGate::ChordEnd, false),
+                )
+            },
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_arpeggiate_up_down_next_note_gate() {
+        let expected = r#"
+mtxt 1.0
+0.25 note E4 dur=0.25
+0.0 note C4 dur=0.25
+0.5 note G4 dur=0.5
+1.5 note A3 dur=1.5
+1.25 note C4 dur=0.25
+1.0 note E4 dur=0.25
+3.0 note D4
+"#;
+        assert_eq_records(
+            TRIADS,
+            |r| {
+                transform(
+                    r,
+                    &config(ArpeggioPattern::UpDown, ArpeggioGate::NextNote, false),
+                )
+            },
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_arpeggiate_note_on_off() {
+        let input = r#"
+mtxt 1.0
+alias CE C4,E4
+0.0 on CE
+0.0 note G4
+1.0 off CE
+2.0 on D4
+"#;
+        let expected = r#"
+mtxt 1.0
+alias CE C4,E4
+0.0 on C4
+0.25 on E4
+0.5 note G4 dur=0.5
+0.25 off C4
+0.5 off E4
+2.0 on D4
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &config(ArpeggioPattern::Up, ArpeggioGate::NextNote, false),
+                )
+            },
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_arpeggiate_five_notes() {
+        let input = r#"
+mtxt 1.0
+alias Ninth C3,E3,G3,Bb3,D4
+ch=2
+0.0 note Ninth dur=0.75 vel=0.8
+"#;
+        let truncated = r#"
+mtxt 1.0
+alias Ninth C3,E3,G3,Bb3,D4
+ch=2
+0.5 note G3 dur=0.25 vel=0.8
+0.25 note Bb3 dur=0.5 vel=0.8
+0.0 note D4 dur=0.75 vel=0.8
+"#;
+        let wrapped = r#"
+mtxt 1.0
+alias Ninth C3,E3,G3,Bb3,D4
+ch=2
+0.25 note C3 dur=0.5 vel=0.8
+0.0 note E3 dur=0.75 vel=0.8
+0.5 note G3 dur=0.25 vel=0.8
+0.25 note Bb3 dur=0.5 vel=0.8
+0.0 note D4 dur=0.75 vel=0.8
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &config(ArpeggioPattern::Down, ArpeggioGate::ChordEnd, false),
+                )
+            },
+            truncated,
+        );
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &config(ArpeggioPattern::Down, ArpeggioGate::ChordEnd, true),
+                )
+            },
+            wrapped,
+        );
+    }
+
+    #[test]
+    fn test_arpeggiate_random_is_deterministic() {
+        let records = parse_mtxt(TRIADS).unwrap().records;
+        let random = |seed| {
+            transform(
+                &records,
+                &config(ArpeggioPattern::Random(seed), ArpeggioGate::ChordEnd, false),
+            )
+        };
+        assert_eq!(random(7), random(7));
+
+        let mut onsets: Vec<String> = random(7)
+            .iter()
+            .filter_map(|line| line.record.time())
+            .take(3)
+            .map(|time| time.to_string())
+            .collect();
+        onsets.sort();
+        assert_eq!(onsets, ["0.0", "0.25", "0.5"]);
+    }
+}
@@ -0,0 +1,73 @@
+use super::legato::find_next_onsets;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+const PERCENT_PRECISION: u64 = 10_000;
+
+/// Sets every note's duration to `percent` of the space available to it:
+/// the time until the next note on its channel, or until the next beat for the last note.
+/// `percent` is clamped to `[0.01, 1.0]` (0.5 = staccato, 1.0 = legato).
+pub fn transform(records: &[MtxtRecordLine], percent: f32) -> Vec<MtxtRecordLine> {
+    let percent = percent.clamp(0.01, 1.0);
+    let numerator = (percent as f64 * PERCENT_PRECISION as f64).round() as u64;
+
+    // First pass: find the space available to every note
+    let next_onsets = find_next_onsets(records);
+
+    // Second pass: update the durations
+    let mut new_records = Vec::with_capacity(records.len());
+    for (line, next_onset) in records.iter().zip(next_onsets) {
+        let mut new_line = line.clone();
+        if let MtxtRecord::Note { time, duration, .. } = &mut new_line.record {
+            let next = next_onset
+                .unwrap_or_else(|| BeatTime::from_parts(time.whole_beats() as u32 + 1, 0.0));
+            let space = next - *time;
+            *duration = Some(space.mul_ratio(numerator, PERCENT_PRECISION));
+        }
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_note_length_percent() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4 dur=0.25
+1.0 note E4 dur=0.25
+2.0 note D4 dur=2 ch=1
+3.0 note F4 dur=0.1
+3.5 note G4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 dur=1
+1.0 note E4 dur=1
+2.0 note D4 dur=0.5 ch=1
+3.0 note F4 dur=0.25
+3.5 note G4 dur=0.25
+"#;
+        assert_eq_records(input, |r| transform(r, 0.5), expected);
+    }
+
+    #[test]
+    fn test_note_length_percent_clamped() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4
+2.0 note D4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 dur=1
+2.0 note D4 dur=1
+"#;
+        assert_eq_records(input, |r| transform(r, 1.5), expected);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use crate::{BeatTime, TempoMap};
+
+/// Re-times the records for a constant tempo of `bpm` so they sound the same as with the
+/// original tempo map, tempo transitions included. Every time is converted to wall-clock time
+/// and back, note durations and CC transition times through their end points.
+/// The tempo changes are replaced by a single tempo at beat 0.
+pub fn transform(records: &[MtxtRecordLine], bpm: f32) -> Vec<MtxtRecordLine> {
+    let tempo_records: Vec<MtxtRecord> = records.iter().map(|line| line.record.clone()).collect();
+    let tempo_map = TempoMap::new(&tempo_records);
+    let bpm = bpm as f64;
+    let convert = |beat: BeatTime| BeatTime::from_micros(tempo_map.micros_at(beat), bpm);
+    let convert_length =
+        |start: BeatTime, length: BeatTime| convert(start + length) - convert(start);
+
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut tempo_placed = false;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        let mut new_line = line.clone();
+        match &mut new_line.record {
+            MtxtRecord::DurationDirective { duration } => {
+                current_duration = *duration;
+                // the directive can't follow the tempo map, the notes get their own durations
+                continue;
+            }
+            MtxtRecord::Tempo { .. } => continue,
+            MtxtRecord::Note { time, duration, .. } => {
+                *duration = Some(convert_length(*time, duration.unwrap_or(current_duration)));
+            }
+            MtxtRecord::ControlChange {
+                time,
+                transition_time: Some(transition_time),
+                ..
+            } => {
+                // the transition ends at the event time
+                *transition_time = convert(*time) - convert(*time - *transition_time);
+            }
+            _ => {}
+        }
+
+        if let Some(time) = new_line.record.time() {
+            if !tempo_placed {
+                new_records.push(MtxtRecordLine::new(MtxtRecord::Tempo {
+                    time: BeatTime::zero(),
+                    bpm: bpm as f32,
+                    transition_curve: None,
+                    transition_time: None,
+                    transition_interval: None,
+                }));
+                tempo_placed = true;
+            }
+            new_line.record.set_time(convert(time));
+        }
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MtxtFile, parse_mtxt};
+
+    // End of the last event in microseconds
+    fn total_micros(records: &[MtxtRecordLine]) -> u64 {
+        MtxtFile::from_records(records.to_vec())
+            .get_output_records()
+            .iter()
+            .map(|record| record.time())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bake_tempo() {
+        let input = r#"mtxt 1.0
+dur=1
+0.0 tempo 120
+0.0 note C4
+1.5 note E4
+2.0 tempo 60
+3.0 note G4 dur=2
+4.0 tempo 90 transition_time=1
+5.0 cc volume 0.5 transition_time=0.5
+6.0 note C5
+0.0 cc volume 1.0
+"#;
+        let records = parse_mtxt(input).unwrap().records;
+        let baked = transform(&records, 100.0);
+        // G4 spans the transition from 60 to 90 BPM
+        let expected = r#"mtxt 1.0
+0.0 tempo 100.0
+0.0 note C4 dur=0.83333
+1.25 note E4 dur=1.25
+3.33333 note G4 dur=2.462665
+"#;
+        let text = MtxtFile::from_records(baked.clone()).to_string();
+        assert!(text.starts_with(expected), "{}", text);
+        assert_eq!(
+            baked
+                .iter()
+                .filter(|line| matches!(line.record, MtxtRecord::Tempo { .. }))
+                .count(),
+            1
+        );
+
+        let original = total_micros(&records);
+        let difference = original.abs_diff(total_micros(&baked));
+        assert!(difference < 100, "{} us of {}", difference, original);
+    }
+
+    #[test]
+    fn test_bake_constant_tempo() {
+        let input = r#"mtxt 1.0
+1.0 note C4 dur=0.5
+"#;
+        let records = parse_mtxt(input).unwrap().records;
+        let expected = "mtxt 1.0\n0.0 tempo 60.0\n0.5 note C4 dur=0.25\n";
+        assert_eq!(
+            MtxtFile::from_records(transform(&records, 60.0)).to_string(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use super::remap;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Channel of a channel-scoped record, CCs and meta without a channel are global
+pub(crate) fn record_channel(record: &MtxtRecord, current_channel: Option<u16>) -> Option<u16> {
+    match record {
+        MtxtRecord::Note { channel, .. }
+        | MtxtRecord::NoteOn { channel, .. }
+        | MtxtRecord::NoteOff { channel, .. }
+        | MtxtRecord::Voice { channel, .. } => channel.or(current_channel),
+        MtxtRecord::ControlChange { channel, .. } | MtxtRecord::Meta { channel, .. } => *channel,
+        MtxtRecord::ChannelDirective { channel } => Some(*channel),
+        _ => None,
+    }
+}
+
+/// Channels with at least one note
+pub fn note_channels(records: &[MtxtRecordLine]) -> BTreeSet<u16> {
+    let mut channels = BTreeSet::new();
+    let mut current_channel: u16 = 0;
+    for line in records {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::Note { channel, .. } | MtxtRecord::NoteOn { channel, .. } => {
+                channels.insert(channel.unwrap_or(current_channel));
+            }
+            _ => {}
+        }
+    }
+    channels
+}
+
+/// Drops the records of channels without notes: voices, CCs, meta, note offs
+/// and channel directives. Global records like tempo and global meta are kept.
+pub fn remove_empty(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let used = note_channels(records);
+    let mut current_channel: u16 = 0;
+
+    records
+        .iter()
+        .filter(|line| {
+            if let MtxtRecord::ChannelDirective { channel } = &line.record {
+                current_channel = *channel;
+            }
+            record_channel(&line.record, Some(current_channel)).is_none_or(|ch| used.contains(&ch))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Drops the conductor records: tempo, time signatures and meta without a channel.
+/// Channel filters keep them, use this when only the channel data is wanted.
+pub fn remove_conductor(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    records
+        .iter()
+        .filter(|line| {
+            !matches!(
+                line.record,
+                MtxtRecord::Tempo { .. }
+                    | MtxtRecord::TimeSignature { .. }
+                    | MtxtRecord::GlobalMeta { .. }
+                    | MtxtRecord::Meta { channel: None, .. }
+            )
+        })
+        .cloned()
+        .collect()
+}
+
+/// Mapping of the channels in use to 1..n, in ascending order.
+/// Print it to update external references to the channels after `compact`.
+pub fn compaction_map(records: &[MtxtRecordLine]) -> BTreeMap<u16, u16> {
+    let mut channels = BTreeSet::new();
+    let mut current_channel: u16 = 0;
+    for line in records {
+        if let MtxtRecord::ChannelDirective { channel } = &line.record {
+            current_channel = *channel;
+        }
+        channels.extend(record_channel(&line.record, Some(current_channel)));
+    }
+    channels.into_iter().zip(1..).collect()
+}
+
+/// Renumbers the channels in use to a contiguous 1..n range, see [`compaction_map`].
+/// Notes relying on the default channel 0 get a channel directive.
+pub fn compact(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let map: HashMap<u16, u16> = compaction_map(records).into_iter().collect();
+    let mut new_records = remap::transform(records, &map);
+
+    // records before the first channel directive are on channel 0, which remap does not see
+    let first_implicit = new_records
+        .iter()
+        .take_while(|line| !matches!(line.record, MtxtRecord::ChannelDirective { .. }))
+        .position(|line| match &line.record {
+            MtxtRecord::Note { channel, .. }
+            | MtxtRecord::NoteOn { channel, .. }
+            | MtxtRecord::NoteOff { channel, .. }
+            | MtxtRecord::Voice { channel, .. } => channel.is_none(),
+            _ => false,
+        });
+    if let (Some(idx), Some(channel)) = (first_implicit, map.get(&0)) {
+        new_records.insert(
+            idx,
+            MtxtRecordLine::new(MtxtRecord::ChannelDirective { channel: *channel }),
+        );
+    }
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use crate::util::assert_eq_records;
+
+    const INPUT: &str = r#"
+mtxt 1.0
+meta global title Sparse
+0.0 tempo 120
+0.0 voice bass
+0.0 note C2
+ch=4
+0.0 voice strings
+0.0 cc volume 0.5
+0.0 meta ch=4 name Pad
+ch=9
+0.0 voice piano
+1.0 note E4
+1.0 cc sustain 1.0 ch=12
+1.0 cc volume 0.8
+2.0 off E4 ch=4
+"#;
+
+    #[test]
+    fn test_remove_empty_channels() {
+        let expected = r#"
+mtxt 1.0
+meta global title Sparse
+0.0 tempo 120
+0.0 voice bass
+0.0 note C2
+0.0 cc volume 0.5
+ch=9
+0.0 voice piano
+1.0 note E4
+1.0 cc volume 0.8
+"#;
+        let records = parse_mtxt(INPUT).unwrap().records;
+        assert_eq!(note_channels(&records), BTreeSet::from([0, 9]));
+        assert_eq_records(INPUT, remove_empty, expected);
+    }
+
+    #[test]
+    fn test_remove_conductor() {
+        let expected = r#"
+mtxt 1.0
+0.0 voice bass
+0.0 note C2
+ch=4
+0.0 voice strings
+0.0 cc volume 0.5
+0.0 meta ch=4 name Pad
+ch=9
+0.0 voice piano
+1.0 note E4
+1.0 cc sustain 1.0 ch=12
+1.0 cc volume 0.8
+2.0 off E4 ch=4
+"#;
+        assert_eq_records(INPUT, remove_conductor, expected);
+    }
+
+    #[test]
+    fn test_compact_channels() {
+        let records = remove_empty(&parse_mtxt(INPUT).unwrap().records);
+        assert_eq!(compaction_map(&records), BTreeMap::from([(0, 1), (9, 2)]));
+
+        let expected = r#"
+mtxt 1.0
+meta global title Sparse
+0.0 tempo 120
+ch=1
+0.0 voice bass
+0.0 note C2
+0.0 cc volume 0.5
+ch=2
+0.0 voice piano
+1.0 note E4
+1.0 cc volume 0.8
+"#;
+        fn compact_non_empty(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+            compact(&remove_empty(records))
+        }
+        assert_eq_records(INPUT, compact_non_empty, expected);
+
+        let sparse = r#"
+mtxt 1.0
+1.0 note C4 ch=3
+2.0 cc pan 0.5 ch=7
+3.0 note D4 ch=15
+"#;
+        let compacted = r#"
+mtxt 1.0
+1.0 note C4 ch=1
+2.0 cc pan 0.5 ch=2
+3.0 note D4 ch=3
+"#;
+        assert_eq_records(sparse, compact, compacted);
+    }
+}
@@ -0,0 +1,301 @@
+use super::merge::pair_note_events;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum StateKey {
+    Tempo,
+    TimeSignature,
+    Voice(u16),
+    ControlChange(u16, String, Option<String>),
+    Tuning(String),
+}
+
+fn get_state_key(record: &MtxtRecord, current_channel: u16) -> Option<StateKey> {
+    match record {
+        MtxtRecord::Tempo { .. } => Some(StateKey::Tempo),
+        MtxtRecord::TimeSignature { .. } => Some(StateKey::TimeSignature),
+        MtxtRecord::Voice { channel, .. } => {
+            Some(StateKey::Voice(channel.unwrap_or(current_channel)))
+        }
+        MtxtRecord::ControlChange {
+            note,
+            controller,
+            channel,
+            ..
+        } => Some(StateKey::ControlChange(
+            channel.unwrap_or(current_channel),
+            controller.clone(),
+            note.as_ref().map(|n| n.to_string()),
+        )),
+        MtxtRecord::Tuning { target, .. } => Some(StateKey::Tuning(target.clone())),
+        _ => None,
+    }
+}
+
+// Indices of the most recent state records before `start` that need to be carried into the window
+fn find_state_records(records: &[MtxtRecordLine], start: BeatTime) -> HashSet<usize> {
+    let mut latest: HashMap<StateKey, (BeatTime, usize)> = HashMap::new();
+    let mut set_at_start: HashSet<StateKey> = HashSet::new();
+    let mut current_channel: u16 = 0;
+
+    for (idx, line) in records.iter().enumerate() {
+        if let MtxtRecord::ChannelDirective { channel } = &line.record {
+            current_channel = *channel;
+        }
+        let (Some(time), Some(key)) = (
+            line.record.time(),
+            get_state_key(&line.record, current_channel),
+        ) else {
+            continue;
+        };
+
+        if time == start {
+            set_at_start.insert(key);
+        } else if time < start {
+            match latest.get(&key) {
+                Some((t, _)) if *t > time => {}
+                _ => {
+                    latest.insert(key, (time, idx));
+                }
+            }
+        }
+    }
+
+    latest
+        .into_iter()
+        .filter(|(key, _)| !set_at_start.contains(key))
+        .map(|(_, (_, idx))| idx)
+        .collect()
+}
+
+// Start and end of a note sounding from `time` to `note_end` within `[start, end)`,
+// None if it is outside or crosses a boundary with `drop_partial`
+fn clip_span(
+    time: BeatTime,
+    note_end: BeatTime,
+    start: BeatTime,
+    end: BeatTime,
+    drop_partial: bool,
+) -> Option<(BeatTime, BeatTime)> {
+    if time >= end || (time < start && note_end <= start) {
+        return None;
+    }
+    let crosses = time < start || note_end > end;
+    if crosses && drop_partial {
+        return None;
+    }
+    Some((time.max(start), note_end.min(end)))
+}
+
+/// Keeps only the records within `[start, end)`.
+/// Notes crossing the boundaries are shortened, or dropped if `drop_partial` is set. Note on / off
+/// pairs are handled the same way: the on moves to `start`, the off to `end`, and both are
+/// dropped together. Unpaired note ons and offs are kept if they are within the range.
+/// The state (tempo, time signature, voice, cc, tuning) active at `start` is preserved.
+/// If `rebase` is set, `start` becomes the new beat 0.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    start: BeatTime,
+    end: BeatTime,
+    drop_partial: bool,
+    rebase: bool,
+) -> Vec<MtxtRecordLine> {
+    let state_records = find_state_records(records, start);
+    let pairs = pair_note_events(records);
+    let pair_ends: HashMap<usize, usize> = pairs.iter().map(|(on, off)| (*off, *on)).collect();
+    let pair_span = |on_idx: usize, off_idx: usize| {
+        let (Some(on_time), Some(off_time)) = (
+            records[on_idx].record.time(),
+            records[off_idx].record.time(),
+        ) else {
+            return None;
+        };
+        clip_span(on_time, off_time, start, end, drop_partial)
+    };
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for (idx, line) in records.iter().enumerate() {
+        if let MtxtRecord::DurationDirective { duration } = &line.record {
+            current_duration = *duration;
+        }
+
+        let Some(time) = line.record.time() else {
+            new_records.push(line.clone());
+            continue;
+        };
+
+        let mut new_line = line.clone();
+
+        if state_records.contains(&idx) {
+            new_line.record.set_time(start);
+            // the state was already reached before the window
+            match &mut new_line.record {
+                MtxtRecord::ControlChange {
+                    transition_time, ..
+                }
+                | MtxtRecord::Tempo {
+                    transition_time, ..
+                } => *transition_time = None,
+                _ => {}
+            }
+        } else if let MtxtRecord::Note { duration, .. } = &mut new_line.record {
+            let note_end = time + duration.unwrap_or(current_duration);
+            let Some((new_time, new_end)) = clip_span(time, note_end, start, end, drop_partial)
+            else {
+                continue;
+            };
+            if new_time != time || new_end != note_end {
+                *duration = Some(new_end - new_time);
+                new_line.record.set_time(new_time);
+            }
+        } else if let Some(off_idx) = pairs.get(&idx) {
+            let Some((new_time, _)) = pair_span(idx, *off_idx) else {
+                continue;
+            };
+            new_line.record.set_time(new_time);
+        } else if let Some(on_idx) = pair_ends.get(&idx) {
+            let Some((_, new_end)) = pair_span(*on_idx, idx) else {
+                continue;
+            };
+            new_line.record.set_time(new_end);
+        } else if time < start || time >= end {
+            continue;
+        }
+
+        if rebase && let Some(t) = new_line.record.time() {
+            new_line.record.set_time(t - start);
+        }
+
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    fn beat(s: &str) -> BeatTime {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_crop_boundaries() {
+        let input = r#"
+mtxt 1.0
+meta global title Song
+0.0 note C4 dur=1
+3.0 note D4 dur=2
+4.5 note E4 dur=1
+7.0 note F4 dur=2
+9.0 note G4 dur=1
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global title Song
+4.0 note D4 dur=1
+4.5 note E4 dur=1
+7.0 note F4 dur=1
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, beat("4.0"), beat("8.0"), false, false),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_crop_drop_partial_and_rebase() {
+        let input = r#"
+mtxt 1.0
+3.0 note D4 dur=2
+4.5 note E4 dur=1
+7.0 note F4 dur=2
+"#;
+        let expected = r#"
+mtxt 1.0
+0.5 note E4 dur=1
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, beat("4.0"), beat("8.0"), true, true),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_crop_keeps_state() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 100
+0.0 timesig 3/4
+ch=1
+0.0 voice piano
+1.0 cc volume 0.5
+2.0 tempo 140 transition_time=1.0
+3.0 cc volume 0.8
+4.0 timesig 4/4
+4.0 note C4
+6.0 tempo 90
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=1
+0.0 voice piano
+0.0 tempo 140
+0.0 cc volume 0.8
+0.0 timesig 4/4
+0.0 note C4
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, beat("4.0"), beat("6.0"), false, true),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_crop_note_on_off() {
+        let input = r#"
+mtxt 1.0
+0.0 on B3
+1.0 on C4
+2.0 off C4
+2.0 on E4
+2.0 off B3
+3.0 on D4
+4.0 off D4
+5.0 off E4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 on B3
+1.0 on C4
+2.0 off C4
+2.0 on E4
+2.0 off B3
+2.5 off E4
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, beat("1.0"), beat("2.5"), false, false),
+            expected,
+        );
+
+        let expected = r#"
+mtxt 1.0
+1.0 on C4
+2.0 off C4
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, beat("1.0"), beat("2.5"), true, false),
+            expected,
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::types::record::{MtxtRecord, MtxtRecordLine, VoiceList};
+use std::collections::HashMap;
+
+/// Drops ControlChange records repeating the last value of their (channel, controller, note),
+/// and Voice records repeating the last voice of their channel.
+/// A Reset clears the remembered state, so the next record is always kept.
+pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let mut last_cc: HashMap<(u16, String, Option<String>), f32> = HashMap::new();
+    let mut last_voice: HashMap<u16, VoiceList> = HashMap::new();
+    let mut current_channel: u16 = 0;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::ControlChange {
+                note,
+                controller,
+                value,
+                channel,
+                ..
+            } => {
+                let key = (
+                    channel.unwrap_or(current_channel),
+                    controller.clone(),
+                    note.as_ref().map(|n| n.to_string()),
+                );
+                if last_cc.insert(key, *value) == Some(*value) {
+                    continue;
+                }
+            }
+            MtxtRecord::Voice {
+                voices, channel, ..
+            } => {
+                let channel = channel.unwrap_or(current_channel);
+                if last_voice.insert(channel, voices.clone()).as_ref() == Some(voices) {
+                    continue;
+                }
+            }
+            MtxtRecord::Reset { target, .. } => {
+                if target == "all" {
+                    last_cc.clear();
+                    last_voice.clear();
+                } else if let Some(channel) = target
+                    .strip_prefix("ch=")
+                    .and_then(|ch| ch.parse::<u16>().ok())
+                {
+                    last_cc.retain(|(ch, _, _), _| *ch != channel);
+                    last_voice.remove(&channel);
+                }
+            }
+            _ => {}
+        }
+        new_records.push(line.clone());
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_dedup_stuck_controllers() {
+        let input = r#"
+mtxt 1.0
+0.0 voice piano
+0.0 cc volume 0.8
+0.0 cc sustain 1.0
+1.0 cc volume 0.8
+1.0 cc sustain 1.0
+1.0 voice piano
+2.0 cc volume 0.8
+2.0 cc volume 0.8 ch=1
+2.5 cc C4 pressure 0.5
+3.0 cc D4 pressure 0.5
+3.5 cc D4 pressure 0.5
+4.0 cc volume 0.6
+5.0 cc volume 0.6
+5.0 voice strings
+6.0 voice strings
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 voice piano
+0.0 cc volume 0.8
+0.0 cc sustain 1.0
+2.0 cc volume 0.8 ch=1
+2.5 cc C4 pressure 0.5
+3.0 cc D4 pressure 0.5
+4.0 cc volume 0.6
+5.0 voice strings
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+
+    #[test]
+    fn test_dedup_after_reset() {
+        let input = r#"
+mtxt 1.0
+0.0 cc volume 0.8 ch=1
+0.0 cc volume 0.8 ch=2
+1.0 reset ch=1
+2.0 cc volume 0.8 ch=1
+2.0 cc volume 0.8 ch=2
+3.0 reset all
+4.0 cc volume 0.8 ch=2
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc volume 0.8 ch=1
+0.0 cc volume 0.8 ch=2
+1.0 reset ch=1
+2.0 cc volume 0.8 ch=1
+3.0 reset all
+4.0 cc volume 0.8 ch=2
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+}
@@ -0,0 +1,295 @@
+use super::invert::{find_exempt_aliases, note_target_and_channel};
+use super::transpose::transpose_notes;
+use crate::types::key::KeySignature;
+use crate::types::note::{Note, NoteTarget, SpellingPreference};
+use crate::types::pitch::{NATURAL_SEMITONES, PitchClass};
+use crate::types::record::{AliasDefinition, MtxtRecord, MtxtRecordLine};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct DiatonicTranspose {
+    /// Number of scale degrees, negative moves down
+    pub degrees: i32,
+    /// Key of the scale, the `key` global meta of the file if `None`
+    pub key: Option<KeySignature>,
+}
+
+// Position of the note name in C D E F G A B
+fn letter(pitch_class: PitchClass) -> i32 {
+    pitch_class.letter() as i32
+}
+
+// Note name with a single accidental, None for double sharps / flats
+fn spell(letter: i32, accidental: i32) -> Option<PitchClass> {
+    if accidental.abs() > 1 {
+        return None;
+    }
+    PitchClass::from_letter(letter as u8, accidental as i8)
+}
+
+// Signed distance between two semitones, between -6 and 5
+fn semitone_offset(from: i32, to: i32) -> i32 {
+    (to - from + 6).rem_euclid(12) - 6
+}
+
+struct DiatonicShifter {
+    tonic_letter: i32,
+    scale: [i32; 7],
+    degrees: i32,
+    key: KeySignature,
+}
+
+impl DiatonicShifter {
+    fn new(key: &KeySignature, degrees: i32) -> Self {
+        let tonic = key.tonic.to_semitone() as i32;
+        Self {
+            tonic_letter: letter(key.tonic),
+            // semitones of the scale degrees above C
+            scale: key.scale_semitones().map(|step| tonic + step as i32),
+            degrees,
+            key: *key,
+        }
+    }
+
+    // The degree of a note is given by its name, chromatic notes keep their accidental
+    // relative to the scale tone of the same name (e.g. C# in C major is degree 1 raised)
+    fn shift(&self, note: &Note) -> Note {
+        let degree = (letter(note.pitch_class) - self.tonic_letter).rem_euclid(7);
+
+        let target = degree + self.degrees;
+        let target_degree = target.rem_euclid(7);
+        let octaves = target.div_euclid(7);
+        let delta = self.scale[target_degree as usize] - self.scale[degree as usize] + octaves * 12;
+        let shifted = transpose_notes(
+            std::slice::from_ref(note),
+            delta,
+            SpellingPreference::Key(self.key),
+        )
+        .remove(0);
+        let new_semitone = shifted.pitch_class.to_semitone() as i32;
+
+        let new_letter = (self.tonic_letter + target_degree).rem_euclid(7);
+        let accidental =
+            semitone_offset(NATURAL_SEMITONES[new_letter as usize] as i32, new_semitone);
+        match spell(new_letter, accidental) {
+            Some(pitch_class) => {
+                Note::spelled(shifted.absolute_semitone(), pitch_class, note.cents)
+            }
+            None => shifted,
+        }
+    }
+
+    fn shift_target(
+        &self,
+        target: &NoteTarget,
+        alias_map: &HashMap<usize, Rc<AliasDefinition>>,
+    ) -> NoteTarget {
+        match target {
+            NoteTarget::Note(n) => NoteTarget::Note(self.shift(n)),
+            NoteTarget::AliasKey(k) => NoteTarget::AliasKey(k.clone()),
+            NoteTarget::Alias(rc) => {
+                let ptr = Rc::as_ptr(rc) as usize;
+                NoteTarget::Alias(alias_map.get(&ptr).cloned().unwrap_or_else(|| rc.clone()))
+            }
+            NoteTarget::Range { low, high } => NoteTarget::Range {
+                low: self.shift(low),
+                high: self.shift(high),
+            },
+        }
+    }
+}
+
+/// Moves every note by a number of scale degrees of the key, keeping it in the key
+/// (e.g. one degree up in C major turns E into F and B into C).
+/// Notes outside of the scale keep their accidental relative to the scale tone with
+/// the same name, so the spelling of the input matters: C# in C major goes to D#, Db to Eb.
+/// Notes on `preserve_channels` (usually drums) are left untouched.
+/// Without a key in the config or in the `key` global meta the records are returned unchanged.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    config: &DiatonicTranspose,
+    preserve_channels: &HashSet<u16>,
+) -> Vec<MtxtRecordLine> {
+    let key = config.key.or_else(|| {
+        records.iter().find_map(|line| match &line.record {
+            MtxtRecord::GlobalMeta { meta_type, value } if meta_type == "key" => value.parse().ok(),
+            _ => None,
+        })
+    });
+    let Some(key) = key else {
+        return records.to_vec();
+    };
+    if config.degrees == 0 {
+        return records.to_vec();
+    }
+
+    let shifter = DiatonicShifter::new(&key, config.degrees);
+    let preserved_aliases = find_exempt_aliases(records, preserve_channels);
+    let mut alias_map: HashMap<usize, Rc<AliasDefinition>> = HashMap::new();
+    let mut current_channel: u16 = 0;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        let mut new_line = line.clone();
+        let preserved = match note_target_and_channel(&line.record) {
+            Some((_, channel)) => preserve_channels.contains(&channel.unwrap_or(current_channel)),
+            None => false,
+        };
+
+        match &mut new_line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::AliasDef { value } if !preserved_aliases.contains(&value.name) => {
+                let new_def = Rc::new(AliasDefinition {
+                    name: value.name.clone(),
+                    notes: value.notes.iter().map(|n| shifter.shift(n)).collect(),
+                });
+                alias_map.insert(Rc::as_ptr(value) as usize, new_def.clone());
+                *value = new_def;
+            }
+            MtxtRecord::Note { note, .. }
+            | MtxtRecord::NoteOn { note, .. }
+            | MtxtRecord::NoteOff { note, .. }
+            | MtxtRecord::ControlChange {
+                note: Some(note), ..
+            } if !preserved => *note = shifter.shift_target(note, &alias_map),
+            _ => {}
+        }
+
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    const FROM_META: DiatonicTranspose = DiatonicTranspose {
+        degrees: 1,
+        key: None,
+    };
+
+    fn config(degrees: i32, key: &str) -> DiatonicTranspose {
+        DiatonicTranspose {
+            degrees,
+            key: Some(key.parse().unwrap()),
+        }
+    }
+
+    fn shift(note: &str, degrees: i32, key: &str) -> String {
+        DiatonicShifter::new(&key.parse().unwrap(), degrees)
+            .shift(&note.parse().unwrap())
+            .to_string()
+    }
+
+    fn shift_all(notes: &str, degrees: i32, key: &str) -> String {
+        notes
+            .split_whitespace()
+            .map(|note| shift(note, degrees, key))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_major_scales() {
+        assert_eq!(
+            shift_all("C4 D4 E4 F4 G4 A4 B4 C5", 1, "C major"),
+            "D4 E4 F4 G4 A4 B4 C5 D5"
+        );
+        assert_eq!(
+            shift_all("D4 E4 F#4 G4 A4 B4 C#5", 1, "D major"),
+            "E4 F#4 G4 A4 B4 C#5 D5"
+        );
+        assert_eq!(
+            shift_all("Bb3 C4 D4 Eb4 F4 G4 A4", 2, "Bb major"),
+            "D4 Eb4 F4 G4 A4 Bb4 C5"
+        );
+        assert_eq!(shift_all("C4 E4 G4", -1, "C major"), "B3 D4 F4");
+        assert_eq!(shift_all("C4 E4 G4", 7, "C major"), "C5 E5 G5");
+        assert_eq!(shift_all("C4 E4 G4", -9, "C major"), "A2 C3 E3");
+        assert_eq!(shift_all("F#4 A4 C#5", 3, "E major"), "B4 D#5 F#5");
+    }
+
+    #[test]
+    fn test_minor_scales() {
+        assert_eq!(
+            shift_all("A3 B3 C4 D4 E4 F4 G4", 1, "A minor"),
+            "B3 C4 D4 E4 F4 G4 A4"
+        );
+        assert_eq!(
+            shift_all("C4 D4 Eb4 F4 G4 Ab4 Bb4", 2, "C minor"),
+            "Eb4 F4 G4 Ab4 Bb4 C5 D5"
+        );
+        assert_eq!(shift_all("E4 G4 B4", -2, "E minor"), "C4 E4 G4");
+        assert_eq!(shift_all("F#4 A4 C#5", 1, "F# minor"), "G#4 B4 D5");
+    }
+
+    #[test]
+    fn test_chromatic_passing_tones() {
+        // raised and lowered scale tones keep their accidental
+        assert_eq!(shift("C#4", 1, "C major"), "D#4");
+        assert_eq!(shift("Db4", 1, "C major"), "Eb4");
+        assert_eq!(shift("F#4", 1, "C major"), "G#4");
+        assert_eq!(shift("Bb4", -1, "C major"), "Ab4");
+        // the raised leading tone of harmonic minor
+        assert_eq!(shift("G#4", 1, "A minor"), "A#4");
+        assert_eq!(shift("G#4", -2, "A minor"), "E#4");
+        // a natural outside of a sharp key is a lowered scale tone
+        assert_eq!(shift("F4", 1, "D major"), "Gb4");
+        assert_eq!(shift("C4", 2, "D major"), "Eb4");
+        // double sharps fall back to the enharmonic spelling
+        assert_eq!(shift("E#4", 2, "E major"), "A4");
+        // cents are kept
+        assert_eq!(shift("E4+25", 1, "C major"), "F4+25");
+    }
+
+    #[test]
+    fn test_diatonic_transform() {
+        let input = r#"
+mtxt 1.0
+meta global key G major
+alias G G3,B3,D4
+alias kick C2
+1.0 note G4
+2.0 note G
+3.0 note C2 ch=9
+3.0 note kick ch=9
+4.0 cc A4 volume 0.5
+5.0 note C5-E5
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global key G major
+alias G A3,C4,E4
+alias kick C2
+1.0 note A4
+2.0 note G
+3.0 note C2 ch=9
+3.0 note kick ch=9
+4.0 cc B4 volume 0.5
+5.0 note D5-F#5
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &FROM_META, &HashSet::from([9])),
+            expected,
+        );
+        // an explicit key overrides the meta
+        assert_eq_records(
+            "\nmtxt 1.0\nmeta global key G major\n1.0 note F4\n",
+            |r| transform(r, &config(1, "F major"), &HashSet::new()),
+            "\nmtxt 1.0\nmeta global key G major\n1.0 note G4\n",
+        );
+        // without a key nothing changes
+        assert_eq_records(
+            "\nmtxt 1.0\n1.0 note F4\n",
+            |r| transform(r, &FROM_META, &HashSet::new()),
+            "\nmtxt 1.0\n1.0 note F4\n",
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use super::DEFAULT_VELOCITY;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct EchoConfig {
+    /// Time between consecutive echoes
+    pub delay: BeatTime,
+    pub repeats: u32,
+    /// Velocity multiplier applied on every repeat
+    pub decay: f32,
+    /// Channel of the echoes, the channel of the original note if `None`
+    pub channel: Option<u16>,
+}
+
+/// Adds `repeats` delayed copies after every note, with velocities multiplied by `decay^k`.
+/// Each note is directly followed by its echoes. CC and other records are not echoed.
+pub fn transform(records: &[MtxtRecordLine], config: &EchoConfig) -> Vec<MtxtRecordLine> {
+    if config.repeats == 0 {
+        return records.to_vec();
+    }
+
+    let mut current_velocity = DEFAULT_VELOCITY;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        new_records.push(line.clone());
+
+        // velocity of the original note, None for note offs
+        let note_velocity = match &line.record {
+            MtxtRecord::VelocityDirective { velocity } => {
+                current_velocity = *velocity;
+                continue;
+            }
+            MtxtRecord::Note { velocity, .. } | MtxtRecord::NoteOn { velocity, .. } => {
+                Some(velocity.unwrap_or(current_velocity))
+            }
+            MtxtRecord::NoteOff { .. } => None,
+            _ => continue,
+        };
+
+        for k in 1..=config.repeats {
+            let mut echo = line.clone();
+            let offset = config.delay.mul_ratio(k as u64, 1);
+            let echo_velocity = note_velocity
+                .map(|velocity| (velocity * config.decay.powi(k as i32)).clamp(0.0, 1.0));
+            match &mut echo.record {
+                MtxtRecord::Note {
+                    time,
+                    velocity,
+                    channel,
+                    ..
+                }
+                | MtxtRecord::NoteOn {
+                    time,
+                    velocity,
+                    channel,
+                    ..
+                } => {
+                    *time = *time + offset;
+                    *velocity = echo_velocity;
+                    if config.channel.is_some() {
+                        *channel = config.channel;
+                    }
+                }
+                MtxtRecord::NoteOff { time, channel, .. } => {
+                    *time = *time + offset;
+                    if config.channel.is_some() {
+                        *channel = config.channel;
+                    }
+                }
+                _ => {}
+            }
+            new_records.push(echo);
+        }
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_echo_velocity_decay() {
+        let input = r#"
+mtxt 1.0
+vel=0.8
+0.0 note C4 dur=0.5
+1.0 note E4 vel=1.0
+1.0 cc volume 0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+vel=0.8
+0.0 note C4 dur=0.5
+0.5 note C4 dur=0.5 vel=0.4
+1.0 note C4 dur=0.5 vel=0.2
+1.5 note C4 dur=0.5 vel=0.1
+1.0 note E4 vel=1.0
+1.5 note E4 vel=0.5
+2.0 note E4 vel=0.25
+2.5 note E4 vel=0.125
+1.0 cc volume 0.5
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &EchoConfig {
+                        delay: BeatTime::from_parts(0, 0.5),
+                        repeats: 3,
+                        decay: 0.5,
+                        channel: None,
+                    },
+                )
+            },
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_echo_channel() {
+        let input = r#"
+mtxt 1.0
+ch=1
+0.0 on C4 vel=0.5
+1.0 off C4
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=1
+0.0 on C4 vel=0.5
+0.75 on C4 vel=0.25 ch=5
+1.0 off C4
+1.75 off C4 ch=5
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &EchoConfig {
+                        delay: BeatTime::from_parts(0, 0.75),
+                        repeats: 1,
+                        decay: 0.5,
+                        channel: Some(5),
+                    },
+                )
+            },
+            expected,
+        );
+    }
+}
@@ -56,8 +56,8 @@ fn extract_property<T: PartialEq + Clone + Copy + std::fmt::Debug>(
                 current_global_value = Some(val);
 
                 // Process the block from i to j
-                for k in i..j {
-                    let mut r_line = records[k].clone();
+                for (k, r_line) in records.iter().enumerate().take(j).skip(i) {
+                    let mut r_line = r_line.clone();
                     if run_indices.contains(&k) {
                         remove_fn(&mut r_line.record);
                     }
@@ -0,0 +1,184 @@
+use super::stretch;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use crate::types::time_signature::TimeSignature;
+
+/// Change of length needed to fit the notes into a number of bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitReport {
+    pub bars: u32,
+    /// Onset of the first note, the phrase is stretched from here
+    pub start: BeatTime,
+    /// Length from the first note until the last event or note end
+    pub length: BeatTime,
+    /// Length of `bars` measures from `start`
+    pub target_length: BeatTime,
+    /// Time scale factor, `target_length / length`
+    pub factor: f64,
+}
+
+// First note onset and the end of the last event or note
+fn phrase_span(records: &[MtxtRecordLine]) -> Option<(BeatTime, BeatTime)> {
+    let mut start: Option<BeatTime> = None;
+    let mut end = BeatTime::zero();
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+
+    for line in records {
+        match &line.record {
+            MtxtRecord::DurationDirective { duration } => current_duration = *duration,
+            MtxtRecord::Note { time, duration, .. } => {
+                start = Some(start.map_or(*time, |start| start.min(*time)));
+                end = end.max(*time + duration.unwrap_or(current_duration));
+            }
+            MtxtRecord::NoteOn { time, .. } => {
+                start = Some(start.map_or(*time, |start| start.min(*time)));
+                end = end.max(*time);
+            }
+            record => {
+                if let Some(time) = record.time() {
+                    end = end.max(time);
+                }
+            }
+        }
+    }
+
+    start.map(|start| (start, end))
+}
+
+// Length of `bars` measures from `start`, following the time signature changes
+fn bars_length(records: &[MtxtRecordLine], start: BeatTime, bars: u32) -> Option<BeatTime> {
+    let mut signatures: Vec<(BeatTime, BeatTime)> = records
+        .iter()
+        .filter_map(|line| match &line.record {
+            MtxtRecord::TimeSignature { time, signature } => {
+                Some((*time, signature.beats_per_measure()))
+            }
+            _ => None,
+        })
+        .collect();
+    signatures.sort_by_key(|(time, _)| *time);
+    let default_length = TimeSignature::default().beats_per_measure();
+
+    let mut position = start;
+    for _ in 0..bars {
+        let measure_length = signatures
+            .iter()
+            .rev()
+            .find(|(time, _)| *time <= position)
+            .map_or(default_length, |(_, length)| *length);
+        position = position.checked_add(measure_length)?;
+    }
+    Some(position - start)
+}
+
+/// Measures the phrase from the first note until the last event or note end,
+/// and the factor that makes it exactly `bars` measures long.
+/// `None` without notes, for a phrase of zero length or for zero bars.
+pub fn estimate(records: &[MtxtRecordLine], bars: u32) -> Option<FitReport> {
+    let (start, end) = phrase_span(records)?;
+    let length = end - start;
+    if bars == 0 || length == BeatTime::zero() {
+        return None;
+    }
+    let target_length = bars_length(records, start, bars)?;
+
+    Some(FitReport {
+        bars,
+        start,
+        length,
+        target_length,
+        factor: target_length.as_f64() / length.as_f64(),
+    })
+}
+
+/// Stretches or squashes the phrase so it is exactly `bars` measures long, the end of the
+/// last note lands on the barline. The first note stays in place, earlier records are unchanged.
+/// The factor is applied as an exact ratio like `--time-scale`, with `preserve_wallclock`
+/// the tempos are scaled so the result sounds identical.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    bars: u32,
+    preserve_wallclock: bool,
+) -> Vec<MtxtRecordLine> {
+    let Some(report) = estimate(records, bars) else {
+        return records.to_vec();
+    };
+    let end = report.start + report.length;
+
+    stretch::transform_ratio(
+        records,
+        report.target_length.units(),
+        report.length.units(),
+        Some((report.start, end)),
+        preserve_wallclock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+
+    fn phrase_end(records: &[MtxtRecordLine]) -> BeatTime {
+        phrase_span(records).unwrap().1
+    }
+
+    #[test]
+    fn test_fit_to_bars() {
+        // 7.3 bars of 4/4
+        let input = r#"mtxt 1.0
+0.0 tempo 100
+0.0 note C4
+7.3 note E4 dur=0.7
+15.0 cc volume 0.5
+28.2 note G4
+"#;
+        let records = parse_mtxt(input).unwrap().records;
+        let report = estimate(&records, 8).unwrap();
+        assert_eq!(report.start, BeatTime::zero());
+        assert_eq!(report.target_length, BeatTime::from_parts(32, 0.0));
+        assert!((report.factor - 32.0 / 29.2).abs() < 1e-6, "{:?}", report);
+
+        let fitted = transform(&records, 8, false);
+        assert_eq!(phrase_end(&fitted), BeatTime::from_parts(32, 0.0));
+        let MtxtRecord::Note { time, duration, .. } = &fitted[5].record else {
+            panic!("{:?}", fitted[5]);
+        };
+        assert_eq!(*time + duration.unwrap(), BeatTime::from_parts(32, 0.0));
+        let MtxtRecord::Tempo { bpm, .. } = &fitted[1].record else {
+            panic!("{:?}", fitted[1]);
+        };
+        assert_eq!(*bpm, 100.0);
+
+        let wallclock = transform(&records, 8, true);
+        let MtxtRecord::Tempo { bpm, .. } = &wallclock[1].record else {
+            panic!("{:?}", wallclock[1]);
+        };
+        assert!((*bpm - 100.0 * 32.0 / 29.2).abs() < 1e-3, "{}", bpm);
+    }
+
+    #[test]
+    fn test_fit_to_bars_with_time_signatures() {
+        // starts on beat 3, one bar of 3/4 and two of 6/8 from there
+        let input = r#"mtxt 1.0
+0.0 timesig 3/4
+1.0 cc volume 0.5
+3.0 note C4
+6.0 timesig 6/8
+7.0 note D4 dur=0.25
+"#;
+        let records = parse_mtxt(input).unwrap().records;
+        let report = estimate(&records, 3).unwrap();
+        assert_eq!(report.start, BeatTime::from_parts(3, 0.0));
+        assert_eq!(report.length, BeatTime::from_parts(4, 0.25));
+        assert_eq!(report.target_length, BeatTime::from_parts(9, 0.0));
+
+        let fitted = transform(&records, 3, false);
+        assert_eq!(fitted[2].record.time(), Some(BeatTime::from_parts(1, 0.0)));
+        assert_eq!(fitted[3].record.time(), Some(BeatTime::from_parts(3, 0.0)));
+        assert_eq!(phrase_end(&fitted), BeatTime::from_parts(12, 0.0));
+
+        assert!(estimate(&records, 0).is_none());
+        assert!(estimate(&parse_mtxt("mtxt 1.0\n1.0 tempo 90\n").unwrap().records, 2).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use super::channels::record_channel;
 use crate::types::record::{MtxtRecord, MtxtRecordLine};
 use std::collections::HashSet;
 
@@ -6,32 +7,18 @@ pub fn transform(records: &[MtxtRecordLine], channels: &HashSet<u16>) -> Vec<Mtx
         return records.to_vec();
     }
 
-    let mut current_channel: Option<u16> = None;
+    let mut current_channel: u16 = 0;
 
+    // records without a channel (tempo, time signatures, global meta and CCs) are always kept,
+    // notes and voices before the first channel directive are on channel 0
     records
         .iter()
-        .filter(|line| match &line.record {
-            MtxtRecord::Note { channel, .. }
-            | MtxtRecord::NoteOn { channel, .. }
-            | MtxtRecord::NoteOff { channel, .. }
-            | MtxtRecord::Voice { channel, .. } => {
-                if let Some(channel) = channel {
-                    channels.contains(channel)
-                } else if let Some(curr) = current_channel {
-                    channels.contains(&curr)
-                } else {
-                    true
-                }
+        .filter(|line| {
+            if let MtxtRecord::ChannelDirective { channel } = &line.record {
+                current_channel = *channel;
             }
-            MtxtRecord::ControlChange { channel, .. } => {
-                // if channel is None, affects all channels
-                channel.is_none_or(|ch| channels.contains(&ch))
-            }
-            MtxtRecord::ChannelDirective { channel } => {
-                current_channel = Some(*channel);
-                channels.contains(channel)
-            }
-            _ => true,
+            record_channel(&line.record, Some(current_channel))
+                .is_none_or(|ch| channels.contains(&ch))
         })
         .cloned()
         .collect()
@@ -76,4 +63,37 @@ ch=5
 
         assert_eq_records(input, include_channels_3_5, expected);
     }
+
+    fn include_channel_3(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+        transform(records, &HashSet::from([3]))
+    }
+
+    #[test]
+    fn test_include_keeps_conductor() {
+        let input = r#"
+mtxt 1.0
+meta global title Trio
+0.0 tempo 96
+0.0 timesig 3/4
+0.0 note C2 dur=3
+0.0 meta ch=1 name Flute
+ch=1
+0.0 note G4 dur=1
+ch=3
+0.0 note E3 dur=1
+2.0 tempo 80
+3.0 timesig 4/4
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global title Trio
+0.0 tempo 96
+0.0 timesig 3/4
+ch=3
+0.0 note E3 dur=1
+2.0 tempo 80
+3.0 timesig 4/4
+"#;
+        assert_eq_records(input, include_channel_3, expected);
+    }
 }
@@ -0,0 +1,285 @@
+use crate::types::key::KeySignature;
+use crate::types::note::{Note, NoteTarget, SpellingPreference};
+use crate::types::record::{AliasDefinition, MtxtRecord, MtxtRecordLine};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+const MIN_NOTE: i32 = 0;
+const MAX_NOTE: i32 = 127;
+
+// Flat keys: anything spelled with a flat, F major and D, G, C, F minor
+fn key_prefers_flats(key: &str) -> bool {
+    key.parse::<KeySignature>()
+        .is_ok_and(|key| key.prefers_flats())
+}
+
+struct Inverter {
+    pivot: i32,
+    pivot_cents: f32,
+    prefer_flats: bool,
+}
+
+impl Inverter {
+    // None if the note leaves the valid range and should be dropped
+    fn invert(&self, note: &Note, drop_out_of_range: bool) -> Option<Note> {
+        let original = note.absolute_semitone();
+        let inverted = 2 * self.pivot - original;
+        let clamped = inverted.clamp(MIN_NOTE, MAX_NOTE);
+        if clamped != inverted && drop_out_of_range {
+            return None;
+        }
+
+        let spelling = if self.prefer_flats {
+            SpellingPreference::Flats
+        } else {
+            SpellingPreference::Sharps
+        };
+        let mut inverted = note
+            .transpose(clamped - original, spelling)
+            .expect("the note is clamped into the MIDI range");
+        inverted.cents = (2.0 * self.pivot_cents - note.cents).clamp(-100.0, 100.0);
+        Some(inverted)
+    }
+
+    fn invert_target(
+        &self,
+        target: &NoteTarget,
+        drop_out_of_range: bool,
+        map: &HashMap<usize, Rc<AliasDefinition>>,
+    ) -> Option<NoteTarget> {
+        match target {
+            NoteTarget::Note(n) => self.invert(n, drop_out_of_range).map(NoteTarget::Note),
+            NoteTarget::AliasKey(k) => Some(NoteTarget::AliasKey(k.clone())),
+            NoteTarget::Alias(rc) => {
+                let ptr = Rc::as_ptr(rc) as usize;
+                Some(NoteTarget::Alias(
+                    map.get(&ptr).cloned().unwrap_or_else(|| rc.clone()),
+                ))
+            }
+            // the inverted ends swap roles
+            NoteTarget::Range { low, high } => Some(NoteTarget::Range {
+                low: self.invert(high, drop_out_of_range)?,
+                high: self.invert(low, drop_out_of_range)?,
+            }),
+        }
+    }
+}
+
+pub(super) fn note_target_and_channel(record: &MtxtRecord) -> Option<(&NoteTarget, Option<u16>)> {
+    match record {
+        MtxtRecord::Note { note, channel, .. }
+        | MtxtRecord::NoteOn { note, channel, .. }
+        | MtxtRecord::NoteOff { note, channel, .. } => Some((note, *channel)),
+        MtxtRecord::ControlChange {
+            note: Some(note),
+            channel,
+            ..
+        } => Some((note, *channel)),
+        _ => None,
+    }
+}
+
+// Aliases played on an exempt channel (e.g. drum kits) must keep their definition
+pub(super) fn find_exempt_aliases(
+    records: &[MtxtRecordLine],
+    exempt_channels: &HashSet<u16>,
+) -> HashSet<String> {
+    let mut current_channel: u16 = 0;
+    let mut exempt = HashSet::new();
+    for line in records {
+        if let MtxtRecord::ChannelDirective { channel } = &line.record {
+            current_channel = *channel;
+        }
+        let name = match note_target_and_channel(&line.record) {
+            Some((NoteTarget::AliasKey(name), channel)) => Some((name.clone(), channel)),
+            Some((NoteTarget::Alias(def), channel)) => Some((def.name.clone(), channel)),
+            _ => None,
+        };
+        if let Some((name, channel)) = name
+            && exempt_channels.contains(&channel.unwrap_or(current_channel))
+        {
+            exempt.insert(name);
+        }
+    }
+    exempt
+}
+
+/// Mirrors every note around `pivot`.
+/// Notes leaving the MIDI range are clamped, or dropped if `drop_out_of_range` is set.
+/// Notes on `exempt_channels` (usually drums) are left untouched.
+/// Results are spelled with flats if the `key` global meta is a flat key, otherwise with sharps.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    pivot: &Note,
+    drop_out_of_range: bool,
+    exempt_channels: &HashSet<u16>,
+) -> Vec<MtxtRecordLine> {
+    let prefer_flats = records.iter().any(|line| match &line.record {
+        MtxtRecord::GlobalMeta { meta_type, value } => {
+            meta_type == "key" && key_prefers_flats(value)
+        }
+        _ => false,
+    });
+    let inverter = Inverter {
+        pivot: pivot.absolute_semitone(),
+        pivot_cents: pivot.cents,
+        prefer_flats,
+    };
+
+    let exempt_aliases = find_exempt_aliases(records, exempt_channels);
+    let mut alias_map: HashMap<usize, Rc<AliasDefinition>> = HashMap::new();
+    let mut current_channel: u16 = 0;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        let mut new_line = line.clone();
+        let exempt = match note_target_and_channel(&line.record) {
+            Some((_, channel)) => exempt_channels.contains(&channel.unwrap_or(current_channel)),
+            None => false,
+        };
+
+        match &mut new_line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::AliasDef { value } if !exempt_aliases.contains(&value.name) => {
+                // a chord can't be partially dropped, so its notes are always clamped
+                let new_def = Rc::new(AliasDefinition {
+                    name: value.name.clone(),
+                    notes: value
+                        .notes
+                        .iter()
+                        .filter_map(|n| inverter.invert(n, false))
+                        .collect(),
+                });
+                alias_map.insert(Rc::as_ptr(value) as usize, new_def.clone());
+                *value = new_def;
+            }
+            MtxtRecord::Note { note, .. }
+            | MtxtRecord::NoteOn { note, .. }
+            | MtxtRecord::NoteOff { note, .. }
+            | MtxtRecord::ControlChange {
+                note: Some(note), ..
+            } if !exempt => match inverter.invert_target(note, drop_out_of_range, &alias_map) {
+                Some(new_note) => *note = new_note,
+                None => continue,
+            },
+            _ => {}
+        }
+
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    fn drums() -> HashSet<u16> {
+        HashSet::from([9])
+    }
+
+    #[test]
+    fn test_key_prefers_flats() {
+        assert!(key_prefers_flats("F major"));
+        assert!(key_prefers_flats("Bb major"));
+        assert!(key_prefers_flats("D minor"));
+        assert!(key_prefers_flats("Gm"));
+        assert!(!key_prefers_flats("C major"));
+        assert!(!key_prefers_flats("A minor"));
+        assert!(!key_prefers_flats("F# major"));
+        assert!(!key_prefers_flats("unknown"));
+    }
+
+    #[test]
+    fn test_invert() {
+        let input = r#"
+mtxt 1.0
+alias Cmaj C4,E4,G4
+1.0 note C4
+2.0 note E4 dur=1
+3.0 note Cmaj
+4.0 note D4 ch=9
+5.0 cc G4 volume 0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+alias Cmaj C4,G#3,F3
+1.0 note C4
+2.0 note G#3 dur=1
+3.0 note Cmaj
+4.0 note D4 ch=9
+5.0 cc F3 volume 0.5
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &"C4".parse().unwrap(), false, &drums()),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_invert_flat_key() {
+        let input = r#"
+mtxt 1.0
+meta global key F major
+1.0 note E4
+2.0 note A4
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global key F major
+1.0 note Ab3
+2.0 note Eb3
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &"C4".parse().unwrap(), false, &drums()),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_invert_out_of_range() {
+        let input = r#"
+mtxt 1.0
+1.0 note C1
+2.0 note C4
+"#;
+        let clamped = r#"
+mtxt 1.0
+1.0 note G9
+2.0 note C9
+"#;
+        let dropped = r#"
+mtxt 1.0
+2.0 note C9
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &"F#6".parse().unwrap(), false, &drums()),
+            clamped,
+        );
+        assert_eq_records(
+            input,
+            |r| transform(r, &"F#6".parse().unwrap(), true, &drums()),
+            dropped,
+        );
+    }
+
+    #[test]
+    fn test_invert_keeps_drum_aliases() {
+        let input = r#"
+mtxt 1.0
+alias kick C2
+ch=9
+1.0 note kick
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &"C4".parse().unwrap(), false, &drums()),
+            input,
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+// Effective channel of every note, resolved through directives
+fn resolve_note_channels(records: &[MtxtRecordLine]) -> Vec<Option<u16>> {
+    let mut current_channel: u16 = 0;
+
+    records
+        .iter()
+        .map(|line| match &line.record {
+            MtxtRecord::ChannelDirective { channel } => {
+                current_channel = *channel;
+                None
+            }
+            MtxtRecord::Note { channel, .. } => Some(channel.unwrap_or(current_channel)),
+            _ => None,
+        })
+        .collect()
+}
+
+// Next distinct note onset on the same channel, for every note
+pub(super) fn find_next_onsets(records: &[MtxtRecordLine]) -> Vec<Option<BeatTime>> {
+    let channels = resolve_note_channels(records);
+
+    let mut onsets: HashMap<u16, BTreeSet<BeatTime>> = HashMap::new();
+    for (line, channel) in records.iter().zip(&channels) {
+        if let (MtxtRecord::Note { time, .. }, Some(channel)) = (&line.record, channel) {
+            onsets.entry(*channel).or_default().insert(*time);
+        }
+    }
+
+    records
+        .iter()
+        .zip(&channels)
+        .map(|(line, channel)| match (&line.record, channel) {
+            (MtxtRecord::Note { time, .. }, Some(channel)) => onsets[channel]
+                .range((Bound::Excluded(*time), Bound::Unbounded))
+                .next()
+                .copied(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Extends every note to the next onset on its channel.
+/// `overlap` is added to the resulting duration (negative leaves a gap).
+/// Notes starting together (chords) all extend to the next distinct onset.
+/// The last note of each channel keeps its duration.
+/// Expects the records to be sorted by time.
+pub fn transform(records: &[MtxtRecordLine], overlap: f32) -> Vec<MtxtRecordLine> {
+    let next_onsets = find_next_onsets(records);
+    let abs_overlap = overlap.abs();
+    let overlap_time = BeatTime::from_parts(abs_overlap.floor() as u32, abs_overlap.fract());
+
+    let mut new_records = Vec::with_capacity(records.len());
+    for (line, next_onset) in records.iter().zip(next_onsets) {
+        let mut new_line = line.clone();
+        if let MtxtRecord::Note { time, duration, .. } = &mut new_line.record
+            && let Some(next) = next_onset
+        {
+            let gap = next - *time;
+            let new_duration = if overlap < 0.0 {
+                gap - overlap_time
+            } else {
+                gap + overlap_time
+            };
+            // a gap longer than the distance to the next note would leave nothing to play
+            if new_duration > BeatTime::zero() {
+                *duration = Some(new_duration);
+            }
+        }
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_legato() {
+        let input = r#"
+mtxt 1.0
+dur=0.25
+1.0 note C4
+2.0 note D4
+2.5 note E4 dur=0.1
+4.0 note F4
+"#;
+        let expected = r#"
+mtxt 1.0
+dur=0.25
+1.0 note C4 dur=1
+2.0 note D4 dur=0.5
+2.5 note E4 dur=1.5
+4.0 note F4
+"#;
+        assert_eq_records(input, |r| transform(r, 0.0), expected);
+    }
+
+    #[test]
+    fn test_legato_overlap_and_gap() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4 dur=0.25
+2.0 note D4 dur=0.25
+"#;
+        let overlapped = r#"
+mtxt 1.0
+1.0 note C4 dur=1.25
+2.0 note D4 dur=0.25
+"#;
+        let gapped = r#"
+mtxt 1.0
+1.0 note C4 dur=0.75
+2.0 note D4 dur=0.25
+"#;
+        assert_eq_records(input, |r| transform(r, 0.25), overlapped);
+        assert_eq_records(input, |r| transform(r, -0.25), gapped);
+    }
+
+    #[test]
+    fn test_legato_chords() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4 dur=0.5
+1.0 note E4 dur=0.5
+1.0 note G4 dur=0.25
+3.0 note F4 dur=0.5
+3.0 note A4 dur=0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 dur=2
+1.0 note E4 dur=2
+1.0 note G4 dur=2
+3.0 note F4 dur=0.5
+3.0 note A4 dur=0.5
+"#;
+        assert_eq_records(input, |r| transform(r, 0.0), expected);
+    }
+
+    #[test]
+    fn test_legato_interleaved_channels() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4 dur=0.5 ch=1
+1.5 note C2 dur=0.5 ch=2
+2.0 note D4 dur=0.5 ch=1
+ch=2
+3.0 note D2 dur=0.5
+4.0 note E4 dur=0.5 ch=1
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 dur=1 ch=1
+1.5 note C2 dur=1.5 ch=2
+2.0 note D4 dur=2 ch=1
+ch=2
+3.0 note D2 dur=0.5
+4.0 note E4 dur=0.5 ch=1
+"#;
+        assert_eq_records(input, |r| transform(r, 0.0), expected);
+    }
+}
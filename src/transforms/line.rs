@@ -0,0 +1,328 @@
+use super::merge::pair_note_events;
+use crate::BeatTime;
+use crate::types::note::{Note, NoteTarget};
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashMap;
+
+/// Which line of the texture to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum LineMode {
+    /// Highest sounding note (melody)
+    Top,
+    /// Lowest sounding note (bass)
+    Bottom,
+}
+
+struct LineNote {
+    idx: usize,
+    channel: u16,
+    start: BeatTime,
+    end: BeatTime,
+    note: Note,
+}
+
+fn pitch_value(note: &Note) -> f32 {
+    note.absolute_semitone() as f32 * 100.0 + note.cents
+}
+
+// Every Note record and paired NoteOn with its channel and range; chords are reduced to their
+// outer note
+fn collect_notes(
+    records: &[MtxtRecordLine],
+    pairs: &HashMap<usize, usize>,
+    mode: LineMode,
+) -> Vec<LineNote> {
+    let mut current_channel: u16 = 0;
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut aliases: HashMap<&str, &[Note]> = HashMap::new();
+    let mut notes = Vec::new();
+
+    let outer_note = |candidates: &[Note]| {
+        let compare = |a: &&Note, b: &&Note| pitch_value(a).total_cmp(&pitch_value(b));
+        match mode {
+            LineMode::Top => candidates.iter().max_by(compare).cloned(),
+            LineMode::Bottom => candidates.iter().min_by(compare).cloned(),
+        }
+    };
+    let resolve = |note: &NoteTarget, aliases: &HashMap<&str, &[Note]>| match note {
+        NoteTarget::Note(n) => Some(n.clone()),
+        NoteTarget::AliasKey(name) => aliases.get(name.as_str()).and_then(|n| outer_note(n)),
+        NoteTarget::Alias(def) => outer_note(&def.notes),
+        NoteTarget::Range { low, high } => match mode {
+            LineMode::Top => Some(high.clone()),
+            LineMode::Bottom => Some(low.clone()),
+        },
+    };
+
+    for (idx, line) in records.iter().enumerate() {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::DurationDirective { duration } => current_duration = *duration,
+            MtxtRecord::AliasDef { value } => {
+                aliases.insert(value.name.as_str(), &value.notes);
+            }
+            MtxtRecord::Note {
+                time,
+                note,
+                duration,
+                channel,
+                ..
+            } => {
+                if let Some(note) = resolve(note, &aliases) {
+                    notes.push(LineNote {
+                        idx,
+                        channel: channel.unwrap_or(current_channel),
+                        start: *time,
+                        end: *time + duration.unwrap_or(current_duration),
+                        note,
+                    });
+                }
+            }
+            MtxtRecord::NoteOn {
+                time,
+                note,
+                channel,
+                ..
+            } => {
+                let end = pairs.get(&idx).and_then(|off| records[*off].record.time());
+                if let (Some(end), Some(note)) = (end, resolve(note, &aliases)) {
+                    notes.push(LineNote {
+                        idx,
+                        channel: channel.unwrap_or(current_channel),
+                        start: *time,
+                        end,
+                        note,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    notes
+}
+
+/// Keeps a single line per channel: at every onset only the highest (`Top`) or lowest
+/// (`Bottom`) note is kept. A note interrupted by a better one is shortened,
+/// or dropped if `drop_overlapping` is set. The result has no overlapping notes.
+/// Paired note on / off events count as one note, a shortened note moves its off event.
+/// Unpaired note ons and offs are kept as they are.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    mode: LineMode,
+    drop_overlapping: bool,
+) -> Vec<MtxtRecordLine> {
+    let pairs = pair_note_events(records);
+    let pair_ends: HashMap<usize, usize> = pairs.iter().map(|(on, off)| (*off, *on)).collect();
+    let mut notes = collect_notes(records, &pairs, mode);
+    let better = |a: &Note, b: &Note| match mode {
+        LineMode::Top => pitch_value(a) > pitch_value(b),
+        LineMode::Bottom => pitch_value(a) < pitch_value(b),
+    };
+
+    // best note first among simultaneous onsets
+    notes.sort_by(|a, b| {
+        (a.channel, a.start)
+            .cmp(&(b.channel, b.start))
+            .then_with(|| {
+                let order = pitch_value(&a.note).total_cmp(&pitch_value(&b.note));
+                match mode {
+                    LineMode::Top => order.reverse(),
+                    LineMode::Bottom => order,
+                }
+            })
+    });
+
+    let mut kept: HashMap<usize, (BeatTime, Note)> = HashMap::new();
+    let mut current: Option<usize> = None;
+    for i in 0..notes.len() {
+        let note = &notes[i];
+        let overlapping = current.filter(|&c| {
+            notes[c].channel == note.channel && kept.contains_key(&notes[c].idx) && {
+                let (end, _) = kept[&notes[c].idx];
+                end > note.start
+            }
+        });
+
+        match overlapping {
+            Some(c) if notes[c].start < note.start && better(&note.note, &notes[c].note) => {
+                if drop_overlapping {
+                    kept.remove(&notes[c].idx);
+                } else if let Some(entry) = kept.get_mut(&notes[c].idx) {
+                    entry.0 = note.start;
+                }
+            }
+            Some(_) => continue,
+            None => {}
+        }
+        kept.insert(note.idx, (note.end, note.note.clone()));
+        current = Some(i);
+    }
+
+    let by_index: HashMap<usize, &LineNote> = notes.iter().map(|n| (n.idx, n)).collect();
+
+    let mut new_records = Vec::with_capacity(records.len());
+    for (idx, line) in records.iter().enumerate() {
+        if let Some(on_idx) = pair_ends.get(&idx)
+            && by_index.contains_key(on_idx)
+        {
+            // the off event follows its note on
+            let Some((end, note)) = kept.get(on_idx) else {
+                continue;
+            };
+            let mut new_line = line.clone();
+            if let MtxtRecord::NoteOff {
+                time, note: target, ..
+            } = &mut new_line.record
+            {
+                *time = *end;
+                *target = NoteTarget::Note(note.clone());
+            }
+            new_records.push(new_line);
+            continue;
+        }
+        let Some(original) = by_index.get(&idx) else {
+            new_records.push(line.clone());
+            continue;
+        };
+        let Some((end, note)) = kept.get(&idx) else {
+            continue;
+        };
+        let mut new_line = line.clone();
+        match &mut new_line.record {
+            MtxtRecord::Note {
+                note: target,
+                duration,
+                ..
+            } => {
+                if *end != original.end {
+                    *duration = Some(*end - original.start);
+                }
+                *target = NoteTarget::Note(note.clone());
+            }
+            MtxtRecord::NoteOn { note: target, .. } => *target = NoteTarget::Note(note.clone()),
+            _ => {}
+        }
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    const PROGRESSION: &str = r#"
+mtxt 1.0
+dur=2
+0.0 note C3
+0.0 note E4
+0.0 note G4
+0.0 note C5 dur=1
+1.0 note D5 dur=0.5
+2.0 note F3
+2.0 note A4
+2.0 note C5
+4.0 note G2 dur=1
+4.0 note B4 dur=1
+4.0 note D5 dur=1
+"#;
+
+    #[test]
+    fn test_extract_top_line() {
+        let expected = r#"
+mtxt 1.0
+dur=2
+0.0 note C5 dur=1
+1.0 note D5 dur=0.5
+2.0 note C5
+4.0 note D5 dur=1
+"#;
+        assert_eq_records(
+            PROGRESSION,
+            |r| transform(r, LineMode::Top, false),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_extract_bottom_line() {
+        let expected = r#"
+mtxt 1.0
+dur=2
+0.0 note C3
+2.0 note F3
+4.0 note G2 dur=1
+"#;
+        assert_eq_records(
+            PROGRESSION,
+            |r| transform(r, LineMode::Bottom, false),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_extract_line_truncate_and_drop() {
+        let input = r#"
+mtxt 1.0
+0.0 note E4 dur=4
+1.0 note C4 dur=1
+2.0 note G4 dur=1
+0.0 note C2 dur=4 ch=1
+2.0 note C5 dur=1 ch=1
+alias Am A3,C4,E4
+6.0 note Am
+"#;
+        let truncated = r#"
+mtxt 1.0
+0.0 note E4 dur=2
+2.0 note G4 dur=1
+0.0 note C2 dur=2 ch=1
+2.0 note C5 dur=1 ch=1
+alias Am A3,C4,E4
+6.0 note E4
+"#;
+        let dropped = r#"
+mtxt 1.0
+2.0 note G4 dur=1
+2.0 note C5 dur=1 ch=1
+alias Am A3,C4,E4
+6.0 note E4
+"#;
+        assert_eq_records(input, |r| transform(r, LineMode::Top, false), truncated);
+        assert_eq_records(input, |r| transform(r, LineMode::Top, true), dropped);
+    }
+
+    #[test]
+    fn test_extract_line_note_on_off() {
+        let input = r#"
+mtxt 1.0
+0.0 on E4
+0.0 note C4 dur=4
+1.0 on G4
+2.0 off G4
+4.0 off E4
+5.0 off A4
+"#;
+        let truncated = r#"
+mtxt 1.0
+0.0 on E4
+1.0 on G4
+2.0 off G4
+1.0 off E4
+5.0 off A4
+"#;
+        let dropped = r#"
+mtxt 1.0
+1.0 on G4
+2.0 off G4
+5.0 off A4
+"#;
+        assert_eq_records(input, |r| transform(r, LineMode::Top, false), truncated);
+        assert_eq_records(input, |r| transform(r, LineMode::Top, true), dropped);
+    }
+}
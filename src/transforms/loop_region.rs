@@ -0,0 +1,191 @@
+use super::merge::pair_note_events;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct LoopRegion {
+    pub start: BeatTime,
+    pub end: BeatTime,
+    /// Number of copies played after the region, 1 plays it twice
+    pub count: u32,
+    /// Copy tempo and time signature changes inside the region as well
+    pub repeat_tempo: bool,
+}
+
+/// Repeats the records within `[start, end)` `count` times right after the region,
+/// and moves the records at or after `end` after the last copy.
+/// Each record is directly followed by its copies. Notes crossing `end` are shortened
+/// to the region in the copies, the original keeps its length. Note on / off pairs starting in
+/// the region and ending after it are closed at the end of the region, in the original and in
+/// every copy, note offs of notes started before the region are not copied.
+pub fn transform(records: &[MtxtRecordLine], config: &LoopRegion) -> Vec<MtxtRecordLine> {
+    if config.count == 0 || config.end <= config.start {
+        return records.to_vec();
+    }
+
+    let length = config.end - config.start;
+    let shift = length.mul_ratio(config.count as u64, 1);
+    let last_time = records.iter().filter_map(|line| line.record.time()).max();
+    if last_time.is_some_and(|time| time.max(config.end).checked_add(shift).is_none()) {
+        return records.to_vec();
+    }
+
+    let in_region = |time: BeatTime| time >= config.start && time < config.end;
+    // NoteOff index -> whether its NoteOn is in the region
+    let pair_ends: HashMap<usize, bool> = pair_note_events(records)
+        .into_iter()
+        .map(|(on, off)| (off, records[on].record.time().is_some_and(in_region)))
+        .collect();
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for (idx, line) in records.iter().enumerate() {
+        if let MtxtRecord::DurationDirective { duration } = &line.record {
+            current_duration = *duration;
+        }
+        let Some(mut time) = line.record.time() else {
+            new_records.push(line.clone());
+            continue;
+        };
+        let mut line = line.clone();
+        // the note off of a pair started in the region ends the note with the region
+        let ends_region_note = pair_ends.get(&idx) == Some(&true);
+        if ends_region_note && time > config.end {
+            time = config.end;
+            line.record.set_time(time);
+        }
+        if time >= config.end && !ends_region_note {
+            line.record.set_time(time + shift);
+            new_records.push(line);
+            continue;
+        }
+
+        new_records.push(line.clone());
+        let copied = match &line.record {
+            _ if pair_ends.contains_key(&idx) => ends_region_note,
+            MtxtRecord::Tempo { .. } | MtxtRecord::TimeSignature { .. } => {
+                config.repeat_tempo && in_region(time)
+            }
+            _ => in_region(time),
+        };
+        if !copied {
+            continue;
+        }
+
+        let mut copy = line.clone();
+        if let MtxtRecord::Note { duration, .. } = &mut copy.record
+            && time + duration.unwrap_or(current_duration) > config.end
+        {
+            *duration = Some(config.end - time);
+        }
+        for k in 1..=config.count {
+            let mut copy = copy.clone();
+            copy.record.set_time(time + length.mul_ratio(k as u64, 1));
+            new_records.push(copy);
+        }
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    fn twice(repeat_tempo: bool) -> LoopRegion {
+        LoopRegion {
+            start: BeatTime::from_parts(2, 0.0),
+            end: BeatTime::from_parts(4, 0.0),
+            count: 2,
+            repeat_tempo,
+        }
+    }
+
+    const INPUT: &str = r#"
+mtxt 1.0
+0.0 tempo 120
+dur=0.5
+0.0 note C4
+meta text untimed
+2.0 note D4
+2.0 cc volume 0.5
+3.0 tempo 90
+3.5 note E4 dur=1
+4.0 note F4
+5.0 meta marker end
+"#;
+
+    #[test]
+    fn test_loop_region() {
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 120
+dur=0.5
+0.0 note C4
+meta text untimed
+2.0 note D4
+4.0 note D4
+6.0 note D4
+2.0 cc volume 0.5
+4.0 cc volume 0.5
+6.0 cc volume 0.5
+3.0 tempo 90
+3.5 note E4 dur=1
+5.5 note E4 dur=0.5
+7.5 note E4 dur=0.5
+8.0 note F4
+9.0 meta marker end
+"#;
+        assert_eq_records(INPUT, |r| transform(r, &twice(false)), expected);
+    }
+
+    #[test]
+    fn test_loop_region_with_tempo() {
+        let records = crate::parse_mtxt(INPUT).unwrap().records;
+        assert_eq!(transform(&records, &twice(false)).len(), records.len() + 6);
+        let looped = transform(&records, &twice(true));
+        // 4 records in the region, each copied twice
+        assert_eq!(looped.len(), records.len() + 8);
+        let tempos: Vec<String> = looped
+            .iter()
+            .filter(|line| matches!(line.record, MtxtRecord::Tempo { .. }))
+            .map(|line| line.record.time().unwrap().to_string())
+            .collect();
+        assert_eq!(tempos, ["0.0", "3.0", "5.0", "7.0"]);
+        assert_eq!(
+            looped.last().unwrap().record.time(),
+            Some(BeatTime::from_parts(9, 0.0))
+        );
+    }
+
+    #[test]
+    fn test_loop_region_note_on_off() {
+        let input = r#"
+mtxt 1.0
+1.0 on C4
+2.5 off C4
+3.5 on E4
+4.5 off E4
+4.5 on F4
+5.0 off F4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 on C4
+2.5 off C4
+3.5 on E4
+5.5 on E4
+7.5 on E4
+4.0 off E4
+6.0 off E4
+8.0 off E4
+8.5 on F4
+9.0 off F4
+"#;
+        assert_eq_records(input, |r| transform(r, &twice(false)), expected);
+    }
+}
@@ -11,14 +11,41 @@ enum NoteKey {
 fn get_key(target: &NoteTarget) -> NoteKey {
     match target {
         NoteTarget::Note(n) => {
-            let semitone = (n.octave as i32 + 1) * 12 + n.pitch_class.to_semitone() as i32;
+            let semitone = n.absolute_semitone();
             NoteKey::Note(semitone, n.cents.to_bits())
         }
         NoteTarget::AliasKey(s) => NoteKey::Alias(s.clone()),
         NoteTarget::Alias(def) => NoteKey::Alias(def.name.clone()),
+        NoteTarget::Range { .. } => NoteKey::Alias(target.to_string()),
     }
 }
 
+/// Index of the matching NoteOff of every NoteOn, paired by channel and note in file order
+/// like `transform` does. NoteOns without a NoteOff are left out.
+pub(crate) fn pair_note_events(records: &[MtxtRecordLine]) -> HashMap<usize, usize> {
+    let mut pairs = HashMap::new();
+    let mut pending: HashMap<(u16, NoteKey), usize> = HashMap::new();
+    let mut current_channel: u16 = 0;
+
+    for (idx, line) in records.iter().enumerate() {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::NoteOn { note, channel, .. } => {
+                pending.insert((channel.unwrap_or(current_channel), get_key(note)), idx);
+            }
+            MtxtRecord::NoteOff { note, channel, .. } => {
+                let key = (channel.unwrap_or(current_channel), get_key(note));
+                if let Some(on_idx) = pending.remove(&key) {
+                    pairs.insert(on_idx, idx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pairs
+}
+
 pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
     let mut new_records = Vec::new();
     // Key: (effective_channel, note_key) -> index in new_records
@@ -186,6 +213,24 @@ mtxt 1.0
         let expected = r#"
 mtxt 1.0
 1.0 off C4
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+
+    #[test]
+    fn test_merge_microtonal_notes() {
+        // a quarter tone apart, not the same pitch
+        let input = r#"
+mtxt 1.0
+1.0 on E4 vel=0.5
+1.5 on E4-50 vel=0.6
+2.0 off E4-50c
+3.0 off E4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note E4 dur=2.0 vel=0.5
+1.5 note E4-50 dur=0.5 vel=0.6
 "#;
         assert_eq_records(input, transform, expected);
     }
@@ -1,84 +1,612 @@
+pub mod align;
 pub mod apply;
+pub mod arpeggio;
+pub mod articulation;
+pub mod bake;
+pub mod channels;
+pub mod crop;
+pub mod dedup;
+pub mod diatonic;
+pub mod echo;
 pub mod exclude;
 pub mod extract;
+pub mod fit;
 pub mod group;
 pub mod include;
+pub mod invert;
+pub mod legato;
+pub mod line;
+pub mod loop_region;
 pub mod merge;
+pub mod monophonic;
 pub mod offset;
+pub mod pipeline;
+#[cfg(feature = "serde")]
+pub mod preset;
 pub mod quantize;
+pub mod remap;
+pub mod remove_cc;
+pub mod retrograde;
+pub mod snap;
 pub mod sort;
+pub mod split;
+pub mod stretch;
+pub mod strum;
+pub mod swing;
+pub mod tempo;
+pub mod thin;
 pub mod transpose;
+pub mod velocity;
+pub mod velocity_filter;
+pub mod voice;
 
-use crate::types::record::MtxtRecordLine;
-use std::collections::HashSet;
+use crate::BeatFraction;
+use crate::BeatTime;
+use crate::Interval;
+use crate::Note;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use anyhow::{Result, bail};
+use arpeggio::ArpeggioConfig;
+use diatonic::DiatonicTranspose;
+use echo::EchoConfig;
+use line::LineMode;
+use loop_region::LoopRegion;
+use monophonic::MonophonicPolicy;
+pub use pipeline::{TransformStep, apply_pipeline};
+#[cfg(feature = "serde")]
+pub use preset::{Preset, apply_transforms_from_json};
+use quantize::{HumanizeConfig, HumanizeDistribution};
+use retrograde::RetrogradeMode;
+use snap::ScaleSnap;
+use std::collections::{HashMap, HashSet};
+use strum::StrumConfig;
+use velocity::VelocityCompression;
 
+// notes without any velocity are played at full velocity
+pub(crate) const DEFAULT_VELOCITY: f32 = 1.0;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct TransformDescriptor {
     pub apply_directives: bool,
     pub extract_directives: bool,
     pub sort_by_time: bool,
     pub merge_notes: bool,
+    pub split_notes: bool,
+    /// Steps per beat, a power of 2, `quantize_step` takes precedence
     pub quantize_grid: u32,
+    /// Grid step as a fraction of a beat, e.g. `1/6` for eighth note triplets
+    pub quantize_step: Option<BeatFraction>,
     pub quantize_swing: f32,
     pub quantize_humanize: f32,
+    pub humanize_velocity: f32,
+    pub humanize_seed: Option<u64>,
+    pub humanize_distribution: HumanizeDistribution,
     pub transpose_amount: i32,
+    pub transpose_interval: Option<Interval>,
+    pub transpose_octaves: i32,
+    pub transpose_preserve_channels: HashSet<u16>,
+    pub diatonic_transpose: Option<DiatonicTranspose>,
+    pub snap_to_scale: Option<ScaleSnap>,
+    pub invert_around: Option<Note>,
+    pub invert_drop_out_of_range: bool,
+    pub invert_exempt_channels: HashSet<u16>,
     pub offset_amount: f32,
+    /// Beat offset per channel, applied after `offset_amount`.
+    /// Moving a record of the channel before beat 0 is an error.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::types::serde_str::channel_map")
+    )]
+    pub channel_offset: HashMap<u16, f32>,
     pub include_channels: HashSet<u16>,
     pub exclude_channels: HashSet<u16>,
     pub group_channels: bool,
+    /// Channel filters never drop tempo, time signatures and global meta,
+    /// set to false to remove them as well
+    pub keep_conductor: bool,
+    pub velocity_normalize: Option<(f32, f32)>,
+    pub velocity_compress: Option<VelocityCompression>,
+    pub velocity_per_channel: bool,
+    pub velocity_min: Option<f32>,
+    pub velocity_max: Option<f32>,
+    pub crop: Option<(BeatTime, BeatTime)>,
+    pub crop_drop_partial: bool,
+    pub crop_rebase: bool,
+    pub align_to_zero: bool,
+    pub loop_region: Option<LoopRegion>,
+    pub time_scale: f32,
+    pub time_scale_region: Option<(BeatTime, BeatTime)>,
+    pub fit_to_bars: Option<u32>,
+    pub preserve_wallclock: bool,
+    pub bake_tempo: Option<f32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::types::serde_str::channel_map")
+    )]
+    pub channel_map: HashMap<u16, u16>,
+    pub remove_empty_channels: bool,
+    pub compact_channels: bool,
+    pub retrograde: Option<RetrogradeMode>,
+    pub legato: Option<f32>,
+    pub note_length_percent: Option<f32>,
+    pub remove_controllers: Vec<String>,
+    pub dedup_cc: bool,
+    pub dedup_voices: bool,
+    pub hoist_voices: bool,
+    pub simplify_tempo: Option<f32>,
+    pub cc_thin_interval: f32,
+    pub cc_thin_min_delta: f32,
+    pub extract_line: Option<LineMode>,
+    pub extract_line_drop_overlapping: bool,
+    pub arpeggiate: Option<ArpeggioConfig>,
+    pub strum: Option<StrumConfig>,
+    pub monophonic_channels: Vec<u16>,
+    pub monophonic_gap: BeatTime,
+    pub monophonic_policy: MonophonicPolicy,
+    pub echo: Option<EchoConfig>,
+    pub remove_swing: Option<u32>,
 }
 
-pub fn apply_transforms(
-    records: &[MtxtRecordLine],
-    transforms: &TransformDescriptor,
-) -> Vec<MtxtRecordLine> {
-    let mut current_records = records.to_vec();
-
-    // order is important here
-
-    if transforms.apply_directives {
-        current_records = apply::transform(&current_records);
+// Every transform disabled, the records pass through unchanged
+impl Default for TransformDescriptor {
+    fn default() -> Self {
+        Self {
+            apply_directives: false,
+            extract_directives: false,
+            sort_by_time: false,
+            merge_notes: false,
+            split_notes: false,
+            quantize_grid: 0,
+            quantize_step: None,
+            quantize_swing: 0.0,
+            quantize_humanize: 0.0,
+            humanize_velocity: 0.0,
+            humanize_seed: None,
+            humanize_distribution: HumanizeDistribution::default(),
+            transpose_amount: 0,
+            transpose_interval: None,
+            transpose_octaves: 0,
+            transpose_preserve_channels: HashSet::new(),
+            diatonic_transpose: None,
+            snap_to_scale: None,
+            invert_around: None,
+            invert_drop_out_of_range: false,
+            invert_exempt_channels: HashSet::new(),
+            offset_amount: 0.0,
+            channel_offset: HashMap::new(),
+            include_channels: HashSet::new(),
+            exclude_channels: HashSet::new(),
+            group_channels: false,
+            keep_conductor: true,
+            velocity_normalize: None,
+            velocity_compress: None,
+            velocity_per_channel: false,
+            velocity_min: None,
+            velocity_max: None,
+            crop: None,
+            crop_drop_partial: false,
+            crop_rebase: false,
+            align_to_zero: false,
+            loop_region: None,
+            time_scale: 1.0,
+            time_scale_region: None,
+            fit_to_bars: None,
+            preserve_wallclock: false,
+            bake_tempo: None,
+            channel_map: HashMap::new(),
+            remove_empty_channels: false,
+            compact_channels: false,
+            retrograde: None,
+            legato: None,
+            note_length_percent: None,
+            remove_controllers: Vec::new(),
+            dedup_cc: false,
+            dedup_voices: false,
+            hoist_voices: false,
+            simplify_tempo: None,
+            cc_thin_interval: 0.0,
+            cc_thin_min_delta: 0.0,
+            extract_line: None,
+            extract_line_drop_overlapping: false,
+            arpeggiate: None,
+            strum: None,
+            monophonic_channels: Vec::new(),
+            monophonic_gap: BeatTime::zero(),
+            monophonic_policy: MonophonicPolicy::default(),
+            echo: None,
+            remove_swing: None,
+        }
     }
+}
 
-    if !transforms.include_channels.is_empty() {
-        current_records = include::transform(&current_records, &transforms.include_channels);
+impl TransformDescriptor {
+    /// Grid step of the quantization, `quantize_step` or `1/quantize_grid`
+    pub fn quantize_grid(&self) -> Option<BeatFraction> {
+        self.quantize_step.or_else(|| {
+            (self.quantize_grid > 0).then_some(BeatFraction {
+                numerator: 1,
+                denominator: self.quantize_grid,
+            })
+        })
     }
 
-    if !transforms.exclude_channels.is_empty() {
-        current_records = exclude::transform(&current_records, &transforms.exclude_channels);
-    }
+    /// Checks for contradictory or meaningless settings, the error lists every violation
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if self.merge_notes && !self.sort_by_time {
+            errors.push("merging notes requires sorting by time".to_string());
+        }
+        if self.quantize_swing != 0.0 && self.quantize_grid().is_none() {
+            errors.push("swing requires a quantize grid".to_string());
+        }
+        if self.quantize_grid != 0 && !self.quantize_grid.is_power_of_two() {
+            errors.push(format!(
+                "quantize grid must be a power of 2, got {}",
+                self.quantize_grid
+            ));
+        }
+        if self.quantize_step.is_some_and(|step| step.numerator == 0) {
+            errors.push("quantize step must not be zero".to_string());
+        }
+        if self.bake_tempo.is_some_and(|bpm| bpm <= 0.0) {
+            errors.push("baked tempo must be positive".to_string());
+        }
+        let mut both: Vec<u16> = self
+            .include_channels
+            .intersection(&self.exclude_channels)
+            .copied()
+            .collect();
+        if !both.is_empty() {
+            both.sort_unstable();
+            let channels: Vec<String> = both.iter().map(u16::to_string).collect();
+            errors.push(format!(
+                "channels both included and excluded: {}",
+                channels.join(", ")
+            ));
+        }
 
-    if transforms.transpose_amount != 0 {
-        current_records = transpose::transform(&current_records, transforms.transpose_amount);
+        if !errors.is_empty() {
+            bail!("Invalid transform settings: {}", errors.join("; "));
+        }
+        Ok(())
     }
+}
 
-    if transforms.offset_amount != 0.0 {
-        current_records = offset::transform(&current_records, transforms.offset_amount);
-    }
+impl TransformDescriptor {
+    /// The enabled transforms as steps, in the fixed order `apply_transforms` uses
+    pub fn to_pipeline(&self) -> Vec<TransformStep> {
+        let mut steps = Vec::new();
 
-    if transforms.merge_notes {
-        current_records = merge::transform(&current_records);
-    }
+        // order is important here
 
-    if transforms.quantize_grid > 0 {
-        current_records = quantize::transform(
-            &current_records,
-            transforms.quantize_grid,
-            transforms.quantize_swing,
-            transforms.quantize_humanize,
-        );
+        if self.apply_directives {
+            steps.push(TransformStep::ApplyDirectives);
+        }
+
+        if !self.include_channels.is_empty() {
+            steps.push(TransformStep::IncludeChannels(
+                self.include_channels.clone(),
+            ));
+        }
+
+        if !self.exclude_channels.is_empty() {
+            steps.push(TransformStep::ExcludeChannels(
+                self.exclude_channels.clone(),
+            ));
+        }
+
+        if !self.keep_conductor {
+            steps.push(TransformStep::RemoveConductor);
+        }
+
+        if self.velocity_min.is_some() || self.velocity_max.is_some() {
+            steps.push(TransformStep::FilterVelocity {
+                min: self.velocity_min,
+                max: self.velocity_max,
+            });
+        }
+
+        if !self.channel_map.is_empty() {
+            steps.push(TransformStep::MapChannels(self.channel_map.clone()));
+        }
+
+        if let Some((start, end)) = self.crop {
+            steps.push(TransformStep::Crop {
+                start,
+                end,
+                drop_partial: self.crop_drop_partial,
+                rebase: self.crop_rebase,
+            });
+        }
+
+        if self.align_to_zero {
+            steps.push(TransformStep::AlignToZero);
+        }
+
+        if let Some(config) = self.loop_region {
+            steps.push(TransformStep::Loop(config));
+        }
+
+        if self.time_scale != 1.0 {
+            steps.push(TransformStep::TimeScale {
+                factor: self.time_scale,
+                region: self.time_scale_region,
+                preserve_wallclock: self.preserve_wallclock,
+            });
+        }
+
+        if let Some(bars) = self.fit_to_bars {
+            steps.push(TransformStep::FitToBars {
+                bars,
+                preserve_wallclock: self.preserve_wallclock,
+            });
+        }
+
+        if let Some(bpm) = self.bake_tempo {
+            steps.push(TransformStep::BakeTempo(bpm));
+        }
+
+        if let Some(mode) = self.retrograde {
+            steps.push(TransformStep::Retrograde(mode));
+        }
+
+        let transpose_semitones = self.transpose_amount
+            + self.transpose_octaves * 12
+            + self.transpose_interval.map_or(0, |i| i.semitones());
+        if transpose_semitones != 0 {
+            steps.push(TransformStep::Transpose {
+                semitones: transpose_semitones,
+                preserve_channels: self.transpose_preserve_channels.clone(),
+            });
+        }
+
+        if let Some(config) = self.diatonic_transpose {
+            steps.push(TransformStep::DiatonicTranspose {
+                config,
+                preserve_channels: self.transpose_preserve_channels.clone(),
+            });
+        }
+
+        if let Some(pivot) = &self.invert_around {
+            steps.push(TransformStep::Invert {
+                pivot: pivot.clone(),
+                drop_out_of_range: self.invert_drop_out_of_range,
+                exempt_channels: self.invert_exempt_channels.clone(),
+            });
+        }
+
+        if let Some(config) = self.snap_to_scale {
+            steps.push(TransformStep::SnapToScale(config));
+        }
+
+        if self.offset_amount != 0.0 {
+            steps.push(TransformStep::Offset(self.offset_amount));
+        }
+
+        if !self.channel_offset.is_empty() {
+            steps.push(TransformStep::ChannelOffset(self.channel_offset.clone()));
+        }
+
+        if self.velocity_normalize.is_some() || self.velocity_compress.is_some() {
+            steps.push(TransformStep::Velocity {
+                normalize: self.velocity_normalize,
+                compress: self.velocity_compress,
+                per_channel: self.velocity_per_channel,
+            });
+        }
+
+        if !self.remove_controllers.is_empty() {
+            steps.push(TransformStep::RemoveControllers(
+                self.remove_controllers.clone(),
+            ));
+        }
+
+        if self.dedup_cc {
+            steps.push(TransformStep::DedupCc);
+        }
+
+        if self.dedup_voices || self.hoist_voices {
+            steps.push(TransformStep::Voices {
+                dedup: self.dedup_voices,
+                hoist: self.hoist_voices,
+            });
+        }
+
+        if let Some(tolerance) = self.simplify_tempo {
+            steps.push(TransformStep::SimplifyTempo(tolerance));
+        }
+
+        if self.cc_thin_interval > 0.0 || self.cc_thin_min_delta > 0.0 {
+            steps.push(TransformStep::ThinCc {
+                interval: self.cc_thin_interval,
+                min_delta: self.cc_thin_min_delta,
+            });
+        }
+
+        if self.merge_notes {
+            steps.push(TransformStep::MergeNotes);
+        }
+
+        if let Some(mode) = self.extract_line {
+            steps.push(TransformStep::ExtractLine {
+                mode,
+                drop_overlapping: self.extract_line_drop_overlapping,
+            });
+        }
+
+        if !self.monophonic_channels.is_empty() {
+            steps.push(TransformStep::Monophonic {
+                channels: self.monophonic_channels.clone(),
+                gap: self.monophonic_gap,
+                policy: self.monophonic_policy,
+            });
+        }
+
+        if let Some(config) = self.arpeggiate {
+            steps.push(TransformStep::Arpeggiate(config));
+        }
+
+        if let Some(config) = self.strum {
+            steps.push(TransformStep::Strum(config));
+        }
+
+        if let Some(config) = self.echo {
+            steps.push(TransformStep::Echo(config));
+        }
+
+        if let Some(grid) = self.remove_swing {
+            steps.push(TransformStep::RemoveSwing(grid));
+        }
+
+        if self.quantize_grid().is_some() || self.humanize_velocity > 0.0 {
+            steps.push(TransformStep::Quantize {
+                grid: self.quantize_grid(),
+                swing: self.quantize_swing,
+                humanize: HumanizeConfig {
+                    timing: self.quantize_humanize,
+                    velocity: self.humanize_velocity,
+                    seed: self.humanize_seed,
+                    distribution: self.humanize_distribution,
+                },
+            });
+        }
+
+        // legato sorts by itself
+        if self.sort_by_time && self.legato.is_none() {
+            steps.push(TransformStep::SortByTime);
+        }
+
+        if let Some(overlap) = self.legato {
+            steps.push(TransformStep::Legato(overlap));
+        }
+
+        if let Some(percent) = self.note_length_percent {
+            steps.push(TransformStep::NoteLength(percent));
+        }
+
+        if self.split_notes {
+            steps.push(TransformStep::SplitNotes);
+        }
+
+        if self.group_channels {
+            steps.push(TransformStep::GroupChannels);
+        }
+
+        if self.extract_directives {
+            steps.push(TransformStep::ExtractDirectives);
+        }
+
+        if self.remove_empty_channels {
+            steps.push(TransformStep::RemoveEmptyChannels);
+        }
+
+        // last, so the mapping can be reported from the result of the other steps
+        if self.compact_channels {
+            steps.push(TransformStep::CompactChannels);
+        }
+
+        steps
     }
+}
 
-    if transforms.sort_by_time {
-        current_records = sort::transform(&current_records);
+/// Validates the descriptor and applies its transforms in a fixed order,
+/// use [`apply_pipeline`] with [`TransformStep`]s to choose the order
+pub fn apply_transforms(
+    records: &[MtxtRecordLine],
+    transforms: &TransformDescriptor,
+) -> Result<Vec<MtxtRecordLine>> {
+    transforms.validate()?;
+    let mut current_records = records.to_vec();
+    for step in transforms.to_pipeline() {
+        // the step drops the records moved before the start, the descriptor reports them
+        if let TransformStep::ChannelOffset(offsets) = &step {
+            offset::check_channels(&current_records, offsets)?;
+        }
+        current_records = step.apply(&current_records);
     }
+    Ok(current_records)
+}
+
+// Whether a step with channel filters works on the record, notes and voices before the first
+// `ch=` are on channel 0, records without a channel (tempo, meta, global CCs) are never selected
+fn in_scope(record: &MtxtRecord, include: &HashSet<u16>, exclude: &HashSet<u16>) -> bool {
+    let channel = match record {
+        MtxtRecord::Note { channel, .. }
+        | MtxtRecord::NoteOn { channel, .. }
+        | MtxtRecord::NoteOff { channel, .. }
+        | MtxtRecord::Voice { channel, .. } => channel.unwrap_or(0),
+        MtxtRecord::ControlChange {
+            channel: Some(channel),
+            ..
+        } => *channel,
+        _ => return false,
+    };
+    (include.is_empty() || include.contains(&channel)) && !exclude.contains(&channel)
+}
+
+// Records a step with channel filters works on, and the ones it leaves unchanged.
+// Directives are resolved into the records first, so each side stands on its own,
+// CCs take the channel of the `ch=` directive before them as in playback.
+pub(crate) fn split_scope(
+    records: &[MtxtRecordLine],
+    include: &HashSet<u16>,
+    exclude: &HashSet<u16>,
+) -> (Vec<MtxtRecordLine>, Vec<MtxtRecordLine>) {
+    apply::transform(&apply::resolve_cc_channels(records))
+        .into_iter()
+        .partition(|line| in_scope(&line.record, include, exclude))
+}
 
-    if transforms.group_channels {
-        current_records = group::transform(&current_records);
+// Merges the processed records back by time,
+// both sides keep their own order and records without a time stay where they were
+pub(crate) fn merge_scope(
+    kept: Vec<MtxtRecordLine>,
+    processed: Vec<MtxtRecordLine>,
+) -> Vec<MtxtRecordLine> {
+    let mut records = Vec::with_capacity(kept.len() + processed.len());
+    let mut processed = processed.into_iter().peekable();
+    for line in kept {
+        if let Some(time) = line.record.time() {
+            while let Some(next) =
+                processed.next_if(|next| next.record.time().is_none_or(|t| t < time))
+            {
+                records.push(next);
+            }
+        }
+        records.push(line);
     }
+    records.extend(processed);
+    records
+}
 
-    if transforms.extract_directives {
-        current_records = extract::transform(&current_records);
+/// Applies the descriptors one after the other, each working on the output of the previous.
+/// Within a single descriptor the order of the transforms is fixed, a chain allows any order.
+///
+/// The channel filters of a step limit what the step works on instead of removing the other
+/// channels: the records of the other channels and the records without a channel are kept
+/// unchanged and merged back by time. Directives are resolved into the records first for such steps.
+pub fn apply_transform_chain(
+    records: &[MtxtRecordLine],
+    chain: &[TransformDescriptor],
+) -> Result<Vec<MtxtRecordLine>> {
+    let mut current_records = records.to_vec();
+
+    for transforms in chain {
+        if transforms.include_channels.is_empty() && transforms.exclude_channels.is_empty() {
+            current_records = apply_transforms(&current_records, transforms)?;
+            continue;
+        }
+
+        let (selected, kept) = split_scope(
+            &current_records,
+            &transforms.include_channels,
+            &transforms.exclude_channels,
+        );
+        let processed = apply_transforms(&selected, transforms)?;
+        current_records = merge_scope(kept, processed);
     }
 
-    current_records
+    Ok(current_records)
 }
@@ -0,0 +1,315 @@
+use super::arpeggio::pitch_value;
+use super::merge::pair_note_events;
+use crate::BeatTime;
+use crate::types::note::{Note, NoteTarget};
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Which note survives when several notes start together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum MonophonicPolicy {
+    #[default]
+    Highest,
+    /// The note written last in the file
+    Last,
+}
+
+struct MonoNote {
+    idx: usize,
+    end: BeatTime,
+    note: Note,
+}
+
+/// Makes the listed channels monophonic: of notes starting together only one is kept
+/// (chords played by an alias are reduced to one of their notes), and every note is
+/// shortened to end `gap` before the next onset on its channel. If the gap doesn't fit
+/// before the next onset, the note ends exactly at it.
+/// Paired note on / off events count as one note, a shortened note moves its off event.
+/// Unpaired note ons and offs are kept as they are.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    channels: &[u16],
+    gap: BeatTime,
+    policy: MonophonicPolicy,
+) -> Vec<MtxtRecordLine> {
+    if channels.is_empty() {
+        return records.to_vec();
+    }
+
+    let pick = |candidates: &[Note]| match policy {
+        MonophonicPolicy::Highest => candidates
+            .iter()
+            .max_by(|a, b| pitch_value(a).total_cmp(&pitch_value(b)))
+            .cloned(),
+        MonophonicPolicy::Last => candidates.last().cloned(),
+    };
+
+    let pairs = pair_note_events(records);
+    let pair_ends: HashMap<usize, usize> = pairs.iter().map(|(on, off)| (*off, *on)).collect();
+    let mut current_channel: u16 = 0;
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut aliases: HashMap<&str, &[Note]> = HashMap::new();
+    // winning note of every onset per channel
+    let mut onsets: HashMap<u16, BTreeMap<BeatTime, MonoNote>> = HashMap::new();
+    let mut dropped: HashSet<usize> = HashSet::new();
+
+    for (idx, line) in records.iter().enumerate() {
+        let (time, note, end, channel) = match &line.record {
+            MtxtRecord::ChannelDirective { channel } => {
+                current_channel = *channel;
+                continue;
+            }
+            MtxtRecord::DurationDirective { duration } => {
+                current_duration = *duration;
+                continue;
+            }
+            MtxtRecord::AliasDef { value } => {
+                aliases.insert(value.name.as_str(), &value.notes);
+                continue;
+            }
+            MtxtRecord::Note {
+                time,
+                note,
+                duration,
+                channel,
+                ..
+            } => (
+                *time,
+                note,
+                *time + duration.unwrap_or(current_duration),
+                channel,
+            ),
+            MtxtRecord::NoteOn {
+                time,
+                note,
+                channel,
+                ..
+            } => match pairs.get(&idx).and_then(|off| records[*off].record.time()) {
+                Some(end) => (*time, note, end, channel),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let channel = channel.unwrap_or(current_channel);
+        if !channels.contains(&channel) {
+            continue;
+        }
+        let resolved = match note {
+            NoteTarget::Note(n) => Some(n.clone()),
+            NoteTarget::AliasKey(name) => aliases.get(name.as_str()).and_then(|n| pick(n)),
+            NoteTarget::Alias(def) => pick(&def.notes),
+            NoteTarget::Range { low, high } => pick(&Note::chromatic_range(low, high)),
+        };
+        let Some(resolved) = resolved else {
+            continue;
+        };
+        let candidate = MonoNote {
+            idx,
+            end,
+            note: resolved,
+        };
+
+        let channel_onsets = onsets.entry(channel).or_default();
+        match channel_onsets.get(&time) {
+            Some(existing)
+                if policy == MonophonicPolicy::Highest
+                    && pitch_value(&existing.note) >= pitch_value(&candidate.note) =>
+            {
+                dropped.insert(idx);
+            }
+            _ => {
+                if let Some(previous) = channel_onsets.insert(time, candidate) {
+                    dropped.insert(previous.idx);
+                }
+            }
+        }
+    }
+
+    // new duration and note of every kept record, duration is None if unchanged
+    let mut kept: HashMap<usize, (Option<BeatTime>, Note)> = HashMap::new();
+    for channel_onsets in onsets.values() {
+        let mut iter = channel_onsets.iter().peekable();
+        while let Some((start, mono)) = iter.next() {
+            let mut duration = None;
+            if let Some((next_start, _)) = iter.peek() {
+                let mut limit = **next_start - gap;
+                if limit <= *start {
+                    limit = **next_start;
+                }
+                if mono.end > limit {
+                    duration = Some(limit - *start);
+                }
+            }
+            kept.insert(mono.idx, (duration, mono.note.clone()));
+        }
+    }
+
+    records
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| {
+            // the off event of a dropped note on goes with it
+            !dropped.contains(idx) && pair_ends.get(idx).is_none_or(|on| !dropped.contains(on))
+        })
+        .map(|(idx, line)| {
+            let mut new_line = line.clone();
+            if let Some((new_duration, note)) = kept.get(&idx) {
+                match &mut new_line.record {
+                    MtxtRecord::Note {
+                        note: target,
+                        duration,
+                        ..
+                    } => {
+                        if new_duration.is_some() {
+                            *duration = *new_duration;
+                        }
+                        *target = NoteTarget::Note(note.clone());
+                    }
+                    MtxtRecord::NoteOn { note: target, .. } => {
+                        *target = NoteTarget::Note(note.clone());
+                    }
+                    _ => {}
+                }
+            } else if let Some(on_idx) = pair_ends.get(&idx)
+                && let Some((new_duration, note)) = kept.get(on_idx)
+                && let MtxtRecord::NoteOff {
+                    time, note: target, ..
+                } = &mut new_line.record
+            {
+                if let (Some(new_duration), Some(on_time)) =
+                    (new_duration, records[*on_idx].record.time())
+                {
+                    *time = on_time + *new_duration;
+                }
+                *target = NoteTarget::Note(note.clone());
+            }
+            new_line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_monophonic_legato_overlaps() {
+        let input = r#"
+mtxt 1.0
+ch=1
+dur=0.6
+0.0 note C3
+0.5 note D3
+1.0 note E3 dur=0.4
+1.5 note F3 dur=1
+2.0 note G3 dur=0.5 ch=2
+2.25 note A3 dur=0.5 ch=2
+2.5 note G3
+"#;
+        let trimmed = r#"
+mtxt 1.0
+ch=1
+dur=0.6
+0.0 note C3 dur=0.5
+0.5 note D3 dur=0.5
+1.0 note E3 dur=0.4
+1.5 note F3 dur=1.0
+2.0 note G3 dur=0.5 ch=2
+2.25 note A3 dur=0.5 ch=2
+2.5 note G3
+"#;
+        let with_gap = r#"
+mtxt 1.0
+ch=1
+dur=0.6
+0.0 note C3 dur=0.375
+0.5 note D3 dur=0.375
+1.0 note E3 dur=0.375
+1.5 note F3 dur=0.875
+2.0 note G3 dur=0.5 ch=2
+2.25 note A3 dur=0.5 ch=2
+2.5 note G3
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &[1], BeatTime::zero(), MonophonicPolicy::Highest),
+            trimmed,
+        );
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &[1],
+                    BeatTime::from_parts(0, 0.125),
+                    MonophonicPolicy::Highest,
+                )
+            },
+            with_gap,
+        );
+    }
+
+    #[test]
+    fn test_monophonic_simultaneous_onsets() {
+        let input = r#"
+mtxt 1.0
+alias Dm D3,F3,A3
+0.0 note E3 dur=2
+0.0 note C3 dur=2
+1.0 note Dm
+"#;
+        let highest = r#"
+mtxt 1.0
+alias Dm D3,F3,A3
+0.0 note E3 dur=1.0
+1.0 note A3
+"#;
+        let last = r#"
+mtxt 1.0
+alias Dm D3,F3,A3
+0.0 note C3 dur=1.0
+1.0 note A3
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &[0], BeatTime::zero(), MonophonicPolicy::Highest),
+            highest,
+        );
+        assert_eq_records(
+            input,
+            |r| transform(r, &[0], BeatTime::zero(), MonophonicPolicy::Last),
+            last,
+        );
+    }
+
+    #[test]
+    fn test_monophonic_note_on_off() {
+        let input = r#"
+mtxt 1.0
+0.0 on C3
+0.0 on E3
+1.0 note G3 dur=2
+2.0 off C3
+2.0 off E3
+4.0 on A3
+5.0 off A3
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 on E3
+1.0 note G3 dur=2
+1.0 off E3
+4.0 on A3
+5.0 off A3
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, &[0], BeatTime::zero(), MonophonicPolicy::Highest),
+            expected,
+        );
+    }
+}
@@ -1,5 +1,8 @@
+use super::{merge_scope, split_scope};
 use crate::BeatTime;
 use crate::types::record::MtxtRecordLine;
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 pub fn transform(records: &[MtxtRecordLine], offset: f32) -> Vec<MtxtRecordLine> {
     if offset == 0.0 {
@@ -31,6 +34,50 @@ pub fn transform(records: &[MtxtRecordLine], offset: f32) -> Vec<MtxtRecordLine>
         .collect()
 }
 
+/// Shifts the notes, voices and CCs of each channel by its own offset in beats, e.g. to line up
+/// overdubs recorded with clock drift. Directives are resolved into the records first, records
+/// without a channel stay in place. Records moved before beat 0 are removed as in `transform`,
+/// `check_channels` reports them instead.
+pub fn transform_channels(
+    records: &[MtxtRecordLine],
+    offsets: &HashMap<u16, f32>,
+) -> Vec<MtxtRecordLine> {
+    let sorted: BTreeMap<u16, f32> = offsets.iter().map(|(ch, offset)| (*ch, *offset)).collect();
+    let mut current_records = records.to_vec();
+    for (channel, offset) in sorted {
+        if offset == 0.0 {
+            continue;
+        }
+        let (selected, kept) =
+            split_scope(&current_records, &HashSet::from([channel]), &HashSet::new());
+        current_records = merge_scope(kept, transform(&selected, offset));
+    }
+    current_records
+}
+
+/// Fails if a negative channel offset would move a record of its channel before beat 0
+pub fn check_channels(records: &[MtxtRecordLine], offsets: &HashMap<u16, f32>) -> Result<()> {
+    let sorted: BTreeMap<u16, f32> = offsets.iter().map(|(ch, offset)| (*ch, *offset)).collect();
+    for (channel, offset) in sorted {
+        if offset >= 0.0 {
+            continue;
+        }
+        let (selected, _) = split_scope(records, &HashSet::from([channel]), &HashSet::new());
+        let first = selected.iter().filter_map(|line| line.record.time()).min();
+        if let Some(first) = first
+            && first.as_f64() < -offset as f64
+        {
+            bail!(
+                "offset {} of channel {} moves the event at beat {} before the start",
+                offset,
+                channel,
+                first
+            );
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,4 +137,45 @@ ch=1
 
         assert_eq_records(input, |r| transform(r, -1.5), expected);
     }
+
+    fn transform_channels_2_3(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+        transform_channels(records, &HashMap::from([(2, 0.25), (3, -0.5)]))
+    }
+
+    #[test]
+    fn test_channel_offset() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 120
+ch=2
+1.0 note C4
+1.5 cc pan 0.3
+2.0 note E4
+ch=3
+1.0 note G4
+1.5 cc volume 0.5 ch=3
+2.0 note B4
+2.0 note D5 ch=1
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 120
+0.5 note G4 ch=3
+1.0 cc volume 0.5 ch=3
+1.25 note C4 ch=2
+1.5 note B4 ch=3
+1.75 cc pan 0.3 ch=2
+2.0 note D5 ch=1
+2.25 note E4 ch=2
+"#;
+        assert_eq_records(input, transform_channels_2_3, expected);
+
+        let records = crate::parse_mtxt(input).unwrap().records;
+        assert!(check_channels(&records, &HashMap::from([(3, -1.0)])).is_ok());
+        let error = check_channels(&records, &HashMap::from([(3, -1.25)])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "offset -1.25 of channel 3 moves the event at beat 1.0 before the start"
+        );
+    }
 }
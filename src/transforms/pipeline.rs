@@ -0,0 +1,539 @@
+use super::arpeggio::{self, ArpeggioConfig};
+use super::diatonic::{self, DiatonicTranspose};
+use super::echo::{self, EchoConfig};
+use super::line::{self, LineMode};
+use super::loop_region::{self, LoopRegion};
+use super::monophonic::{self, MonophonicPolicy};
+use super::quantize::{self, HumanizeConfig};
+use super::retrograde::{self, RetrogradeMode};
+use super::snap::{self, ScaleSnap};
+use super::strum::{self, StrumConfig};
+use super::velocity::{self, VelocityCompression};
+use super::{
+    align, apply, articulation, bake, channels, crop, dedup, exclude, extract, fit, group, include,
+    invert, legato, merge, offset, remap, remove_cc, sort, split, stretch, swing, tempo, thin,
+    transpose, velocity_filter, voice,
+};
+use super::{merge_scope, split_scope};
+use crate::types::record::MtxtRecordLine;
+use crate::{BeatFraction, BeatTime, Interval, Note};
+use anyhow::{Result, anyhow, bail};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// A single transform with its parameters, see the module of each transform for details.
+/// A list of steps is applied in order by [`apply_pipeline`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(rename_all = "kebab-case", deny_unknown_fields)
+)]
+pub enum TransformStep {
+    ApplyDirectives,
+    ExtractDirectives,
+    SortByTime,
+    MergeNotes,
+    SplitNotes,
+    IncludeChannels(HashSet<u16>),
+    ExcludeChannels(HashSet<u16>),
+    GroupChannels,
+    RemoveConductor,
+    FilterVelocity {
+        #[cfg_attr(feature = "serde", serde(default))]
+        min: Option<f32>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        max: Option<f32>,
+    },
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::types::serde_str::channel_map")
+    )]
+    MapChannels(HashMap<u16, u16>),
+    Crop {
+        start: BeatTime,
+        end: BeatTime,
+        #[cfg_attr(feature = "serde", serde(default))]
+        drop_partial: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        rebase: bool,
+    },
+    AlignToZero,
+    Loop(LoopRegion),
+    TimeScale {
+        factor: f32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        region: Option<(BeatTime, BeatTime)>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        preserve_wallclock: bool,
+    },
+    FitToBars {
+        bars: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        preserve_wallclock: bool,
+    },
+    BakeTempo(f32),
+    Retrograde(RetrogradeMode),
+    Transpose {
+        semitones: i32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        preserve_channels: HashSet<u16>,
+    },
+    DiatonicTranspose {
+        config: DiatonicTranspose,
+        #[cfg_attr(feature = "serde", serde(default))]
+        preserve_channels: HashSet<u16>,
+    },
+    Invert {
+        pivot: Note,
+        #[cfg_attr(feature = "serde", serde(default))]
+        drop_out_of_range: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        exempt_channels: HashSet<u16>,
+    },
+    SnapToScale(ScaleSnap),
+    Offset(f32),
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::types::serde_str::channel_map")
+    )]
+    ChannelOffset(HashMap<u16, f32>),
+    Velocity {
+        #[cfg_attr(feature = "serde", serde(default))]
+        normalize: Option<(f32, f32)>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        compress: Option<VelocityCompression>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        per_channel: bool,
+    },
+    RemoveControllers(Vec<String>),
+    DedupCc,
+    Voices {
+        #[cfg_attr(feature = "serde", serde(default))]
+        dedup: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        hoist: bool,
+    },
+    SimplifyTempo(f32),
+    ThinCc {
+        #[cfg_attr(feature = "serde", serde(default))]
+        interval: f32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        min_delta: f32,
+    },
+    ExtractLine {
+        mode: LineMode,
+        #[cfg_attr(feature = "serde", serde(default))]
+        drop_overlapping: bool,
+    },
+    Monophonic {
+        channels: Vec<u16>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        gap: BeatTime,
+        #[cfg_attr(feature = "serde", serde(default))]
+        policy: MonophonicPolicy,
+    },
+    Arpeggiate(ArpeggioConfig),
+    Strum(StrumConfig),
+    Echo(EchoConfig),
+    RemoveSwing(u32),
+    Quantize {
+        #[cfg_attr(feature = "serde", serde(default))]
+        grid: Option<BeatFraction>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        swing: f32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        humanize: HumanizeConfig,
+    },
+    Legato(f32),
+    NoteLength(f32),
+    RemoveEmptyChannels,
+    CompactChannels,
+    /// Applies the step to the notes, voices and CCs of the channels only, the other records
+    /// pass through unchanged. Directives are resolved into the records first.
+    OnChannels {
+        channels: HashSet<u16>,
+        step: Box<TransformStep>,
+    },
+}
+
+impl TransformStep {
+    pub fn apply(&self, records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+        match self {
+            Self::ApplyDirectives => apply::transform(records),
+            Self::ExtractDirectives => extract::transform(records),
+            Self::SortByTime => sort::transform(records),
+            Self::MergeNotes => merge::transform(records),
+            Self::SplitNotes => split::transform(records),
+            Self::IncludeChannels(channels) => include::transform(records, channels),
+            Self::ExcludeChannels(channels) => exclude::transform(records, channels),
+            Self::GroupChannels => group::transform(records),
+            Self::RemoveConductor => channels::remove_conductor(records),
+            Self::FilterVelocity { min, max } => velocity_filter::transform(records, *min, *max),
+            Self::MapChannels(map) => remap::transform(records, map),
+            Self::Crop {
+                start,
+                end,
+                drop_partial,
+                rebase,
+            } => crop::transform(records, *start, *end, *drop_partial, *rebase),
+            Self::AlignToZero => align::transform(records),
+            Self::Loop(config) => loop_region::transform(records, config),
+            Self::TimeScale {
+                factor,
+                region,
+                preserve_wallclock,
+            } => stretch::transform(records, *factor, *region, *preserve_wallclock),
+            Self::FitToBars {
+                bars,
+                preserve_wallclock,
+            } => fit::transform(records, *bars, *preserve_wallclock),
+            Self::BakeTempo(bpm) => bake::transform(records, *bpm),
+            Self::Retrograde(mode) => retrograde::transform(records, *mode),
+            Self::Transpose {
+                semitones,
+                preserve_channels,
+            } => transpose::transform(records, *semitones, preserve_channels),
+            Self::DiatonicTranspose {
+                config,
+                preserve_channels,
+            } => diatonic::transform(records, config, preserve_channels),
+            Self::Invert {
+                pivot,
+                drop_out_of_range,
+                exempt_channels,
+            } => invert::transform(records, pivot, *drop_out_of_range, exempt_channels),
+            Self::SnapToScale(config) => snap::transform(records, config),
+            Self::Offset(amount) => offset::transform(records, *amount),
+            Self::ChannelOffset(offsets) => offset::transform_channels(records, offsets),
+            Self::Velocity {
+                normalize,
+                compress,
+                per_channel,
+            } => velocity::transform(records, *normalize, *compress, *per_channel),
+            Self::RemoveControllers(names) => remove_cc::transform(records, names),
+            Self::DedupCc => dedup::transform(records),
+            Self::Voices { dedup, hoist } => voice::transform(records, *dedup, *hoist),
+            Self::SimplifyTempo(tolerance) => tempo::transform(records, *tolerance),
+            Self::ThinCc {
+                interval,
+                min_delta,
+            } => thin::transform(records, *interval, *min_delta),
+            Self::ExtractLine {
+                mode,
+                drop_overlapping,
+            } => line::transform(records, *mode, *drop_overlapping),
+            Self::Monophonic {
+                channels,
+                gap,
+                policy,
+            } => monophonic::transform(records, channels, *gap, *policy),
+            Self::Arpeggiate(config) => arpeggio::transform(records, config),
+            Self::Strum(config) => strum::transform(records, config),
+            Self::Echo(config) => echo::transform(records, config),
+            Self::RemoveSwing(grid) => swing::transform(records, *grid),
+            Self::Quantize {
+                grid,
+                swing,
+                humanize,
+            } => quantize::transform(records, *grid, *swing, humanize),
+            // legato needs the records in time order
+            Self::Legato(overlap) => legato::transform(&sort::transform(records), *overlap),
+            Self::NoteLength(percent) => articulation::transform(records, *percent),
+            Self::RemoveEmptyChannels => channels::remove_empty(records),
+            Self::CompactChannels => channels::compact(records),
+            Self::OnChannels { channels, step } => {
+                let (selected, kept) = split_scope(records, channels, &HashSet::new());
+                merge_scope(kept, step.apply(&selected))
+            }
+        }
+    }
+}
+
+fn parse_channels(value: &str) -> Result<HashSet<u16>> {
+    value
+        .split(',')
+        .map(|channel| {
+            channel
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| anyhow!("Invalid channel: {}", channel))
+        })
+        .collect()
+}
+
+/// Grid step of `--quantize`: a fraction of a beat (e.g. `1/6`),
+/// or the number of steps per beat (e.g. `16` for `1/16`)
+pub fn parse_grid(value: &str) -> Result<BeatFraction> {
+    let step: BeatFraction = match value.parse::<u32>() {
+        Ok(grid) => BeatFraction::new(1, grid)?,
+        Err(_) => value.parse()?,
+    };
+    if step.numerator == 0 {
+        bail!("Quantize grid must not be zero");
+    }
+    Ok(step)
+}
+
+fn parse_range(value: &str) -> Result<(BeatTime, BeatTime)> {
+    let (start, end) = value
+        .split_once(',')
+        .ok_or_else(|| anyhow!("Expected START,END, got {}", value))?;
+    Ok((start.trim().parse()?, end.trim().parse()?))
+}
+
+/// Text form of a step used by the `--transform` CLI option: the name of the matching CLI flag,
+/// followed by `=` and its value for transforms with parameters, e.g. `quantize=16`,
+/// `transpose=P5`, `crop=4,8` or `map-channel=3:1,1:3`.
+/// The other settings of a transform take their default values.
+/// A `@` and a list of channels limits the step to them, e.g. `transpose=12@2` or `sort@1,2`.
+impl FromStr for TransformStep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((step, channels)) = s.rsplit_once('@') {
+            return Ok(Self::OnChannels {
+                channels: parse_channels(channels)?,
+                step: Box::new(step.parse()?),
+            });
+        }
+
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (s.trim(), None),
+        };
+        let required = || value.ok_or_else(|| anyhow!("Transform {} expects a value", name));
+
+        let step = match name {
+            "apply-directives" => Self::ApplyDirectives,
+            "extract-directives" => Self::ExtractDirectives,
+            "sort" => Self::SortByTime,
+            "merge-notes" => Self::MergeNotes,
+            "split-notes" => Self::SplitNotes,
+            "group-channels" => Self::GroupChannels,
+            "align-to-zero" => Self::AlignToZero,
+            "dedup-cc" => Self::DedupCc,
+            "dedup-voices" => Self::Voices {
+                dedup: true,
+                hoist: false,
+            },
+            "hoist-voices" => Self::Voices {
+                dedup: false,
+                hoist: true,
+            },
+            "remove-empty-channels" => Self::RemoveEmptyChannels,
+            "remove-conductor" => Self::RemoveConductor,
+            "compact-channels" => Self::CompactChannels,
+            "retrograde" => Self::Retrograde(RetrogradeMode::Reposition),
+            "include-channels" => Self::IncludeChannels(parse_channels(required()?)?),
+            "exclude-channels" => Self::ExcludeChannels(parse_channels(required()?)?),
+            "map-channel" => {
+                let mut map = HashMap::new();
+                for mapping in required()?.split(',') {
+                    let (from, to) = mapping
+                        .split_once(':')
+                        .and_then(|(from, to)| {
+                            Some((from.trim().parse().ok()?, to.trim().parse().ok()?))
+                        })
+                        .ok_or_else(|| anyhow!("Invalid channel mapping: {}", mapping))?;
+                    map.insert(from, to);
+                }
+                Self::MapChannels(map)
+            }
+            "transpose" => {
+                let value = required()?;
+                let semitones = match value.parse::<i32>() {
+                    Ok(semitones) => semitones,
+                    Err(_) => value.parse::<Interval>()?.semitones(),
+                };
+                Self::Transpose {
+                    semitones,
+                    preserve_channels: HashSet::new(),
+                }
+            }
+            "offset" => Self::Offset(required()?.parse()?),
+            "channel-offset" => {
+                let mut offsets = HashMap::new();
+                for entry in required()?.split(',') {
+                    let (channel, offset) = entry
+                        .split_once(':')
+                        .and_then(|(channel, offset)| {
+                            Some((channel.trim().parse().ok()?, offset.trim().parse().ok()?))
+                        })
+                        .ok_or_else(|| anyhow!("Invalid channel offset: {}", entry))?;
+                    offsets.insert(channel, offset);
+                }
+                Self::ChannelOffset(offsets)
+            }
+            "quantize" => Self::Quantize {
+                grid: Some(parse_grid(required()?)?),
+                swing: 0.0,
+                humanize: HumanizeConfig::default(),
+            },
+            "crop" => {
+                let (start, end) = parse_range(required()?)?;
+                Self::Crop {
+                    start,
+                    end,
+                    drop_partial: false,
+                    rebase: false,
+                }
+            }
+            "time-scale" => Self::TimeScale {
+                factor: required()?.parse()?,
+                region: None,
+                preserve_wallclock: false,
+            },
+            "fit-to-bars" => Self::FitToBars {
+                bars: required()?.parse()?,
+                preserve_wallclock: false,
+            },
+            "bake-tempo" => Self::BakeTempo(required()?.parse()?),
+            "invert" => Self::Invert {
+                pivot: required()?.parse()?,
+                drop_out_of_range: false,
+                exempt_channels: HashSet::from([9]),
+            },
+            "legato" => Self::Legato(value.unwrap_or("0").parse()?),
+            "note-length" => Self::NoteLength(required()?.parse()?),
+            "remove-cc" => Self::RemoveControllers(
+                required()?
+                    .split(',')
+                    .map(|n| n.trim().to_string())
+                    .collect(),
+            ),
+            "simplify-tempo" => Self::SimplifyTempo(required()?.parse()?),
+            "remove-swing" => Self::RemoveSwing(required()?.parse()?),
+            _ => bail!("Unknown transform: {}", name),
+        };
+        Ok(step)
+    }
+}
+
+/// Applies the steps one after the other, each working on the output of the previous
+pub fn apply_pipeline(records: &[MtxtRecordLine], steps: &[TransformStep]) -> Vec<MtxtRecordLine> {
+    let mut current_records = records.to_vec();
+    for step in steps {
+        current_records = step.apply(&current_records);
+    }
+    current_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MtxtFile, parse_mtxt};
+
+    const INPUT: &str = r#"mtxt 1.0
+0.0 note C4 dur=1.0
+1.1 note D4 dur=1.0
+2.6 note E4 dur=1.0
+"#;
+
+    // Applies steps in their text form, separated by spaces
+    fn run(steps: &str) -> String {
+        let steps: Vec<TransformStep> =
+            steps.split(' ').map(|step| step.parse().unwrap()).collect();
+        let records = parse_mtxt(INPUT).unwrap().records;
+        MtxtFile::from_records(apply_pipeline(&records, &steps)).to_string()
+    }
+
+    #[test]
+    fn test_crop_offset_order() {
+        // cropping first keeps D4 and E4 (shortened to the crop end) and moves them later
+        let crop_then_offset = r#"mtxt 1.0
+2.1 note D4 dur=1.0
+3.6 note E4 dur=0.4
+"#;
+        assert_eq!(run("crop=1,3 offset=1"), crop_then_offset);
+
+        // moving first brings C4 into the range and pushes E4 out of it
+        let offset_then_crop = r#"mtxt 1.0
+1.0 note C4 dur=1.0
+2.1 note D4 dur=0.9
+"#;
+        assert_eq!(run("offset=1 crop=1,3"), offset_then_crop);
+    }
+
+    #[test]
+    fn test_quantize_offset_order() {
+        // the offset is not on the grid, the last step decides where the notes land
+        let quantize_then_offset = r#"mtxt 1.0
+0.3 note C4 dur=1.0
+1.3 note D4 dur=1.0
+2.8 note E4 dur=1.0
+"#;
+        assert_eq!(run("quantize=4 offset=0.3"), quantize_then_offset);
+
+        let offset_then_quantize = r#"mtxt 1.0
+0.25 note C4 dur=1.0
+1.5 note D4 dur=1.0
+3.0 note E4 dur=1.0
+"#;
+        assert_eq!(run("offset=0.3 quantize=4"), offset_then_quantize);
+    }
+
+    #[test]
+    fn test_scoped_steps() {
+        let input = r#"mtxt 1.0
+ch=2
+0.0 note C4 dur=1.0
+ch=10
+0.1 note D4 dur=1.0
+0.1 cc volume 0.5
+1.1 note E4 dur=1.0 ch=2
+"#;
+        let steps: Vec<TransformStep> = ["transpose=+12@2", "quantize=4@10"]
+            .iter()
+            .map(|step| step.parse().unwrap())
+            .collect();
+        let records = parse_mtxt(input).unwrap().records;
+        // channel 2 is transposed but not quantized, channel 10 the other way round,
+        // the CC is quantized with the notes of its channel
+        let expected = r#"mtxt 1.0
+0.0 note C5 dur=1.0 ch=2
+0.0 note D4 dur=1.0 ch=10
+0.0 cc volume 0.5 ch=10
+1.1 note E5 dur=1.0 ch=2
+"#;
+        assert_eq!(
+            MtxtFile::from_records(apply_pipeline(&records, &steps)).to_string(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(
+            "transpose=P5".parse::<TransformStep>().unwrap(),
+            TransformStep::Transpose {
+                semitones: 7,
+                preserve_channels: HashSet::new(),
+            }
+        );
+        assert_eq!(
+            "map-channel=3:1,1:3".parse::<TransformStep>().unwrap(),
+            TransformStep::MapChannels(HashMap::from([(3, 1), (1, 3)]))
+        );
+        assert_eq!(
+            "channel-offset=2:0.05,3:-0.5"
+                .parse::<TransformStep>()
+                .unwrap(),
+            TransformStep::ChannelOffset(HashMap::from([(2, 0.05), (3, -0.5)]))
+        );
+        assert_eq!(
+            "include-channels=1, 2".parse::<TransformStep>().unwrap(),
+            TransformStep::IncludeChannels(HashSet::from([1, 2]))
+        );
+        assert_eq!(
+            "sort@1,2".parse::<TransformStep>().unwrap(),
+            TransformStep::OnChannels {
+                channels: HashSet::from([1, 2]),
+                step: Box::new(TransformStep::SortByTime),
+            }
+        );
+        assert!("sort@drums".parse::<TransformStep>().is_err());
+        assert!("quantize".parse::<TransformStep>().is_err());
+        assert!("reverse".parse::<TransformStep>().is_err());
+    }
+}
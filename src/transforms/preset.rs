@@ -0,0 +1,64 @@
+use super::{TransformDescriptor, TransformStep, apply_pipeline, apply_transforms};
+use crate::types::record::MtxtRecordLine;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Transform settings stored in a JSON or TOML file. The descriptor is applied first,
+/// then the pipeline steps in order. Missing keys take their defaults, unknown keys are errors.
+///
+/// ```toml
+/// pipeline = ["dedup-cc", { quantize = { grid = { numerator = 1, denominator = 16 } } }]
+///
+/// [transforms]
+/// sort_by_time = true
+/// velocity_normalize = [0.2, 0.9]
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub transforms: TransformDescriptor,
+    pub pipeline: Vec<TransformStep>,
+}
+
+impl Preset {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid JSON preset")
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).context("Invalid TOML preset")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn apply(&self, records: &[MtxtRecordLine]) -> Result<Vec<MtxtRecordLine>> {
+        let records = apply_transforms(records, &self.transforms)?;
+        Ok(apply_pipeline(&records, &self.pipeline))
+    }
+}
+
+impl TransformDescriptor {
+    /// The settings as JSON, e.g. to store a user's configuration
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Settings saved with `to_json`, missing keys take their defaults
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid JSON transform settings")
+    }
+}
+
+/// Applies a preset given as JSON, see [`Preset`]
+pub fn apply_transforms_from_json(
+    records: &[MtxtRecordLine],
+    json: &str,
+) -> Result<Vec<MtxtRecordLine>> {
+    Preset::from_json(json)?.apply(records)
+}
@@ -1,39 +1,97 @@
+use super::DEFAULT_VELOCITY;
+use crate::BeatFraction;
 use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
+/// Shape of the random deviations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum HumanizeDistribution {
+    /// Every deviation up to the full amount is equally likely
+    #[default]
+    Uniform,
+    /// Small deviations are more likely, the full amount is at three standard deviations
+    Gaussian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct HumanizeConfig {
+    /// Timing randomization (0.0 to 1.0), relative to the grid
+    pub timing: f32,
+    /// Largest velocity change of a note (e.g. `0.1` for +-0.1)
+    pub velocity: f32,
+    /// Seed of the random generator, the output is reproducible if set
+    pub seed: Option<u64>,
+    pub distribution: HumanizeDistribution,
+}
+
+// Random value between -1.0 and 1.0
+fn jitter(rng: &mut StdRng, distribution: HumanizeDistribution) -> f64 {
+    match distribution {
+        HumanizeDistribution::Uniform => rng.gen_range(-1.0..=1.0),
+        HumanizeDistribution::Gaussian => {
+            // Box-Muller transform, truncated at 3 sigma
+            let u1: f64 = 1.0 - rng.r#gen::<f64>();
+            let u2: f64 = rng.r#gen();
+            let normal = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+            (normal / 3.0).clamp(-1.0, 1.0)
+        }
+    }
+}
+
+/// Snaps times to multiples of the `grid` step (skipped if `None`), then randomizes
+/// the timing and the velocity of the notes as set by `humanize`.
+/// Random values are drawn in record order, so a seed gives the same output on every run.
 pub fn transform(
     records: &[MtxtRecordLine],
-    grid: u32,
+    grid: Option<BeatFraction>,
     swing: f32,
-    humanize: f32,
+    humanize: &HumanizeConfig,
 ) -> Vec<MtxtRecordLine> {
-    if grid == 0 {
+    if grid.is_none() && humanize.velocity <= 0.0 {
         return records.to_vec();
     }
 
+    let mut rng = match humanize.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let humanize_timing = grid.is_some() && humanize.timing > 0.0;
+    let mut current_velocity = DEFAULT_VELOCITY;
+
     records
         .iter()
         .map(|line| {
             let mut new_line = line.clone();
-            match &mut new_line.record {
-                MtxtRecord::Note { time, .. }
-                | MtxtRecord::NoteOn { time, .. }
-                | MtxtRecord::NoteOff { time, .. }
-                | MtxtRecord::ControlChange { time, .. }
-                | MtxtRecord::Voice { time, .. }
-                | MtxtRecord::Tempo { time, .. }
-                | MtxtRecord::TimeSignature { time, .. }
-                | MtxtRecord::Tuning { time, .. }
-                | MtxtRecord::Reset { time, .. }
-                | MtxtRecord::SysEx { time, .. } => {
-                    *time = time.quantize(grid, swing, humanize);
-                }
-                MtxtRecord::Meta { time, .. } => {
-                    if let Some(t) = time {
-                        *t = t.quantize(grid, swing, humanize);
-                    }
+            let record = &mut new_line.record;
+
+            if let Some(time) = record.time()
+                && let Some(step) = grid
+            {
+                let offset = if humanize_timing {
+                    jitter(&mut rng, humanize.distribution)
+                } else {
+                    0.0
+                };
+                record.set_time(time.quantize_to_step(step, swing, humanize.timing, offset));
+            }
+
+            match record {
+                MtxtRecord::VelocityDirective { velocity } => current_velocity = *velocity,
+                MtxtRecord::Note { velocity, .. } | MtxtRecord::NoteOn { velocity, .. }
+                    if humanize.velocity > 0.0 =>
+                {
+                    let offset = jitter(&mut rng, humanize.distribution) as f32;
+                    let base = velocity.unwrap_or(current_velocity);
+                    *velocity = Some((base + offset * humanize.velocity).clamp(0.0, 1.0));
                 }
                 _ => {}
             }
+
             new_line
         })
         .collect()
@@ -58,6 +116,113 @@ mtxt 1.0
 2.0 note E4
 4.0 note G4
 "#;
-        assert_eq_records(input, |r| transform(r, 4, 0.0, 0.0), expected);
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    Some(BeatFraction::new(1, 4).unwrap()),
+                    0.0,
+                    &HumanizeConfig::default(),
+                )
+            },
+            expected,
+        );
+    }
+
+    fn humanized(seed: u64, distribution: HumanizeDistribution) -> String {
+        let input =
+            "mtxt 1.0\nvel=0.8\n0.0 note C4\n1.0 note E4 vel=0.5\n2.0 cc volume 0.5\n3.0 note G4\n";
+        let records = crate::parse_mtxt(input).unwrap().records;
+        let humanize = HumanizeConfig {
+            timing: 1.0,
+            velocity: 0.2,
+            seed: Some(seed),
+            distribution,
+        };
+        crate::MtxtFile::from_records(transform(
+            &records,
+            Some(BeatFraction::new(1, 4).unwrap()),
+            0.0,
+            &humanize,
+        ))
+        .to_string()
+    }
+
+    #[test]
+    fn test_humanize_seed() {
+        for distribution in [
+            HumanizeDistribution::Uniform,
+            HumanizeDistribution::Gaussian,
+        ] {
+            assert_eq!(humanized(42, distribution), humanized(42, distribution));
+            assert_ne!(humanized(42, distribution), humanized(43, distribution));
+        }
+        assert_ne!(
+            humanized(42, HumanizeDistribution::Uniform),
+            humanized(42, HumanizeDistribution::Gaussian)
+        );
+    }
+
+    #[test]
+    fn test_humanize_velocity_range() {
+        let input = "mtxt 1.0\nvel=0.5\n".to_string()
+            + &(0..200)
+                .map(|i| format!("{} note C4\n", i))
+                .collect::<String>();
+        let records = crate::parse_mtxt(&input).unwrap().records;
+        let humanize = HumanizeConfig {
+            velocity: 0.1,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let velocities: Vec<f32> = transform(&records, None, 0.0, &humanize)
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note { time, velocity, .. } => {
+                    assert_eq!(time.as_f64().fract(), 0.0);
+                    *velocity
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(velocities.len(), 200);
+        assert!(velocities.iter().all(|v| (0.4..=0.6).contains(v)));
+        assert!(velocities.iter().any(|v| *v != 0.5));
+    }
+
+    #[test]
+    fn test_quantize_triplets() {
+        // eighth note triplets: every time lands on the closest beat time to k/6
+        let input = "mtxt 1.0\n".to_string()
+            + &(0..600)
+                .map(|i| format!("{}.{:02} note C4\n", i / 100, i % 100))
+                .collect::<String>();
+        let records = crate::parse_mtxt(&input).unwrap().records;
+        let step = BeatFraction::new(1, 6).unwrap();
+        for line in transform(&records, Some(step), 0.0, &HumanizeConfig::default()) {
+            if let MtxtRecord::Note { time, .. } = line.record {
+                let k = (time.as_f64() * 6.0).round() as u32;
+                assert_eq!(time, BeatFraction::new(k, 6).unwrap().as_beat_time());
+            }
+        }
+
+        // dotted eighths
+        let input = r#"
+mtxt 1.0
+0.8 note C4
+1.4 note E4
+2.1 note G4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.75 note C4
+1.5 note E4
+2.25 note G4
+"#;
+        fn dotted(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+            transform(records, "3/4".parse().ok(), 0.0, &HumanizeConfig::default())
+        }
+        assert_eq_records(input, dotted, expected);
     }
 }
@@ -0,0 +1,82 @@
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashMap;
+
+/// Rewrites channels according to `channel_map`.
+/// All mappings are applied at once, so `1 -> 3, 3 -> 1` swaps the two channels.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    channel_map: &HashMap<u16, u16>,
+) -> Vec<MtxtRecordLine> {
+    if channel_map.is_empty() {
+        return records.to_vec();
+    }
+
+    let remap = |ch: &mut u16| {
+        if let Some(new_ch) = channel_map.get(ch) {
+            *ch = *new_ch;
+        }
+    };
+
+    records
+        .iter()
+        .map(|line| {
+            let mut new_line = line.clone();
+            match &mut new_line.record {
+                MtxtRecord::ChannelDirective { channel } => remap(channel),
+                MtxtRecord::Note { channel, .. }
+                | MtxtRecord::NoteOn { channel, .. }
+                | MtxtRecord::NoteOff { channel, .. }
+                | MtxtRecord::ControlChange { channel, .. }
+                | MtxtRecord::Voice { channel, .. }
+                | MtxtRecord::Meta { channel, .. } => {
+                    if let Some(ch) = channel {
+                        remap(ch);
+                    }
+                }
+                _ => {}
+            }
+            new_line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    fn swap_1_3_map_7_10(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+        transform(records, &HashMap::from([(3, 1), (1, 3), (7, 10)]))
+    }
+
+    #[test]
+    fn test_remap_channels() {
+        let input = r#"
+mtxt 1.0
+ch=1
+0.0 voice piano
+0.0 meta name Lead
+1.0 note C4
+1.0 note E4 ch=3
+2.0 cc volume 0.5 ch=7
+2.0 cc pan 0.5
+ch=10
+3.0 note G4
+3.0 note A4 ch=2
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=3
+0.0 voice piano
+0.0 meta name Lead
+1.0 note C4
+1.0 note E4 ch=1
+2.0 cc volume 0.5 ch=10
+2.0 cc pan 0.5
+ch=10
+3.0 note G4
+3.0 note A4 ch=2
+"#;
+        assert_eq_records(input, swap_1_3_map_7_10, expected);
+    }
+}
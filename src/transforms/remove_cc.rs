@@ -0,0 +1,87 @@
+use crate::types::controller::controller_number;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+/// Drops every CC of the named controllers, note-targeted ones included.
+/// Names are case-insensitive and match the same MIDI CC: `sustain`, `cc64` and `64`
+/// are the same controller. `all` drops every CC.
+pub fn transform(records: &[MtxtRecordLine], controllers: &[String]) -> Vec<MtxtRecordLine> {
+    if controllers.is_empty() {
+        return records.to_vec();
+    }
+    let remove_all = controllers
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case("all"));
+    let numbers: Vec<u8> = controllers
+        .iter()
+        .filter_map(|name| controller_number(name))
+        .collect();
+
+    let matches = |controller: &str| {
+        remove_all
+            || controller_number(controller).is_some_and(|number| numbers.contains(&number))
+            || controllers
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(controller))
+    };
+
+    records
+        .iter()
+        .filter(|line| match &line.record {
+            MtxtRecord::ControlChange { controller, .. } => !matches(controller),
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    const INPUT: &str = r#"
+mtxt 1.0
+0.0 cc sustain 1.0 ch=1
+0.0 note C4
+0.5 cc C4 pitch 0.5 ch=1
+1.0 cc cc64 0.0
+1.0 cc Pitch -1
+1.0 cc volume 0.8
+2.0 cc brightness 0.2
+"#;
+
+    #[test]
+    fn test_remove_cc() {
+        let expected = r#"
+mtxt 1.0
+0.0 note C4
+1.0 cc volume 0.8
+2.0 cc brightness 0.2
+"#;
+        fn remove(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+            transform(records, &["SUSTAIN".to_string(), "pitch".to_string()])
+        }
+        assert_eq_records(INPUT, remove, expected);
+    }
+
+    #[test]
+    fn test_remove_cc_numeric_and_all() {
+        let expected = r#"
+mtxt 1.0
+0.0 cc sustain 1.0 ch=1
+0.0 note C4
+0.5 cc C4 pitch 0.5 ch=1
+1.0 cc cc64 0.0
+1.0 cc Pitch -1
+1.0 cc volume 0.8
+"#;
+        assert_eq_records(INPUT, |r| transform(r, &["cc74".to_string()]), expected);
+
+        let notes_only = r#"
+mtxt 1.0
+0.0 note C4
+"#;
+        assert_eq_records(INPUT, |r| transform(r, &["all".to_string()]), notes_only);
+        assert_eq_records(INPUT, |r| transform(r, &[]), INPUT);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+/// How non-note records are handled when reversing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum RetrogradeMode {
+    /// Records are only moved to their mirrored time. State changes (cc, tempo, voice...)
+    /// keep their values, so the value that was in effect before a change now applies after it.
+    #[default]
+    Reposition,
+}
+
+// Effective duration of every record, notes resolved through duration directives
+fn record_durations(records: &[MtxtRecordLine]) -> Vec<BeatTime> {
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    records
+        .iter()
+        .map(|line| match &line.record {
+            MtxtRecord::DurationDirective { duration } => {
+                current_duration = *duration;
+                BeatTime::zero()
+            }
+            MtxtRecord::Note { duration, .. } => duration.unwrap_or(current_duration),
+            _ => BeatTime::zero(),
+        })
+        .collect()
+}
+
+/// Mirrors all timed records around the total duration of the file.
+/// A note at `t` with duration `d` moves to `total - t - d`, note on / off events swap roles.
+pub fn transform(records: &[MtxtRecordLine], _mode: RetrogradeMode) -> Vec<MtxtRecordLine> {
+    let durations = record_durations(records);
+
+    let total = records
+        .iter()
+        .zip(&durations)
+        .filter_map(|(line, duration)| line.record.time().map(|t| t + *duration))
+        .max();
+
+    let Some(total) = total else {
+        return records.to_vec();
+    };
+
+    records
+        .iter()
+        .zip(durations)
+        .map(|(line, duration)| {
+            let Some(time) = line.record.time() else {
+                return line.clone();
+            };
+            let new_time = total - time - duration;
+
+            let record = match &line.record {
+                MtxtRecord::NoteOn {
+                    note,
+                    velocity,
+                    channel,
+                    ..
+                } => MtxtRecord::NoteOff {
+                    time: new_time,
+                    note: note.clone(),
+                    off_velocity: *velocity,
+                    channel: *channel,
+                },
+                MtxtRecord::NoteOff {
+                    note,
+                    off_velocity,
+                    channel,
+                    ..
+                } => MtxtRecord::NoteOn {
+                    time: new_time,
+                    note: note.clone(),
+                    velocity: *off_velocity,
+                    channel: *channel,
+                },
+                other => {
+                    let mut record = other.clone();
+                    record.set_time(new_time);
+                    record
+                }
+            };
+
+            MtxtRecordLine {
+                record,
+                comment: line.comment.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_retrograde() {
+        let input = r#"
+mtxt 1.0
+meta global title Forward
+meta ch=1 name Lead
+dur=0.5
+0.0 tempo 120
+0.0 note C4 dur=1
+1.0 note D4
+2.0 note E4 dur=2
+2.5 cc volume 0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+meta global title Forward
+meta ch=1 name Lead
+dur=0.5
+4.0 tempo 120
+3.0 note C4 dur=1
+2.5 note D4
+0.0 note E4 dur=2
+1.5 cc volume 0.5
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, RetrogradeMode::Reposition),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_retrograde_note_on_off() {
+        let input = r#"
+mtxt 1.0
+0.0 on C4 vel=0.8
+1.5 off C4 offvel=0.2
+2.0 note E4
+"#;
+        let expected = r#"
+mtxt 1.0
+3.0 off C4 offvel=0.8
+1.5 on C4 vel=0.2
+0.0 note E4
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, RetrogradeMode::Reposition),
+            expected,
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::types::key::{KeySignature, ScaleMode};
+use crate::types::note::{Note, NoteTarget};
+use crate::types::pitch::PitchClass;
+use crate::types::record::{AliasDefinition, MtxtRecord, MtxtRecordLine};
+use crate::types::scale::ScaleType;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Where a note goes when it is exactly between two scale tones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum SnapTie {
+    #[default]
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct ScaleSnap {
+    pub root: PitchClass,
+    pub scale: ScaleType,
+    pub tie: SnapTie,
+}
+
+struct Snapper {
+    root: i32,
+    // whether every semitone above the root is a scale tone
+    in_scale: [bool; 12],
+    tie: SnapTie,
+    prefer_flats: bool,
+}
+
+impl Snapper {
+    fn new(config: &ScaleSnap) -> Self {
+        let mut in_scale = [false; 12];
+        for semitone in config.scale.semitones() {
+            in_scale[*semitone as usize] = true;
+        }
+        let mode = if config.scale.is_minor() {
+            ScaleMode::Minor
+        } else {
+            ScaleMode::Major
+        };
+        Self {
+            root: config.root.to_semitone() as i32,
+            in_scale,
+            tie: config.tie,
+            prefer_flats: KeySignature {
+                tonic: config.root,
+                mode,
+            }
+            .prefers_flats(),
+        }
+    }
+
+    fn is_scale_tone(&self, absolute: i32) -> bool {
+        self.in_scale[(absolute - self.root).rem_euclid(12) as usize]
+    }
+
+    fn snap(&self, note: &Note) -> Note {
+        let absolute = note.absolute_semitone();
+        if self.is_scale_tone(absolute) {
+            return note.clone();
+        }
+
+        let directions = match self.tie {
+            SnapTie::Down => [-1, 1],
+            SnapTie::Up => [1, -1],
+        };
+        let new_absolute = (1..12)
+            .flat_map(|distance| directions.map(|direction| absolute + direction * distance))
+            .find(|candidate| self.is_scale_tone(*candidate))
+            .unwrap_or(absolute);
+
+        Note {
+            pitch_class: PitchClass::from_semitone(
+                new_absolute.rem_euclid(12) as u8,
+                self.prefer_flats,
+            ),
+            octave: (new_absolute.div_euclid(12) - 1) as i8,
+            cents: note.cents,
+        }
+    }
+
+    fn snap_target(
+        &self,
+        target: &NoteTarget,
+        alias_map: &HashMap<usize, Rc<AliasDefinition>>,
+    ) -> NoteTarget {
+        match target {
+            NoteTarget::Note(n) => NoteTarget::Note(self.snap(n)),
+            NoteTarget::AliasKey(k) => NoteTarget::AliasKey(k.clone()),
+            NoteTarget::Alias(rc) => {
+                let ptr = Rc::as_ptr(rc) as usize;
+                NoteTarget::Alias(alias_map.get(&ptr).cloned().unwrap_or_else(|| rc.clone()))
+            }
+            NoteTarget::Range { low, high } => NoteTarget::Range {
+                low: self.snap(low),
+                high: self.snap(high),
+            },
+        }
+    }
+}
+
+/// Moves every note outside of the scale to the nearest scale tone,
+/// notes exactly between two scale tones go in the direction of `tie`.
+/// Notes in the scale keep their spelling, cents are kept.
+/// Use the channel filters to leave channels (e.g. drums) untouched.
+pub fn transform(records: &[MtxtRecordLine], config: &ScaleSnap) -> Vec<MtxtRecordLine> {
+    let snapper = Snapper::new(config);
+    let mut alias_map: HashMap<usize, Rc<AliasDefinition>> = HashMap::new();
+
+    records
+        .iter()
+        .map(|line| {
+            let mut new_line = line.clone();
+            match &mut new_line.record {
+                MtxtRecord::AliasDef { value } => {
+                    let new_def = Rc::new(AliasDefinition {
+                        name: value.name.clone(),
+                        notes: value.notes.iter().map(|n| snapper.snap(n)).collect(),
+                    });
+                    alias_map.insert(Rc::as_ptr(value) as usize, new_def.clone());
+                    *value = new_def;
+                }
+                MtxtRecord::Note { note, .. }
+                | MtxtRecord::NoteOn { note, .. }
+                | MtxtRecord::NoteOff { note, .. }
+                | MtxtRecord::ControlChange {
+                    note: Some(note), ..
+                } => *note = snapper.snap_target(note, &alias_map),
+                _ => {}
+            }
+            new_line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    const C_MAJOR: ScaleSnap = ScaleSnap {
+        root: PitchClass::C,
+        scale: ScaleType::Major,
+        tie: SnapTie::Down,
+    };
+    const C_MAJOR_UP: ScaleSnap = ScaleSnap {
+        tie: SnapTie::Up,
+        ..C_MAJOR
+    };
+
+    fn snap_all(notes: &str, root: &str, scale: &str) -> String {
+        let snapper = Snapper::new(&ScaleSnap {
+            root: root.parse().unwrap(),
+            scale: scale.parse().unwrap(),
+            tie: SnapTie::Down,
+        });
+        notes
+            .split_whitespace()
+            .map(|note| snapper.snap(&note.parse().unwrap()).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_snap_in_scale_is_unchanged() {
+        let input = r#"
+mtxt 1.0
+alias Am A3,C4,E4
+0.0 note C4
+1.0 note D4 dur=0.5
+2.0 note Am
+3.0 on B4
+4.0 off B4
+5.0 note F3-A3
+"#;
+        assert_eq_records(input, |r| transform(r, &C_MAJOR), input);
+        assert_eq_records(input, |r| transform(r, &C_MAJOR_UP), input);
+    }
+
+    #[test]
+    fn test_snap_ties() {
+        let input = r#"
+mtxt 1.0
+alias Chromatic C#4,F#4
+0.0 note C#4
+1.0 note Chromatic
+2.0 note Bb3-Eb4
+"#;
+        let down = r#"
+mtxt 1.0
+alias Chromatic C4,F4
+0.0 note C4
+1.0 note Chromatic
+2.0 note A3-D4
+"#;
+        let up = r#"
+mtxt 1.0
+alias Chromatic D4,G4
+0.0 note D4
+1.0 note Chromatic
+2.0 note B3-E4
+"#;
+        assert_eq_records(input, |r| transform(r, &C_MAJOR), down);
+        assert_eq_records(input, |r| transform(r, &C_MAJOR_UP), up);
+    }
+
+    #[test]
+    fn test_snap_scales() {
+        assert_eq!(
+            snap_all("C4 C#4 D4 D#4 E4 F4", "C", "pentatonic"),
+            "C4 C4 D4 D4 E4 E4"
+        );
+        assert_eq!(snap_all("F#4 G4", "A", "harmonic-minor"), "F4 G#4");
+        assert_eq!(snap_all("E4 A4 B4", "C", "blues"), "Eb4 Bb4 Bb4");
+        assert_eq!(snap_all("B3 C#4 G#4", "D", "dorian"), "B3 C4 G4");
+        assert_eq!(snap_all("F#4 A#4", "G", "mixolydian"), "F4 A4");
+        assert_eq!(snap_all("B4+30", "C", "minor"), "Bb4+30");
+    }
+}
@@ -1,6 +1,19 @@
-use crate::types::record::MtxtRecordLine;
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
 use std::cmp::Ordering;
 
+/// Sort key of a file sorted as a whole: (order group, time).
+/// Order groups: 0 = header, global meta and aliases, 1 = meta without a time, 2 = other
+pub(crate) fn sort_key(record: &MtxtRecord) -> (u8, BeatTime) {
+    match record {
+        MtxtRecord::Header { .. } | MtxtRecord::GlobalMeta { .. } | MtxtRecord::AliasDef { .. } => {
+            (0, BeatTime::zero())
+        }
+        MtxtRecord::Meta { time: None, .. } => (1, BeatTime::zero()),
+        record => (2, record.time().unwrap_or(BeatTime::zero())),
+    }
+}
+
 pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
     let mut new_records = Vec::with_capacity(records.len());
     let mut buffer: Vec<MtxtRecordLine> = Vec::new();
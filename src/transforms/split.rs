@@ -0,0 +1,225 @@
+use crate::BeatTime;
+use crate::types::note::NoteTarget;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::BTreeMap;
+
+struct PendingOff {
+    note: NoteTarget,
+    off_velocity: Option<f32>,
+    channel: Option<u16>,
+    // channel and off velocity in effect at the note
+    effective_channel: u16,
+    effective_off_velocity: f32,
+}
+
+/// Converts every note into a note on at its time and a note off at its end,
+/// the inverse of `merge_notes`. The note off is placed before the first later record
+/// at or after its time, so the records stay in time order if they were sorted.
+/// Notes without a duration use the `dur` directive in effect (1 beat by default).
+/// A note off placed after a `ch` or `offvel` directive gets the value of the note inline.
+pub fn transform(records: &[MtxtRecordLine]) -> Vec<MtxtRecordLine> {
+    let mut new_records = Vec::with_capacity(records.len() * 2);
+    // (off time, order of the notes) -> note off
+    let mut pending: BTreeMap<(BeatTime, usize), PendingOff> = BTreeMap::new();
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    let mut current_channel: u16 = 0;
+    let mut current_off_velocity: f32 = 0.0;
+
+    for (idx, line) in records.iter().enumerate() {
+        if let Some(time) = line.record.time() {
+            flush(
+                &mut pending,
+                Some(time),
+                current_channel,
+                current_off_velocity,
+                &mut new_records,
+            );
+        }
+
+        match &line.record {
+            MtxtRecord::DurationDirective { duration } => current_duration = *duration,
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::OffVelocityDirective { off_velocity } => {
+                current_off_velocity = *off_velocity
+            }
+            _ => {}
+        }
+
+        let MtxtRecord::Note {
+            time,
+            note,
+            duration,
+            velocity,
+            off_velocity,
+            channel,
+        } = &line.record
+        else {
+            new_records.push(line.clone());
+            continue;
+        };
+
+        new_records.push(MtxtRecordLine {
+            record: MtxtRecord::NoteOn {
+                time: *time,
+                note: note.clone(),
+                velocity: *velocity,
+                channel: *channel,
+            },
+            comment: line.comment.clone(),
+        });
+        pending.insert(
+            (*time + duration.unwrap_or(current_duration), idx),
+            PendingOff {
+                note: note.clone(),
+                off_velocity: *off_velocity,
+                channel: *channel,
+                effective_channel: channel.unwrap_or(current_channel),
+                effective_off_velocity: current_off_velocity,
+            },
+        );
+    }
+
+    flush(
+        &mut pending,
+        None,
+        current_channel,
+        current_off_velocity,
+        &mut new_records,
+    );
+    new_records
+}
+
+// Emits the note offs until `until`, every pending one without a limit
+fn flush(
+    pending: &mut BTreeMap<(BeatTime, usize), PendingOff>,
+    until: Option<BeatTime>,
+    current_channel: u16,
+    current_off_velocity: f32,
+    new_records: &mut Vec<MtxtRecordLine>,
+) {
+    while let Some(entry) = pending.first_entry() {
+        let off_time = entry.key().0;
+        if until.is_some_and(|until| off_time > until) {
+            break;
+        }
+        let off = entry.remove();
+        let channel = off
+            .channel
+            .or((off.effective_channel != current_channel).then_some(off.effective_channel));
+        let off_velocity = off
+            .off_velocity
+            .or((off.effective_off_velocity != current_off_velocity)
+                .then_some(off.effective_off_velocity));
+        new_records.push(MtxtRecordLine::new(MtxtRecord::NoteOff {
+            time: off_time,
+            note: off.note,
+            off_velocity,
+            channel,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::merge;
+    use crate::types::note::Note;
+    use crate::util::assert_eq_records;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_split_notes() {
+        let input = r#"
+mtxt 1.0
+ch=1
+dur=0.5
+0.0 tempo 120
+1.0 note C4 dur=2 vel=0.5 offvel=0.8 // long
+1.5 note E4
+2.0 cc volume 0.5
+4.0 note G4 dur=0
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=1
+dur=0.5
+0.0 tempo 120
+1.0 on C4 vel=0.5 // long
+1.5 on E4
+2.0 off E4
+2.0 cc volume 0.5
+3.0 off C4 offvel=0.8
+4.0 on G4
+4.0 off G4
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+
+    #[test]
+    fn test_split_notes_keeps_channel() {
+        let input = r#"
+mtxt 1.0
+ch=1
+offvel=0.3
+0.0 note C4 dur=2
+ch=2
+offvel=0.6
+1.0 note C4 dur=0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=1
+offvel=0.3
+0.0 on C4
+ch=2
+offvel=0.6
+1.0 on C4
+1.5 off C4
+2.0 off C4 offvel=0.3 ch=1
+"#;
+        assert_eq_records(input, transform, expected);
+    }
+
+    // Non-overlapping notes per channel and pitch, in time order
+    fn random_notes(rng: &mut StdRng) -> Vec<MtxtRecordLine> {
+        let mut notes = Vec::new();
+        for channel in 0..3u16 {
+            for pitch in ["C4", "E4", "G4+25", "Bb2"] {
+                let mut time = BeatTime::from_parts(rng.gen_range(0..4), 0.0);
+                for _ in 0..rng.gen_range(0..6) {
+                    let duration = BeatTime::from_parts(0, 0.25).mul_ratio(rng.gen_range(0..8), 1);
+                    notes.push(MtxtRecordLine::new(MtxtRecord::Note {
+                        time,
+                        note: NoteTarget::Note(pitch.parse::<Note>().unwrap()),
+                        duration: Some(duration),
+                        velocity: rng.gen_bool(0.5).then(|| rng.gen_range(0.0..1.0)),
+                        off_velocity: rng.gen_bool(0.5).then(|| rng.gen_range(0.0..1.0)),
+                        // only channel 0 notes may omit their channel
+                        channel: (channel > 0 || rng.gen_bool(0.5)).then_some(channel),
+                    }));
+                    time = time
+                        + duration
+                        + BeatTime::from_parts(0, 0.5).mul_ratio(rng.gen_range(0..3), 1);
+                }
+            }
+        }
+        notes.sort_by_key(|line| line.record.time());
+        notes
+    }
+
+    #[test]
+    fn test_split_merge_round_trip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..200 {
+            let notes = random_notes(&mut rng);
+            let split = transform(&notes);
+            assert!(
+                split
+                    .windows(2)
+                    .all(|w| w[0].record.time() <= w[1].record.time())
+            );
+            assert_eq!(merge::transform(&split), notes);
+        }
+    }
+}
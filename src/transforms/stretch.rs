@@ -0,0 +1,321 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+const DEFAULT_BPM: f32 = 120.0;
+
+// Approximates the factor with a fraction, so that e.g. 1/3 or 1.5 are applied exactly
+fn factor_to_ratio(factor: f32) -> (u64, u64) {
+    const MAX_DENOMINATOR: u64 = 1_000_000;
+    let target = factor as f64;
+
+    // continued fraction expansion
+    let (mut p0, mut q0, mut p1, mut q1) = (0u64, 1u64, 1u64, 0u64);
+    let mut x = target;
+    loop {
+        let a = x.floor();
+        let p2 = a as u64 * p1 + p0;
+        let q2 = a as u64 * q1 + q0;
+        if q2 > MAX_DENOMINATOR {
+            break;
+        }
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+
+        let approx = p1 as f64 / q1 as f64;
+        if (approx - target).abs() <= target * 1e-6 || x - a < 1e-12 {
+            break;
+        }
+        x = 1.0 / (x - a);
+    }
+
+    (p1, q1.max(1))
+}
+
+struct TimeMap {
+    numerator: u64,
+    denominator: u64,
+    region: Option<(BeatTime, BeatTime)>,
+}
+
+impl TimeMap {
+    fn map(&self, t: BeatTime) -> BeatTime {
+        match self.region {
+            None => t.mul_ratio(self.numerator, self.denominator),
+            Some((start, end)) => {
+                if t <= start {
+                    t
+                } else if t <= end {
+                    start + (t - start).mul_ratio(self.numerator, self.denominator)
+                } else {
+                    // later records are pushed out (or pulled in) by the change of the region length
+                    start + (end - start).mul_ratio(self.numerator, self.denominator) + (t - end)
+                }
+            }
+        }
+    }
+
+    // Maps a span ending at `end`, keeping both of its endpoints consistent
+    fn map_span_before(&self, end: BeatTime, span: BeatTime) -> BeatTime {
+        self.map(end) - self.map(end - span)
+    }
+
+    fn map_span_after(&self, start: BeatTime, span: BeatTime) -> BeatTime {
+        self.map(start + span) - self.map(start)
+    }
+}
+
+fn tempo_at(records: &[MtxtRecordLine], time: BeatTime) -> Option<(f32, bool)> {
+    let mut result: Option<(BeatTime, f32)> = None;
+    for line in records {
+        if let MtxtRecord::Tempo { time: t, bpm, .. } = &line.record
+            && *t <= time
+            && result.is_none_or(|(rt, _)| rt <= *t)
+        {
+            result = Some((*t, *bpm));
+        }
+    }
+    result.map(|(t, bpm)| (bpm, t == time))
+}
+
+fn tempo_record(time: BeatTime, bpm: f32) -> MtxtRecordLine {
+    MtxtRecordLine::new(MtxtRecord::Tempo {
+        time,
+        bpm,
+        transition_curve: None,
+        transition_time: None,
+        transition_interval: None,
+    })
+}
+
+/// Multiplies every time and duration by `factor`.
+/// If `region` is set, only that range is stretched and later records are shifted accordingly.
+/// If `preserve_wallclock` is set, tempos are scaled so that the result sounds identical.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    factor: f32,
+    region: Option<(BeatTime, BeatTime)>,
+    preserve_wallclock: bool,
+) -> Vec<MtxtRecordLine> {
+    if factor <= 0.0 || factor == 1.0 {
+        return records.to_vec();
+    }
+
+    let (numerator, denominator) = factor_to_ratio(factor);
+    transform_ratio(records, numerator, denominator, region, preserve_wallclock)
+}
+
+/// Same as [`transform`] with the exact factor `numerator / denominator`
+pub fn transform_ratio(
+    records: &[MtxtRecordLine],
+    numerator: u64,
+    denominator: u64,
+    region: Option<(BeatTime, BeatTime)>,
+    preserve_wallclock: bool,
+) -> Vec<MtxtRecordLine> {
+    if numerator == 0 || denominator == 0 || numerator == denominator {
+        return records.to_vec();
+    }
+
+    let factor = numerator as f64 / denominator as f64;
+    let time_map = TimeMap {
+        numerator,
+        denominator,
+        region,
+    };
+    let in_region = |t: BeatTime| region.is_none_or(|(start, end)| t >= start && t < end);
+
+    let default_duration = BeatTime::from_parts(1, 0.0);
+    let mut old_directive = default_duration;
+    let mut new_directive = default_duration;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    for line in records {
+        let mut new_line = line.clone();
+        let time = line.record.time();
+
+        match &mut new_line.record {
+            MtxtRecord::DurationDirective { duration } => {
+                old_directive = *duration;
+                if region.is_none() {
+                    *duration = duration.mul_ratio(numerator, denominator);
+                }
+                new_directive = *duration;
+            }
+            MtxtRecord::Note { time, duration, .. } => {
+                let old_duration = duration.unwrap_or(old_directive);
+                let new_duration = time_map.map_span_after(*time, old_duration);
+                if duration.is_some() || new_duration != new_directive {
+                    *duration = Some(new_duration);
+                }
+            }
+            MtxtRecord::ControlChange {
+                time,
+                transition_time,
+                ..
+            } => {
+                *transition_time = transition_time.map(|t| time_map.map_span_before(*time, t));
+            }
+            MtxtRecord::Tempo {
+                time,
+                bpm,
+                transition_time,
+                ..
+            } => {
+                *transition_time = transition_time.map(|t| time_map.map_span_before(*time, t));
+                if preserve_wallclock && in_region(*time) {
+                    *bpm = (*bpm as f64 * factor) as f32;
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(t) = time {
+            new_line.record.set_time(time_map.map(t));
+        }
+        new_records.push(new_line);
+    }
+
+    // The tempo in effect when entering / leaving the region has to be adjusted as well,
+    // without a region this is the default tempo before the first tempo record
+    if preserve_wallclock {
+        let start = region.map_or(BeatTime::zero(), |(start, _)| start);
+        let (start_bpm, tempo_at_start) = tempo_at(records, start).unwrap_or((DEFAULT_BPM, false));
+
+        if let Some((_, end)) = region {
+            let (end_bpm, tempo_at_end) = tempo_at(records, end).unwrap_or((DEFAULT_BPM, false));
+            if !tempo_at_end {
+                let new_end = time_map.map(end);
+                let idx = new_records
+                    .iter()
+                    .position(|line| line.record.time().is_some_and(|t| t >= new_end))
+                    .unwrap_or(new_records.len());
+                new_records.insert(idx, tempo_record(new_end, end_bpm));
+            }
+        }
+        if !tempo_at_start {
+            let idx = new_records
+                .iter()
+                .position(|line| line.record.time().is_some_and(|t| t >= start))
+                .unwrap_or(new_records.len());
+            new_records.insert(idx, tempo_record(start, (start_bpm as f64 * factor) as f32));
+        }
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_factor_to_ratio() {
+        assert_eq!(factor_to_ratio(0.5), (1, 2));
+        assert_eq!(factor_to_ratio(1.5), (3, 2));
+        assert_eq!(factor_to_ratio(2.0), (2, 1));
+        assert_eq!(factor_to_ratio(1.0 / 3.0), (1, 3));
+        assert_eq!(factor_to_ratio(2.0 / 3.0), (2, 3));
+        assert_eq!(factor_to_ratio(1.25), (5, 4));
+    }
+
+    #[test]
+    fn test_stretch() {
+        let input = r#"
+mtxt 1.0
+dur=0.5
+0.0 tempo 120
+1.0 note C4
+1.5 note E4 dur=0.25
+3.0 cc volume 1.0 transition_time=1.0
+"#;
+        let expected = r#"
+mtxt 1.0
+dur=0.75
+0.0 tempo 120
+1.5 note C4
+2.25 note E4 dur=0.375
+4.5 cc volume 1.0 transition_time=1.5
+"#;
+        assert_eq_records(input, |r| transform(r, 1.5, None, false), expected);
+    }
+
+    #[test]
+    fn test_stretch_triplets() {
+        let input = r#"
+mtxt 1.0
+0.0 note C4 dur=1.5
+1.5 note E4 dur=1.5
+3.0 note G4 dur=1.5
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 note C4 dur=1.0
+1.0 note E4 dur=1.0
+2.0 note G4 dur=1.0
+"#;
+        assert_eq_records(input, |r| transform(r, 2.0 / 3.0, None, false), expected);
+    }
+
+    #[test]
+    fn test_stretch_preserve_wallclock() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 100
+1.0 note C4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 50
+0.5 note C4 dur=0.5
+"#;
+        assert_eq_records(input, |r| transform(r, 0.5, None, true), expected);
+    }
+
+    #[test]
+    fn test_stretch_preserve_wallclock_default_tempo() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4
+2.0 tempo 100
+3.0 note D4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 240
+2.0 note C4 dur=2.0
+4.0 tempo 200
+6.0 note D4 dur=2.0
+"#;
+        assert_eq_records(input, |r| transform(r, 2.0, None, true), expected);
+    }
+
+    #[test]
+    fn test_stretch_region() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 100
+0.0 note C4
+2.0 note D4 dur=1
+3.0 note E4 dur=2
+6.0 note F4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 100
+0.0 note C4
+2.0 tempo 200
+2.0 note D4 dur=2
+4.0 note E4 dur=3
+6.0 tempo 100
+8.0 note F4
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                let region = ("2.0".parse().unwrap(), "4.0".parse().unwrap());
+                transform(r, 2.0, Some(region), true)
+            },
+            expected,
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use super::arpeggio::{PlacedNote, collect_chords, pitch_value, place_notes};
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashMap;
+
+const DEFAULT_BPM: f32 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum StrumDirection {
+    /// Lowest note first
+    Down,
+    /// Highest note first
+    Up,
+}
+
+/// Delay between consecutive notes of a strum
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum StrumSpread {
+    Beats(BeatTime),
+    /// Converted to beats at the tempo in effect at the chord (tempo ramps are ignored)
+    Millis(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct StrumConfig {
+    pub direction: StrumDirection,
+    pub spread: StrumSpread,
+    /// Velocity change of the last note of the strum relative to the first
+    /// (e.g. `-0.2` plays it 20% softer), interpolated linearly in between
+    pub velocity_ramp: f32,
+}
+
+fn spread_at(spread: StrumSpread, tempos: &[(BeatTime, f32)], time: BeatTime) -> BeatTime {
+    match spread {
+        StrumSpread::Beats(beats) => beats,
+        StrumSpread::Millis(millis) => {
+            let bpm = tempos
+                .iter()
+                .take_while(|(tempo_time, _)| *tempo_time <= time)
+                .last()
+                .map_or(DEFAULT_BPM, |(_, bpm)| *bpm);
+            BeatTime::from_micros((millis.max(0.0) * 1000.0) as u64, bpm as f64)
+        }
+    }
+}
+
+/// Offsets the onsets of chord notes (notes starting together on a channel)
+/// by increasing multiples of the spread, keeping their original ends.
+/// Paired note on / off events take part like notes, unpaired ones are kept as they are.
+/// Aliases played as chords are expanded into their notes.
+pub fn transform(records: &[MtxtRecordLine], config: &StrumConfig) -> Vec<MtxtRecordLine> {
+    let mut tempos: Vec<(BeatTime, f32)> = records
+        .iter()
+        .filter_map(|line| match &line.record {
+            MtxtRecord::Tempo { time, bpm, .. } => Some((*time, *bpm)),
+            _ => None,
+        })
+        .collect();
+    tempos.sort_by_key(|(time, _)| *time);
+
+    let mut placed: HashMap<(usize, usize), Option<PlacedNote>> = HashMap::new();
+    for ((_, start), mut chord) in collect_chords(records) {
+        if chord.len() < 2 {
+            continue;
+        }
+
+        chord.sort_by(|a, b| pitch_value(&a.note).total_cmp(&pitch_value(&b.note)));
+        if config.direction == StrumDirection::Up {
+            chord.reverse();
+        }
+
+        let spread = spread_at(config.spread, &tempos, start);
+        let last = (chord.len() - 1) as f32;
+        for (position, note) in chord.into_iter().enumerate() {
+            // never move a note past its own end
+            let onset = (start + spread.mul_ratio(position as u64, 1)).min(note.end);
+            let velocity = (config.velocity_ramp != 0.0).then(|| {
+                let factor = 1.0 + config.velocity_ramp * position as f32 / last;
+                (note.velocity * factor).clamp(0.0, 1.0)
+            });
+            placed.insert(
+                (note.idx, note.sub),
+                Some(PlacedNote {
+                    time: onset,
+                    duration: note.end - onset,
+                    note: note.note,
+                    velocity,
+                }),
+            );
+        }
+    }
+
+    place_notes(records, &placed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_strum_down_and_up() {
+        let input = r#"
+mtxt 1.0
+0.0 note E4 dur=2
+0.0 note C4 dur=2
+0.0 note G4 dur=1
+4.0 note D4 ch=1
+"#;
+        let down = r#"
+mtxt 1.0
+0.125 note E4 dur=1.875
+0.0 note C4 dur=2.0
+0.25 note G4 dur=0.75
+4.0 note D4 ch=1
+"#;
+        let up = r#"
+mtxt 1.0
+0.125 note E4 dur=1.875
+0.25 note C4 dur=1.75
+0.0 note G4 dur=1.0
+4.0 note D4 ch=1
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &StrumConfig {
+                        direction: StrumDirection::Down,
+                        spread: StrumSpread::Beats(BeatTime::from_parts(0, 0.125)),
+                        velocity_ramp: 0.0,
+                    },
+                )
+            },
+            down,
+        );
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &StrumConfig {
+                        direction: StrumDirection::Up,
+                        spread: StrumSpread::Beats(BeatTime::from_parts(0, 0.125)),
+                        velocity_ramp: 0.0,
+                    },
+                )
+            },
+            up,
+        );
+    }
+
+    #[test]
+    fn test_strum_note_on_off() {
+        let input = r#"
+mtxt 1.0
+0.0 on E4 vel=0.5
+0.0 on C4
+0.0 note G4 dur=1
+2.0 off E4
+2.0 off C4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.125 on E4 vel=0.5
+0.0 on C4
+0.25 note G4 dur=0.75
+2.0 off E4
+2.0 off C4
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                transform(
+                    r,
+                    &StrumConfig {
+                        direction: StrumDirection::Down,
+                        spread: StrumSpread::Beats(BeatTime::from_parts(0, 0.125)),
+                        velocity_ramp: 0.0,
+                    },
+                )
+            },
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_strum_millis_and_velocity_ramp() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 60
+vel=0.8
+alias Em E2,B2,E3,G3,B3,E4
+1.0 note Em dur=3
+"#;
+        let config = StrumConfig {
+            direction: StrumDirection::Down,
+            spread: StrumSpread::Millis(10.0),
+            velocity_ramp: -0.5,
+        };
+        let file = parse_mtxt(input).unwrap();
+        let strummed = transform(&file.records, &config);
+
+        let notes: Vec<(BeatTime, BeatTime, f32)> = strummed
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note {
+                    time,
+                    duration,
+                    velocity,
+                    ..
+                } => Some((*time, *time + duration.unwrap(), velocity.unwrap())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(notes.len(), 6);
+
+        let chord_end = BeatTime::from_parts(4, 0.0);
+        for (idx, (onset, end, _)) in notes.iter().enumerate() {
+            // 10ms at 60 BPM is 0.01 beats
+            let expected = 1.0 + idx as f64 * 0.01;
+            assert!((onset.as_f64() - expected).abs() < 1e-6, "{}", onset);
+            assert_eq!(*end, chord_end);
+        }
+        assert!(notes.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(notes.windows(2).all(|pair| pair[0].2 > pair[1].2));
+        assert!((notes[0].2 - 0.8).abs() < 1e-6);
+        assert!((notes[5].2 - 0.4).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+
+/// Swing measured on the off-beat subdivisions of a grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwingReport {
+    pub grid: u32,
+    /// Swing amount in the units of `--swing` (1.0 delays off-beats by 1/6 of a grid step)
+    pub swing: f32,
+    /// Share of the first note of each off-beat pair, 0.5 is straight
+    pub ratio: f32,
+    /// Number of off-beat notes the estimate is based on
+    pub off_beat_notes: usize,
+}
+
+// Position inside a pair of grid steps, in grid steps (0.0 <= phase < 2.0)
+fn phase(time: BeatTime, grid: u32) -> (f64, f64) {
+    let steps = time.as_f64() * grid as f64;
+    let pair_start = (steps / 2.0).floor() * 2.0;
+    (pair_start, steps - pair_start)
+}
+
+fn from_steps(steps: f64, grid: u32) -> BeatTime {
+    let beats = (steps / grid as f64).max(0.0);
+    BeatTime::from_parts(beats.floor() as u32, beats.fract() as f32)
+}
+
+/// Estimates the swing of note onsets on a grid (e.g. 8 for eighth notes).
+/// Uses the median delay of the off-beat notes, so a few outliers don't skew the result.
+/// `None` if no note is close to an off-beat.
+pub fn estimate(records: &[MtxtRecordLine], grid: u32) -> Option<SwingReport> {
+    if grid == 0 {
+        return None;
+    }
+
+    let mut offsets: Vec<f64> = records
+        .iter()
+        .filter_map(|line| match &line.record {
+            MtxtRecord::Note { time, .. } | MtxtRecord::NoteOn { time, .. } => {
+                let (_, phase) = phase(*time, grid);
+                // notes within half a step of the off-beat
+                (0.5..1.5).contains(&phase).then_some(phase - 1.0)
+            }
+            _ => None,
+        })
+        .collect();
+    if offsets.is_empty() {
+        return None;
+    }
+
+    offsets.sort_by(f64::total_cmp);
+    let middle = offsets.len() / 2;
+    let offset = if offsets.len().is_multiple_of(2) {
+        (offsets[middle - 1] + offsets[middle]) / 2.0
+    } else {
+        offsets[middle]
+    };
+
+    Some(SwingReport {
+        grid,
+        swing: (offset * 6.0) as f32,
+        ratio: ((1.0 + offset) / 2.0) as f32,
+        off_beat_notes: offsets.len(),
+    })
+}
+
+/// Removes the swing estimated on `grid`: the swung off-beat is moved back to the middle
+/// of its pair of steps, and times in between are stretched linearly, so timing deviations
+/// and note lengths are kept relative to the grid. Inverse of quantizing with swing.
+pub fn transform(records: &[MtxtRecordLine], grid: u32) -> Vec<MtxtRecordLine> {
+    let Some(report) = estimate(records, grid) else {
+        return records.to_vec();
+    };
+    let off_beat = 1.0 + report.swing as f64 / 6.0;
+    if off_beat == 1.0 {
+        return records.to_vec();
+    }
+
+    let straighten = |time: BeatTime| {
+        let (pair_start, phase) = phase(time, grid);
+        let straight = if phase < off_beat {
+            phase / off_beat
+        } else {
+            1.0 + (phase - off_beat) / (2.0 - off_beat)
+        };
+        from_steps(pair_start + straight, grid)
+    };
+
+    let mut current_duration = BeatTime::from_parts(1, 0.0);
+    records
+        .iter()
+        .map(|line| {
+            let mut new_line = line.clone();
+            match &mut new_line.record {
+                MtxtRecord::DurationDirective { duration } => current_duration = *duration,
+                MtxtRecord::Note { time, duration, .. } => {
+                    let old_duration = duration.unwrap_or(current_duration);
+                    let start = straighten(*time);
+                    let new_duration = straighten(*time + old_duration) - start;
+                    if new_duration != old_duration {
+                        *duration = Some(new_duration);
+                    }
+                    *time = start;
+                }
+                record => {
+                    if let Some(time) = record.time() {
+                        record.set_time(straighten(time));
+                    }
+                }
+            }
+            new_line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use crate::transforms::quantize;
+
+    fn onsets(records: &[MtxtRecordLine]) -> Vec<f64> {
+        records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note { time, .. } => Some(time.as_f64()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_swing_estimate_and_removal() {
+        let mut input = String::from("mtxt 1.0\ndur=0.25\n");
+        for i in 0..32 {
+            input.push_str(&format!("{} note C4\n", i as f64 * 0.5));
+        }
+        let straight = parse_mtxt(&input).unwrap().records;
+        let mut swung =
+            quantize::transform(&straight, "1/2".parse().ok(), 0.6, &Default::default());
+        // a few notes far from the swung position
+        let outliers = parse_mtxt("mtxt 1.0\n3.7 note E4\n5.3 note E4\n").unwrap();
+        swung.extend(outliers.records.into_iter().skip(1));
+
+        let report = estimate(&swung, 2).unwrap();
+        assert!((report.swing - 0.6).abs() < 1e-4, "{:?}", report);
+        assert!((report.ratio - 0.55).abs() < 1e-4, "{:?}", report);
+        assert_eq!(report.off_beat_notes, 18);
+        assert_eq!(estimate(&straight, 2).unwrap().swing, 0.0);
+
+        let restored = transform(&swung, 2);
+        for (restored, original) in onsets(&restored).iter().zip(onsets(&straight)).take(32) {
+            assert!(
+                (restored - original).abs() < 1e-4,
+                "{} != {}",
+                restored,
+                original
+            );
+        }
+        assert!(estimate(&restored, 2).unwrap().swing.abs() < 1e-4);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashSet;
+
+/// Removes tempo records whose BPM is within `tolerance` of the last kept tempo,
+/// and time signatures repeating the previous one.
+/// Tempo ramps are only removed if they are exact duplicates. The first tempo is always kept.
+pub fn transform(records: &[MtxtRecordLine], tolerance: f32) -> Vec<MtxtRecordLine> {
+    let mut tempos: Vec<usize> = Vec::new();
+    let mut signatures: Vec<usize> = Vec::new();
+    for (idx, line) in records.iter().enumerate() {
+        match &line.record {
+            MtxtRecord::Tempo { .. } => tempos.push(idx),
+            MtxtRecord::TimeSignature { .. } => signatures.push(idx),
+            _ => {}
+        }
+    }
+    let by_time = |idx: &usize| records[*idx].record.time();
+    tempos.sort_by_key(by_time);
+    signatures.sort_by_key(by_time);
+
+    let mut dropped: HashSet<usize> = HashSet::new();
+
+    let mut last_kept: Option<&MtxtRecord> = None;
+    for idx in tempos {
+        let record = &records[idx].record;
+        if let (
+            Some(MtxtRecord::Tempo { bpm: last_bpm, .. }),
+            MtxtRecord::Tempo {
+                bpm,
+                transition_time,
+                ..
+            },
+        ) = (last_kept, record)
+        {
+            let duplicate = last_kept.is_some_and(|last| same_tempo(last, record));
+            let within_tolerance = transition_time.is_none() && (bpm - last_bpm).abs() < tolerance;
+            if duplicate || within_tolerance {
+                dropped.insert(idx);
+                continue;
+            }
+        }
+        last_kept = Some(record);
+    }
+
+    let mut last_signature = None;
+    for idx in signatures {
+        if let MtxtRecord::TimeSignature { signature, .. } = &records[idx].record {
+            if last_signature == Some(signature) {
+                dropped.insert(idx);
+            }
+            last_signature = Some(signature);
+        }
+    }
+
+    records
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !dropped.contains(idx))
+        .map(|(_, line)| line.clone())
+        .collect()
+}
+
+// Equal apart from the time
+fn same_tempo(a: &MtxtRecord, b: &MtxtRecord) -> bool {
+    let mut b = b.clone();
+    if let Some(time) = a.time() {
+        b.set_time(time);
+    }
+    *a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+    use crate::{MtxtFile, parse_mtxt};
+
+    fn total_micros(records: Vec<MtxtRecordLine>) -> u64 {
+        MtxtFile::from_records(records)
+            .get_output_records()
+            .iter()
+            .map(|record| record.time())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn assert_duration_preserved(input: &str, tolerance: f32, epsilon_micros: u64) {
+        let file = parse_mtxt(input).unwrap();
+        let before = total_micros(file.records.clone());
+        let after = total_micros(transform(&file.records, tolerance));
+        assert!(
+            before.abs_diff(after) < epsilon_micros,
+            "duration changed from {} to {}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn test_simplify_tempo() {
+        let input = r#"
+mtxt 1.0
+0.0 tempo 120
+0.0 timesig 4/4
+1.0 tempo 120.001
+2.0 tempo 119.999
+3.0 tempo 140
+4.0 timesig 4/4
+4.0 tempo 140
+5.0 tempo 100 transition_time=1
+6.0 tempo 100.001 transition_time=1
+7.0 timesig 3/4
+8.0 note C4
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 120
+0.0 timesig 4/4
+3.0 tempo 140
+5.0 tempo 100 transition_time=1
+6.0 tempo 100.001 transition_time=1
+7.0 timesig 3/4
+8.0 note C4
+"#;
+        assert_eq_records(input, |r| transform(r, 0.01), expected);
+        assert_duration_preserved(input, 0.01, 100);
+    }
+
+    #[test]
+    fn test_simplify_tempo_automation() {
+        let mut input = String::from("mtxt 1.0\n0.0 tempo 120\n");
+        for i in 1..200 {
+            let bpm = 120.0 + if i % 2 == 0 { 0.001 } else { -0.001 };
+            input.push_str(&format!("{}.0 tempo {}\n", i, bpm));
+        }
+        input.push_str("200.0 note C4\n");
+
+        let simplified = transform(&parse_mtxt(&input).unwrap().records, 0.01);
+        let tempo_count = simplified
+            .iter()
+            .filter(|line| matches!(line.record, MtxtRecord::Tempo { .. }))
+            .count();
+        assert_eq!(tempo_count, 1);
+        assert_duration_preserved(&input, 0.01, 1_000);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{HashMap, HashSet};
+
+// (channel, controller, note)
+type CcGroup = (u16, String, Option<String>);
+
+// Group of every ControlChange record, resolved through directives
+fn resolve_cc_groups(records: &[MtxtRecordLine]) -> Vec<Option<CcGroup>> {
+    let mut current_channel: u16 = 0;
+
+    records
+        .iter()
+        .map(|line| match &line.record {
+            MtxtRecord::ChannelDirective { channel } => {
+                current_channel = *channel;
+                None
+            }
+            MtxtRecord::ControlChange {
+                note,
+                controller,
+                channel,
+                ..
+            } => Some((
+                channel.unwrap_or(current_channel),
+                controller.clone(),
+                note.as_ref().map(|n| n.to_string()),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_local_extremum(prev: f32, value: f32, next: f32) -> bool {
+    (value > prev && value > next) || (value < prev && value < next)
+}
+
+/// Drops ControlChange records (including pitch bend) that add little to the curve.
+/// Within every (channel, controller, note) group, a point is dropped if it is closer than
+/// `interval` beats AND less than `min_delta` away from the last kept point.
+/// A criterion set to 0 is ignored. The first, last and local extrema are always kept.
+pub fn transform(records: &[MtxtRecordLine], interval: f32, min_delta: f32) -> Vec<MtxtRecordLine> {
+    if interval <= 0.0 && min_delta <= 0.0 {
+        return records.to_vec();
+    }
+
+    let mut groups: HashMap<CcGroup, Vec<(usize, BeatTime, f32)>> = HashMap::new();
+    for (idx, (line, group)) in records.iter().zip(resolve_cc_groups(records)).enumerate() {
+        if let (MtxtRecord::ControlChange { time, value, .. }, Some(group)) = (&line.record, group)
+        {
+            groups.entry(group).or_default().push((idx, *time, *value));
+        }
+    }
+
+    let mut dropped: HashSet<usize> = HashSet::new();
+    for points in groups.values_mut() {
+        points.sort_by_key(|(_, time, _)| *time);
+
+        let Some(&(_, mut last_time, mut last_value)) = points.first() else {
+            continue;
+        };
+        for i in 1..points.len().saturating_sub(1) {
+            let (idx, time, value) = points[i];
+            let close = interval <= 0.0 || ((time - last_time).as_f64() as f32) < interval;
+            let small = min_delta <= 0.0 || (value - last_value).abs() < min_delta;
+            let extremum = is_local_extremum(points[i - 1].2, value, points[i + 1].2);
+
+            if close && small && !extremum {
+                dropped.insert(idx);
+            } else {
+                last_time = time;
+                last_value = value;
+            }
+        }
+    }
+
+    records
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !dropped.contains(idx))
+        .map(|(_, line)| line.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_thin_ramp() {
+        let input = r#"
+mtxt 1.0
+0.0 cc modulation 0.0
+0.1 cc modulation 0.01
+0.2 cc modulation 0.02
+0.3 cc modulation 0.03
+0.4 cc modulation 0.04
+0.5 cc modulation 0.2
+0.6 cc modulation 0.19
+0.7 cc modulation 0.18
+0.8 cc modulation 0.17
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc modulation 0.0
+0.5 cc modulation 0.2
+0.8 cc modulation 0.17
+"#;
+        assert_eq_records(input, |r| transform(r, 1.0, 0.05), expected);
+    }
+
+    #[test]
+    fn test_thin_interval() {
+        let input = r#"
+mtxt 1.0
+0.0 cc pitch 0.0
+0.25 cc pitch 0.1
+0.5 cc pitch 0.2
+0.75 cc pitch 0.3
+1.0 cc pitch 0.4
+1.25 cc pitch 0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc pitch 0.0
+0.5 cc pitch 0.2
+1.0 cc pitch 0.4
+1.25 cc pitch 0.5
+"#;
+        assert_eq_records(input, |r| transform(r, 0.5, 0.0), expected);
+    }
+
+    #[test]
+    fn test_thin_groups() {
+        let input = r#"
+mtxt 1.0
+0.0 cc volume 0.5 ch=1
+0.0 cc volume 0.5 ch=2
+0.1 cc volume 0.51 ch=1
+0.1 cc volume 0.51 ch=2
+0.2 cc volume 0.52 ch=1
+0.3 cc volume 0.53 ch=1
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 cc volume 0.5 ch=1
+0.0 cc volume 0.5 ch=2
+0.1 cc volume 0.51 ch=2
+0.3 cc volume 0.53 ch=1
+"#;
+        assert_eq_records(input, |r| transform(r, 1.0, 0.1), expected);
+    }
+}
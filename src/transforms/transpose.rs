@@ -1,15 +1,53 @@
-use crate::types::note::{Note, NoteTarget};
+use super::invert::{find_exempt_aliases, note_target_and_channel};
+use crate::types::note::{Note, NoteTarget, SpellingPreference};
 use crate::types::record::{AliasDefinition, MtxtRecord, MtxtRecordLine};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+const MIN_NOTE: i32 = 0;
+const MAX_NOTE: i32 = 127;
+
+/// Transposes notes together, by an octave less as long as they would leave the MIDI range,
+/// but never in the opposite direction. Notes that still don't fit are kept as they are.
+pub(super) fn transpose_notes(
+    notes: &[Note],
+    semitones: i32,
+    spelling: SpellingPreference,
+) -> Vec<Note> {
+    let pitches = notes.iter().map(Note::absolute_semitone);
+    let (Some(lowest), Some(highest)) = (pitches.clone().min(), pitches.max()) else {
+        return Vec::new();
+    };
+    let mut semitones = semitones;
+    while highest + semitones > MAX_NOTE && semitones >= 12 {
+        semitones -= 12;
+    }
+    while lowest + semitones < MIN_NOTE && semitones <= -12 {
+        semitones += 12;
+    }
+    notes
+        .iter()
+        .map(|note| note.transpose(semitones, spelling))
+        .collect::<anyhow::Result<Vec<Note>>>()
+        .unwrap_or_else(|_| notes.to_vec())
+}
+
+fn transpose_note(note: &Note, amount: i32) -> Note {
+    transpose_notes(
+        std::slice::from_ref(note),
+        amount,
+        SpellingPreference::Common,
+    )
+    .remove(0)
+}
+
 fn transpose_target(
     target: &NoteTarget,
     amount: i32,
     map: &HashMap<usize, Rc<AliasDefinition>>,
 ) -> NoteTarget {
     match target {
-        NoteTarget::Note(n) => NoteTarget::Note(n.transpose(amount)),
+        NoteTarget::Note(n) => NoteTarget::Note(transpose_note(n, amount)),
         NoteTarget::AliasKey(k) => NoteTarget::AliasKey(k.clone()),
         NoteTarget::Alias(rc) => {
             let ptr = Rc::as_ptr(rc) as usize;
@@ -21,23 +59,53 @@ fn transpose_target(
                 NoteTarget::Alias(rc.clone())
             }
         }
+        NoteTarget::Range { low, high } => {
+            let notes = transpose_notes(
+                &[low.clone(), high.clone()],
+                amount,
+                SpellingPreference::Common,
+            );
+            NoteTarget::Range {
+                low: notes[0].clone(),
+                high: notes[1].clone(),
+            }
+        }
     }
 }
 
-pub fn transform(records: &[MtxtRecordLine], amount: i32) -> Vec<MtxtRecordLine> {
+/// Transposes every note by `amount` semitones.
+/// Notes on `preserve_channels` (usually drums) are left untouched. Notes that would leave the
+/// MIDI range are transposed by whole octaves less, chords and ranges as a whole, and are kept
+/// as they are if even that leaves the range.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    amount: i32,
+    preserve_channels: &HashSet<u16>,
+) -> Vec<MtxtRecordLine> {
     if amount == 0 {
         return records.to_vec();
     }
 
+    let preserved_aliases = find_exempt_aliases(records, preserve_channels);
     let mut new_records = Vec::with_capacity(records.len());
     let mut alias_map: HashMap<usize, Rc<AliasDefinition>> = HashMap::new();
+    let mut current_channel: u16 = 0;
 
     for line in records {
         let record = &line.record;
+        if let MtxtRecord::ChannelDirective { channel } = record {
+            current_channel = *channel;
+        }
+        if let Some((_, channel)) = note_target_and_channel(record)
+            && preserve_channels.contains(&channel.unwrap_or(current_channel))
+        {
+            new_records.push(line.clone());
+            continue;
+        }
+
         let new_record = match record {
-            MtxtRecord::AliasDef { value } => {
-                let new_notes: Vec<Note> =
-                    value.notes.iter().map(|n| n.transpose(amount)).collect();
+            MtxtRecord::AliasDef { value } if !preserved_aliases.contains(&value.name) => {
+                let new_notes = transpose_notes(&value.notes, amount, SpellingPreference::Common);
                 let new_def = Rc::new(AliasDefinition {
                     name: value.name.clone(),
                     notes: new_notes,
@@ -135,6 +203,78 @@ alias Cmaj B2,Eb3,F#3
 3.0 cc B0 volume 0.5
 "#;
 
-        assert_eq_records(input, |records| transform(records, -13), expected);
+        assert_eq_records(
+            input,
+            |records| transform(records, -13, &HashSet::new()),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_transpose_preserve_channels() {
+        let input = r#"
+mtxt 1.0
+alias kick C2
+alias Cmaj C4,E4,G4
+ch=9
+1.0 note kick
+1.0 note D2
+2.0 note Cmaj ch=0
+ch=0
+3.0 note D4
+"#;
+        let expected = r#"
+mtxt 1.0
+alias kick C2
+alias Cmaj F4,A4,C5
+ch=9
+1.0 note kick
+1.0 note D2
+2.0 note Cmaj ch=0
+ch=0
+3.0 note G4
+"#;
+        assert_eq_records(
+            input,
+            |records| transform(records, 5, &HashSet::from([9])),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_transpose_out_of_range() {
+        let input = r#"
+mtxt 1.0
+alias high C9,G9
+1.0 note E9
+2.0 note high
+3.0 note C8-C9
+4.0 note D0
+5.0 note C9
+"#;
+        // notes that would pass G9 move an octave less, the ones at the top of the range
+        // can't move up at all and stay
+        let expected = r#"
+mtxt 1.0
+alias high C9,G9
+1.0 note E9
+2.0 note high
+3.0 note G8-G9
+4.0 note A1
+5.0 note G9
+"#;
+        assert_eq_records(
+            input,
+            |records| transform(records, 19, &HashSet::new()),
+            expected,
+        );
+
+        let input = "mtxt 1.0\n1.0 note D0\n2.0 note C-1-G9\n3.0 note C-1\n";
+        let expected = "mtxt 1.0\n1.0 note G-1\n2.0 note C-1-G9\n3.0 note C-1\n";
+        assert_eq_records(
+            input,
+            |records| transform(records, -19, &HashSet::new()),
+            expected,
+        );
     }
 }
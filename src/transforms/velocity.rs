@@ -0,0 +1,246 @@
+use super::DEFAULT_VELOCITY;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct VelocityCompression {
+    pub threshold: f32,
+    pub ratio: f32,
+}
+
+impl VelocityCompression {
+    fn apply(&self, velocity: f32) -> f32 {
+        if self.ratio <= 0.0 || velocity <= self.threshold {
+            return velocity;
+        }
+        self.threshold + (velocity - self.threshold) / self.ratio
+    }
+}
+
+// Effective (channel, velocity) of every note-on-like record, resolved through directives
+fn resolve_velocities(records: &[MtxtRecordLine]) -> Vec<Option<(u16, f32)>> {
+    let mut current_channel: u16 = 0;
+    let mut current_velocity = DEFAULT_VELOCITY;
+
+    records
+        .iter()
+        .map(|line| match &line.record {
+            MtxtRecord::ChannelDirective { channel } => {
+                current_channel = *channel;
+                None
+            }
+            MtxtRecord::VelocityDirective { velocity } => {
+                current_velocity = *velocity;
+                None
+            }
+            MtxtRecord::Note {
+                velocity, channel, ..
+            }
+            | MtxtRecord::NoteOn {
+                velocity, channel, ..
+            } => Some((
+                channel.unwrap_or(current_channel),
+                velocity.unwrap_or(current_velocity),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn transform(
+    records: &[MtxtRecordLine],
+    normalize: Option<(f32, f32)>,
+    compress: Option<VelocityCompression>,
+    per_channel: bool,
+) -> Vec<MtxtRecordLine> {
+    if normalize.is_none() && compress.is_none() {
+        return records.to_vec();
+    }
+
+    // First pass: compress and collect the observed range per group
+    let velocities: Vec<Option<(u16, f32)>> = resolve_velocities(records)
+        .into_iter()
+        .map(|entry| {
+            entry.map(|(ch, v)| {
+                let group = if per_channel { ch } else { 0 };
+                (group, compress.map_or(v, |c| c.apply(v)))
+            })
+        })
+        .collect();
+
+    let mut ranges: HashMap<u16, (f32, f32)> = HashMap::new();
+    for (group, v) in velocities.iter().flatten() {
+        let range = ranges.entry(*group).or_insert((*v, *v));
+        range.0 = range.0.min(*v);
+        range.1 = range.1.max(*v);
+    }
+
+    // Second pass: write the effective velocities inline
+    let mut new_records = Vec::with_capacity(records.len());
+    for (line, entry) in records.iter().zip(velocities) {
+        let mut new_line = line.clone();
+        match &mut new_line.record {
+            // every affected note gets an inline velocity, so directives are redundant
+            MtxtRecord::VelocityDirective { .. } => continue,
+            MtxtRecord::Note { velocity, .. } | MtxtRecord::NoteOn { velocity, .. } => {
+                if let Some((group, v)) = entry {
+                    let mapped = match normalize {
+                        Some((target_min, target_max)) => {
+                            let (min, max) = ranges[&group];
+                            if max - min <= f32::EPSILON {
+                                // single velocity in group, nothing to scale
+                                (target_min + target_max) / 2.0
+                            } else {
+                                target_min + (v - min) / (max - min) * (target_max - target_min)
+                            }
+                        }
+                        None => v,
+                    };
+                    *velocity = Some(mapped.clamp(0.0, 1.0));
+                }
+            }
+            _ => {}
+        }
+        new_records.push(new_line);
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use crate::util::assert_eq_records;
+
+    fn velocity_range(records: &[MtxtRecordLine], channel: Option<u16>) -> (f32, f32) {
+        records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note {
+                    velocity: Some(v),
+                    channel: ch,
+                    ..
+                } if channel.is_none() || *ch == channel => Some(*v),
+                _ => None,
+            })
+            .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                (min.min(v), max.max(v))
+            })
+    }
+
+    #[test]
+    fn test_normalize() {
+        let input = parse_mtxt(
+            r#"
+mtxt 1.0
+1.0 note C4 vel=0.3
+2.0 note E4 vel=0.5
+3.0 note G4 vel=0.4
+"#,
+        )
+        .unwrap();
+
+        let output = transform(&input.records, Some((0.2, 1.0)), None, false);
+        let (min, max) = velocity_range(&output, None);
+        assert!((min - 0.2).abs() < 1e-6);
+        assert!((max - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_normalize_implicit_velocity() {
+        // notes without a velocity are played at full velocity
+        let input = parse_mtxt(
+            r#"
+mtxt 1.0
+1.0 note C4 vel=0.2
+2.0 note E4 vel=0.4
+3.0 note G4
+"#,
+        )
+        .unwrap();
+
+        let output = transform(&input.records, Some((0.5, 0.6)), None, false);
+        let velocities: Vec<f32> = output
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::Note { velocity, .. } => *velocity,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(velocities.len(), 3);
+        for (velocity, expected) in velocities.iter().zip([0.5, 0.525, 0.6]) {
+            assert!((velocity - expected).abs() < 1e-6, "{}", velocity);
+        }
+    }
+
+    #[test]
+    fn test_normalize_per_channel() {
+        let input = parse_mtxt(
+            r#"
+mtxt 1.0
+1.0 note C4 vel=0.1 ch=1
+2.0 note E4 vel=0.2 ch=1
+1.0 note C3 vel=0.7 ch=2
+2.0 note E3 vel=0.9 ch=2
+"#,
+        )
+        .unwrap();
+
+        let output = transform(&input.records, Some((0.5, 0.8)), None, true);
+        for ch in [1, 2] {
+            let (min, max) = velocity_range(&output, Some(ch));
+            assert!((min - 0.5).abs() < 1e-6);
+            assert!((max - 0.8).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_normalize_single_velocity() {
+        let input = r#"
+mtxt 1.0
+vel=0.3
+1.0 note C4
+2.0 on E4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 vel=0.5
+2.0 on E4 vel=0.5
+"#;
+        assert_eq_records(
+            input,
+            |r| transform(r, Some((0.2, 0.8)), None, false),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_compress() {
+        let input = r#"
+mtxt 1.0
+1.0 note C4 vel=0.4
+2.0 note E4 vel=1.0
+3.0 note G4
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C4 vel=0.4
+2.0 note E4 vel=0.75
+3.0 note G4 vel=0.75
+"#;
+        assert_eq_records(
+            input,
+            |r| {
+                let compression = VelocityCompression {
+                    threshold: 0.5,
+                    ratio: 2.0,
+                };
+                transform(r, None, Some(compression), false)
+            },
+            expected,
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use super::DEFAULT_VELOCITY;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Drops notes whose effective velocity is outside `[min, max]`.
+/// NoteOff records are dropped together with their NoteOn.
+pub fn transform(
+    records: &[MtxtRecordLine],
+    min: Option<f32>,
+    max: Option<f32>,
+) -> Vec<MtxtRecordLine> {
+    if min.is_none() && max.is_none() {
+        return records.to_vec();
+    }
+    let in_window = |v: f32| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max);
+
+    let mut current_channel: u16 = 0;
+    let mut current_velocity = DEFAULT_VELOCITY;
+    // FIFO of "was dropped" flags for note ons waiting for their note off
+    let mut open_notes: HashMap<(u16, String), VecDeque<bool>> = HashMap::new();
+    let mut dropped: HashSet<usize> = HashSet::new();
+
+    for (idx, line) in records.iter().enumerate() {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::VelocityDirective { velocity } => current_velocity = *velocity,
+            MtxtRecord::Note { velocity, .. }
+                if !in_window(velocity.unwrap_or(current_velocity)) =>
+            {
+                dropped.insert(idx);
+            }
+            MtxtRecord::NoteOn {
+                note,
+                velocity,
+                channel,
+                ..
+            } => {
+                let drop = !in_window(velocity.unwrap_or(current_velocity));
+                let key = (channel.unwrap_or(current_channel), note.to_string());
+                open_notes.entry(key).or_default().push_back(drop);
+                if drop {
+                    dropped.insert(idx);
+                }
+            }
+            MtxtRecord::NoteOff { note, channel, .. } => {
+                let key = (channel.unwrap_or(current_channel), note.to_string());
+                if open_notes
+                    .get_mut(&key)
+                    .and_then(|queue| queue.pop_front())
+                    .unwrap_or(false)
+                {
+                    dropped.insert(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    records
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !dropped.contains(idx))
+        .map(|(_, line)| line.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    #[test]
+    fn test_velocity_filter_notes() {
+        let input = r#"
+mtxt 1.0
+1.0 note C2 vel=0.9
+1.5 note D2 vel=0.3
+vel=0.7
+2.0 note C2
+2.5 note D2 vel=0.5
+"#;
+        let expected = r#"
+mtxt 1.0
+1.0 note C2 vel=0.9
+vel=0.7
+2.0 note C2
+"#;
+        assert_eq_records(input, |r| transform(r, Some(0.6), None), expected);
+    }
+
+    #[test]
+    fn test_velocity_filter_note_on_off() {
+        let input = r#"
+mtxt 1.0
+ch=9
+1.0 on C2 vel=0.9
+1.0 on F#2 vel=0.2
+1.25 off F#2
+1.5 off C2
+1.5 on F#2 vel=0.8
+1.75 off F#2
+2.0 on C2 vel=0.4 ch=1
+2.5 on C2 vel=0.95 ch=1
+3.0 off C2 ch=1
+3.5 off C2 ch=1
+"#;
+        let expected = r#"
+mtxt 1.0
+ch=9
+1.0 on C2 vel=0.9
+1.5 off C2
+1.5 on F#2 vel=0.8
+1.75 off F#2
+2.5 on C2 vel=0.95 ch=1
+3.5 off C2 ch=1
+"#;
+        assert_eq_records(input, |r| transform(r, Some(0.5), Some(1.0)), expected);
+    }
+}
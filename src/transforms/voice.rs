@@ -0,0 +1,218 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine, VoiceList};
+use std::collections::{HashMap, HashSet};
+
+// Channels cleared by a reset target (`all` or `ch=N`), `None` for all channels
+fn reset_channel(target: &str) -> Option<Option<u16>> {
+    if target == "all" {
+        return Some(None);
+    }
+    target
+        .strip_prefix("ch=")
+        .and_then(|ch| ch.parse::<u16>().ok())
+        .map(Some)
+}
+
+// Indices of the first Voice record of each channel that can move to beat 0:
+// no note of the channel starts before it and no reset of the channel precedes it
+fn hoistable(records: &[MtxtRecordLine]) -> HashSet<usize> {
+    let mut first_voice: HashMap<u16, Option<usize>> = HashMap::new();
+    let mut earliest_note: HashMap<u16, BeatTime> = HashMap::new();
+    let mut reset_before: HashSet<u16> = HashSet::new();
+    let mut reset_all = false;
+    let mut current_channel: u16 = 0;
+
+    for (idx, line) in records.iter().enumerate() {
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::Note { time, channel, .. } | MtxtRecord::NoteOn { time, channel, .. } => {
+                let channel = channel.unwrap_or(current_channel);
+                let earliest = earliest_note.entry(channel).or_insert(*time);
+                *earliest = (*earliest).min(*time);
+            }
+            MtxtRecord::Voice { time, channel, .. } => {
+                let channel = channel.unwrap_or(current_channel);
+                if first_voice.contains_key(&channel) {
+                    continue;
+                }
+                let movable =
+                    *time > BeatTime::zero() && !reset_all && !reset_before.contains(&channel);
+                first_voice.insert(channel, movable.then_some(idx));
+            }
+            MtxtRecord::Reset { target, .. } => match reset_channel(target) {
+                Some(None) => reset_all = true,
+                Some(Some(channel)) => {
+                    reset_before.insert(channel);
+                }
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    first_voice
+        .into_iter()
+        .filter_map(|(channel, idx)| {
+            let idx = idx?;
+            let time = records[idx].record.time()?;
+            earliest_note
+                .get(&channel)
+                .is_none_or(|note| *note >= time)
+                .then_some(idx)
+        })
+        .collect()
+}
+
+/// Cleans up Voice records: with `dedup` a Voice repeating the previous voice of its channel
+/// is removed, a Reset of the channel forgets the previous voice.
+/// With `hoist` the first Voice of each channel moves to beat 0 if no note sounds on the channel
+/// before it, so instruments are configured up front. It is placed after the records at beat 0
+/// with its channel written inline.
+pub fn transform(records: &[MtxtRecordLine], dedup: bool, hoist: bool) -> Vec<MtxtRecordLine> {
+    let hoisted = if hoist {
+        hoistable(records)
+    } else {
+        HashSet::new()
+    };
+    let mut pending: Vec<MtxtRecordLine> = Vec::new();
+    let mut last_voice: HashMap<u16, VoiceList> = HashMap::new();
+    let mut current_channel: u16 = 0;
+    let mut new_records = Vec::with_capacity(records.len());
+
+    // voices to hoist in file order, with their channel resolved
+    for (idx, line) in records.iter().enumerate() {
+        if !hoisted.contains(&idx) {
+            continue;
+        }
+        let mut voice = line.clone();
+        if let MtxtRecord::Voice { time, channel, .. } = &mut voice.record {
+            *time = BeatTime::zero();
+            *channel = Some(channel.unwrap_or_else(|| {
+                records[..idx]
+                    .iter()
+                    .rev()
+                    .find_map(|line| match line.record {
+                        MtxtRecord::ChannelDirective { channel } => Some(channel),
+                        _ => None,
+                    })
+                    .unwrap_or(0)
+            }));
+        }
+        pending.push(voice);
+    }
+    let mut pending = pending.into_iter();
+
+    for (idx, line) in records.iter().enumerate() {
+        if line
+            .record
+            .time()
+            .is_some_and(|time| time > BeatTime::zero())
+        {
+            new_records.extend(pending.by_ref());
+        }
+
+        match &line.record {
+            MtxtRecord::ChannelDirective { channel } => current_channel = *channel,
+            MtxtRecord::Voice {
+                voices, channel, ..
+            } => {
+                let channel = channel.unwrap_or(current_channel);
+                let repeated = last_voice.insert(channel, voices.clone()).as_ref() == Some(voices);
+                if hoisted.contains(&idx) || (dedup && repeated) {
+                    continue;
+                }
+            }
+            MtxtRecord::Reset { target, .. } => match reset_channel(target) {
+                Some(None) => last_voice.clear(),
+                Some(Some(channel)) => {
+                    last_voice.remove(&channel);
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        new_records.push(line.clone());
+    }
+
+    new_records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::assert_eq_records;
+
+    const INPUT: &str = r#"
+mtxt 1.0
+0.0 tempo 120
+ch=1
+1.5 voice piano
+2.0 note C4
+ch=2
+0.0 note E4
+1.0 voice strings
+3.0 voice ch=1 piano
+4.0 voice ch=1 piano
+4.0 voice ch=1 organ
+5.0 voice strings
+"#;
+
+    #[test]
+    fn test_dedup_voices() {
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 120
+ch=1
+1.5 voice piano
+2.0 note C4
+ch=2
+0.0 note E4
+1.0 voice strings
+4.0 voice ch=1 organ
+"#;
+        assert_eq_records(INPUT, |r| transform(r, true, false), expected);
+    }
+
+    #[test]
+    fn test_hoist_voices() {
+        // strings on channel 2 starts after a note, only piano on channel 1 moves
+        let expected = r#"
+mtxt 1.0
+0.0 tempo 120
+ch=1
+0.0 voice ch=1 piano
+2.0 note C4
+ch=2
+0.0 note E4
+1.0 voice strings
+3.0 voice ch=1 piano
+4.0 voice ch=1 piano
+4.0 voice ch=1 organ
+5.0 voice strings
+"#;
+        assert_eq_records(INPUT, |r| transform(r, false, true), expected);
+    }
+
+    #[test]
+    fn test_hoist_keeps_voice_changes() {
+        let input = r#"
+mtxt 1.0
+0.0 note C4 ch=3
+2.0 voice ch=1 bass
+3.0 reset ch=2
+4.0 voice ch=1 piano
+4.0 voice ch=2 choir
+4.0 note D4 ch=1
+"#;
+        let expected = r#"
+mtxt 1.0
+0.0 note C4 ch=3
+0.0 voice ch=1 bass
+3.0 reset ch=2
+4.0 voice ch=1 piano
+4.0 voice ch=2 choir
+4.0 note D4 ch=1
+"#;
+        assert_eq_records(input, |r| transform(r, true, true), expected);
+    }
+}
@@ -7,7 +7,7 @@ use std::collections::HashMap;
 /// - curve > 0: ease-in (starts slow, ends fast)
 /// - curve < 0: ease-out (starts fast, ends slow)
 /// - curve = 0: linear interpolation
-fn apply_transition_curve(v0: f32, v1: f32, pos: f32, curve: f32) -> f32 {
+pub(crate) fn apply_transition_curve(v0: f32, v1: f32, pos: f32, curve: f32) -> f32 {
     v0 + (v1 - v0)
         * (pos + curve.max(0.0) * (pos.powi(4) - pos)
             - (-curve).max(0.0) * ((1.0 - (1.0 - pos).powi(4)) - pos))
@@ -149,9 +149,8 @@ impl TransitionProcessor {
                 transition.original_record.transition_time.as_f64() * transition.next_pos as f64;
             let target_beat_f64 =
                 transition.original_record.start_beat_time.as_f64() + transition_progress;
-            let target_beat = BeatTime::from_parts(
-                target_beat_f64.floor() as u32,
-                (target_beat_f64.fract()) as f32,
+            let target_beat = BeatTime::from_units(
+                (target_beat_f64 * BeatTime::FRAC_BEAT_COUNT as f64).round() as u64,
             );
             let remaining_beats = target_beat - self.current_beat_time;
             transition.next_micros = self.current_micros + remaining_beats.as_micros(bpm as f64);
@@ -0,0 +1,722 @@
+use crate::{BeatFraction, BeatTime};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl BeatOperator {
+    // `/` is left out, it's also the separator of fraction literals
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(BeatOperator::Add),
+            '-' => Some(BeatOperator::Subtract),
+            '*' => Some(BeatOperator::Multiply),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BeatOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatOperator::Add => write!(f, "+"),
+            BeatOperator::Subtract => write!(f, "-"),
+            BeatOperator::Multiply => write!(f, "*"),
+            BeatOperator::Divide => write!(f, "/"),
+        }
+    }
+}
+
+/// An operand: either an exact fraction (`1/3`, `2`) or a decimal beat time (`1.5`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatValue {
+    Fraction(BeatFraction),
+    Time(BeatTime),
+}
+
+impl BeatValue {
+    pub fn as_beat_time(&self) -> BeatTime {
+        match self {
+            BeatValue::Fraction(fraction) => fraction.as_beat_time(),
+            BeatValue::Time(time) => *time,
+        }
+    }
+}
+
+impl fmt::Display for BeatValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatValue::Fraction(fraction) if fraction.denominator == 1 => {
+                write!(f, "{}", fraction.numerator)
+            }
+            BeatValue::Fraction(fraction) => write!(f, "{}", fraction.to_string_verbatim()),
+            BeatValue::Time(time) => write!(f, "{}", time),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatExpressionItem {
+    Value(BeatValue),
+    Operator(BeatOperator),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BeatExpressionError {
+    #[error("Beat expression is empty")]
+    Empty,
+    #[error("Spaces are not allowed in beat expressions")]
+    SpacesNotAllowed,
+    #[error("Beat expression is negative ({pos} - {neg})")]
+    NegativeResult { pos: BeatTime, neg: BeatTime },
+    #[error("Invalid operand in beat expression: \"{0}\"")]
+    InvalidOperand(String),
+    #[error("Multiplication operand must be a fraction, got \"{operand}\"")]
+    MultiplyOperandNotFraction { operand: String },
+    #[error("Division operand must be a fraction, got \"{operand}\"")]
+    DivideOperandNotFraction { operand: String },
+    #[error("Division by zero in beat expression")]
+    DivideByZero,
+    #[error("Beat expression cannot start or end with \"{0}\"")]
+    OperatorAtBoundary(BeatOperator),
+    #[error("Beat expression is not a single fraction: \"{0}\"")]
+    NotAFraction(String),
+}
+
+/// Sum of beat values, e.g. `1.0+1/3` or `3*1/4-1/8`.
+/// Multiplication and division bind tighter than addition and only accept fractions.
+/// The first `/` between whole numbers makes a fraction, any later one divides:
+/// `1/2/3` is a sixth and `1.5/3` is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeatExpression {
+    pub items: Vec<BeatExpressionItem>,
+}
+
+fn parse_operand(s: &str) -> Result<BeatValue, BeatExpressionError> {
+    let invalid = || BeatExpressionError::InvalidOperand(s.to_string());
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    if s.contains('.') {
+        return s.parse().map(BeatValue::Time).map_err(|_| invalid());
+    }
+
+    let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+    let numerator: u32 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: u32 = denominator.parse().map_err(|_| invalid())?;
+    if denominator == 0 {
+        return Err(BeatExpressionError::DivideByZero);
+    }
+    Ok(BeatValue::Fraction(BeatFraction {
+        numerator,
+        denominator,
+    }))
+}
+
+// Operand with any divisions after it, e.g. `1/2/3`, the divisors are whole numbers
+fn parse_divisions(
+    s: &str,
+    items: &mut Vec<BeatExpressionItem>,
+) -> Result<(), BeatExpressionError> {
+    let mut parts = s.split('/');
+    let first = parts.next().unwrap_or_default();
+    let mut rest: Vec<&str> = parts.collect();
+    let operand = match rest.first() {
+        Some(denominator) if !first.contains('.') => {
+            let literal = &s[..first.len() + 1 + denominator.len()];
+            rest.remove(0);
+            literal
+        }
+        _ => first,
+    };
+    items.push(BeatExpressionItem::Value(parse_operand(operand)?));
+    for divisor in rest {
+        let value = parse_operand(divisor)?;
+        if value == BeatValue::Fraction(BeatFraction::new(0, 1).unwrap()) {
+            return Err(BeatExpressionError::DivideByZero);
+        }
+        items.push(BeatExpressionItem::Operator(BeatOperator::Divide));
+        items.push(BeatExpressionItem::Value(value));
+    }
+    Ok(())
+}
+
+fn product(factors: &[(BeatOperator, BeatValue)]) -> Result<BeatValue, BeatExpressionError> {
+    if let [(_, value)] = factors {
+        return Ok(*value);
+    }
+
+    let mut product = BeatFraction::default();
+    for (idx, (operator, factor)) in factors.iter().enumerate() {
+        // the first factor takes the operator after it
+        let operator = match factors.get(1) {
+            Some((next, _)) if idx == 0 => *next,
+            _ => *operator,
+        };
+        let BeatValue::Fraction(fraction) = factor else {
+            let operand = factor.to_string();
+            return Err(match operator {
+                BeatOperator::Divide => BeatExpressionError::DivideOperandNotFraction { operand },
+                _ => BeatExpressionError::MultiplyOperandNotFraction { operand },
+            });
+        };
+        let fraction = if idx > 0 && operator == BeatOperator::Divide {
+            if fraction.numerator == 0 {
+                return Err(BeatExpressionError::DivideByZero);
+            }
+            BeatFraction {
+                numerator: fraction.denominator,
+                denominator: fraction.numerator,
+            }
+        } else {
+            *fraction
+        };
+        product = product
+            .checked_mul(fraction)
+            .ok_or_else(|| BeatExpressionError::InvalidOperand(factor.to_string()))?;
+    }
+    Ok(BeatValue::Fraction(product))
+}
+
+// Sum of the terms of one sign, fractions kept exact as long as they fit
+#[derive(Default)]
+struct TermSum {
+    fraction: Option<BeatFraction>,
+    time: BeatTime,
+}
+
+impl TermSum {
+    fn add(&mut self, term: BeatValue) {
+        let BeatValue::Fraction(term) = term else {
+            self.time = self.time + term.as_beat_time();
+            return;
+        };
+        match self.fraction.map_or(Some(term), |sum| sum.add_exact(term)) {
+            Some(sum) => self.fraction = Some(sum),
+            None => self.time = self.time + term.as_beat_time(),
+        }
+    }
+
+    fn total(&self) -> BeatTime {
+        self.time
+            + self
+                .fraction
+                .map_or(BeatTime::zero(), |sum| sum.as_beat_time())
+    }
+}
+
+// Cancels the fraction parts of the positive and negative sums exactly
+fn cancel_fractions(pos: &mut TermSum, neg: &mut TermSum) {
+    if let (Some(pos_fraction), Some(neg_fraction)) = (pos.fraction, neg.fraction) {
+        if let Some(difference) = pos_fraction.checked_sub(neg_fraction) {
+            (pos.fraction, neg.fraction) = (Some(difference), None);
+        } else if let Some(difference) = neg_fraction.checked_sub(pos_fraction) {
+            (pos.fraction, neg.fraction) = (None, Some(difference));
+        }
+    }
+}
+
+impl BeatExpression {
+    /// Computes the value of the expression. Only the final value must not be negative, the
+    /// order of the terms doesn't matter: `1/4-1/2+1.0` is 0.75 although reading from the left
+    /// it dips below zero.
+    pub fn evaluate(&self) -> Result<BeatTime, BeatExpressionError> {
+        let (mut pos, mut neg) = self.term_sums()?;
+        let (pos_total, neg_total) = (pos.total(), neg.total());
+        cancel_fractions(&mut pos, &mut neg);
+        if pos.total() < neg.total() {
+            return Err(BeatExpressionError::NegativeResult {
+                pos: pos_total,
+                neg: neg_total,
+            });
+        }
+        Ok(pos.total() - neg.total())
+    }
+
+    /// The same value with the fraction terms and the decimal terms each combined into one,
+    /// zeros left out, e.g. `1/4+1/4` is `1/2` and `1.0+0.0` is `1.0`.
+    /// Added terms come first, fractions before decimals: `1/8+0.5+1/8` is `1/4+0.5`.
+    /// Expressions with the same sum of fractions and the same sum of decimals simplify to the
+    /// same expression, an expression that can't be evaluated is returned unchanged.
+    pub fn simplify(&self) -> BeatExpression {
+        if self.evaluate().is_err() {
+            return self.clone();
+        }
+        let Ok((mut pos, mut neg)) = self.term_sums() else {
+            return self.clone();
+        };
+        cancel_fractions(&mut pos, &mut neg);
+
+        // (subtracted, value)
+        let mut terms: Vec<(bool, BeatValue)> = Vec::new();
+        for (negative, sum) in [(false, &pos), (true, &neg)] {
+            if let Some(fraction) = sum.fraction
+                && fraction.numerator > 0
+            {
+                terms.push((negative, BeatValue::Fraction(fraction.simplify())));
+            }
+        }
+        if pos.time >= neg.time {
+            terms.push((false, BeatValue::Time(pos.time - neg.time)));
+        } else {
+            terms.push((true, BeatValue::Time(neg.time - pos.time)));
+        }
+        terms.retain(|(_, value)| *value != BeatValue::Time(BeatTime::zero()));
+        terms.sort_by_key(|(negative, value)| (*negative, matches!(value, BeatValue::Time(_))));
+
+        let mut items = Vec::new();
+        for (negative, value) in terms {
+            if !items.is_empty() {
+                items.push(BeatExpressionItem::Operator(if negative {
+                    BeatOperator::Subtract
+                } else {
+                    BeatOperator::Add
+                }));
+            }
+            items.push(BeatExpressionItem::Value(value));
+        }
+        if items.is_empty() {
+            return BeatExpression::from(BeatFraction::new(0, 1).unwrap());
+        }
+        BeatExpression { items }
+    }
+
+    // Sums of the added and of the subtracted terms
+    fn term_sums(&self) -> Result<(TermSum, TermSum), BeatExpressionError> {
+        let mut pos = TermSum::default();
+        let mut neg = TermSum::default();
+        let mut negative = false;
+        // factors of the current term with the operator before them
+        let mut factors: Vec<(BeatOperator, BeatValue)> = Vec::new();
+        let mut factor_operator = BeatOperator::Multiply;
+
+        for item in self
+            .items
+            .iter()
+            .chain([&BeatExpressionItem::Operator(BeatOperator::Add)])
+        {
+            match item {
+                BeatExpressionItem::Value(value) => factors.push((factor_operator, *value)),
+                BeatExpressionItem::Operator(
+                    operator @ (BeatOperator::Multiply | BeatOperator::Divide),
+                ) => factor_operator = *operator,
+                BeatExpressionItem::Operator(operator) => {
+                    if factors.is_empty() {
+                        return Err(BeatExpressionError::OperatorAtBoundary(*operator));
+                    }
+                    let term = product(&factors)?;
+                    if negative {
+                        neg.add(term);
+                    } else {
+                        pos.add(term);
+                    }
+                    factors.clear();
+                    factor_operator = BeatOperator::Multiply;
+                    negative = *operator == BeatOperator::Subtract;
+                }
+            }
+        }
+        Ok((pos, neg))
+    }
+}
+
+impl fmt::Display for BeatExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, item) in self.items.iter().enumerate() {
+            match item {
+                // a whole dividend is written as a fraction, `2/1/3` rather than the third `2/3`
+                BeatExpressionItem::Value(BeatValue::Fraction(fraction))
+                    if fraction.denominator == 1
+                        && self.items.get(idx + 1)
+                            == Some(&BeatExpressionItem::Operator(BeatOperator::Divide))
+                        && (idx == 0
+                            || self.items[idx - 1]
+                                != BeatExpressionItem::Operator(BeatOperator::Divide)) =>
+                {
+                    write!(f, "{}", fraction.to_string_verbatim())?
+                }
+                BeatExpressionItem::Value(value) => write!(f, "{}", value)?,
+                BeatExpressionItem::Operator(operator) => write!(f, "{}", operator)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<BeatFraction> for BeatExpression {
+    fn from(fraction: BeatFraction) -> Self {
+        Self {
+            items: vec![BeatExpressionItem::Value(BeatValue::Fraction(fraction))],
+        }
+    }
+}
+
+impl From<BeatTime> for BeatExpression {
+    fn from(time: BeatTime) -> Self {
+        Self {
+            items: vec![BeatExpressionItem::Value(BeatValue::Time(time))],
+        }
+    }
+}
+
+/// The fraction of an expression made of a single fraction term
+impl TryFrom<BeatExpression> for BeatFraction {
+    type Error = BeatExpressionError;
+
+    fn try_from(expression: BeatExpression) -> Result<Self, Self::Error> {
+        match expression.items.as_slice() {
+            [BeatExpressionItem::Value(BeatValue::Fraction(fraction))] => Ok(*fraction),
+            _ => Err(BeatExpressionError::NotAFraction(expression.to_string())),
+        }
+    }
+}
+
+impl FromStr for BeatExpression {
+    type Err = BeatExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(BeatExpressionError::Empty);
+        }
+        if s.chars().any(char::is_whitespace) {
+            return Err(BeatExpressionError::SpacesNotAllowed);
+        }
+
+        let mut items = Vec::new();
+        let mut operand_start = 0;
+        for (idx, c) in s.char_indices() {
+            let operator = match c {
+                '/' => Some(BeatOperator::Divide),
+                c => BeatOperator::from_char(c),
+            };
+            if let Some(operator) = operator
+                && (idx == 0 || idx == s.len() - 1)
+            {
+                return Err(BeatExpressionError::OperatorAtBoundary(operator));
+            }
+            if let Some(operator) = BeatOperator::from_char(c) {
+                parse_divisions(&s[operand_start..idx], &mut items)?;
+                items.push(BeatExpressionItem::Operator(operator));
+                operand_start = idx + 1;
+            }
+        }
+        parse_divisions(&s[operand_start..], &mut items)?;
+
+        let expression = Self { items };
+        expression.evaluate()?;
+        Ok(expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    fn beat(s: &str) -> BeatTime {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parsing() {
+        let cases = [
+            ("1/4", "0.25"),
+            ("2", "2.0"),
+            ("1.5", "1.5"),
+            ("1.0+1/2", "1.5"),
+            ("1/2-1/4", "0.25"),
+            ("3*1/4", "0.75"),
+            ("1.0+3*1/4-1/8", "1.625"),
+            ("4/3*3/4", "1.0"),
+            ("1/3+1/3+1/3", "1.0"),
+            ("1.0-1/7*7", "0.0"),
+            ("1/2/3", "0.16667"),
+            ("3/4/3", "0.25"),
+            ("2/1/4", "0.5"),
+            ("1/2/3*3", "0.5"),
+            ("3*1/2/3", "0.5"),
+            ("1.0+1/3/2-1/12", "1.08333"),
+        ];
+        for (input, expected) in cases {
+            let expression: BeatExpression = input.parse().unwrap();
+            assert_eq!(expression.evaluate().unwrap(), beat(expected), "{}", input);
+            assert_eq!(expression.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_negative_intermediate() {
+        let evaluate = |s: &str| s.parse::<BeatExpression>().map(|e| e.evaluate().unwrap());
+        assert_eq!(evaluate("1/4-1/2+1.0"), Ok(beat("0.75")));
+        assert_eq!(evaluate("1/8-1/4-1/4+1/2"), Ok(beat("0.125")));
+        assert_eq!(evaluate("1/4-1/2+1/4"), Ok(BeatTime::zero()));
+        assert_eq!(evaluate("0.5-2*1/2+1/2"), Ok(BeatTime::zero()));
+
+        assert_eq!(
+            evaluate("1/2+1/4-1.0"),
+            Err(BeatExpressionError::NegativeResult {
+                pos: beat("0.75"),
+                neg: beat("1.0"),
+            })
+        );
+        assert_eq!(
+            evaluate("1.0-1/2-1/2-1/3/4"),
+            Err(BeatExpressionError::NegativeResult {
+                pos: beat("1.0"),
+                neg: "1/2+1/2+1/12"
+                    .parse::<BeatExpression>()
+                    .unwrap()
+                    .evaluate()
+                    .unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_exact_fractions() {
+        // seventeenths aren't whole ticks, only the exact sum is
+        let evaluate = |s: &str| s.parse::<BeatExpression>().unwrap().evaluate().unwrap();
+        assert_eq!(evaluate("1/17+16/17"), beat("1.0"));
+        assert_eq!(evaluate("5/17-5*1/17"), BeatTime::zero());
+        assert_eq!(evaluate("1/17+1/17+16/17-1/17"), beat("1.0"));
+        assert_eq!(evaluate("0.5+1/17+16/17"), beat("1.5"));
+    }
+
+    #[test]
+    fn test_division() {
+        // a whole dividend keeps its denominator so the expression reads back the same
+        let expression = BeatExpression {
+            items: vec![
+                BeatExpressionItem::Value(BeatValue::Fraction(BeatFraction::new(2, 1).unwrap())),
+                BeatExpressionItem::Operator(BeatOperator::Divide),
+                BeatExpressionItem::Value(BeatValue::Fraction(BeatFraction::new(3, 1).unwrap())),
+            ],
+        };
+        assert_eq!(expression.to_string(), "2/1/3");
+        assert_eq!(
+            expression.to_string().parse::<BeatExpression>(),
+            Ok(expression)
+        );
+
+        // a divisor of zero value that the parser couldn't see
+        let expression = BeatExpression {
+            items: vec![
+                BeatExpressionItem::Value(BeatValue::Fraction(BeatFraction::new(1, 2).unwrap())),
+                BeatExpressionItem::Operator(BeatOperator::Divide),
+                BeatExpressionItem::Value(BeatValue::Fraction(BeatFraction::new(0, 4).unwrap())),
+            ],
+        };
+        assert_eq!(
+            expression.evaluate(),
+            Err(BeatExpressionError::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn test_simplify() {
+        let simplify = |s: &str| s.parse::<BeatExpression>().unwrap().simplify().to_string();
+        let cases = [
+            ("1/4+1/4", "1/2"),
+            ("1.0+0.0", "1.0"),
+            ("2/4", "1/2"),
+            ("4/2", "2"),
+            ("1/8+0.5+1/8", "1/4+0.5"),
+            ("0.5+3*1/4-1/2", "1/4+0.5"),
+            ("1.0-1/4", "1.0-1/4"),
+            ("1/2-0.25", "1/2-0.25"),
+            ("1/3-1/3+0.0", "0"),
+            ("1/2/3+1/6", "1/3"),
+            ("1/17+16/17", "1"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(simplify(input), expected, "{}", input);
+        }
+
+        // invalid expressions are kept as they are
+        let negative = BeatExpression {
+            items: vec![
+                BeatExpressionItem::Value(BeatValue::Fraction(BeatFraction::new(1, 4).unwrap())),
+                BeatExpressionItem::Operator(BeatOperator::Subtract),
+                BeatExpressionItem::Value(BeatValue::Time(beat("1.0"))),
+            ],
+        };
+        assert_eq!(negative.simplify(), negative);
+    }
+
+    // Random terms as (subtracted, operand), with a whole beat added first so most sums are
+    // positive
+    fn random_terms(rng: &mut StdRng) -> Vec<(bool, String)> {
+        let mut terms = vec![(false, format!("{}.0", rng.gen_range(1..4)))];
+        for _ in 0..rng.gen_range(0..6) {
+            let operand = if rng.gen_bool(0.5) {
+                let denominator = [1, 2, 3, 4, 6, 8, 12, 16][rng.gen_range(0..8)];
+                format!("{}/{}", rng.gen_range(0..8), denominator)
+            } else {
+                format!(
+                    "{}.{}",
+                    rng.gen_range(0..3),
+                    ["0", "25", "5", "75"][rng.gen_range(0..4)]
+                )
+            };
+            terms.push((rng.gen_bool(0.3), operand));
+        }
+        terms
+    }
+
+    fn join_terms(terms: &[(bool, String)]) -> String {
+        let mut text = String::new();
+        for (idx, (negative, operand)) in terms.iter().enumerate() {
+            if idx > 0 {
+                text.push(if *negative { '-' } else { '+' });
+            }
+            text.push_str(operand);
+        }
+        text
+    }
+
+    #[test]
+    fn test_simplify_properties() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..500 {
+            let terms = random_terms(&mut rng);
+            let Ok(expression) = join_terms(&terms).parse::<BeatExpression>() else {
+                continue;
+            };
+            let simplified = expression.simplify();
+            assert_eq!(
+                simplified.evaluate(),
+                expression.evaluate(),
+                "{}",
+                expression
+            );
+            assert_eq!(simplified.simplify(), simplified, "{}", expression);
+            assert_eq!(
+                simplified.to_string().parse::<BeatExpression>(),
+                Ok(simplified.clone())
+            );
+
+            // the same terms in another order, with the fractions written as products
+            let mut reordered = terms.clone();
+            reordered.shuffle(&mut rng);
+            if reordered[0].0 {
+                continue;
+            }
+            for (_, operand) in reordered.iter_mut() {
+                if let Some((numerator, denominator)) = operand.split_once('/') {
+                    *operand = format!("{}*1/{}", numerator, denominator);
+                }
+            }
+            let reordered: BeatExpression = join_terms(&reordered).parse().unwrap();
+            assert_eq!(
+                reordered.simplify().to_string(),
+                simplified.to_string(),
+                "{} {}",
+                expression,
+                reordered
+            );
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        let fraction = BeatFraction::new(1, 4).unwrap();
+        let expression = BeatExpression::from(fraction);
+        assert_eq!(expression, "1/4".parse().unwrap());
+        assert_eq!(BeatFraction::try_from(expression), Ok(fraction));
+
+        let expression = BeatExpression::from(beat("1.5"));
+        assert_eq!(expression.to_string(), "1.5");
+        assert_eq!(expression.evaluate().unwrap(), beat("1.5"));
+        assert_eq!(
+            BeatFraction::try_from(expression),
+            Err(BeatExpressionError::NotAFraction("1.5".to_string()))
+        );
+        let sum: BeatExpression = "1/4+1/8".parse().unwrap();
+        assert!(BeatFraction::try_from(sum).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("", BeatExpressionError::Empty),
+            ("1/4 + 1/4", BeatExpressionError::SpacesNotAllowed),
+            (
+                "1/4-1/2",
+                BeatExpressionError::NegativeResult {
+                    pos: beat("0.25"),
+                    neg: beat("0.5"),
+                },
+            ),
+            (
+                "1/4+x",
+                BeatExpressionError::InvalidOperand("x".to_string()),
+            ),
+            (
+                "1/4++1",
+                BeatExpressionError::InvalidOperand("".to_string()),
+            ),
+            (
+                "1.5*2",
+                BeatExpressionError::MultiplyOperandNotFraction {
+                    operand: "1.5".to_string(),
+                },
+            ),
+            ("1/0", BeatExpressionError::DivideByZero),
+            ("1/2/0", BeatExpressionError::DivideByZero),
+            (
+                "1/2/x",
+                BeatExpressionError::InvalidOperand("x".to_string()),
+            ),
+            (
+                "1/2//3",
+                BeatExpressionError::InvalidOperand("".to_string()),
+            ),
+            (
+                "1.5/3",
+                BeatExpressionError::DivideOperandNotFraction {
+                    operand: "1.5".to_string(),
+                },
+            ),
+            (
+                "1/2/0.5",
+                BeatExpressionError::DivideOperandNotFraction {
+                    operand: "0.5".to_string(),
+                },
+            ),
+            (
+                "1/4/",
+                BeatExpressionError::OperatorAtBoundary(BeatOperator::Divide),
+            ),
+            (
+                "/4",
+                BeatExpressionError::OperatorAtBoundary(BeatOperator::Divide),
+            ),
+            (
+                "-1/4",
+                BeatExpressionError::OperatorAtBoundary(BeatOperator::Subtract),
+            ),
+            (
+                "1/4*",
+                BeatExpressionError::OperatorAtBoundary(BeatOperator::Multiply),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<BeatExpression>().unwrap_err(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+}
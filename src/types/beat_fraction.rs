@@ -0,0 +1,470 @@
+use crate::BeatTime;
+use crate::types::math::{gcd, gcd_u64, lcm};
+use anyhow::{Result, anyhow, bail};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+/// A beat duration expressed as a fraction of beats (e.g. `1/3` for a triplet eighth).
+/// Fractions compare by value, `2/4` equals `1/2`, also with beat times: `1/9` is not equal to
+/// the beat time it rounds to.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct BeatFraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl BeatFraction {
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self> {
+        if denominator == 0 {
+            bail!("Denominator of a beat fraction cannot be zero");
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// Reduces the fraction to lowest terms (e.g. `2/4` becomes `1/2`)
+    #[doc(alias = "reduce")]
+    pub fn simplify(&self) -> Self {
+        let divisor = gcd(self.numerator, self.denominator);
+        if divisor <= 1 {
+            return *self;
+        }
+        Self {
+            numerator: self.numerator / divisor,
+            denominator: self.denominator / divisor,
+        }
+    }
+
+    /// Sum of the fractions in lowest terms, with the common denominator as the starting point.
+    /// `None` if the result doesn't fit in `u32`.
+    pub fn add_exact(&self, other: BeatFraction) -> Option<BeatFraction> {
+        let denominator = lcm(self.denominator, other.denominator)?;
+        let numerator = self.numerator as u64 * (denominator / self.denominator) as u64
+            + other.numerator as u64 * (denominator / other.denominator) as u64;
+        // gcd(n, d) == gcd(n mod d, d), which keeps the gcd in u32
+        let divisor = gcd((numerator % denominator as u64) as u32, denominator);
+        Some(Self {
+            numerator: u32::try_from(numerator / divisor as u64).ok()?,
+            denominator: denominator / divisor,
+        })
+    }
+
+    /// Whole beats of the fraction, e.g. 2 for `7/3`
+    pub fn whole_beats(&self) -> u32 {
+        self.numerator / self.denominator
+    }
+
+    /// The fraction without its whole beats over the same denominator, e.g. `1/3` for `7/3`
+    pub fn fractional_part(&self) -> BeatFraction {
+        Self {
+            numerator: self.numerator % self.denominator,
+            denominator: self.denominator,
+        }
+    }
+
+    pub fn is_whole(&self) -> bool {
+        self.fractional_part().numerator == 0
+    }
+
+    /// Difference of the fractions in lowest terms, `None` if it's negative
+    /// or doesn't fit in `u32`
+    pub fn checked_sub(&self, other: BeatFraction) -> Option<BeatFraction> {
+        let denominator = self.denominator as u64 * other.denominator as u64;
+        let numerator = (self.numerator as u64 * other.denominator as u64)
+            .checked_sub(other.numerator as u64 * self.denominator as u64)?;
+        Self::reduced(numerator, denominator)
+    }
+
+    /// Product of the fractions in lowest terms, `None` if it doesn't fit in `u32`
+    pub fn checked_mul(&self, other: BeatFraction) -> Option<BeatFraction> {
+        Self::reduced(
+            self.numerator as u64 * other.numerator as u64,
+            self.denominator as u64 * other.denominator as u64,
+        )
+    }
+
+    fn reduced(numerator: u64, denominator: u64) -> Option<BeatFraction> {
+        let divisor = gcd_u64(numerator, denominator).max(1);
+        Some(Self {
+            numerator: u32::try_from(numerator / divisor).ok()?,
+            denominator: u32::try_from(denominator / divisor).ok()?,
+        })
+    }
+
+    /// Formats the fraction as written, without simplifying it
+    pub fn to_string_verbatim(&self) -> String {
+        format!("{}/{}", self.numerator, self.denominator)
+    }
+
+    /// Exact for denominators dividing [`BeatTime::FRAC_BEAT_COUNT`] (2^8 and 5^5 times 3, 7, 11
+    /// and 13 once each), e.g. every tuplet up to 16 but 9; others round to the nearest tick.
+    pub fn as_beat_time(&self) -> BeatTime {
+        BeatTime::from_rational(self.numerator as i64, self.denominator as u64)
+            .expect("Denominator of a beat fraction cannot be zero")
+    }
+
+    // Compares the exact values, cross-multiplied in u128
+    fn cmp_beat_time(&self, time: &BeatTime) -> Ordering {
+        (self.numerator as u128 * BeatTime::FRAC_BEAT_COUNT as u128)
+            .cmp(&(time.units() as u128 * self.denominator as u128))
+    }
+
+    /// Whole through thirty-second note values, followed by the dotted ones
+    pub fn standard_note_values() -> Vec<BeatFraction> {
+        [(1, 1), (1, 2), (1, 4), (1, 8), (1, 16), (1, 32)]
+            .into_iter()
+            .chain([(3, 8), (3, 4), (3, 16), (3, 32)])
+            .map(|(numerator, denominator)| Self {
+                numerator,
+                denominator,
+            })
+            .collect()
+    }
+
+    /// Triplet grid values: `1/3`, `1/6`, `1/12` and `1/24`
+    pub fn triplet_grid() -> Vec<BeatFraction> {
+        Self::tuplet_grids(3)
+    }
+
+    /// Grid values of an n-tuplet: `1/n` halved down to `1/(8n)`.
+    /// Empty for a zero denominator.
+    pub fn tuplet_grids(denominator: u32) -> Vec<BeatFraction> {
+        if denominator == 0 {
+            return Vec::new();
+        }
+        [1, 2, 4, 8]
+            .into_iter()
+            .filter_map(|subdivision| denominator.checked_mul(subdivision))
+            .map(|denominator| Self {
+                numerator: 1,
+                denominator,
+            })
+            .collect()
+    }
+}
+
+impl Default for BeatFraction {
+    fn default() -> Self {
+        Self {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+}
+
+impl fmt::Display for BeatFraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let simplified = self.simplify();
+        write!(f, "{}/{}", simplified.numerator, simplified.denominator)
+    }
+}
+
+impl PartialEq for BeatFraction {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BeatFraction {}
+
+impl Hash for BeatFraction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let simplified = self.simplify();
+        (simplified.numerator, simplified.denominator).hash(state);
+    }
+}
+
+impl PartialOrd for BeatFraction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BeatFraction {
+    // cross-multiplied in u64, which can't overflow
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator as u64 * other.denominator as u64)
+            .cmp(&(other.numerator as u64 * self.denominator as u64))
+    }
+}
+
+impl Add for BeatFraction {
+    type Output = Self;
+
+    /// Panics if the sum doesn't fit in `u32`, see [`BeatFraction::add_exact`]
+    fn add(self, other: Self) -> Self::Output {
+        self.add_exact(other).expect("beat fraction sum overflows")
+    }
+}
+
+impl Sub for BeatFraction {
+    type Output = Self;
+
+    /// Panics if the difference is negative, see [`BeatFraction::checked_sub`]
+    fn sub(self, other: Self) -> Self::Output {
+        self.checked_sub(other)
+            .expect("beat fraction difference is negative or overflows")
+    }
+}
+
+impl Mul for BeatFraction {
+    type Output = Self;
+
+    /// Panics if the product doesn't fit in `u32`, see [`BeatFraction::checked_mul`]
+    fn mul(self, other: Self) -> Self::Output {
+        self.checked_mul(other)
+            .expect("beat fraction product overflows")
+    }
+}
+
+/// The exact fraction of a beat time in lowest terms, the denominator divides
+/// [`BeatTime::FRAC_BEAT_COUNT`]. Errors if the numerator doesn't fit in `u32`.
+impl TryFrom<BeatTime> for BeatFraction {
+    type Error = anyhow::Error;
+
+    fn try_from(time: BeatTime) -> Result<Self> {
+        Self::reduced(time.units(), BeatTime::FRAC_BEAT_COUNT)
+            .ok_or_else(|| anyhow!("{} beats is too long for a beat fraction", time))
+    }
+}
+
+impl PartialEq<BeatFraction> for BeatTime {
+    fn eq(&self, other: &BeatFraction) -> bool {
+        other.cmp_beat_time(self) == Ordering::Equal
+    }
+}
+
+impl PartialOrd<BeatFraction> for BeatTime {
+    fn partial_cmp(&self, other: &BeatFraction) -> Option<Ordering> {
+        Some(other.cmp_beat_time(self).reverse())
+    }
+}
+
+impl PartialEq<BeatTime> for BeatFraction {
+    fn eq(&self, other: &BeatTime) -> bool {
+        self.cmp_beat_time(other) == Ordering::Equal
+    }
+}
+
+impl PartialOrd<BeatTime> for BeatFraction {
+    fn partial_cmp(&self, other: &BeatTime) -> Option<Ordering> {
+        Some(self.cmp_beat_time(other))
+    }
+}
+
+impl FromStr for BeatFraction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+        let numerator = numerator
+            .parse()
+            .map_err(|_| anyhow!("Invalid beat fraction: {}", s))?;
+        let denominator = denominator
+            .parse()
+            .map_err(|_| anyhow!("Invalid beat fraction: {}", s))?;
+        Self::new(numerator, denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing() {
+        let fraction: BeatFraction = "3/4".parse().unwrap();
+        assert_eq!(fraction.numerator, 3);
+        assert_eq!(fraction.denominator, 4);
+        assert_eq!(fraction.to_string(), "3/4");
+        assert_eq!("2".parse::<BeatFraction>().unwrap().to_string(), "2/1");
+
+        assert!("1/0".parse::<BeatFraction>().is_err());
+        assert!("-1/2".parse::<BeatFraction>().is_err());
+        assert!("a/2".parse::<BeatFraction>().is_err());
+    }
+
+    #[test]
+    fn test_simplify() {
+        let fraction = BeatFraction::new(2, 4).unwrap();
+        assert_eq!(fraction.simplify(), BeatFraction::new(1, 2).unwrap());
+        assert_eq!(fraction.to_string(), "1/2");
+        assert_eq!(fraction.to_string_verbatim(), "2/4");
+        assert_eq!(BeatFraction::new(6, 3).unwrap().to_string(), "2/1");
+        assert_eq!(BeatFraction::new(0, 8).unwrap().to_string(), "0/1");
+        assert_eq!(BeatFraction::new(3, 7).unwrap().to_string(), "3/7");
+    }
+
+    #[test]
+    fn test_add_exact() {
+        let third = BeatFraction::new(1, 3).unwrap();
+        let sixth = BeatFraction::new(1, 6).unwrap();
+        assert_eq!(
+            third.add_exact(sixth),
+            Some(BeatFraction::new(1, 2).unwrap())
+        );
+        assert_eq!(
+            BeatFraction::new(1, 4).unwrap().add_exact(third),
+            Some(BeatFraction::new(7, 12).unwrap())
+        );
+        let huge = BeatFraction::new(1, u32::MAX).unwrap();
+        assert_eq!(
+            huge.add_exact(BeatFraction::new(1, u32::MAX - 1).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let fraction = |s: &str| s.parse::<BeatFraction>().unwrap();
+        assert_eq!(fraction("1/3") + fraction("1/6"), fraction("1/2"));
+        assert_eq!(
+            (fraction("1/3") + fraction("1/6")).to_string_verbatim(),
+            "1/2"
+        );
+        assert_eq!(fraction("3/4") - fraction("1/4"), fraction("1/2"));
+        assert_eq!(fraction("2/3") * fraction("3/8"), fraction("1/4"));
+        assert_eq!(fraction("1/4").checked_sub(fraction("1/3")), None);
+        assert_eq!(fraction("1/65536").checked_mul(fraction("1/65536")), None);
+        assert_eq!(fraction("2/4"), fraction("1/2"));
+    }
+
+    #[test]
+    fn test_whole_and_fractional_part() {
+        let fraction = BeatFraction::new(7, 3).unwrap();
+        assert_eq!(fraction.whole_beats(), 2);
+        assert_eq!(fraction.fractional_part().to_string_verbatim(), "1/3");
+        assert!(!fraction.is_whole());
+
+        let fraction = BeatFraction::new(6, 4).unwrap();
+        assert_eq!(fraction.whole_beats(), 1);
+        assert_eq!(fraction.fractional_part().to_string_verbatim(), "2/4");
+
+        assert!(BeatFraction::new(8, 4).unwrap().is_whole());
+        assert!(BeatFraction::new(0, 5).unwrap().is_whole());
+        assert_eq!(BeatFraction::new(1, 8).unwrap().whole_beats(), 0);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let fraction = |s: &str| s.parse::<BeatFraction>().unwrap();
+        assert!(fraction("1/3") < fraction("1/2"));
+        assert!(fraction("5/4") > fraction("1/1"));
+        // products of the terms exceed u32
+        let big = BeatFraction::new(u32::MAX - 1, u32::MAX).unwrap();
+        let bigger = BeatFraction::new(u32::MAX, u32::MAX - 1).unwrap();
+        assert!(big < bigger);
+        assert!(
+            BeatFraction::new(u32::MAX, 1).unwrap() > BeatFraction::new(u32::MAX - 1, 1).unwrap()
+        );
+        let mut values = vec![fraction("3/4"), fraction("1/8"), fraction("1/3")];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![fraction("1/8"), fraction("1/3"), fraction("3/4")]
+        );
+    }
+
+    #[test]
+    fn test_from_beat_time() {
+        let time: BeatTime = "0.33333".parse().unwrap();
+        assert_eq!(
+            BeatFraction::try_from(time).unwrap().to_string_verbatim(),
+            "1/3"
+        );
+        let time = BeatTime::from_parts(2, 0.75);
+        assert_eq!(
+            BeatFraction::try_from(time).unwrap().to_string_verbatim(),
+            "11/4"
+        );
+        assert!(BeatFraction::try_from(BeatTime::from_parts(u32::MAX, 0.5)).is_err());
+    }
+
+    #[test]
+    fn test_as_beat_time() {
+        let fraction = BeatFraction::new(3, 2).unwrap();
+        assert_eq!(fraction.as_beat_time().to_string(), "1.5");
+        assert_eq!(BeatFraction::default().as_beat_time().to_string(), "1.0");
+
+        let third: BeatFraction = "1/3".parse().unwrap();
+        assert_eq!(third.as_beat_time().mul_ratio(3, 1), BeatTime::from(1));
+        assert_eq!(third.as_beat_time().to_string(), "0.33333");
+        for denominator in (1..=16).filter(|denominator| *denominator != 9).chain([
+            64,
+            1 << 8,
+            100_000,
+            3 * 5 * 7 * 11 * 13,
+        ]) {
+            let fraction = BeatFraction::new(1, denominator).unwrap();
+            assert_eq!(
+                fraction.as_beat_time().mul_ratio(denominator as u64, 1),
+                BeatTime::from(1),
+                "1/{}",
+                denominator
+            );
+        }
+        // ninths are the nearest tick
+        let ninth = BeatFraction::new(1, 9).unwrap().as_beat_time();
+        assert_eq!(ninth.units(), (BeatTime::FRAC_BEAT_COUNT + 4) / 9);
+        let big = BeatFraction::new(u32::MAX, 7).unwrap();
+        assert_eq!(big.as_beat_time().mul_ratio(7, 1), BeatTime::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_grid_values() {
+        let to_strings = |values: Vec<BeatFraction>| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            to_strings(BeatFraction::standard_note_values()),
+            [
+                "1/1", "1/2", "1/4", "1/8", "1/16", "1/32", "3/8", "3/4", "3/16", "3/32"
+            ]
+        );
+        assert_eq!(
+            to_strings(BeatFraction::triplet_grid()),
+            ["1/3", "1/6", "1/12", "1/24"]
+        );
+        assert_eq!(
+            to_strings(BeatFraction::tuplet_grids(5)),
+            ["1/5", "1/10", "1/20", "1/40"]
+        );
+        assert!(BeatFraction::tuplet_grids(0).is_empty());
+    }
+
+    #[test]
+    fn test_compare_with_beat_time() {
+        let quarter = BeatFraction::new(1, 4).unwrap();
+        let time: BeatTime = "0.25".parse().unwrap();
+        assert!(time == quarter);
+        assert!(quarter == time);
+        assert!(time == BeatFraction::new(2, 8).unwrap());
+        assert!("0.5".parse::<BeatTime>().unwrap() > quarter);
+        assert!(quarter < "0.5".parse::<BeatTime>().unwrap());
+        assert!(BeatTime::zero() < quarter);
+        assert!(BeatFraction::new(1, 3).unwrap() >= BeatTime::zero());
+        assert!(time != BeatFraction::new(1, 3).unwrap());
+
+        // exact for every fraction, the nearest tick of 1/9 is just below it
+        let ninth = BeatFraction::new(1, 9).unwrap();
+        assert!(ninth.as_beat_time() != ninth);
+        assert!(ninth != ninth.as_beat_time());
+        assert!(ninth.as_beat_time() < ninth);
+        assert!(ninth > ninth.as_beat_time());
+        let third = BeatFraction::new(1, 3).unwrap();
+        assert!(third.as_beat_time() == third);
+        let big = BeatFraction::new(u32::MAX, 7).unwrap();
+        assert!(big == big.as_beat_time());
+    }
+}
@@ -1,44 +1,162 @@
+use crate::types::time_signature::TimeSignatureMap;
+use crate::{BeatFraction, TimeSignature};
 use anyhow::Result;
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use std::fmt;
 use std::ops::{Add, Sub};
 use std::str::FromStr;
 
-/// Beat-based time notation using fixed-point units
+/// How beat times are written, see [`BeatTime::display_with`]. The default is the format of
+/// `Display`: the shortest value that reads back as the same time, without trailing zeros and
+/// always with a decimal point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatTimeFormat {
+    /// Maximum digits after the decimal point, at most 10. Fewer are written if they read back
+    /// as the same time, 10 always do.
+    pub max_decimals: u32,
+    /// Write whole beats as `1.0` instead of `1`
+    pub always_decimal_point: bool,
+    /// Write fewer digits if the value is at most this many beats away,
+    /// e.g. `0.001` writes 2.9996 as `3.0`. The value is not changed.
+    pub snap_tolerance: f64,
+}
+
+impl Default for BeatTimeFormat {
+    fn default() -> Self {
+        Self {
+            max_decimals: BeatTime::MAX_DECIMALS,
+            always_decimal_point: true,
+            snap_tolerance: 0.0,
+        }
+    }
+}
+
+/// A beat time written with a [`BeatTimeFormat`], supports padding like `Display`
+pub struct BeatTimeDisplay {
+    time: BeatTime,
+    format: BeatTimeFormat,
+}
+
+impl fmt::Display for BeatTimeDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max_decimals = self.format.max_decimals.min(BeatTime::MAX_DECIMALS);
+        // the fewest decimals that read back as the time or are within the tolerance,
+        // rounding otherwise
+        let decimals = (0..max_decimals)
+            .find(|decimals| {
+                let (beat, frac) = self.time.decimal_parts(*decimals);
+                if BeatTime::from_decimal(beat, frac as u128, *decimals) == self.time {
+                    return true;
+                }
+                let value = beat as f64 + frac as f64 / 10f64.powi(*decimals as i32);
+                (value - self.time.as_f64()).abs() <= self.format.snap_tolerance
+            })
+            .unwrap_or(max_decimals);
+        let (beat, frac_val) = self.time.decimal_parts(decimals);
+
+        let mut frac = format!("{:0width$}", frac_val, width = decimals as usize);
+        while frac.ends_with('0') {
+            frac.pop();
+        }
+        if frac.is_empty() && self.format.always_decimal_point {
+            frac.push('0');
+        }
+        if frac.is_empty() {
+            f.pad(&beat.to_string())
+        } else {
+            f.pad(&format!("{}.{}", beat, frac))
+        }
+    }
+}
+
+/// Beat-based time notation counting ticks of a fixed fraction of a beat. The tick count per
+/// beat is divisible by 2^8, 3, 5^5, 7, 11 and 13, so tuplet and dotted positions and times
+/// written with up to 5 decimals are exact.
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
 pub struct BeatTime {
     repr: u64,
 }
 
 impl BeatTime {
-    /// Number of bits for the sub-beat units.
-    const FRAC_BEAT_BITS: u32 = 32;
-    /// The number of sub-units in a single beat (2^32).
-    pub const FRAC_BEAT_COUNT: u64 = 1 << Self::FRAC_BEAT_BITS;
-    /// Bitmask to extract the sub-unit part from the total units.
-    const FRAC_BEAT_MASK: u64 = Self::FRAC_BEAT_COUNT - 1;
+    /// The number of ticks in a single beat (2^8 * 3 * 5^5 * 7 * 11 * 13),
+    /// which fits `u32::MAX` beats in a `u64`.
+    pub const FRAC_BEAT_COUNT: u64 = 2_402_400_000;
+    /// Written values with at least this many decimals may be rounded output and are snapped.
+    const SNAP_MIN_DECIMALS: u32 = 5;
+    /// Most decimal places `display_with` writes, enough to tell every tick apart.
+    const MAX_DECIMALS: u32 = 10;
+    /// Digits after the decimal point that are read, more don't change the value.
+    const MAX_PARSED_DECIMALS: u32 = 20;
+    /// Largest denominator of the fractions a rounded written value is snapped to.
+    const SNAP_MAX_DENOMINATOR: u64 = 128;
+    /// Bits of the sub-beat part in the binary format, which stores 32.32 fixed-point values.
+    const FIXED_POINT_BITS: u32 = 32;
 
     pub const fn zero() -> Self {
         Self { repr: 0 }
     }
 
-    const fn from_units(units: u64) -> Self {
+    pub(crate) const fn from_units(units: u64) -> Self {
         Self { repr: units }
     }
 
+    // Raw tick count, for exact ratios between times
+    pub(crate) const fn units(&self) -> u64 {
+        self.repr
+    }
+
+    // 32.32 fixed-point value of the binary format
+    pub(crate) fn as_fixed_point(&self) -> u64 {
+        let frac = (self.repr_frac() as u128) << Self::FIXED_POINT_BITS;
+        let frac = (frac + Self::FRAC_BEAT_COUNT as u128 / 2) / Self::FRAC_BEAT_COUNT as u128;
+        (self.repr_beat() << Self::FIXED_POINT_BITS) + frac as u64
+    }
+
+    pub(crate) fn from_fixed_point(value: u64) -> Self {
+        let beat = value >> Self::FIXED_POINT_BITS;
+        let frac = (value & ((1 << Self::FIXED_POINT_BITS) - 1)) as u128;
+        let frac = (frac * Self::FRAC_BEAT_COUNT as u128 + (1 << (Self::FIXED_POINT_BITS - 1)))
+            >> Self::FIXED_POINT_BITS;
+        Self::from_units(beat * Self::FRAC_BEAT_COUNT + frac as u64)
+    }
+
+    /// `beat + frac` beats, the fraction is read as its shortest decimal like a written time,
+    /// so 0.3 is the time written as `0.3` rather than the float nearest to it
     pub fn from_parts(beat: u32, frac: f32) -> Self {
         assert!((0.0..=1.0).contains(&frac));
-        if frac == 1.0 {
-            return Self::from_parts(beat + 1, 0.0);
+        // the fewest decimals that give back the float, beyond that it is taken as it is
+        let frac_time = (0..=Self::MAX_PARSED_DECIMALS)
+            .find_map(|decimals| {
+                let scale = 10f64.powi(decimals as i32);
+                let numerator = (frac as f64 * scale).round();
+                ((numerator / scale) as f32 == frac)
+                    .then(|| Self::from_decimal(0, numerator as u128, decimals))
+            })
+            .unwrap_or_else(|| {
+                Self::from_units((frac as f64 * Self::FRAC_BEAT_COUNT as f64).round() as u64)
+            });
+        Self::from_units(beat as u64 * Self::FRAC_BEAT_COUNT + frac_time.repr)
+    }
+
+    /// `numerator / denominator` beats, exact if the denominator divides the tick count of a
+    /// beat (see [`FRAC_BEAT_COUNT`](Self::FRAC_BEAT_COUNT)) and the nearest tick otherwise.
+    /// Errors for a zero denominator and for values below zero or too large to store.
+    pub fn from_rational(numerator: i64, denominator: u64) -> Result<Self> {
+        if denominator == 0 {
+            bail!("denominator of {}/{} is zero", numerator, denominator);
         }
-        let frac_repr = (frac as f64 * Self::FRAC_BEAT_COUNT as f64) as u64;
-        Self {
-            repr: (beat as u64) << Self::FRAC_BEAT_BITS | frac_repr,
+        if numerator < 0 {
+            bail!("{}/{} beats is negative", numerator, denominator);
         }
+        let units = (numerator as u128 * Self::FRAC_BEAT_COUNT as u128 + denominator as u128 / 2)
+            / denominator as u128;
+        let units = u64::try_from(units)
+            .map_err(|_| anyhow!("{}/{} beats is too large", numerator, denominator))?;
+        Ok(Self::from_units(units))
     }
 
     pub fn as_f64(&self) -> f64 {
-        self.repr_beat() as f64 + self.repr_frac_f32() as f64
+        self.repr as f64 / Self::FRAC_BEAT_COUNT as f64
     }
 
     pub fn as_micros(&self, bpm: f64) -> u64 {
@@ -49,13 +167,11 @@ impl BeatTime {
     pub fn from_micros(micros: u64, bpm: f64) -> Self {
         let micros_per_beat = 60_000_000.0 / bpm;
         let beat = micros as f64 / micros_per_beat;
-        let beat_int = beat.floor() as u32;
-        let frac = beat.fract() as f32;
-        Self::from_parts(beat_int, frac)
+        Self::from_units((beat * Self::FRAC_BEAT_COUNT as f64).round() as u64)
     }
 
     fn repr_beat(&self) -> u64 {
-        self.repr >> Self::FRAC_BEAT_BITS
+        self.repr / Self::FRAC_BEAT_COUNT
     }
 
     pub fn whole_beats(&self) -> u64 {
@@ -63,66 +179,206 @@ impl BeatTime {
     }
 
     fn repr_frac(&self) -> u64 {
-        self.repr & Self::FRAC_BEAT_MASK
+        self.repr % Self::FRAC_BEAT_COUNT
+    }
+
+    /// Writes the time with the given format, `Display` uses the default one
+    pub fn display_with(&self, format: BeatTimeFormat) -> BeatTimeDisplay {
+        BeatTimeDisplay {
+            time: *self,
+            format,
+        }
+    }
+
+    // Whole beats and the sub-beat part with `decimals` digits, rounded half up
+    fn decimal_parts(&self, decimals: u32) -> (u64, u64) {
+        let count = Self::FRAC_BEAT_COUNT as u128;
+        let scale = 10u128.pow(decimals);
+        let frac = (2 * self.repr_frac() as u128 * scale + count) / (2 * count);
+        if frac == scale {
+            (self.repr_beat() + 1, 0)
+        } else {
+            (self.repr_beat(), frac as u64)
+        }
+    }
+
+    // Time written as `beat.frac` with `decimals` digits after the point, as parsed
+    fn from_decimal(beat: u64, frac: u128, decimals: u32) -> Self {
+        let count = Self::FRAC_BEAT_COUNT as u128;
+        let scale = 10u128.pow(decimals);
+        let nearest = ((2 * frac * count + scale) / (2 * scale)) as u64;
+        let ticks = if decimals >= Self::SNAP_MIN_DECIMALS {
+            Self::snap_written_frac(frac, scale).unwrap_or(nearest)
+        } else {
+            nearest
+        };
+        Self::from_units(beat * Self::FRAC_BEAT_COUNT + ticks)
+    }
+
+    // Values written with at least the display decimals may be rounded output, e.g. 0.33333
+    // for 1/3. Of the ticks written as `numerator / scale`, the one of the simplest fraction
+    // with a denominator up to SNAP_MAX_DENOMINATOR, if there is one.
+    fn snap_written_frac(numerator: u128, scale: u128) -> Option<u64> {
+        let count = Self::FRAC_BEAT_COUNT as u128;
+        let low = (2 * numerator * count)
+            .saturating_sub(count)
+            .div_ceil(2 * scale);
+        let high = ((2 * numerator + 1) * count).div_ceil(2 * scale);
+        let nearest = (2 * numerator * count + scale) / (2 * scale);
+
+        (1..=Self::SNAP_MAX_DENOMINATOR as u128)
+            .filter(|denominator| count.is_multiple_of(*denominator))
+            .map(|denominator| {
+                let step = count / denominator;
+                (nearest + step / 2) / step * step
+            })
+            .find(|tick| (low..high).contains(tick))
+            .map(|tick| tick as u64)
+    }
+
+    /// Multiplies the time by `numerator / denominator` using exact integer arithmetic.
+    pub fn mul_ratio(&self, numerator: u64, denominator: u64) -> Self {
+        assert!(denominator > 0);
+        let scaled =
+            (self.repr as u128 * numerator as u128 + denominator as u128 / 2) / denominator as u128;
+        Self::from_units(scaled.min(u64::MAX as u128) as u64)
+    }
+
+    /// Time of a 1-based bar and beat, where beats count the signature's note value (eighths in
+    /// 7/8) from 1.0 on the downbeat. Bar 0 is the pickup, counted as the end of a full bar, so
+    /// a one-beat pickup in 4/4 starts at bar 0 beat 4. Errors if the beat isn't in the bar.
+    pub fn from_bar_beat(bar: u32, beat: f64, map: &TimeSignatureMap) -> Result<Self> {
+        if !beat.is_finite() || beat < 1.0 {
+            bail!("beat {} of bar {} is before the downbeat", beat, bar);
+        }
+        let bar_start = map.bar_start(bar);
+        let signature = map.signature_at(bar_start);
+        if beat >= signature.numerator as f64 + 1.0 {
+            bail!("bar {} in {} has no beat {}", bar, signature, beat);
+        }
+        let offset = (beat - 1.0) * 4.0 / signature.denominator.max(1) as f64;
+        let offset = Self::from_units((offset * Self::FRAC_BEAT_COUNT as f64).round() as u64);
+        let time = if bar == 0 {
+            let skipped = signature.beats_per_measure() - map.bar_start(1);
+            if offset < skipped {
+                bail!("beat {} is before the start of the pickup", beat);
+            }
+            offset - skipped
+        } else {
+            bar_start + offset
+        };
+        if map.bar_at(time) != bar {
+            bail!("bar {} ends before beat {}", bar, beat);
+        }
+        Ok(time)
+    }
+
+    /// Bar and beat of the time, the inverse of [`from_bar_beat`](Self::from_bar_beat)
+    pub fn to_bar_beat(&self, map: &TimeSignatureMap) -> (u32, f64) {
+        let bar = map.bar_at(*self);
+        let offset = if bar == 0 {
+            let signature = map.signature_at(*self);
+            *self + signature.beats_per_measure() - map.bar_start(1)
+        } else {
+            *self - map.bar_start(bar)
+        };
+        let denominator = map.signature_at(*self).denominator.max(1) as f64;
+        (bar, 1.0 + offset.as_f64() * denominator / 4.0)
     }
 
-    fn repr_frac_f32(&self) -> f32 {
-        (self.repr_frac() as f64 / Self::FRAC_BEAT_COUNT as f64) as f32
+    /// `None` if the sum doesn't fit in a beat time
+    pub fn checked_add(&self, other: BeatTime) -> Option<Self> {
+        self.repr.checked_add(other.repr).map(Self::from_units)
+    }
+
+    /// Position within the current measure, e.g. beat 5.0 is 1.0 in 4/4
+    pub fn wrap_to_measure(&self, time_sig: &TimeSignature) -> BeatTime {
+        let measure_length = time_sig.beats_per_measure();
+        if measure_length.repr == 0 {
+            return *self;
+        }
+        Self::from_units(self.repr % measure_length.repr)
     }
 
     pub fn quantize(&self, grid: u32, swing: f32, humanize: f32) -> Self {
+        let jitter = (rand::random::<f64>() - 0.5) * 2.0;
+        self.quantize_with_jitter(grid, swing, humanize, jitter)
+    }
+
+    /// Same as `quantize`, but the humanization uses the given random value (-1.0 to 1.0)
+    /// instead of drawing one, so seeded generators can be used
+    pub fn quantize_with_jitter(&self, grid: u32, swing: f32, humanize: f32, jitter: f64) -> Self {
         if grid == 0 {
             return *self;
         }
+        let step = BeatFraction {
+            numerator: 1,
+            denominator: grid,
+        };
+        self.quantize_to_step(step, swing, humanize, jitter)
+    }
 
-        let grid_size = Self::FRAC_BEAT_COUNT as f64 / grid as f64;
-        let total_sub_units = self.repr as f64;
+    /// Snaps to the nearest multiple of `step` (e.g. `1/6` for eighth note triplets), swing and
+    /// humanization as in `quantize_with_jitter`. The grid position is computed with integers,
+    /// so it is the closest beat time to the exact multiple wherever it is in the file.
+    pub fn quantize_to_step(
+        &self,
+        step: BeatFraction,
+        swing: f32,
+        humanize: f32,
+        jitter: f64,
+    ) -> Self {
+        if step.numerator == 0 || step.denominator == 0 {
+            return *self;
+        }
 
-        let mut quantized_units = if swing == 0.0 {
-            (total_sub_units / grid_size).round() * grid_size
-        } else {
-            let grid_index = (total_sub_units / grid_size).round() as u32;
-            let base_position = grid_index as f64 * grid_size;
-
-            if grid_index.is_multiple_of(2) {
-                // On-beat: snap to the main grid
-                base_position
-            } else {
-                // Off-beat: apply swing
-                // The "swing" factor moves the note from the straight 50% position
-                // towards the classic triplet-feel 66.7% position.
-                let swing_shift = (grid_size / 6.0) * swing as f64;
-                base_position + swing_shift
-            }
-        };
+        // step length is step_units / denominator units
+        let step_units = step.numerator as u128 * Self::FRAC_BEAT_COUNT as u128;
+        let denominator = step.denominator as u128;
+        let grid_index = (self.repr as u128 * denominator + step_units / 2) / step_units;
+        let base_position = ((grid_index * step_units + denominator / 2) / denominator)
+            .min(u64::MAX as u128) as u64;
+        let grid_size = step_units as f64 / denominator as f64;
+
+        let mut offset = 0.0;
+        if swing != 0.0 && !grid_index.is_multiple_of(2) {
+            // Off-beat: apply swing
+            // The "swing" factor moves the note from the straight 50% position
+            // towards the classic triplet-feel 66.7% position.
+            offset += (grid_size / 6.0) * swing as f64;
+        }
 
         if humanize > 0.0 {
             // Humanize around the quantized position. The amount of randomization
             // is a quarter of the sub-grid size, scaled by the humanize factor.
             let sub_grid_size = grid_size / 2.0;
             let humanize_amount = sub_grid_size * 0.25 * humanize as f64;
-            let humanize_offset = (rand::random::<f64>() - 0.5) * 2.0 * humanize_amount;
-            quantized_units += humanize_offset;
+            offset += jitter.clamp(-1.0, 1.0) * humanize_amount;
+        }
+
+        if offset == 0.0 {
+            return Self::from_units(base_position);
         }
+        Self::from_units((base_position as f64 + offset).round().max(0.0) as u64)
+    }
+}
+
+impl Default for BeatTime {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
 
-        Self::from_units(quantized_units.round() as u64)
+/// Whole beats
+impl From<u32> for BeatTime {
+    fn from(beats: u32) -> Self {
+        Self::from_units(beats as u64 * Self::FRAC_BEAT_COUNT)
     }
 }
 
 impl fmt::Display for BeatTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let beat = self.repr_beat();
-        let frac_val = (self.repr_frac_f32() * 100_000.0).round() as u32;
-
-        let mut frac = format!("{:05}", frac_val);
-        while frac.ends_with('0') {
-            frac.pop();
-            if frac.is_empty() {
-                frac.push('0');
-                break;
-            }
-        }
-        f.pad(&format!("{}.{}", beat, frac))
+        fmt::Display::fmt(&self.display_with(BeatTimeFormat::default()), f)
     }
 }
 
@@ -167,23 +423,81 @@ impl FromStr for BeatTime {
             return Err(anyhow!("Invalid time: {}", s));
         }
 
-        let frac = format!("0.{}", frac_str)
-            .parse()
-            .map_err(|_e| anyhow!("Invalid time: {}", s))?;
+        // digits past the precision of u128 arithmetic don't change the value
+        let digits = &frac_str[..frac_str.len().min(Self::MAX_PARSED_DECIMALS as usize)];
+        let numerator: u128 = if digits.is_empty() {
+            0
+        } else {
+            digits
+                .parse()
+                .map_err(|_e| anyhow!("Invalid time: {}", s))?
+        };
 
-        Ok(Self::from_parts(beat, frac))
+        Ok(Self::from_decimal(
+            beat as u64,
+            numerator,
+            digits.len() as u32,
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parse_mtxt;
+
+    #[test]
+    fn test_bar_beat() {
+        let file =
+            parse_mtxt("mtxt 1.0\n0.0 timesig 4/4\n8.0 timesig 7/8\n11.5 timesig 3/4\n").unwrap();
+        let map = TimeSignatureMap::from_records(&file.records);
+        let cases = [
+            (0.0, 1, 1.0),
+            (2.5, 1, 3.5),
+            (4.0, 2, 1.0),
+            (8.0, 3, 1.0),
+            (8.5, 3, 2.0),
+            (11.0, 3, 7.0),
+            (11.5, 4, 1.0),
+            (13.5, 4, 3.0),
+            (14.5, 5, 1.0),
+            (22.5, 7, 3.0),
+        ];
+        for (time, bar, beat) in cases {
+            let time: BeatTime = time.to_string().parse().unwrap();
+            assert_eq!(time.to_bar_beat(&map), (bar, beat), "{}", time);
+            assert_eq!(BeatTime::from_bar_beat(bar, beat, &map).unwrap(), time);
+        }
+
+        assert!(BeatTime::from_bar_beat(1, 0.5, &map).is_err());
+        assert!(BeatTime::from_bar_beat(1, 5.0, &map).is_err());
+        assert!(BeatTime::from_bar_beat(3, 8.0, &map).is_err());
+        assert!(BeatTime::from_bar_beat(0, 4.0, &map).is_err());
+    }
+
+    #[test]
+    fn test_bar_beat_pickup() {
+        let file = parse_mtxt("mtxt 1.0\nmeta global pickup 1.0\n0.0 timesig 4/4\n").unwrap();
+        let map = TimeSignatureMap::from_records(&file.records);
+        assert_eq!(BeatTime::zero().to_bar_beat(&map), (0, 4.0));
+        assert_eq!(BeatTime::from_parts(0, 0.5).to_bar_beat(&map), (0, 4.5));
+        assert_eq!(BeatTime::from_parts(1, 0.0).to_bar_beat(&map), (1, 1.0));
+        assert_eq!(
+            BeatTime::from_bar_beat(0, 4.0, &map).unwrap(),
+            BeatTime::zero()
+        );
+        assert_eq!(
+            BeatTime::from_bar_beat(2, 1.0, &map).unwrap(),
+            BeatTime::from_parts(5, 0.0)
+        );
+        assert!(BeatTime::from_bar_beat(0, 3.0, &map).is_err());
+    }
 
     #[test]
     fn test_parsing() {
         let time: BeatTime = "4.123".parse().unwrap();
         assert_eq!(time.repr_beat(), 4);
-        assert_eq!(time.repr_frac_f32(), 0.123);
+        assert!((time.as_f64() - 4.123).abs() < 1e-6);
         assert_eq!(time.to_string(), "4.123");
 
         assert_eq!("0".parse::<BeatTime>().unwrap().to_string(), "0.0");
@@ -204,14 +518,14 @@ mod tests {
             "4294967295.99999"
         );
 
-        // Test rounding
+        // Written back as parsed
         assert_eq!(
             "0.123456".parse::<BeatTime>().unwrap().to_string(),
-            "0.12346"
+            "0.123456"
         );
         assert_eq!(
-            "0.123454".parse::<BeatTime>().unwrap().to_string(),
-            "0.12345"
+            "1.000001".parse::<BeatTime>().unwrap().to_string(),
+            "1.000001"
         );
     }
 
@@ -245,6 +559,221 @@ mod tests {
         assert_eq!(sum.to_string(), "5.023");
     }
 
+    #[test]
+    fn test_exact_rationals() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let denominators = [1, 2, 3, 4, 5, 6, 7, 8, 10, 12, 13, 16, 24, 96, 1001];
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..1000 {
+            let mut random = || {
+                let denominator = denominators[rng.gen_range(0..denominators.len())];
+                BeatFraction::new(rng.gen_range(0..1000), denominator)
+                    .unwrap()
+                    .as_beat_time()
+            };
+            let (a, b) = (random(), random());
+            assert_eq!((a + b) - b, a);
+        }
+
+        // values written from tuplet positions read back exactly
+        for (numerator, denominator) in [(1, 3), (2, 3), (1, 6), (5, 12), (7, 24), (1, 7), (4, 5)] {
+            let time = BeatFraction::new(numerator + 3 * denominator, denominator)
+                .unwrap()
+                .as_beat_time();
+            let written = time.to_string();
+            assert_eq!(written.parse::<BeatTime>().unwrap(), time, "{}", written);
+        }
+        assert_eq!(
+            "0.33333".parse::<BeatTime>().unwrap().mul_ratio(3, 1),
+            BeatTime::from_parts(1, 0.0)
+        );
+
+        // values with fewer decimals are taken as written, to the nearest tick
+        let third: BeatTime = "0.333".parse().unwrap();
+        assert_eq!(third.to_string(), "0.333");
+        assert_ne!(third.mul_ratio(3, 1), BeatTime::from(1));
+    }
+
+    #[test]
+    fn test_display_format() {
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+        let third = BeatFraction::new(1, 3).unwrap().as_beat_time();
+        let format = |max_decimals, always_decimal_point, snap_tolerance| BeatTimeFormat {
+            max_decimals,
+            always_decimal_point,
+            snap_tolerance,
+        };
+
+        assert_eq!(
+            third.display_with(BeatTimeFormat::default()).to_string(),
+            "0.33333"
+        );
+        assert_eq!(
+            third.display_with(format(3, true, 0.0)).to_string(),
+            "0.333"
+        );
+        // 5 decimals already read back as 1/3
+        assert_eq!(
+            third.display_with(format(9, true, 0.0)).to_string(),
+            "0.33333"
+        );
+        let tick = BeatTime::from_units(1);
+        assert_eq!(tick.to_string(), "0.0000000004");
+        assert_eq!(tick.display_with(format(9, true, 0.0)).to_string(), "0.0");
+        let thousand_first = BeatFraction::new(1, 1001).unwrap().as_beat_time();
+        assert_eq!(thousand_first.to_string(), "0.000999001");
+        assert_eq!(third.display_with(format(0, true, 0.0)).to_string(), "0.0");
+        assert_eq!(
+            beat("2.0").display_with(format(5, false, 0.0)).to_string(),
+            "2"
+        );
+        assert_eq!(
+            beat("2.5").display_with(format(5, false, 0.0)).to_string(),
+            "2.5"
+        );
+        assert_eq!(
+            beat("2.9996")
+                .display_with(format(5, true, 0.001))
+                .to_string(),
+            "3.0"
+        );
+        assert_eq!(
+            beat("2.9996")
+                .display_with(format(5, true, 0.0001))
+                .to_string(),
+            "2.9996"
+        );
+        assert_eq!(
+            beat("2.75")
+                .display_with(format(5, true, 0.001))
+                .to_string(),
+            "2.75"
+        );
+        assert_eq!(
+            format!("{:>8}|", beat("1.5").display_with(format(2, true, 0.0))),
+            "     1.5|"
+        );
+    }
+
+    #[test]
+    fn test_display_format_round_trip() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let random: Vec<BeatTime> = (0..200)
+            .map(|_| BeatTime::from_units(rng.gen_range(0..BeatTime::FRAC_BEAT_COUNT * 64)))
+            .collect();
+        let mut tuplets = Vec::new();
+        for denominator in [2, 3, 5, 6, 7, 12, 24, 96] {
+            for numerator in 0..2 * denominator {
+                tuplets.push(
+                    BeatFraction::new(numerator, denominator)
+                        .unwrap()
+                        .as_beat_time(),
+                );
+            }
+        }
+
+        for max_decimals in 0..=10 {
+            for always_decimal_point in [true, false] {
+                let format = BeatTimeFormat {
+                    max_decimals,
+                    always_decimal_point,
+                    snap_tolerance: 0.0,
+                };
+                for time in random.iter().chain(&tuplets) {
+                    let written = time.display_with(format).to_string();
+                    let parsed: BeatTime = written.parse().unwrap();
+                    let rewritten = parsed.display_with(format).to_string();
+                    assert_eq!(
+                        rewritten.parse::<BeatTime>().unwrap(),
+                        parsed,
+                        "{}",
+                        written
+                    );
+                    // longer values may be snapped to a nearby fraction with a shorter form
+                    if max_decimals < BeatTime::SNAP_MIN_DECIMALS {
+                        assert_eq!(rewritten, written);
+                    }
+                    // the value itself survives if its shortest form has few enough decimals
+                    let shortest = time.to_string();
+                    let decimals = shortest.split_once('.').map_or(0, |(_, frac)| frac.len());
+                    if decimals <= max_decimals as usize {
+                        assert_eq!(parsed, *time, "{} {}", written, shortest);
+                    }
+                }
+                // with 5 decimals or more tuplets survive
+                if max_decimals >= 5 {
+                    for time in &tuplets {
+                        let written = time.display_with(format).to_string();
+                        assert_eq!(written.parse::<BeatTime>().unwrap(), *time, "{}", written);
+                    }
+                }
+            }
+        }
+
+        // the default writes every time losslessly
+        for time in random.iter().chain(&tuplets) {
+            assert_eq!(time.to_string().parse::<BeatTime>().unwrap(), *time);
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(BeatTime::default(), BeatTime::zero());
+    }
+
+    #[test]
+    fn test_wrap_to_measure() {
+        let beat = |s: &str| s.parse::<BeatTime>().unwrap();
+        let sig = |s: &str| s.parse::<TimeSignature>().unwrap();
+        assert_eq!(beat("5.0").wrap_to_measure(&sig("4/4")), beat("1.0"));
+        assert_eq!(beat("3.75").wrap_to_measure(&sig("4/4")), beat("3.75"));
+        assert_eq!(beat("8.0").wrap_to_measure(&sig("4/4")), beat("0.0"));
+        assert_eq!(beat("7.5").wrap_to_measure(&sig("6/8")), beat("1.5"));
+        assert_eq!(beat("4.0").wrap_to_measure(&sig("7/8")), beat("0.5"));
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let third = BeatTime::from_rational(1, 3).unwrap();
+        assert_eq!(third.units() * 3, BeatTime::FRAC_BEAT_COUNT);
+        assert_eq!(
+            BeatTime::from_rational(7, 2).unwrap(),
+            BeatTime::from_parts(3, 0.5)
+        );
+        assert_eq!(
+            BeatTime::from_rational(1000, 480).unwrap()
+                + BeatTime::from_rational(440, 480).unwrap(),
+            BeatTime::from_parts(3, 0.0)
+        );
+        assert_eq!(BeatTime::from_rational(0, 5).unwrap(), BeatTime::zero());
+        assert!(BeatTime::from_rational(1, 0).is_err());
+        assert!(BeatTime::from_rational(-1, 4).is_err());
+        assert!(BeatTime::from_rational(i64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_checked_add() {
+        let time: BeatTime = "1.5".parse().unwrap();
+        assert_eq!(time.checked_add(time).unwrap().to_string(), "3.0");
+        assert!(BeatTime::from_units(u64::MAX).checked_add(time).is_none());
+    }
+
+    #[test]
+    fn test_mul_ratio() {
+        let time: BeatTime = "1.5".parse().unwrap();
+        assert_eq!(time.mul_ratio(2, 3).to_string(), "1.0");
+        assert_eq!(time.mul_ratio(3, 2).to_string(), "2.25");
+        assert_eq!(
+            "0.75".parse::<BeatTime>().unwrap().mul_ratio(4, 3),
+            BeatTime::from_parts(1, 0.0)
+        );
+    }
+
     #[test]
     fn test_quantize() {
         let time: BeatTime = "0.12".parse().unwrap();
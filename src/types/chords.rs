@@ -0,0 +1,530 @@
+use crate::types::interval::{Interval, IntervalQuality};
+use crate::{Note, PitchClass, SpellingPreference};
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+// Chord types recognized by `match_chord`, larger chords win over smaller ones, ties go to the
+// earlier quality
+const TEMPLATE_QUALITIES: [ChordQuality; 7] = [
+    ChordQuality::Major,
+    ChordQuality::Minor,
+    ChordQuality::Dominant7,
+    ChordQuality::Major7,
+    ChordQuality::Minor7,
+    ChordQuality::Diminished,
+    ChordQuality::Augmented,
+];
+
+/// Pitch class sets (C = 0) of the common chord types in every root, with their chords
+pub static CHORD_TEMPLATES: LazyLock<Vec<([bool; 12], Chord)>> = LazyLock::new(|| {
+    TEMPLATE_QUALITIES
+        .into_iter()
+        .flat_map(|quality| {
+            (0..12).map(move |root| {
+                let chord = Chord {
+                    root: PitchClass::from_semitone(root, false).to_canonical(),
+                    quality,
+                    modifiers: Vec::new(),
+                    bass: None,
+                };
+                (chord.pitch_classes(), chord)
+            })
+        })
+        .collect()
+});
+
+pub const NO_CHORD: &str = "N.C.";
+
+/// The largest chord of [`CHORD_TEMPLATES`] contained in the pitch class set,
+/// allowing at most one additional pitch class
+pub fn match_chord(pitch_classes: &[bool; 12]) -> Option<&'static Chord> {
+    let mut best: Option<(usize, &Chord)> = None;
+    for (template, chord) in CHORD_TEMPLATES.iter() {
+        let contained = template
+            .iter()
+            .zip(pitch_classes)
+            .all(|(required, present)| !required || *present);
+        if !contained {
+            continue;
+        }
+        let size = template.iter().filter(|t| **t).count();
+        let extra = pitch_classes.iter().filter(|p| **p).count() - size;
+        if extra <= 1 && best.is_none_or(|(best_size, _)| size > best_size) {
+            best = Some((size, chord));
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+/// Root of a chord symbol as a semitone (C = 0), `None` for `N.C.` and invalid symbols
+pub fn chord_root(symbol: &str) -> Option<u8> {
+    symbol
+        .parse::<Chord>()
+        .ok()
+        .map(|chord| chord.root.to_semitone())
+}
+
+/// Basic chord type, the part of a chord symbol after the root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Power,
+    Sixth,
+    MinorSixth,
+    SixNine,
+    MinorSixNine,
+    Dominant7,
+    Major7,
+    Minor7,
+    MinorMajor7,
+    HalfDiminished7,
+    Diminished7,
+    Dominant9,
+    Major9,
+    Minor9,
+    Dominant11,
+    Minor11,
+    Dominant13,
+}
+
+impl ChordQuality {
+    pub const ALL: [ChordQuality; 21] = [
+        ChordQuality::Major,
+        ChordQuality::Minor,
+        ChordQuality::Diminished,
+        ChordQuality::Augmented,
+        ChordQuality::Power,
+        ChordQuality::Sixth,
+        ChordQuality::MinorSixth,
+        ChordQuality::SixNine,
+        ChordQuality::MinorSixNine,
+        ChordQuality::Dominant7,
+        ChordQuality::Major7,
+        ChordQuality::Minor7,
+        ChordQuality::MinorMajor7,
+        ChordQuality::HalfDiminished7,
+        ChordQuality::Diminished7,
+        ChordQuality::Dominant9,
+        ChordQuality::Major9,
+        ChordQuality::Minor9,
+        ChordQuality::Dominant11,
+        ChordQuality::Minor11,
+        ChordQuality::Dominant13,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Power => "5",
+            ChordQuality::Sixth => "6",
+            ChordQuality::MinorSixth => "m6",
+            ChordQuality::SixNine => "6/9",
+            ChordQuality::MinorSixNine => "m6/9",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::MinorMajor7 => "mMaj7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+            ChordQuality::Dominant9 => "9",
+            ChordQuality::Major9 => "maj9",
+            ChordQuality::Minor9 => "m9",
+            ChordQuality::Dominant11 => "11",
+            ChordQuality::Minor11 => "m11",
+            ChordQuality::Dominant13 => "13",
+        }
+    }
+
+    /// Intervals above the root, the 13th chord leaves out the 11th
+    fn interval_names(self) -> &'static [&'static str] {
+        match self {
+            ChordQuality::Major => &["P1", "M3", "P5"],
+            ChordQuality::Minor => &["P1", "m3", "P5"],
+            ChordQuality::Diminished => &["P1", "m3", "d5"],
+            ChordQuality::Augmented => &["P1", "M3", "A5"],
+            ChordQuality::Power => &["P1", "P5"],
+            ChordQuality::Sixth => &["P1", "M3", "P5", "M6"],
+            ChordQuality::MinorSixth => &["P1", "m3", "P5", "M6"],
+            ChordQuality::SixNine => &["P1", "M3", "P5", "M6", "M9"],
+            ChordQuality::MinorSixNine => &["P1", "m3", "P5", "M6", "M9"],
+            ChordQuality::Dominant7 => &["P1", "M3", "P5", "m7"],
+            ChordQuality::Major7 => &["P1", "M3", "P5", "M7"],
+            ChordQuality::Minor7 => &["P1", "m3", "P5", "m7"],
+            ChordQuality::MinorMajor7 => &["P1", "m3", "P5", "M7"],
+            ChordQuality::HalfDiminished7 => &["P1", "m3", "d5", "m7"],
+            ChordQuality::Diminished7 => &["P1", "m3", "d5", "d7"],
+            ChordQuality::Dominant9 => &["P1", "M3", "P5", "m7", "M9"],
+            ChordQuality::Major9 => &["P1", "M3", "P5", "M7", "M9"],
+            ChordQuality::Minor9 => &["P1", "m3", "P5", "m7", "M9"],
+            ChordQuality::Dominant11 => &["P1", "M3", "P5", "m7", "M9", "P11"],
+            ChordQuality::Minor11 => &["P1", "m3", "P5", "m7", "M9", "P11"],
+            ChordQuality::Dominant13 => &["P1", "M3", "P5", "m7", "M9", "M13"],
+        }
+    }
+}
+
+/// Change to the chord tones written after the quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordModifier {
+    /// `sus2`, the second replaces the third
+    Sus2,
+    /// `sus4`, the fourth replaces the third
+    Sus4,
+    /// `add9`, adds the major or perfect interval of the number
+    Add(u32),
+    /// `b5`, `#9`, replaces the chord tone of the same number or adds it
+    Alter(Interval),
+}
+
+const ADD_NUMBERS: [u32; 6] = [2, 4, 6, 9, 11, 13];
+const ALTER_NUMBERS: [u32; 4] = [5, 9, 11, 13];
+
+// Major or perfect interval of the number
+fn plain_interval(number: u32) -> Interval {
+    let quality = match (number - 1) % 7 {
+        0 | 3 | 4 => IntervalQuality::Perfect,
+        _ => IntervalQuality::Major,
+    };
+    Interval {
+        quality,
+        number,
+        descending: false,
+    }
+}
+
+impl fmt::Display for ChordModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChordModifier::Sus2 => write!(f, "sus2"),
+            ChordModifier::Sus4 => write!(f, "sus4"),
+            ChordModifier::Add(number) => write!(f, "add{}", number),
+            ChordModifier::Alter(interval) => {
+                let sign = if interval.quality == IntervalQuality::Augmented {
+                    "#"
+                } else {
+                    "b"
+                };
+                write!(f, "{}{}", sign, interval.number)
+            }
+        }
+    }
+}
+
+/// A chord symbol like `Cmaj7`, `F#m7b5`, `Bb7sus4` or `G/B`: a root, a quality, modifiers
+/// and an optional bass note
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    pub root: PitchClass,
+    pub quality: ChordQuality,
+    pub modifiers: Vec<ChordModifier>,
+    pub bass: Option<PitchClass>,
+}
+
+impl Chord {
+    /// Intervals of the chord tones above the root, from low to high
+    pub fn intervals(&self) -> Vec<Interval> {
+        let mut intervals: Vec<Interval> = self
+            .quality
+            .interval_names()
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect();
+        for modifier in &self.modifiers {
+            let added = match modifier {
+                ChordModifier::Sus2 | ChordModifier::Sus4 => {
+                    intervals.retain(|interval| interval.number != 3);
+                    plain_interval(if *modifier == ChordModifier::Sus2 {
+                        2
+                    } else {
+                        4
+                    })
+                }
+                ChordModifier::Add(number) => plain_interval(*number),
+                ChordModifier::Alter(altered) => {
+                    intervals.retain(|interval| interval.number != altered.number);
+                    *altered
+                }
+            };
+            if !intervals.contains(&added) {
+                intervals.push(added);
+            }
+        }
+        intervals.sort_by_key(|interval| (interval.semitones(), interval.number));
+        intervals
+    }
+
+    /// Pitch classes (C = 0) of the chord tones and the bass note
+    pub fn pitch_classes(&self) -> [bool; 12] {
+        let root = self.root.to_semitone() as i32;
+        let mut result = [false; 12];
+        for interval in self.intervals() {
+            result[(root + interval.semitones()).rem_euclid(12) as usize] = true;
+        }
+        if let Some(bass) = self.bass {
+            result[bass.to_semitone() as usize % 12] = true;
+        }
+        result
+    }
+
+    /// Notes of the chord in root position with the root in the octave, spelled by their
+    /// intervals (Bb7 is Bb D F Ab). The bass note is placed below the root, chord tones
+    /// are kept even if the bass doubles one (G/B is B3 G4 B4 D5). Notes outside the MIDI
+    /// range are left out.
+    pub fn notes(&self, octave: i8) -> Vec<Note> {
+        let root = Note {
+            pitch_class: self.root,
+            octave,
+            cents: 0.0,
+        };
+        let mut notes = Vec::new();
+        if let Some(bass) = self.bass {
+            let below =
+                (self.root.to_semitone() as i32 - bass.to_semitone() as i32 - 1).rem_euclid(12) + 1;
+            let absolute = root.absolute_semitone() - below;
+            if (0..=127).contains(&absolute) {
+                notes.push(Note::spelled(absolute, bass, 0.0));
+            }
+        }
+        for interval in self.intervals() {
+            // tones needing a triple accidental (Fb dim7) are spelled plainly
+            let note = root
+                .add_interval(interval)
+                .or_else(|_| root.transpose(interval.semitones(), SpellingPreference::Common));
+            if let Ok(note) = note {
+                notes.push(note);
+            }
+        }
+        notes
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.root, self.quality.symbol())?;
+        for modifier in &self.modifiers {
+            write!(f, "{}", modifier)?;
+        }
+        if let Some(bass) = self.bass {
+            write!(f, "/{}", bass)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Chord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // A slash is a bass note only before a pitch class, `C6/9` is a quality
+        let (symbol, bass) = match s.rsplit_once('/') {
+            Some((symbol, bass)) if bass.starts_with(|ch: char| ch.is_ascii_uppercase()) => {
+                (symbol, Some(bass.parse::<PitchClass>()?))
+            }
+            _ => (s, None),
+        };
+
+        // Root letter with up to two accidentals
+        let mut root_end = symbol.chars().next().map_or(0, char::len_utf8);
+        for ch in symbol[root_end..].chars().take(2) {
+            if !matches!(ch, '#' | 'b' | '\u{266f}' | '\u{266d}') {
+                break;
+            }
+            root_end += ch.len_utf8();
+        }
+        if !symbol.starts_with(|ch: char| ch.is_ascii_uppercase()) {
+            bail!("Invalid chord root: {}", s);
+        }
+        let root: PitchClass = symbol[..root_end].parse()?;
+        let rest = &symbol[root_end..];
+
+        // The longest matching quality, so `m7b5` wins over `m7` and `m`
+        let quality = ChordQuality::ALL
+            .into_iter()
+            .filter(|quality| rest.starts_with(quality.symbol()))
+            .max_by_key(|quality| quality.symbol().len())
+            .unwrap();
+        let mut rest = &rest[quality.symbol().len()..];
+
+        let mut modifiers = Vec::new();
+        while !rest.is_empty() {
+            let (modifier, remaining) = if let Some(remaining) = rest.strip_prefix("sus2") {
+                (ChordModifier::Sus2, remaining)
+            } else if let Some(remaining) = rest.strip_prefix("sus4") {
+                (ChordModifier::Sus4, remaining)
+            } else {
+                let (prefix, remaining) = if let Some(remaining) = rest.strip_prefix("add") {
+                    ("add", remaining)
+                } else if rest.starts_with(['b', '#']) {
+                    rest.split_at(1)
+                } else {
+                    bail!("Invalid chord symbol: {}", s);
+                };
+                let digits = remaining
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .unwrap_or(remaining.len());
+                let number: u32 = remaining[..digits]
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid chord symbol: {}", s))?;
+                let modifier = match prefix {
+                    "add" if ADD_NUMBERS.contains(&number) => ChordModifier::Add(number),
+                    "b" | "#" if ALTER_NUMBERS.contains(&number) => {
+                        let plain = plain_interval(number);
+                        let quality = match (prefix, plain.quality) {
+                            ("#", _) => IntervalQuality::Augmented,
+                            (_, IntervalQuality::Perfect) => IntervalQuality::Diminished,
+                            _ => IntervalQuality::Minor,
+                        };
+                        ChordModifier::Alter(Interval { quality, ..plain })
+                    }
+                    _ => bail!("Invalid chord modifier {}{} in {}", prefix, number, s),
+                };
+                (modifier, &remaining[digits..])
+            };
+            modifiers.push(modifier);
+            rest = remaining;
+        }
+
+        Ok(Self {
+            root,
+            quality,
+            modifiers,
+            bass,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(pitch_classes: &[usize]) -> [bool; 12] {
+        let mut result = [false; 12];
+        for pc in pitch_classes {
+            result[pc % 12] = true;
+        }
+        result
+    }
+
+    #[test]
+    fn test_chord_templates() {
+        let template = |index: usize| {
+            let (pitch_classes, chord) = &CHORD_TEMPLATES[index];
+            (*pitch_classes, chord.to_string())
+        };
+        assert_eq!(CHORD_TEMPLATES.len(), 84);
+        assert_eq!(template(0), (set(&[0, 4, 7]), "C".to_string()));
+        assert_eq!(template(12 + 9), (set(&[9, 0, 4]), "Am".to_string()));
+        assert_eq!(template(24 + 7), (set(&[7, 11, 2, 5]), "G7".to_string()));
+        assert_eq!(template(83), (set(&[11, 3, 7]), "Baug".to_string()));
+    }
+
+    #[test]
+    fn test_match_chord() {
+        let symbol =
+            |pitch_classes: &[usize]| match_chord(&set(pitch_classes)).map(Chord::to_string);
+        assert_eq!(symbol(&[0, 4, 7, 11]).as_deref(), Some("Cmaj7"));
+        assert_eq!(symbol(&[5, 8, 0]).as_deref(), Some("Fm"));
+        assert_eq!(symbol(&[7, 11, 2, 5]).as_deref(), Some("G7"));
+        assert_eq!(symbol(&[0, 4, 7, 2]).as_deref(), Some("C"));
+        assert_eq!(symbol(&[0, 1, 2]), None);
+        assert_eq!(symbol(&[]), None);
+    }
+
+    #[test]
+    fn test_chord_root() {
+        assert_eq!(chord_root("C"), Some(0));
+        assert_eq!(chord_root("Ebm7"), Some(3));
+        assert_eq!(chord_root("Baug"), Some(11));
+        assert_eq!(chord_root("F#m7b5/C"), Some(6));
+        assert_eq!(chord_root(NO_CHORD), None);
+    }
+
+    #[test]
+    fn test_chord_notes() {
+        let cases = [
+            ("C", "C4 E4 G4"),
+            ("Cm", "C4 Eb4 G4"),
+            ("Cdim", "C4 Eb4 Gb4"),
+            ("Caug", "C4 E4 G#4"),
+            ("C5", "C4 G4"),
+            ("C6", "C4 E4 G4 A4"),
+            ("Am6", "A4 C5 E5 F#5"),
+            ("C6/9", "C4 E4 G4 A4 D5"),
+            ("Am6/9", "A4 C5 E5 F#5 B5"),
+            ("F6/9/A", "A3 F4 A4 C5 D5 G5"),
+            ("G7", "G4 B4 D5 F5"),
+            ("Cmaj7", "C4 E4 G4 B4"),
+            ("Dm7", "D4 F4 A4 C5"),
+            ("CmMaj7", "C4 Eb4 G4 B4"),
+            ("F#m7b5", "F#4 A4 C5 E5"),
+            ("Bdim7", "B4 D5 F5 Ab5"),
+            ("C9", "C4 E4 G4 Bb4 D5"),
+            ("Ebmaj9", "Eb4 G4 Bb4 D5 F5"),
+            ("Am9", "A4 C5 E5 G5 B5"),
+            ("C11", "C4 E4 G4 Bb4 D5 F5"),
+            ("Dm11", "D4 F4 A4 C5 E5 G5"),
+            ("G13", "G4 B4 D5 F5 A5 E6"),
+            ("Csus2", "C4 D4 G4"),
+            ("Dsus4", "D4 G4 A4"),
+            ("Bb7sus4", "Bb4 Eb5 F5 Ab5"),
+            ("Cadd9", "C4 E4 G4 D5"),
+            ("Fmadd9", "F4 Ab4 C5 G5"),
+            ("C7b5", "C4 E4 Gb4 Bb4"),
+            ("E7#9", "E4 G#4 B4 D5 F##5"),
+            ("G7b9", "G4 B4 D5 F5 Ab5"),
+            ("Cmaj7#11", "C4 E4 G4 B4 F#5"),
+            ("C7#5b9", "C4 E4 G#4 Bb4 Db5"),
+            ("Db", "Db4 F4 Ab4"),
+            ("G/B", "B3 G4 B4 D5"),
+            ("C/G", "G3 C4 E4 G4"),
+            ("Am7/G", "G4 A4 C5 E5 G5"),
+            ("D/C", "C4 D4 F#4 A4"),
+        ];
+        for (symbol, expected) in cases {
+            let chord: Chord = symbol.parse().unwrap();
+            let notes: Vec<String> = chord.notes(4).iter().map(|n| n.to_string()).collect();
+            let expected = expected.replace("##", "x");
+            assert_eq!(notes.join(" "), expected, "{}", symbol);
+            assert_eq!(chord.to_string(), symbol);
+        }
+    }
+
+    #[test]
+    fn test_chord_parsing() {
+        let chord: Chord = "Bb7sus4/F".parse().unwrap();
+        assert_eq!(chord.root, PitchClass::Bb);
+        assert_eq!(chord.quality, ChordQuality::Dominant7);
+        assert_eq!(chord.modifiers, vec![ChordModifier::Sus4]);
+        assert_eq!(chord.bass, Some(PitchClass::F));
+        assert_eq!(
+            "F#m7b5".parse::<Chord>().unwrap().quality,
+            ChordQuality::HalfDiminished7
+        );
+
+        // accidentals belong to the root
+        assert_eq!("Cb6".parse::<Chord>().unwrap().root, PitchClass::Cb);
+        for symbol in [
+            "", "c", "H7", "Cfoo", "C/", "C/H", "C/9", "C6/", "Cadd", "Cadd3", "C#3", "C7b",
+        ] {
+            assert!(symbol.parse::<Chord>().is_err(), "{}", symbol);
+        }
+
+        // far ends of the MIDI range lose the notes that don't fit
+        let notes: Vec<String> = "G13"
+            .parse::<Chord>()
+            .unwrap()
+            .notes(8)
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(notes, ["G8", "B8", "D9", "F9"]);
+    }
+}
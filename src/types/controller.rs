@@ -0,0 +1,67 @@
+pub(crate) const MIDI_CC_MAPPINGS: &[(u8, &str)] = &[
+    (1, "vibrato"),
+    (2, "breath"),
+    (4, "foot"),
+    (5, "portamento"),
+    (7, "volume"),
+    (8, "balance"),
+    (10, "pan"),
+    (11, "expression"),
+    (64, "sustain"),
+    (65, "portamento_switch"),
+    (66, "sostenuto"),
+    (67, "soft"),
+    (68, "legato"),
+    (70, "sound_variation"),
+    (71, "timbre"),
+    (73, "attack"),
+    (74, "cutoff"),
+    (75, "decay"),
+    (76, "vibrato_rate"),
+    (77, "vibrato_depth"),
+    (78, "vibrato_delay"),
+    (91, "reverb"),
+    (92, "tremolo"),
+    (93, "chorus"),
+    (94, "detune"),
+    (95, "phaser"),
+];
+
+// Alternative names of standard controllers
+const CC_ALIASES: &[(&str, &str)] = &[("resonance", "timbre"), ("brightness", "cutoff")];
+
+/// MIDI CC number of a controller name (case-insensitive), its alias,
+/// or a numeric specification like `64` or `cc64`.
+/// `None` for controllers without a CC number such as `pitch` and `aftertouch`.
+pub fn controller_number(name: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    let name = CC_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name.as_str(), |(_, target)| target);
+    if let Some((number, _)) = MIDI_CC_MAPPINGS.iter().find(|(_, n)| *n == name) {
+        return Some(*number);
+    }
+    name.strip_prefix("cc")
+        .unwrap_or(name)
+        .parse::<u8>()
+        .ok()
+        .filter(|number| *number <= 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controller_number() {
+        assert_eq!(controller_number("sustain"), Some(64));
+        assert_eq!(controller_number("Sustain"), Some(64));
+        assert_eq!(controller_number("cc64"), Some(64));
+        assert_eq!(controller_number("CC7"), Some(7));
+        assert_eq!(controller_number("74"), Some(74));
+        assert_eq!(controller_number("brightness"), Some(74));
+        assert_eq!(controller_number("cc128"), None);
+        assert_eq!(controller_number("pitch"), None);
+    }
+}
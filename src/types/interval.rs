@@ -0,0 +1,326 @@
+use crate::Note;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalQuality {
+    Perfect,
+    Major,
+    Minor,
+    Augmented,
+    Diminished,
+}
+
+/// A musical interval like a perfect fifth (`P5`) or a descending major second (`-M2`).
+/// Compound intervals (e.g. `M9`) and whole octaves (`2oct`, same as `P15`) are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub quality: IntervalQuality,
+    /// Interval number, 1 is the unison, 8 the octave
+    pub number: u32,
+    pub descending: bool,
+}
+
+// Semitones of the major / perfect simple intervals, from unison to seventh
+const SIMPLE_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// 10 octaves, already more than the MIDI range
+const MAX_NUMBER: u32 = 71;
+
+fn is_perfect_number(number: u32) -> bool {
+    matches!((number - 1) % 7, 0 | 3 | 4)
+}
+
+impl Interval {
+    pub fn octaves(octaves: u32) -> Self {
+        Self {
+            quality: IntervalQuality::Perfect,
+            number: octaves * 7 + 1,
+            descending: false,
+        }
+    }
+
+    pub fn semitones(&self) -> i32 {
+        let simple = (self.number - 1) % 7;
+        let octaves = ((self.number - 1) / 7) as i32;
+        let base = SIMPLE_SEMITONES[simple as usize] + octaves * 12;
+        let semitones = match self.quality {
+            IntervalQuality::Perfect | IntervalQuality::Major => base,
+            IntervalQuality::Minor => base - 1,
+            IntervalQuality::Augmented => base + 1,
+            IntervalQuality::Diminished if is_perfect_number(self.number) => base - 1,
+            IntervalQuality::Diminished => base - 2,
+        };
+        if self.descending {
+            -semitones
+        } else {
+            semitones
+        }
+    }
+
+    /// Letter steps between the notes, `number - 1` and negative for descending intervals
+    pub fn steps(&self) -> i32 {
+        let steps = (self.number - 1) as i32;
+        if self.descending { -steps } else { steps }
+    }
+
+    /// The interval that completes this one to an octave, compound intervals are reduced to
+    /// simple ones first (M3 becomes m6, M9 becomes m7, P8 becomes P1)
+    pub fn invert(&self) -> Self {
+        let mut simple = (self.number - 1) % 7 + 1;
+        // octaves invert to unisons, except augmented ones which reduce to A1
+        if simple == 1 && self.number > 1 && self.quality != IntervalQuality::Augmented {
+            simple = 8;
+        }
+        let quality = match self.quality {
+            IntervalQuality::Perfect => IntervalQuality::Perfect,
+            IntervalQuality::Major => IntervalQuality::Minor,
+            IntervalQuality::Minor => IntervalQuality::Major,
+            IntervalQuality::Augmented => IntervalQuality::Diminished,
+            IntervalQuality::Diminished => IntervalQuality::Augmented,
+        };
+        Self {
+            quality,
+            number: 9 - simple,
+            descending: self.descending,
+        }
+    }
+
+    /// Interval from `a` to `b` named by their spelling, so C4 to F#4 is A4 and C4 to Gb4 is
+    /// d5. Descending if `b` is lower. Cents are ignored. Errors for intervals that are more
+    /// than augmented or diminished (Cb4 to B#4).
+    pub fn between(a: &Note, b: &Note) -> Result<Self> {
+        let mut steps = b.diatonic_position() - a.diatonic_position();
+        let mut semitones = b.absolute_semitone() - a.absolute_semitone();
+        let descending = steps < 0 || (steps == 0 && semitones < 0);
+        if descending {
+            steps = -steps;
+            semitones = -semitones;
+        }
+        let number = steps as u32 + 1;
+        if number > MAX_NUMBER {
+            bail!("Interval from {} to {} is too large", a, b);
+        }
+
+        let base = SIMPLE_SEMITONES[(steps % 7) as usize] + steps / 7 * 12;
+        let quality = match (is_perfect_number(number), semitones - base) {
+            (true, 0) => IntervalQuality::Perfect,
+            (false, 0) => IntervalQuality::Major,
+            (false, -1) => IntervalQuality::Minor,
+            (_, 1) => IntervalQuality::Augmented,
+            (true, -1) if number > 1 => IntervalQuality::Diminished,
+            (false, -2) => IntervalQuality::Diminished,
+            _ => bail!("No interval from {} to {}", a, b),
+        };
+        Ok(Self {
+            quality,
+            number,
+            descending,
+        })
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quality = match self.quality {
+            IntervalQuality::Perfect => "P",
+            IntervalQuality::Major => "M",
+            IntervalQuality::Minor => "m",
+            IntervalQuality::Augmented => "A",
+            IntervalQuality::Diminished => "d",
+        };
+        let sign = if self.descending { "-" } else { "" };
+        write!(f, "{}{}{}", sign, quality, self.number)
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Parse interval like "P5", "m3", "-M2", "+M9", "2oct", "-1oct"
+        let (descending, name) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+
+        if let Some(octaves) = name.strip_suffix("oct") {
+            let octaves: u32 = octaves
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid interval: {}", s))?;
+            if octaves > (MAX_NUMBER - 1) / 7 {
+                bail!("Interval is too large: {}", s);
+            }
+            return Ok(Self {
+                descending,
+                ..Self::octaves(octaves)
+            });
+        }
+
+        let mut chars = name.chars();
+        let quality = match chars.next() {
+            Some('P') => IntervalQuality::Perfect,
+            Some('M') => IntervalQuality::Major,
+            Some('m') => IntervalQuality::Minor,
+            Some('A') => IntervalQuality::Augmented,
+            Some('d') => IntervalQuality::Diminished,
+            _ => bail!("Invalid interval quality: {}", s),
+        };
+        let number: u32 = chars
+            .as_str()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid interval number: {}", s))?;
+        if number == 0 {
+            bail!("Invalid interval number: {}", s);
+        }
+        if number > MAX_NUMBER {
+            bail!("Interval is too large: {}", s);
+        }
+
+        let valid = match quality {
+            IntervalQuality::Perfect => is_perfect_number(number),
+            IntervalQuality::Major | IntervalQuality::Minor => !is_perfect_number(number),
+            IntervalQuality::Augmented => true,
+            // there is no diminished unison
+            IntervalQuality::Diminished => number > 1,
+        };
+        if !valid {
+            bail!("Invalid interval: {}", s);
+        }
+
+        Ok(Self {
+            quality,
+            number,
+            descending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn semitones(s: &str) -> i32 {
+        s.parse::<Interval>().unwrap().semitones()
+    }
+
+    #[test]
+    fn test_simple_intervals() {
+        assert_eq!(semitones("P1"), 0);
+        assert_eq!(semitones("m2"), 1);
+        assert_eq!(semitones("M2"), 2);
+        assert_eq!(semitones("m3"), 3);
+        assert_eq!(semitones("M3"), 4);
+        assert_eq!(semitones("P4"), 5);
+        assert_eq!(semitones("A4"), 6);
+        assert_eq!(semitones("d5"), 6);
+        assert_eq!(semitones("P5"), 7);
+        assert_eq!(semitones("m6"), 8);
+        assert_eq!(semitones("d7"), 9);
+        assert_eq!(semitones("M7"), 11);
+        assert_eq!(semitones("P8"), 12);
+    }
+
+    #[test]
+    fn test_negative_and_compound_intervals() {
+        assert_eq!(semitones("-M2"), -2);
+        assert_eq!(semitones("+P5"), 7);
+        assert_eq!(semitones("-P8"), -12);
+        assert_eq!(semitones("M9"), 14);
+        assert_eq!(semitones("P11"), 17);
+        assert_eq!(semitones("-m10"), -15);
+        assert_eq!(semitones("+2oct"), 24);
+        assert_eq!(semitones("-1oct"), -12);
+        assert_eq!("2oct".parse::<Interval>().unwrap().to_string(), "P15");
+        assert_eq!("-m3".parse::<Interval>().unwrap().to_string(), "-m3");
+    }
+
+    #[test]
+    fn test_invalid_intervals() {
+        for s in [
+            "",
+            "5",
+            "P3",
+            "M5",
+            "m4",
+            "d1",
+            "P0",
+            "X5",
+            "oct",
+            "-",
+            "P5x",
+            "11oct",
+            "P99999999999",
+        ] {
+            assert!(s.parse::<Interval>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_invert() {
+        let invert = |s: &str| s.parse::<Interval>().unwrap().invert().to_string();
+        assert_eq!(invert("P1"), "P8");
+        assert_eq!(invert("P8"), "P1");
+        assert_eq!(invert("M3"), "m6");
+        assert_eq!(invert("m7"), "M2");
+        assert_eq!(invert("A4"), "d5");
+        assert_eq!(invert("d5"), "A4");
+        assert_eq!(invert("P5"), "P4");
+        assert_eq!(invert("M9"), "m7");
+        assert_eq!(invert("A8"), "d8");
+        assert_eq!(invert("-m3"), "-M6");
+        for s in ["P1", "m2", "M3", "A4", "d5", "m6", "M7", "d8", "A1"] {
+            let interval: Interval = s.parse().unwrap();
+            assert_eq!(
+                interval.semitones() + interval.invert().semitones(),
+                12,
+                "{}",
+                s
+            );
+            assert_eq!(interval.invert().invert(), interval, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_between() {
+        let between = |a: &str, b: &str| {
+            Interval::between(&a.parse().unwrap(), &b.parse().unwrap()).map(|i| i.to_string())
+        };
+        assert_eq!(between("C4", "F#4").unwrap(), "A4");
+        assert_eq!(between("C4", "Gb4").unwrap(), "d5");
+        assert_eq!(between("C4", "C4").unwrap(), "P1");
+        assert_eq!(between("C4", "C#4").unwrap(), "A1");
+        assert_eq!(between("C4", "Cb4").unwrap(), "-A1");
+        assert_eq!(between("C4", "Db4").unwrap(), "m2");
+        assert_eq!(between("E4", "C4").unwrap(), "-M3");
+        assert_eq!(between("C4", "D5").unwrap(), "M9");
+        assert_eq!(between("B3", "C4").unwrap(), "m2");
+        assert_eq!(between("B#3", "C4").unwrap(), "d2");
+        assert_eq!(between("C#4", "Bb4").unwrap(), "d7");
+        assert_eq!(between("C4", "C6").unwrap(), "P15");
+        assert!(between("Cb4", "B#4").is_err());
+        assert!(between("C4", "Fbb4").is_err());
+    }
+
+    #[test]
+    fn test_add_interval_round_trip() {
+        for a in ["C4", "F#3", "Bb4", "B#3", "Cb5", "Ebb4", "Gx2"] {
+            let a: Note = a.parse().unwrap();
+            for s in [
+                "P1", "A1", "m2", "M2", "A2", "d3", "m3", "M3", "P4", "A4", "d5", "P5", "m6", "M6",
+                "d7", "m7", "M7", "P8", "M9", "P11", "-m2", "-M3", "-P5", "-A4", "-P8", "-m10",
+            ] {
+                let interval: Interval = s.parse().unwrap();
+                let Ok(b) = a.add_interval(interval) else {
+                    continue;
+                };
+                assert_eq!(
+                    b.absolute_semitone() - a.absolute_semitone(),
+                    interval.semitones()
+                );
+                assert_eq!(Interval::between(&a, &b).unwrap(), interval, "{} {}", a, s);
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::PitchClass;
+use crate::types::scale::ScaleType;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleMode {
+    Major,
+    Minor,
+}
+
+impl ScaleMode {
+    pub fn scale(&self) -> ScaleType {
+        match self {
+            ScaleMode::Major => ScaleType::Major,
+            ScaleMode::Minor => ScaleType::NaturalMinor,
+        }
+    }
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleMode::Major => write!(f, "major"),
+            ScaleMode::Minor => write!(f, "minor"),
+        }
+    }
+}
+
+impl FromStr for ScaleMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "major" | "maj" => Ok(ScaleMode::Major),
+            "minor" | "min" => Ok(ScaleMode::Minor),
+            _ => bail!("Invalid scale mode: {}", s),
+        }
+    }
+}
+
+/// A key like `D major` or `F# minor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeySignature {
+    pub tonic: PitchClass,
+    pub mode: ScaleMode,
+}
+
+impl KeySignature {
+    /// Semitones of the scale degrees above the tonic (natural minor for minor keys)
+    pub fn scale_semitones(&self) -> [u8; 7] {
+        let mut semitones = [0; 7];
+        semitones.copy_from_slice(self.mode.scale().semitones());
+        semitones
+    }
+
+    /// Whether the key signature has flats (e.g. F major, D minor, Bb major)
+    pub fn prefers_flats(&self) -> bool {
+        match self.tonic.accidental() {
+            0 => matches!(
+                (self.tonic.to_semitone(), self.mode),
+                (5, ScaleMode::Major) | (0 | 2 | 5 | 7, ScaleMode::Minor)
+            ),
+            accidental => accidental < 0,
+        }
+    }
+
+    /// Spells a semitone (0-11, C = 0) in the key. Scale tones are named after their degree
+    /// (e.g. E# in F# major), other semitones get sharps or flats like the key signature.
+    pub fn spell(&self, semitone: u8) -> PitchClass {
+        let tonic = self.tonic.to_semitone();
+        self.scale_semitones()
+            .iter()
+            .position(|step| (tonic + step) % 12 == semitone % 12)
+            .and_then(|degree| {
+                let letter = (self.tonic.letter() + degree as u8) % 7;
+                let natural = PitchClass::from_letter(letter, 0)?.to_semitone() as i8;
+                let accidental = (semitone as i8 - natural + 6).rem_euclid(12) - 6;
+                PitchClass::from_letter(letter, accidental)
+            })
+            .unwrap_or_else(|| PitchClass::from_semitone(semitone, self.prefers_flats()))
+    }
+}
+
+impl fmt::Display for KeySignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.tonic, self.mode)
+    }
+}
+
+impl FromStr for KeySignature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Parse key like "D major", "F# minor", "Bb", "Gm"
+        let mut parts = s.split_whitespace();
+        let Some(tonic) = parts.next() else {
+            bail!("Empty key");
+        };
+        let (tonic, mut mode) = match tonic.strip_suffix('m') {
+            Some(t) if t.parse::<PitchClass>().is_ok() => (t, ScaleMode::Minor),
+            _ => (tonic, ScaleMode::Major),
+        };
+        let tonic: PitchClass = tonic.parse()?;
+        if let Some(mode_str) = parts.next() {
+            mode = mode_str.parse()?;
+        }
+        if parts.next().is_some() {
+            bail!("Invalid key: {}", s);
+        }
+        Ok(Self { tonic, mode })
+    }
+}
+
+// Krumhansl-Kessler probe tone ratings, starting at the tonic
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const fn build_key_profiles() -> [[f32; 12]; 24] {
+    let mut profiles = [[0.0; 12]; 24];
+    let mut tonic = 0;
+    while tonic < 12 {
+        let mut pitch_class = 0;
+        while pitch_class < 12 {
+            let degree = (pitch_class + 12 - tonic) % 12;
+            profiles[tonic][pitch_class] = MAJOR_PROFILE[degree];
+            profiles[tonic + 12][pitch_class] = MINOR_PROFILE[degree];
+            pitch_class += 1;
+        }
+        tonic += 1;
+    }
+    profiles
+}
+
+/// Profiles indexed by pitch class: C major .. B major, then C minor .. B minor
+pub const KEY_PROFILES: [[f32; 12]; 24] = build_key_profiles();
+
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a) * (x - mean_a);
+        variance_b += (y - mean_b) * (y - mean_b);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+/// Best matching key of a pitch class histogram (C = 0) as (tonic semitone, mode, correlation)
+pub fn find_key(histogram: &[f32; 12]) -> (u8, ScaleMode, f32) {
+    let mut best = (0, ScaleMode::Major, f32::MIN);
+    for (index, profile) in KEY_PROFILES.iter().enumerate() {
+        let score = correlation(histogram, profile);
+        if score > best.2 {
+            let mode = if index < 12 {
+                ScaleMode::Major
+            } else {
+                ScaleMode::Minor
+            };
+            best = ((index % 12) as u8, mode, score);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_signature() {
+        let key = |s: &str| s.parse::<KeySignature>().unwrap().to_string();
+        assert_eq!(key("D major"), "D major");
+        assert_eq!(key("F# minor"), "F# minor");
+        assert_eq!(key("Bb"), "Bb major");
+        assert_eq!(key("Gm"), "G minor");
+        assert_eq!(key("eb min"), "Eb minor");
+        assert!("".parse::<KeySignature>().is_err());
+        assert!("H major".parse::<KeySignature>().is_err());
+        assert!("C dorian".parse::<KeySignature>().is_err());
+    }
+
+    #[test]
+    fn test_key_profiles() {
+        assert_eq!(KEY_PROFILES[0], MAJOR_PROFILE);
+        assert_eq!(KEY_PROFILES[12], MINOR_PROFILE);
+        // G major has its tonic at pitch class 7
+        assert_eq!(KEY_PROFILES[7][7], MAJOR_PROFILE[0]);
+        assert_eq!(KEY_PROFILES[7][0], MAJOR_PROFILE[5]);
+    }
+
+    #[test]
+    fn test_find_key() {
+        let (tonic, mode, score) = find_key(&KEY_PROFILES[14]);
+        assert_eq!((tonic, mode), (2, ScaleMode::Minor));
+        assert!((score - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,49 @@
+//! Integer helpers for working with beat fractions, e.g. to find a grid that contains
+//! the positions of several tuplet grids.
+
+/// Greatest common divisor (Euclidean algorithm), `gcd(a, 0)` is `a`
+pub fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// [`gcd`] of `u64` values, e.g. of products of fraction terms
+pub fn gcd_u64(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd_u64(b, a % b) }
+}
+
+/// Least common multiple, 0 if either value is 0. `None` if it doesn't fit in `u32`.
+pub fn lcm(a: u32, b: u32) -> Option<u32> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+/// Least common multiple of all values, 1 for an empty slice. `None` if it doesn't fit
+/// in `u32`.
+pub fn lcm_many(values: &[u32]) -> Option<u32> {
+    values.iter().try_fold(1, |acc, value| lcm(acc, *value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcd_lcm() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(7, 0), 7);
+        assert_eq!(gcd(0, 7), 7);
+        assert_eq!(gcd_u64(1 << 40, 6 << 20), 2 << 20);
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(3, 0), Some(0));
+        assert_eq!(lcm(u32::MAX, u32::MAX - 1), None);
+        assert_eq!(lcm(1 << 16, 1 << 31), Some(1 << 31));
+
+        // grid containing eighth notes, eighth note triplets and quintuplets
+        assert_eq!(lcm_many(&[2, 3, 5]), Some(30));
+        assert_eq!(lcm_many(&[4, 6, 8]), Some(24));
+        assert_eq!(lcm_many(&[]), Some(1));
+        assert_eq!(lcm_many(&[1 << 30, 3, 5]), None);
+    }
+}
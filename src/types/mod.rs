@@ -1,7 +1,18 @@
+pub mod beat_expression;
+pub mod beat_fraction;
 pub mod beat_time;
+pub mod chords;
+pub mod controller;
+pub mod interval;
+pub mod key;
+pub mod math;
 pub mod note;
 pub mod output_record;
 pub mod pitch;
 pub mod record;
+pub mod scale;
+#[cfg(feature = "serde")]
+pub(crate) mod serde_str;
+pub mod tempo_map;
 pub mod time_signature;
 pub mod version;
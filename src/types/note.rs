@@ -1,4 +1,6 @@
 use crate::PitchClass;
+use crate::types::interval::Interval;
+use crate::types::key::KeySignature;
 use crate::types::record::AliasDefinition;
 use anyhow::{Result, bail};
 use std::fmt;
@@ -29,40 +31,121 @@ impl Note {
 
     /// Convert to MIDI note number (C4 = 60)
     pub fn to_midi_note(&self) -> u8 {
-        let base = (self.octave + 1) * 12 + self.pitch_class.to_semitone() as i8;
-        base.clamp(0, 127) as u8
-    }
-
-    pub fn transpose(&self, semitones: i32) -> Note {
-        let current_semitone = self.pitch_class.to_semitone() as i32; // 0-11
-        let current_abs = (self.octave as i32 + 1) * 12 + current_semitone;
-        let new_abs = current_abs + semitones;
-
-        let new_octave = new_abs.div_euclid(12) - 1;
-        let new_semitone_idx = new_abs.rem_euclid(12);
-
-        let new_pitch_class = match new_semitone_idx {
-            0 => PitchClass::C,
-            1 => PitchClass::CSharp,
-            2 => PitchClass::D,
-            3 => PitchClass::DSharp,
-            4 => PitchClass::E,
-            5 => PitchClass::F,
-            6 => PitchClass::FSharp,
-            7 => PitchClass::G,
-            8 => PitchClass::GSharp,
-            9 => PitchClass::A,
-            10 => PitchClass::ASharp,
-            11 => PitchClass::B,
-            _ => unreachable!(),
-        }
-        .to_canonical();
+        self.absolute_semitone().clamp(0, 127) as u8
+    }
+
+    /// Semitones above C-1, the MIDI note number without clamping. The octave belongs to the
+    /// note name, so B#3 is 60 like C4 and Cb4 is 59 like B3.
+    pub fn absolute_semitone(&self) -> i32 {
+        (self.octave as i32 + 1 + self.pitch_class.octave_offset() as i32) * 12
+            + self.pitch_class.to_semitone() as i32
+    }
 
+    /// Note at an absolute semitone (see [`Note::absolute_semitone`]) spelled with the pitch
+    /// class, which has to name that semitone
+    pub(crate) fn spelled(absolute_semitone: i32, pitch_class: PitchClass, cents: f32) -> Note {
+        debug_assert_eq!(
+            absolute_semitone.rem_euclid(12),
+            pitch_class.to_semitone() as i32
+        );
+        let octave = absolute_semitone.div_euclid(12) - 1 - pitch_class.octave_offset() as i32;
         Note {
-            pitch_class: new_pitch_class,
-            octave: new_octave as i8,
-            cents: self.cents,
+            pitch_class,
+            octave: octave as i8,
+            cents,
+        }
+    }
+
+    /// Other spellings of the same pitch, with the fewest accidentals first (e.g. B#3 and Dbb4
+    /// for C4)
+    pub fn enharmonic_equivalents(&self) -> Vec<Note> {
+        let absolute = self.absolute_semitone();
+        let mut notes: Vec<Note> = PitchClass::ALL
+            .iter()
+            .filter(|pitch_class| {
+                **pitch_class != self.pitch_class
+                    && pitch_class.to_semitone() == self.pitch_class.to_semitone()
+            })
+            .map(|pitch_class| Note::spelled(absolute, *pitch_class, self.cents))
+            .collect();
+        notes.sort_by_key(|note| note.pitch_class.accidental().abs());
+        notes
+    }
+
+    /// The same pitch spelled following the preference
+    pub fn respell(&self, preference: SpellingPreference) -> Note {
+        let pitch_class = preference.spell(self.pitch_class.to_semitone());
+        Note::spelled(self.absolute_semitone(), pitch_class, self.cents)
+    }
+
+    /// Moves the note by cents, whole semitones past +-100 cents carry into the note name
+    /// (E4+50 up by 75 cents is F4+25). Errors like [`Note::transpose`].
+    pub fn transpose_cents(&self, cents: f32) -> Result<Note> {
+        let total = self.cents + cents;
+        let semitones = (total / 100.0).trunc();
+        let mut note = if semitones == 0.0 {
+            self.clone()
+        } else {
+            self.transpose(semitones as i32, SpellingPreference::Common)?
+        };
+        note.cents = total - semitones * 100.0;
+        Ok(note)
+    }
+
+    /// Moves the note by semitones and spells it following the preference, cents are kept.
+    /// Errors if the note leaves the MIDI range C-1 to G9.
+    pub fn transpose(&self, semitones: i32, spelling: SpellingPreference) -> Result<Note> {
+        let new_abs = self.absolute_semitone() + semitones;
+        if !(0..=127).contains(&new_abs) {
+            bail!(
+                "Transposing {} by {} semitones leaves the MIDI range",
+                self,
+                semitones
+            );
+        }
+        let pitch_class = spelling.spell(new_abs.rem_euclid(12) as u8);
+        Ok(Note::spelled(new_abs, pitch_class, self.cents))
+    }
+
+    /// Letters above C0 as written, so B#3 is one letter below C4
+    pub(crate) fn diatonic_position(&self) -> i32 {
+        self.octave as i32 * 7 + self.pitch_class.letter() as i32
+    }
+
+    /// The note the interval away, spelled by the interval (C4 up an A4 is F#4, up a d5 is
+    /// Gb4), cents are kept. Errors if the note needs more than a double accidental or
+    /// leaves the MIDI range C-1 to G9.
+    pub fn add_interval(&self, interval: Interval) -> Result<Note> {
+        let new_abs = self.absolute_semitone() + interval.semitones();
+        if !(0..=127).contains(&new_abs) {
+            bail!("{} {} leaves the MIDI range", self, interval);
         }
+        let position = self.diatonic_position() + interval.steps();
+        let letter = position.rem_euclid(7) as u8;
+        let octave = position.div_euclid(7);
+        let natural = Note {
+            pitch_class: PitchClass::from_letter(letter, 0).unwrap(),
+            octave: octave as i8,
+            cents: 0.0,
+        };
+        let accidental = new_abs - natural.absolute_semitone();
+        let Some(pitch_class) = i8::try_from(accidental)
+            .ok()
+            .and_then(|accidental| PitchClass::from_letter(letter, accidental))
+        else {
+            bail!("{} {} can't be spelled", self, interval);
+        };
+        Ok(Note::spelled(new_abs, pitch_class, self.cents))
+    }
+}
+
+impl Note {
+    /// Every semitone from `low` to `high` inclusive, starting from `low`
+    pub fn chromatic_range(low: &Note, high: &Note) -> Vec<Note> {
+        let steps = high.to_midi_note() as i32 - low.to_midi_note() as i32;
+        (0..=steps)
+            .filter_map(|step| low.transpose(step, SpellingPreference::Common).ok())
+            .collect()
     }
 }
 
@@ -80,7 +163,7 @@ impl FromStr for Note {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        // Parse note like "C4", "D#5", "Bb3+50", "F4-25"
+        // Parse note like "C4", "D#5", "Bb3+50", "F4-25", "E4+50c", "Fx5", "Ebb2"
         let mut chars = s.chars().peekable();
 
         // Parse pitch class
@@ -89,20 +172,24 @@ impl FromStr for Note {
             .ok_or_else(|| anyhow::anyhow!("Empty note string"))?;
         let mut pitch_str = pitch_char.to_string();
 
-        // Check for accidental
-        if let Some(&next_char) = chars.peek()
-            && (next_char == '#' || next_char == 'b' || next_char == 'B') {
-                pitch_str.push(chars.next().unwrap());
-            }
+        // Accidentals, validated by the pitch class
+        while let Some(&next_char) = chars.peek()
+            && matches!(
+                next_char,
+                '#' | 'b' | 'B' | 'x' | 'X' | '\u{266f}' | '\u{266d}' | '\u{1d12a}' | '\u{1d12b}'
+            )
+        {
+            pitch_str.push(chars.next().unwrap());
+        }
 
         let pitch_class: PitchClass = pitch_str.parse()?;
 
         // Parse octave
         let remaining: String = chars.collect();
 
-        // Find where octave ends and cents begin
+        // Find where octave ends and cents begin, a leading `-` is the sign of the octave (C-1)
         let mut octave_end = remaining.len();
-        for (i, ch) in remaining.chars().enumerate() {
+        for (i, ch) in remaining.chars().enumerate().skip(1) {
             if ch == '+' || ch == '-' {
                 octave_end = i;
                 break;
@@ -111,9 +198,10 @@ impl FromStr for Note {
 
         let octave: i8 = remaining[..octave_end].parse()?;
 
-        // Parse cents if present
+        // Parse cents if present, optionally followed by `c`
         let cents = if octave_end < remaining.len() {
-            remaining[octave_end..].parse()?
+            let cents = &remaining[octave_end..];
+            cents.strip_suffix('c').unwrap_or(cents).parse()?
         } else {
             0.0f32
         };
@@ -122,11 +210,58 @@ impl FromStr for Note {
     }
 }
 
+/// How notes are spelled when only their pitch is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpellingPreference {
+    /// C# D# F# G# A#
+    #[default]
+    Sharps,
+    /// Db Eb Gb Ab Bb
+    Flats,
+    /// The most common name of each black key: C# Eb F# Ab Bb
+    Common,
+    /// Scale tones named after their degree in the key, other notes like the key signature
+    Key(KeySignature),
+}
+
+impl SpellingPreference {
+    /// Spells a semitone (0-11, C = 0)
+    pub fn spell(&self, semitone: u8) -> PitchClass {
+        match self {
+            SpellingPreference::Sharps => PitchClass::from_semitone(semitone, false),
+            SpellingPreference::Flats => PitchClass::from_semitone(semitone, true),
+            SpellingPreference::Common => PitchClass::from_semitone(semitone, false).to_canonical(),
+            SpellingPreference::Key(key) => key.spell(semitone),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum NoteTarget {
     Note(Note),
     AliasKey(String),
     Alias(Rc<AliasDefinition>),
+    /// Every note from `low` to `high` inclusive, written as `C3-C5`
+    Range {
+        low: Note,
+        high: Note,
+    },
+}
+
+impl NoteTarget {
+    /// Whether `note` is addressed by this target. Alias keys are unresolved and never match.
+    pub fn matches(&self, note: &Note) -> bool {
+        let same_pitch =
+            |other: &Note| other.to_midi_note() == note.to_midi_note() && other.cents == note.cents;
+        match self {
+            NoteTarget::Note(target) => same_pitch(target),
+            NoteTarget::AliasKey(_) => false,
+            NoteTarget::Alias(def) => def.notes.iter().any(same_pitch),
+            NoteTarget::Range { low, high } => {
+                (low.to_midi_note()..=high.to_midi_note()).contains(&note.to_midi_note())
+            }
+        }
+    }
 }
 
 impl fmt::Display for NoteTarget {
@@ -135,6 +270,7 @@ impl fmt::Display for NoteTarget {
             NoteTarget::Note(note) => write!(f, "{}", note),
             NoteTarget::AliasKey(alias) => write!(f, "{}", alias),
             NoteTarget::Alias(alias) => write!(f, "{}", alias.name),
+            NoteTarget::Range { low, high } => write!(f, "{}-{}", low, high),
         }
     }
 }
@@ -148,6 +284,16 @@ impl FromStr for NoteTarget {
             return Ok(NoteTarget::Note(note));
         }
 
+        // try to parse as range, the low note can have negative cents (C3-50-C5)
+        for (idx, _) in s.match_indices('-') {
+            if let (Ok(low), Ok(high)) = (s[..idx].parse::<Note>(), s[idx + 1..].parse::<Note>()) {
+                if low.to_midi_note() > high.to_midi_note() {
+                    bail!("Invalid note range {}: {} is higher than {}", s, low, high);
+                }
+                return Ok(NoteTarget::Range { low, high });
+            }
+        }
+
         Ok(NoteTarget::AliasKey(s.to_string()))
     }
 }
@@ -176,6 +322,45 @@ mod tests {
         assert_eq!("Db0".parse::<Note>().unwrap().pitch_class, PitchClass::Db);
     }
 
+    #[test]
+    fn note_range() {
+        let target: NoteTarget = "C3-C5".parse().unwrap();
+        assert_eq!(
+            target,
+            NoteTarget::Range {
+                low: "C3".parse().unwrap(),
+                high: "C5".parse().unwrap(),
+            }
+        );
+        assert_eq!(target.to_string(), "C3-C5");
+        assert!(target.matches(&"C3".parse().unwrap()));
+        assert!(target.matches(&"F#4".parse().unwrap()));
+        assert!(target.matches(&"C5".parse().unwrap()));
+        assert!(!target.matches(&"C#5".parse().unwrap()));
+
+        let target: NoteTarget = "D4-50-E4".parse().unwrap();
+        assert_eq!(target.to_string(), "D4-50-E4");
+        assert!(matches!("D4-50".parse().unwrap(), NoteTarget::Note(_)));
+        assert!("C5-C3".parse::<NoteTarget>().is_err());
+
+        let notes = Note::chromatic_range(&"A3".parse().unwrap(), &"C4".parse().unwrap());
+        let names: Vec<String> = notes.iter().map(|n| n.to_string()).collect();
+        assert_eq!(names, ["A3", "Bb3", "B3", "C4"]);
+    }
+
+    #[test]
+    fn note_target_matches() {
+        let note: Note = "E4".parse().unwrap();
+        assert!(NoteTarget::Note("Fb4".parse().unwrap()).matches(&note));
+        assert!(!NoteTarget::Note("E4+10".parse().unwrap()).matches(&note));
+        assert!(!NoteTarget::AliasKey("E4".to_string()).matches(&note));
+        let alias = AliasDefinition {
+            name: "Cmaj".to_string(),
+            notes: vec!["C4".parse().unwrap(), "E4".parse().unwrap()],
+        };
+        assert!(NoteTarget::Alias(Rc::new(alias)).matches(&note));
+    }
+
     #[test]
     fn cents() {
         assert_eq!("D4+50.5".parse::<Note>().unwrap().cents, 50.5);
@@ -183,5 +368,139 @@ mod tests {
         assert_eq!("D4".parse::<Note>().unwrap().cents, 0.0);
         assert_eq!("D4-0".parse::<Note>().unwrap().cents, 0.0);
         assert_eq!("D4+0".parse::<Note>().unwrap().cents, 0.0);
+        assert_eq!("C-1".parse::<Note>().unwrap().to_midi_note(), 0);
+        assert_eq!("C#-1-50".parse::<Note>().unwrap().cents, -50.0);
+        assert_eq!("E4+50c".parse::<Note>().unwrap().cents, 50.0);
+        assert_eq!("E4-14c".parse::<Note>().unwrap().to_string(), "E4-14");
+        assert_eq!("E4+13.7c".parse::<Note>().unwrap().to_string(), "E4+13.7");
+        assert!("E4c".parse::<Note>().is_err());
+        assert!("E4+50cc".parse::<Note>().is_err());
+    }
+
+    #[test]
+    fn transpose_cents() {
+        let note: Note = "E4+50".parse().unwrap();
+        assert_eq!(
+            note.transpose(2, SpellingPreference::Common)
+                .unwrap()
+                .to_string(),
+            "F#4+50"
+        );
+        assert_eq!(note.transpose_cents(-14.0).unwrap().to_string(), "E4+36");
+        assert_eq!(note.transpose_cents(75.0).unwrap().to_string(), "F4+25");
+        assert_eq!(note.transpose_cents(-170.0).unwrap().to_string(), "Eb4-20");
+        // the spelling is kept within the semitone
+        let note: Note = "Fb4".parse().unwrap();
+        assert_eq!(note.transpose_cents(50.0).unwrap().to_string(), "Fb4+50");
+        assert!(
+            "G9+50"
+                .parse::<Note>()
+                .unwrap()
+                .transpose_cents(60.0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn spelling() {
+        let midi = |name: &str| name.parse::<Note>().unwrap().to_midi_note();
+        assert_eq!(midi("B#3"), midi("C4"));
+        assert_eq!(midi("B#3"), 60);
+        assert_eq!(midi("Cb4"), midi("B3"));
+        assert_eq!(midi("Cb4"), 59);
+        assert_eq!(midi("Bx3"), 61);
+        assert_eq!(midi("Cbb4"), 58);
+        assert_eq!(midi("Fx5"), midi("G5"));
+        assert_eq!(midi("Ebb2"), midi("D2"));
+        assert_eq!(midi("E\u{266d}4"), midi("D#4"));
+
+        for name in ["Cb4", "B#3", "Fx5", "Ebb2", "Bbb2+10.5"] {
+            assert_eq!(name.parse::<Note>().unwrap().to_string(), name);
+        }
+        assert_eq!(
+            "F\u{266f}\u{266f}5".parse::<Note>().unwrap().to_string(),
+            "Fx5"
+        );
+
+        let names = |notes: Vec<Note>| notes.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let c4: Note = "C4".parse().unwrap();
+        assert_eq!(names(c4.enharmonic_equivalents()), ["B#3", "Dbb4"]);
+        let g_sharp: Note = "G#4+20".parse().unwrap();
+        assert_eq!(names(g_sharp.enharmonic_equivalents()), ["Ab4+20"]);
+
+        let b3: Note = "Cb4".parse().unwrap();
+        assert_eq!(b3.respell(SpellingPreference::Sharps).to_string(), "B3");
+        let e_flat: Note = "D#4".parse().unwrap();
+        assert_eq!(e_flat.respell(SpellingPreference::Flats).to_string(), "Eb4");
+        let key = |key: &str| SpellingPreference::Key(key.parse().unwrap());
+        let f: Note = "F5".parse().unwrap();
+        assert_eq!(f.respell(key("F# major")).to_string(), "E#5");
+        let b: Note = "B3".parse().unwrap();
+        assert_eq!(b.respell(key("Gb major")).to_string(), "Cb4");
+        assert_eq!(e_flat.respell(key("E major")).to_string(), "D#4");
+        assert_eq!(e_flat.respell(key("F major")).to_string(), "Eb4");
+    }
+
+    #[test]
+    fn transpose() {
+        let lowest: Note = "C-1".parse().unwrap();
+        let highest: Note = "G9".parse().unwrap();
+        let sharps = SpellingPreference::Sharps;
+        let flats = SpellingPreference::Flats;
+
+        // every key of the range reached from both ends, spelled both ways
+        for key in 0..=127 {
+            let up = lowest.transpose(key, sharps).unwrap();
+            let down = highest.transpose(key - 127, flats).unwrap();
+            assert_eq!(up.to_midi_note() as i32, key);
+            assert_eq!(down.to_midi_note() as i32, key);
+            assert!(up.pitch_class.accidental() >= 0, "{}", up);
+            assert!(down.pitch_class.accidental() <= 0, "{}", down);
+            assert_eq!(up.to_string().parse::<Note>().unwrap(), up);
+        }
+        assert!(lowest.transpose(-1, sharps).is_err());
+        assert!(lowest.transpose(128, flats).is_err());
+        assert!(highest.transpose(1, sharps).is_err());
+        assert!(highest.transpose(-128, flats).is_err());
+        assert!("B#9".parse::<Note>().unwrap().transpose(0, sharps).is_err());
+
+        let note: Note = "B#3-20".parse().unwrap();
+        assert_eq!(note.transpose(1, sharps).unwrap().to_string(), "C#4-20");
+        assert_eq!(note.transpose(1, flats).unwrap().to_string(), "Db4-20");
+        assert_eq!(note.transpose(-1, sharps).unwrap().to_string(), "B3-20");
+        let e_major = SpellingPreference::Key("E major".parse().unwrap());
+        assert_eq!(note.transpose(3, e_major).unwrap().to_string(), "D#4-20");
+        let f_major = SpellingPreference::Key("F major".parse().unwrap());
+        assert_eq!(note.transpose(3, f_major).unwrap().to_string(), "Eb4-20");
+        assert_eq!(
+            note.transpose(8, SpellingPreference::Common)
+                .unwrap()
+                .to_string(),
+            "Ab4-20"
+        );
+    }
+
+    #[test]
+    fn add_interval() {
+        let add = |note: &str, interval: &str| {
+            let note: Note = note.parse().unwrap();
+            note.add_interval(interval.parse().unwrap())
+                .map(|note| note.to_string())
+        };
+        assert_eq!(add("C4", "A4").unwrap(), "F#4");
+        assert_eq!(add("C4", "d5").unwrap(), "Gb4");
+        assert_eq!(add("C4", "M9").unwrap(), "D5");
+        assert_eq!(add("C4", "-m3").unwrap(), "A3");
+        assert_eq!(add("B3", "m2").unwrap(), "C4");
+        assert_eq!(add("B3", "A1").unwrap(), "B#3");
+        assert_eq!(add("E4+30", "-P8").unwrap(), "E3+30");
+        assert_eq!(add("F#4", "A4").unwrap(), "B#4");
+        assert_eq!(add("Bb4", "d5").unwrap(), "Fb5");
+        assert_eq!(add("Ebb4", "P5").unwrap(), "Bbb4");
+        // a triple flat and a triple sharp
+        assert!(add("Ebb4", "d5").is_err());
+        assert!(add("Gx4", "A4").is_err());
+        assert!(add("G9", "m2").is_err());
+        assert!(add("C-1", "-m2").is_err());
     }
 }
@@ -1,6 +1,8 @@
+use crate::BeatTime;
 use crate::Note;
 use crate::TimeSignature;
 use crate::types::record::VoiceList;
+use crate::types::tempo_map::TempoMap;
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -62,7 +64,63 @@ pub enum MtxtOutputRecord {
     },
 }
 
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn json_note(note: &Note) -> [(&'static str, String); 2] {
+    [
+        ("note", json_string(&note.to_string())),
+        ("midi_note", note.to_midi_note().to_string()),
+    ]
+}
+
 impl MtxtOutputRecord {
+    /// Order of records at the same time: note offs, resets, CCs, tempo and other events,
+    /// note ons. Releasing first keeps a repeated note from being cut off by the end of the
+    /// previous one, a reset doesn't undo the controllers set with it, and controllers and
+    /// voices are set before the notes they affect.
+    pub fn order_priority(&self) -> u8 {
+        match self {
+            MtxtOutputRecord::NoteOff { .. } => 0,
+            MtxtOutputRecord::Reset { .. } => 1,
+            MtxtOutputRecord::ControlChange { .. } => 2,
+            MtxtOutputRecord::NoteOn { .. } => 4,
+            _ => 3,
+        }
+    }
+
+    /// Time of the record in microseconds from the start of the file
     pub fn time(&self) -> u64 {
         match self {
             MtxtOutputRecord::NoteOn { time, .. }
@@ -95,6 +153,235 @@ impl MtxtOutputRecord {
         };
     }
 
+    /// Time of the record in beats at a constant tempo, `micros * bpm / 60_000_000`
+    pub fn time_as_beat_time(&self, tempo: f64) -> BeatTime {
+        BeatTime::from_micros(self.time(), tempo)
+    }
+
+    /// Time of the record in beats following tempo changes, given as (beat time, BPM) pairs
+    /// sorted by time. Tempo changes are immediate, the tempo is 120 BPM before the first one.
+    pub fn time_as_beat_time_with_map(&self, tempo_map: &[(BeatTime, f64)]) -> BeatTime {
+        TempoMap::from_changes(tempo_map).beat_at_micros(self.time())
+    }
+
+    /// The record as a JSON object with `type` (the variant name), `time_micros`,
+    /// `channel` if the record has one, and the fields of the variant in snake case, e.g.
+    /// `{"type":"NoteOn","time_micros":0,"channel":1,"note":"C4","midi_note":60,"velocity":0.8}`
+    pub fn to_json(&self) -> String {
+        let (kind, channel, fields): (&str, Option<u16>, Vec<(&str, String)>) = match self {
+            MtxtOutputRecord::NoteOn {
+                note,
+                velocity,
+                channel,
+                ..
+            } => {
+                let mut fields = json_note(note).to_vec();
+                fields.push(("velocity", json_number(*velocity)));
+                ("NoteOn", Some(*channel), fields)
+            }
+            MtxtOutputRecord::NoteOff {
+                note,
+                off_velocity,
+                channel,
+                ..
+            } => {
+                let mut fields = json_note(note).to_vec();
+                fields.push(("off_velocity", json_number(*off_velocity)));
+                ("NoteOff", Some(*channel), fields)
+            }
+            MtxtOutputRecord::ControlChange {
+                note,
+                controller,
+                value,
+                channel,
+                ..
+            } => {
+                let mut fields = match note {
+                    Some(note) => json_note(note).to_vec(),
+                    None => vec![("note", "null".to_string())],
+                };
+                fields.push(("controller", json_string(controller)));
+                fields.push(("value", json_number(*value)));
+                ("ControlChange", Some(*channel), fields)
+            }
+            MtxtOutputRecord::Voice {
+                voices, channel, ..
+            } => {
+                let voices: Vec<String> = voices.voices.iter().map(|v| json_string(v)).collect();
+                (
+                    "Voice",
+                    Some(*channel),
+                    vec![("voices", format!("[{}]", voices.join(",")))],
+                )
+            }
+            MtxtOutputRecord::Tempo { bpm, .. } => {
+                ("Tempo", None, vec![("bpm", json_number(*bpm))])
+            }
+            MtxtOutputRecord::TimeSignature { signature, .. } => (
+                "TimeSignature",
+                None,
+                vec![
+                    ("numerator", signature.numerator.to_string()),
+                    ("denominator", signature.denominator.to_string()),
+                ],
+            ),
+            MtxtOutputRecord::Reset { target, .. } => {
+                ("Reset", None, vec![("target", json_string(target))])
+            }
+            MtxtOutputRecord::GlobalMeta {
+                meta_type, value, ..
+            } => (
+                "GlobalMeta",
+                None,
+                vec![
+                    ("meta_type", json_string(meta_type)),
+                    ("value", json_string(value)),
+                ],
+            ),
+            MtxtOutputRecord::ChannelMeta {
+                channel,
+                meta_type,
+                value,
+                ..
+            } => (
+                "ChannelMeta",
+                Some(*channel),
+                vec![
+                    ("meta_type", json_string(meta_type)),
+                    ("value", json_string(value)),
+                ],
+            ),
+            MtxtOutputRecord::Beat { beat, .. } => ("Beat", None, vec![("beat", beat.to_string())]),
+            MtxtOutputRecord::SysEx { data, .. } => {
+                let data: Vec<String> = data.iter().map(|byte| byte.to_string()).collect();
+                (
+                    "SysEx",
+                    None,
+                    vec![("data", format!("[{}]", data.join(",")))],
+                )
+            }
+        };
+
+        let mut json = format!("{{\"type\":\"{}\",\"time_micros\":{}", kind, self.time());
+        if let Some(channel) = channel {
+            json.push_str(&format!(",\"channel\":{}", channel));
+        }
+        for (key, value) in fields {
+            json.push_str(&format!(",\"{}\":{}", key, value));
+        }
+        json.push('}');
+        json
+    }
+
+    /// Column names of [`MtxtOutputRecord::to_csv`]
+    pub const CSV_HEADER: &'static str = "type,time_micros,channel,note,midi_note,name,value";
+
+    /// The record as a CSV row with the columns of [`MtxtOutputRecord::CSV_HEADER`]. `name` is
+    /// the controller, reset target, meta type or the voices, `value` is the velocity, CC value,
+    /// BPM, time signature, meta value, beat number or the SysEx bytes. Unused columns are empty.
+    pub fn to_csv(&self) -> String {
+        let note_columns = |note: &Note| (note.to_string(), note.to_midi_note().to_string());
+        let none = || (String::new(), String::new());
+        let (kind, channel, (note, midi_note), name, value) = match self {
+            MtxtOutputRecord::NoteOn {
+                note,
+                velocity,
+                channel,
+                ..
+            } => (
+                "NoteOn",
+                Some(*channel),
+                note_columns(note),
+                String::new(),
+                velocity.to_string(),
+            ),
+            MtxtOutputRecord::NoteOff {
+                note,
+                off_velocity,
+                channel,
+                ..
+            } => (
+                "NoteOff",
+                Some(*channel),
+                note_columns(note),
+                String::new(),
+                off_velocity.to_string(),
+            ),
+            MtxtOutputRecord::ControlChange {
+                note,
+                controller,
+                value,
+                channel,
+                ..
+            } => (
+                "ControlChange",
+                Some(*channel),
+                note.as_ref().map_or_else(none, note_columns),
+                controller.clone(),
+                value.to_string(),
+            ),
+            MtxtOutputRecord::Voice {
+                voices, channel, ..
+            } => (
+                "Voice",
+                Some(*channel),
+                none(),
+                voices.voices.join(", "),
+                String::new(),
+            ),
+            MtxtOutputRecord::Tempo { bpm, .. } => {
+                ("Tempo", None, none(), String::new(), bpm.to_string())
+            }
+            MtxtOutputRecord::TimeSignature { signature, .. } => (
+                "TimeSignature",
+                None,
+                none(),
+                String::new(),
+                format!("{}/{}", signature.numerator, signature.denominator),
+            ),
+            MtxtOutputRecord::Reset { target, .. } => {
+                ("Reset", None, none(), target.clone(), String::new())
+            }
+            MtxtOutputRecord::GlobalMeta {
+                meta_type, value, ..
+            } => ("GlobalMeta", None, none(), meta_type.clone(), value.clone()),
+            MtxtOutputRecord::ChannelMeta {
+                channel,
+                meta_type,
+                value,
+                ..
+            } => (
+                "ChannelMeta",
+                Some(*channel),
+                none(),
+                meta_type.clone(),
+                value.clone(),
+            ),
+            MtxtOutputRecord::Beat { beat, .. } => {
+                ("Beat", None, none(), String::new(), beat.to_string())
+            }
+            MtxtOutputRecord::SysEx { data, .. } => {
+                let data: Vec<String> = data.iter().map(|byte| byte.to_string()).collect();
+                ("SysEx", None, none(), String::new(), data.join(" "))
+            }
+        };
+
+        let channel = channel
+            .map(|channel| channel.to_string())
+            .unwrap_or_default();
+        [
+            kind,
+            &self.time().to_string(),
+            &channel,
+            &note,
+            &midi_note,
+            &name,
+            &value,
+        ]
+        .map(csv_field)
+        .join(",")
+    }
+
     // used for transitions
     pub fn get_parameter_value(&self) -> Option<f32> {
         match self {
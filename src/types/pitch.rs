@@ -2,91 +2,201 @@ use anyhow::{Result, bail};
 use std::fmt;
 use std::str::FromStr;
 
+/// Spelled pitch class: a note name with up to two sharps or flats
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PitchClass {
+    Cbb,
     Cb,
     C,
     CSharp,
+    CDoubleSharp,
+    Dbb,
     Db,
     D,
     DSharp,
+    DDoubleSharp,
+    Ebb,
     Eb,
     E,
     ESharp,
+    EDoubleSharp,
+    Fbb,
     Fb,
     F,
     FSharp,
+    FDoubleSharp,
+    Gbb,
     Gb,
     G,
     GSharp,
+    GDoubleSharp,
+    Abb,
     Ab,
     A,
     ASharp,
+    ADoubleSharp,
+    Bbb,
     Bb,
     B,
     BSharp,
+    BDoubleSharp,
 }
 
+// Note names C D E F G A B
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+// Semitones of the natural notes C D E F G A B
+pub(crate) const NATURAL_SEMITONES: [i8; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// Spellings by letter, from double flat to double sharp
+const SPELLINGS: [[PitchClass; 5]; 7] = [
+    [
+        PitchClass::Cbb,
+        PitchClass::Cb,
+        PitchClass::C,
+        PitchClass::CSharp,
+        PitchClass::CDoubleSharp,
+    ],
+    [
+        PitchClass::Dbb,
+        PitchClass::Db,
+        PitchClass::D,
+        PitchClass::DSharp,
+        PitchClass::DDoubleSharp,
+    ],
+    [
+        PitchClass::Ebb,
+        PitchClass::Eb,
+        PitchClass::E,
+        PitchClass::ESharp,
+        PitchClass::EDoubleSharp,
+    ],
+    [
+        PitchClass::Fbb,
+        PitchClass::Fb,
+        PitchClass::F,
+        PitchClass::FSharp,
+        PitchClass::FDoubleSharp,
+    ],
+    [
+        PitchClass::Gbb,
+        PitchClass::Gb,
+        PitchClass::G,
+        PitchClass::GSharp,
+        PitchClass::GDoubleSharp,
+    ],
+    [
+        PitchClass::Abb,
+        PitchClass::Ab,
+        PitchClass::A,
+        PitchClass::ASharp,
+        PitchClass::ADoubleSharp,
+    ],
+    [
+        PitchClass::Bbb,
+        PitchClass::Bb,
+        PitchClass::B,
+        PitchClass::BSharp,
+        PitchClass::BDoubleSharp,
+    ],
+];
+
 impl PitchClass {
+    /// Every spelling, by letter and from double flat to double sharp
+    pub const ALL: [PitchClass; 35] = {
+        let mut all = [PitchClass::C; 35];
+        let mut i = 0;
+        while i < 35 {
+            all[i] = SPELLINGS[i / 5][i % 5];
+            i += 1;
+        }
+        all
+    };
+
+    /// Spelling of a letter (0-6 for C..B) with an accidental (-2 to 2 for double flat to
+    /// double sharp)
+    pub fn from_letter(letter: u8, accidental: i8) -> Option<Self> {
+        let index = usize::try_from(accidental + 2).ok()?;
+        SPELLINGS.get(letter as usize)?.get(index).copied()
+    }
+
+    /// Position of the note name in C D E F G A B
+    pub fn letter(self) -> u8 {
+        self.spelling().0
+    }
+
+    /// Sharps (positive) or flats (negative) of the spelling
+    pub fn accidental(self) -> i8 {
+        self.spelling().1
+    }
+
+    // the variants are declared in the order of SPELLINGS
+    fn spelling(self) -> (u8, i8) {
+        let index = self as usize;
+        ((index / 5) as u8, (index % 5) as i8 - 2)
+    }
+
     pub fn to_semitone(self) -> u8 {
-        match self {
-            PitchClass::BSharp | PitchClass::C => 0,
-            PitchClass::CSharp | PitchClass::Db => 1,
-            PitchClass::D => 2,
-            PitchClass::DSharp | PitchClass::Eb => 3,
-            PitchClass::Fb | PitchClass::E => 4,
-            PitchClass::ESharp | PitchClass::F => 5,
-            PitchClass::FSharp | PitchClass::Gb => 6,
-            PitchClass::G => 7,
-            PitchClass::GSharp | PitchClass::Ab => 8,
-            PitchClass::A => 9,
-            PitchClass::ASharp | PitchClass::Bb => 10,
-            PitchClass::Cb | PitchClass::B => 11,
+        self.unwrapped_semitone().rem_euclid(12) as u8
+    }
+
+    /// Octaves the pitch is moved by its accidentals past C, -1 for Cb and Cbb, 1 for B# and
+    /// B## (Cb4 sounds as B3, B#3 as C4)
+    pub fn octave_offset(self) -> i8 {
+        self.unwrapped_semitone().div_euclid(12)
+    }
+
+    fn unwrapped_semitone(self) -> i8 {
+        let (letter, accidental) = self.spelling();
+        NATURAL_SEMITONES[letter as usize] + accidental
+    }
+
+    /// Spells a semitone (0-11, C = 0) with sharps or flats
+    pub fn from_semitone(semitone: u8, prefer_flats: bool) -> Self {
+        match (semitone % 12, prefer_flats) {
+            (0, _) => PitchClass::C,
+            (1, false) => PitchClass::CSharp,
+            (1, true) => PitchClass::Db,
+            (2, _) => PitchClass::D,
+            (3, false) => PitchClass::DSharp,
+            (3, true) => PitchClass::Eb,
+            (4, _) => PitchClass::E,
+            (5, _) => PitchClass::F,
+            (6, false) => PitchClass::FSharp,
+            (6, true) => PitchClass::Gb,
+            (7, _) => PitchClass::G,
+            (8, false) => PitchClass::GSharp,
+            (8, true) => PitchClass::Ab,
+            (9, _) => PitchClass::A,
+            (10, false) => PitchClass::ASharp,
+            (10, true) => PitchClass::Bb,
+            _ => PitchClass::B,
         }
     }
 
     pub fn to_canonical(self) -> Self {
-        match self {
-            PitchClass::Cb => PitchClass::B,
-            PitchClass::Db => PitchClass::CSharp,
-            PitchClass::DSharp => PitchClass::Eb,
-            PitchClass::ESharp => PitchClass::F,
-            PitchClass::Fb => PitchClass::E,
-            PitchClass::Gb => PitchClass::FSharp,
-            PitchClass::GSharp => PitchClass::Ab,
-            PitchClass::ASharp => PitchClass::Bb,
-            PitchClass::BSharp => PitchClass::C,
-            _ => self,
+        match self.to_semitone() {
+            1 => PitchClass::CSharp,
+            3 => PitchClass::Eb,
+            6 => PitchClass::FSharp,
+            8 => PitchClass::Ab,
+            10 => PitchClass::Bb,
+            semitone => PitchClass::from_semitone(semitone, false),
         }
     }
 }
 
 impl fmt::Display for PitchClass {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            PitchClass::Cb => "Cb",
-            PitchClass::C => "C",
-            PitchClass::CSharp => "C#",
-            PitchClass::Db => "Db",
-            PitchClass::D => "D",
-            PitchClass::DSharp => "D#",
-            PitchClass::Eb => "Eb",
-            PitchClass::E => "E",
-            PitchClass::ESharp => "E#",
-            PitchClass::Fb => "Fb",
-            PitchClass::F => "F",
-            PitchClass::FSharp => "F#",
-            PitchClass::Gb => "Gb",
-            PitchClass::G => "G",
-            PitchClass::GSharp => "G#",
-            PitchClass::Ab => "Ab",
-            PitchClass::A => "A",
-            PitchClass::ASharp => "A#",
-            PitchClass::Bb => "Bb",
-            PitchClass::B => "B",
-            PitchClass::BSharp => "B#",
+        let (letter, accidental) = self.spelling();
+        let accidental = match accidental {
+            -2 => "bb",
+            -1 => "b",
+            1 => "#",
+            2 => "x",
+            _ => "",
         };
-        write!(f, "{}", s)
+        write!(f, "{}{}", LETTERS[letter as usize], accidental)
     }
 }
 
@@ -94,29 +204,20 @@ impl FromStr for PitchClass {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let s_upper = s.to_uppercase();
-        match s_upper.as_str() {
-            "CB" => Ok(PitchClass::Cb),
-            "C" => Ok(PitchClass::C),
-            "C#" => Ok(PitchClass::CSharp),
-            "DB" => Ok(PitchClass::Db),
-            "D" => Ok(PitchClass::D),
-            "D#" => Ok(PitchClass::DSharp),
-            "EB" => Ok(PitchClass::Eb),
-            "E" => Ok(PitchClass::E),
-            "E#" => Ok(PitchClass::ESharp),
-            "FB" => Ok(PitchClass::Fb),
-            "F" => Ok(PitchClass::F),
-            "F#" => Ok(PitchClass::FSharp),
-            "GB" => Ok(PitchClass::Gb),
-            "G" => Ok(PitchClass::G),
-            "G#" => Ok(PitchClass::GSharp),
-            "AB" => Ok(PitchClass::Ab),
-            "A" => Ok(PitchClass::A),
-            "A#" => Ok(PitchClass::ASharp),
-            "BB" => Ok(PitchClass::Bb),
-            "B" => Ok(PitchClass::B),
-            "B#" => Ok(PitchClass::BSharp),
+        let mut chars = s.chars();
+        let letter = chars
+            .next()
+            .and_then(|letter| LETTERS.iter().position(|l| l.eq_ignore_ascii_case(&letter)));
+        let accidental = match chars.as_str().to_lowercase().as_str() {
+            "" => Some(0),
+            "#" | "\u{266f}" => Some(1),
+            "b" | "\u{266d}" => Some(-1),
+            "x" | "##" | "\u{266f}\u{266f}" | "\u{1d12a}" => Some(2),
+            "bb" | "\u{266d}\u{266d}" | "\u{1d12b}" => Some(-2),
+            _ => None,
+        };
+        match (letter, accidental) {
+            (Some(letter), Some(accidental)) => Ok(SPELLINGS[letter][(accidental + 2) as usize]),
             _ => bail!("Invalid pitch class: {}", s),
         }
     }
@@ -133,6 +234,22 @@ mod tests {
         assert_eq!("Cb".parse::<PitchClass>().unwrap(), PitchClass::Cb);
     }
 
+    #[test]
+    fn test_from_semitone() {
+        for semitone in 0..12 {
+            assert_eq!(
+                PitchClass::from_semitone(semitone, false).to_semitone(),
+                semitone
+            );
+            assert_eq!(
+                PitchClass::from_semitone(semitone, true).to_semitone(),
+                semitone
+            );
+        }
+        assert_eq!(PitchClass::from_semitone(1, false), PitchClass::CSharp);
+        assert_eq!(PitchClass::from_semitone(1, true), PitchClass::Db);
+    }
+
     #[test]
     fn test_case_insensitive_pitch_class_parsing() {
         assert_eq!("c".parse::<PitchClass>().unwrap(), PitchClass::C);
@@ -145,4 +262,47 @@ mod tests {
         assert_eq!("F#".parse::<PitchClass>().unwrap(), PitchClass::FSharp);
         assert_eq!("f#".parse::<PitchClass>().unwrap(), PitchClass::FSharp);
     }
+
+    #[test]
+    fn test_double_accidentals() {
+        assert_eq!(
+            "Fx".parse::<PitchClass>().unwrap(),
+            PitchClass::FDoubleSharp
+        );
+        assert_eq!(
+            "F##".parse::<PitchClass>().unwrap(),
+            PitchClass::FDoubleSharp
+        );
+        assert_eq!("Ebb".parse::<PitchClass>().unwrap(), PitchClass::Ebb);
+        assert_eq!("bbb".parse::<PitchClass>().unwrap(), PitchClass::Bbb);
+        assert_eq!("E\u{266d}".parse::<PitchClass>().unwrap(), PitchClass::Eb);
+        assert_eq!(
+            "C\u{266f}".parse::<PitchClass>().unwrap(),
+            PitchClass::CSharp
+        );
+        assert_eq!("D\u{1d12b}".parse::<PitchClass>().unwrap(), PitchClass::Dbb);
+        assert!("Cbbb".parse::<PitchClass>().is_err());
+        assert!("H".parse::<PitchClass>().is_err());
+
+        assert_eq!(PitchClass::FDoubleSharp.to_semitone(), 7);
+        assert_eq!(PitchClass::Dbb.to_semitone(), 0);
+        assert_eq!(PitchClass::Cbb.to_semitone(), 10);
+        assert_eq!(PitchClass::Cbb.octave_offset(), -1);
+        assert_eq!(PitchClass::BSharp.octave_offset(), 1);
+        assert_eq!(PitchClass::ADoubleSharp.octave_offset(), 0);
+        assert_eq!(PitchClass::BDoubleSharp.to_canonical(), PitchClass::CSharp);
+
+        for pitch_class in PitchClass::ALL {
+            assert_eq!(
+                pitch_class.to_string().parse::<PitchClass>().unwrap(),
+                pitch_class
+            );
+            assert_eq!(
+                PitchClass::from_letter(pitch_class.letter(), pitch_class.accidental()),
+                Some(pitch_class)
+            );
+        }
+        assert_eq!(PitchClass::from_letter(7, 0), None);
+        assert_eq!(PitchClass::from_letter(0, 3), None);
+    }
 }
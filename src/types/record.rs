@@ -2,8 +2,11 @@ use crate::BeatTime;
 use crate::Note;
 use crate::TimeSignature;
 use crate::Version;
+use crate::record_parser::parse_record_of_type;
+use crate::types::beat_time::BeatTimeFormat;
 use crate::types::note::NoteTarget;
 use crate::util::format_float32;
+use anyhow::Result;
 use std::fmt;
 use std::rc::Rc;
 
@@ -174,8 +177,34 @@ impl MtxtRecordLine {
     }
 }
 
+/// A record with its durations and transition times written with a [`BeatTimeFormat`]
+pub struct MtxtRecordDisplay<'a> {
+    record: &'a MtxtRecord,
+    format: BeatTimeFormat,
+}
+
+impl fmt::Display for MtxtRecordDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.record.fmt_with(f, self.format)
+    }
+}
+
 impl fmt::Display for MtxtRecord {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, BeatTimeFormat::default())
+    }
+}
+
+impl MtxtRecord {
+    /// Writes the record without its time, the beat values use the given format
+    pub fn display_with(&self, format: BeatTimeFormat) -> MtxtRecordDisplay<'_> {
+        MtxtRecordDisplay {
+            record: self,
+            format,
+        }
+    }
+
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, format: BeatTimeFormat) -> fmt::Result {
         match self {
             MtxtRecord::Header { version } => {
                 write!(f, "mtxt {}", version)
@@ -193,7 +222,7 @@ impl fmt::Display for MtxtRecord {
                 write!(f, "offvel={}", format_float32(*off_velocity))
             }
             MtxtRecord::DurationDirective { duration } => {
-                write!(f, "dur={}", *duration)
+                write!(f, "dur={}", duration.display_with(format))
             }
             MtxtRecord::TransitionCurveDirective { curve } => {
                 write!(f, "transition_curve={}", format_float32(*curve))
@@ -219,9 +248,9 @@ impl fmt::Display for MtxtRecord {
                 off_velocity,
                 channel,
             } => {
-                write!(f, "note {}", note)?;
+                write!(f, "{} {}", self.type_str(), note)?;
                 if let Some(duration) = duration {
-                    write!(f, " dur={}", *duration)?;
+                    write!(f, " dur={}", duration.display_with(format))?;
                 }
                 if let Some(vel) = velocity {
                     write!(f, " vel={}", format_float32(*vel))?;
@@ -240,7 +269,7 @@ impl fmt::Display for MtxtRecord {
                 velocity,
                 channel,
             } => {
-                write!(f, "on {}", note)?;
+                write!(f, "{} {}", self.type_str(), note)?;
                 if let Some(vel) = velocity {
                     write!(f, " vel={}", format_float32(*vel))?;
                 }
@@ -255,7 +284,7 @@ impl fmt::Display for MtxtRecord {
                 off_velocity,
                 channel,
             } => {
-                write!(f, "off {}", note)?;
+                write!(f, "{} {}", self.type_str(), note)?;
                 if let Some(off_vel) = off_velocity {
                     write!(f, " offvel={}", format_float32(*off_vel))?;
                 }
@@ -274,7 +303,7 @@ impl fmt::Display for MtxtRecord {
                 transition_time,
                 transition_interval,
             } => {
-                write!(f, "cc")?;
+                write!(f, "{}", self.type_str())?;
                 if let Some(n) = note {
                     write!(f, " {}", n)?;
                 }
@@ -288,7 +317,7 @@ impl fmt::Display for MtxtRecord {
                     write!(f, " transition_curve={}", format_float32(*curve))?;
                 }
                 if let Some(time) = transition_time {
-                    write!(f, " transition_time={}", *time)?;
+                    write!(f, " transition_time={}", time.display_with(format))?;
                 }
                 if let Some(interval) = transition_interval {
                     write!(f, " transition_interval={}", format_float32(*interval))?;
@@ -300,7 +329,7 @@ impl fmt::Display for MtxtRecord {
                 voices,
                 channel,
             } => {
-                write!(f, "voice")?;
+                write!(f, "{}", self.type_str())?;
                 if let Some(ch) = channel {
                     write!(f, " ch={}", ch)?;
                 }
@@ -315,12 +344,12 @@ impl fmt::Display for MtxtRecord {
                 transition_time,
                 transition_interval,
             } => {
-                write!(f, "tempo {}", format_float32(*bpm))?;
+                write!(f, "{} {}", self.type_str(), format_float32(*bpm))?;
                 if let Some(curve) = transition_curve {
                     write!(f, " transition_curve={}", format_float32(*curve))?;
                 }
                 if let Some(time) = transition_time {
-                    write!(f, " transition_time={}", *time)?;
+                    write!(f, " transition_time={}", time.display_with(format))?;
                 }
                 if let Some(interval) = transition_interval {
                     write!(f, " transition_interval={}", format_float32(*interval))?;
@@ -328,7 +357,7 @@ impl fmt::Display for MtxtRecord {
                 Ok(())
             }
             MtxtRecord::TimeSignature { time: _, signature } => {
-                write!(f, "timesig {}", signature)
+                write!(f, "{} {}", self.type_str(), signature)
             }
             MtxtRecord::Tuning {
                 time: _,
@@ -343,7 +372,7 @@ impl fmt::Display for MtxtRecord {
                 }
             }
             MtxtRecord::Reset { time: _, target } => {
-                write!(f, "reset {}", target)
+                write!(f, "{} {}", self.type_str(), target)
             }
             MtxtRecord::Meta {
                 time: _,
@@ -358,7 +387,7 @@ impl fmt::Display for MtxtRecord {
                 write!(f, " {} {}", meta_type, value)
             }
             MtxtRecord::SysEx { time: _, data } => {
-                write!(f, "sysex")?;
+                write!(f, "{}", self.type_str())?;
                 for byte in data {
                     write!(f, " {:02x}", byte)?;
                 }
@@ -372,6 +401,59 @@ impl fmt::Display for MtxtRecord {
 }
 
 impl MtxtRecord {
+    /// Keyword used for this record type in MTXT files
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            MtxtRecord::Header { .. } => "mtxt",
+            MtxtRecord::GlobalMeta { .. } | MtxtRecord::Meta { .. } => "meta",
+            MtxtRecord::ChannelDirective { .. } => "ch",
+            MtxtRecord::VelocityDirective { .. } => "vel",
+            MtxtRecord::OffVelocityDirective { .. } => "offvel",
+            MtxtRecord::DurationDirective { .. } => "dur",
+            MtxtRecord::TransitionCurveDirective { .. } => "transition_curve",
+            MtxtRecord::TransitionIntervalDirective { .. } => "transition_interval",
+            MtxtRecord::AliasDef { .. } => "alias",
+            MtxtRecord::Note { .. } => "note",
+            MtxtRecord::NoteOn { .. } => "on",
+            MtxtRecord::NoteOff { .. } => "off",
+            MtxtRecord::ControlChange { .. } => "cc",
+            MtxtRecord::Voice { .. } => "voice",
+            MtxtRecord::Tempo { .. } => "tempo",
+            MtxtRecord::TimeSignature { .. } => "timesig",
+            MtxtRecord::Tuning { .. } => "tuning",
+            MtxtRecord::Reset { .. } => "reset",
+            MtxtRecord::SysEx { .. } => "sysex",
+            MtxtRecord::EmptyLine => "",
+        }
+    }
+
+    /// Parser for a full line that must contain a record of the given type
+    pub fn from_type_str(s: &str) -> Option<fn(&str) -> Result<MtxtRecord>> {
+        let parser: fn(&str) -> Result<MtxtRecord> = match s {
+            "mtxt" => |line| parse_record_of_type(line, "mtxt"),
+            "meta" => |line| parse_record_of_type(line, "meta"),
+            "ch" => |line| parse_record_of_type(line, "ch"),
+            "vel" => |line| parse_record_of_type(line, "vel"),
+            "offvel" => |line| parse_record_of_type(line, "offvel"),
+            "dur" => |line| parse_record_of_type(line, "dur"),
+            "transition_curve" => |line| parse_record_of_type(line, "transition_curve"),
+            "transition_interval" => |line| parse_record_of_type(line, "transition_interval"),
+            "alias" => |line| parse_record_of_type(line, "alias"),
+            "note" => |line| parse_record_of_type(line, "note"),
+            "on" => |line| parse_record_of_type(line, "on"),
+            "off" => |line| parse_record_of_type(line, "off"),
+            "cc" => |line| parse_record_of_type(line, "cc"),
+            "voice" => |line| parse_record_of_type(line, "voice"),
+            "tempo" => |line| parse_record_of_type(line, "tempo"),
+            "timesig" => |line| parse_record_of_type(line, "timesig"),
+            "tuning" => |line| parse_record_of_type(line, "tuning"),
+            "reset" => |line| parse_record_of_type(line, "reset"),
+            "sysex" => |line| parse_record_of_type(line, "sysex"),
+            _ => return None,
+        };
+        Some(parser)
+    }
+
     pub fn time(&self) -> Option<BeatTime> {
         match self {
             MtxtRecord::Note { time, .. }
@@ -406,3 +488,41 @@ impl MtxtRecord {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_parser::parse_mtxt_line;
+
+    #[test]
+    fn test_type_str_round_trip() {
+        for line in [
+            "mtxt 1.0",
+            "meta global title Song",
+            "ch=2",
+            "dur=0.5",
+            "alias Cmaj C4,E4,G4",
+            "1.0 note C4 dur=1",
+            "1.0 on C4",
+            "1.0 off C4",
+            "1.0 cc volume 0.5",
+            "1.0 voice piano",
+            "1.0 tempo 120",
+            "1.0 timesig 3/4",
+            "1.0 tuning A4 +10",
+            "1.0 reset all",
+            "1.0 sysex f0 7e f7",
+        ] {
+            let record = parse_mtxt_line(line).unwrap().record;
+            let parser = MtxtRecord::from_type_str(record.type_str()).unwrap();
+            assert_eq!(parser(line).unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn test_from_type_str_rejects_other_types() {
+        let parser = MtxtRecord::from_type_str("note").unwrap();
+        assert!(parser("1.0 tempo 120").is_err());
+        assert!(MtxtRecord::from_type_str("unknown").is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleType {
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Dorian,
+    Mixolydian,
+    /// Minor pentatonic with the flat fifth
+    Blues,
+}
+
+impl ScaleType {
+    /// Semitones of the scale tones above the root, in ascending order
+    pub fn semitones(&self) -> &'static [u8] {
+        match self {
+            ScaleType::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleType::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleType::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            ScaleType::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleType::MinorPentatonic => &[0, 3, 5, 7, 10],
+            ScaleType::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleType::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleType::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+
+    /// Whether the scale has a minor third
+    pub fn is_minor(&self) -> bool {
+        self.semitones().contains(&3)
+    }
+}
+
+impl fmt::Display for ScaleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScaleType::Major => "major",
+            ScaleType::NaturalMinor => "minor",
+            ScaleType::HarmonicMinor => "harmonic-minor",
+            ScaleType::MajorPentatonic => "pentatonic",
+            ScaleType::MinorPentatonic => "minor-pentatonic",
+            ScaleType::Dorian => "dorian",
+            ScaleType::Mixolydian => "mixolydian",
+            ScaleType::Blues => "blues",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ScaleType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().replace(['_', ' '], "-").as_str() {
+            "major" | "maj" | "ionian" => Ok(ScaleType::Major),
+            "minor" | "min" | "natural-minor" | "aeolian" => Ok(ScaleType::NaturalMinor),
+            "harmonic-minor" => Ok(ScaleType::HarmonicMinor),
+            "pentatonic" | "major-pentatonic" => Ok(ScaleType::MajorPentatonic),
+            "minor-pentatonic" => Ok(ScaleType::MinorPentatonic),
+            "dorian" => Ok(ScaleType::Dorian),
+            "mixolydian" => Ok(ScaleType::Mixolydian),
+            "blues" => Ok(ScaleType::Blues),
+            _ => bail!("Invalid scale: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_type() {
+        let scale = |s: &str| s.parse::<ScaleType>().unwrap();
+        assert_eq!(scale("major"), ScaleType::Major);
+        assert_eq!(scale("Natural Minor"), ScaleType::NaturalMinor);
+        assert_eq!(scale("harmonic_minor"), ScaleType::HarmonicMinor);
+        assert_eq!(scale("minor-pentatonic"), ScaleType::MinorPentatonic);
+        assert_eq!(scale("Blues").to_string(), "blues");
+        assert!("lydian".parse::<ScaleType>().is_err());
+
+        for scale in [ScaleType::Major, ScaleType::Dorian, ScaleType::Blues] {
+            assert_eq!(scale.to_string().parse::<ScaleType>().unwrap(), scale);
+            assert!(scale.semitones().windows(2).all(|w| w[0] < w[1]));
+        }
+        assert!(ScaleType::Dorian.is_minor());
+        assert!(!ScaleType::Mixolydian.is_minor());
+    }
+}
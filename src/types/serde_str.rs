@@ -0,0 +1,98 @@
+//! Serde support for the value types, written in their text form (e.g. `"C4"`, `"P5"`, `"1.5"`)
+//! so presets read like the MTXT files and CLI options.
+
+use super::beat_time::BeatTime;
+use super::interval::Interval;
+use super::key::KeySignature;
+use super::note::Note;
+use super::pitch::PitchClass;
+use super::scale::ScaleType;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+struct FromStrVisitor<T>(PhantomData<T>);
+
+impl<T> Visitor<'_> for FromStrVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    // numbers are accepted for beat times
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+}
+
+macro_rules! serde_via_str {
+    ($($ty:ty),*) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(FromStrVisitor(PhantomData))
+            }
+        }
+    )*};
+}
+
+serde_via_str!(
+    BeatTime,
+    Interval,
+    KeySignature,
+    Note,
+    PitchClass,
+    ScaleType
+);
+
+/// Maps keyed by channel, e.g. channel mappings, with the channels as string keys as TOML requires
+pub(crate) mod channel_map {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<u16, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let sorted: BTreeMap<u16, &V> = map.iter().map(|(from, to)| (*from, to)).collect();
+        serializer.collect_map(sorted.iter().map(|(from, to)| (from.to_string(), to)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<u16, V>, D::Error> {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(from, to)| {
+                from.parse()
+                    .map(|from| (from, to))
+                    .map_err(|_| D::Error::custom(format!("invalid channel: {}", from)))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,205 @@
+use crate::BeatTime;
+use crate::process::process_records;
+use crate::types::output_record::MtxtOutputRecord;
+use crate::types::record::MtxtRecord;
+
+const DEFAULT_BPM: f64 = 120.0;
+const MICROS_PER_MINUTE: f64 = 60_000_000.0;
+
+// Tempo in effect from a beat, with the wall-clock time of that beat
+#[derive(Debug, Clone, PartialEq)]
+struct TempoSegment {
+    beat: BeatTime,
+    micros: f64,
+    bpm: f64,
+}
+
+/// Converts between beats and wall-clock time following the tempo changes of a piece.
+///
+/// The tempo is 120 BPM before the first change. Tempo transitions are followed as they are
+/// played back, in steps of their transition interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    // sorted by beat and by time, starting at beat 0
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    /// Tempo map of the tempo records and the transition directives among the records
+    pub fn new(records: &[MtxtRecord]) -> Self {
+        let tempo_records: Vec<MtxtRecord> = records
+            .iter()
+            .filter(|record| {
+                matches!(
+                    record,
+                    MtxtRecord::Tempo { .. }
+                        | MtxtRecord::TransitionCurveDirective { .. }
+                        | MtxtRecord::TransitionIntervalDirective { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        Self::from_output_records(&process_records(&tempo_records))
+    }
+
+    /// Tempo map of the tempo events among output records, which are sorted by time
+    pub fn from_output_records(records: &[MtxtOutputRecord]) -> Self {
+        let mut map = Self::from_changes(&[]);
+        for record in records {
+            let MtxtOutputRecord::Tempo { time, bpm } = record else {
+                continue;
+            };
+            let last = map.segments.last().unwrap();
+            let beat = last.beat + BeatTime::from_micros(*time - last.micros as u64, last.bpm);
+            map.push(beat, *time as f64, *bpm as f64);
+        }
+        map
+    }
+
+    /// Tempo map of immediate changes given as (beat time, BPM) pairs sorted by time
+    pub fn from_changes(changes: &[(BeatTime, f64)]) -> Self {
+        let mut map = Self {
+            segments: vec![TempoSegment {
+                beat: BeatTime::zero(),
+                micros: 0.0,
+                bpm: DEFAULT_BPM,
+            }],
+        };
+        for (beat, bpm) in changes {
+            let last = map.segments.last().unwrap();
+            let beat = (*beat).max(last.beat);
+            let micros = last.micros + (beat - last.beat).as_f64() * MICROS_PER_MINUTE / last.bpm;
+            map.push(beat, micros, *bpm);
+        }
+        map
+    }
+
+    fn push(&mut self, beat: BeatTime, micros: f64, bpm: f64) {
+        let bpm = if bpm > 0.0 { bpm } else { DEFAULT_BPM };
+        let last = self.segments.last_mut().unwrap();
+        // a later change at the same time replaces the earlier one
+        if last.beat == beat {
+            last.bpm = bpm;
+        } else {
+            self.segments.push(TempoSegment { beat, micros, bpm });
+        }
+    }
+
+    /// Tempo in effect at the beat
+    pub fn bpm_at(&self, beat: BeatTime) -> f64 {
+        self.segment_at_beat(beat).bpm
+    }
+
+    pub fn seconds_at(&self, beat: BeatTime) -> f64 {
+        self.micros_at_f64(beat) / 1_000_000.0
+    }
+
+    /// Time of the beat rounded to whole microseconds, as in the output records
+    pub fn micros_at(&self, beat: BeatTime) -> u64 {
+        self.micros_at_f64(beat).round() as u64
+    }
+
+    /// Beat played at the time, beat 0 for negative times
+    pub fn beat_at_seconds(&self, seconds: f64) -> BeatTime {
+        let micros = (seconds * 1_000_000.0).max(0.0);
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.micros <= micros);
+        let segment = &self.segments[idx.saturating_sub(1)];
+        let beats = (micros - segment.micros) * segment.bpm / MICROS_PER_MINUTE;
+        segment.beat
+            + BeatTime::from_units((beats * BeatTime::FRAC_BEAT_COUNT as f64).round() as u64)
+    }
+
+    pub fn beat_at_micros(&self, micros: u64) -> BeatTime {
+        self.beat_at_seconds(micros as f64 / 1_000_000.0)
+    }
+
+    fn segment_at_beat(&self, beat: BeatTime) -> &TempoSegment {
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.beat <= beat);
+        &self.segments[idx.saturating_sub(1)]
+    }
+
+    fn micros_at_f64(&self, beat: BeatTime) -> f64 {
+        let segment = self.segment_at_beat(beat);
+        segment.micros + (beat - segment.beat).as_f64() * MICROS_PER_MINUTE / segment.bpm
+    }
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::from_changes(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_mtxt;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_tempo_map() {
+        let file = parse_mtxt("mtxt 1.0\n0.0 tempo 60\n2.0 tempo 120\n4.0 tempo 30\n").unwrap();
+        let records: Vec<MtxtRecord> = file.records.iter().map(|l| l.record.clone()).collect();
+        let map = TempoMap::new(&records);
+
+        let beat = |beat: u32| BeatTime::from_parts(beat, 0.0);
+        assert_eq!(map.seconds_at(beat(1)), 1.0);
+        assert_eq!(map.seconds_at(beat(3)), 2.5);
+        assert_eq!(map.seconds_at(beat(5)), 5.0);
+        assert_eq!(map.micros_at(beat(4)), 3_000_000);
+        assert_eq!(map.beat_at_seconds(2.5), beat(3));
+        assert_eq!(map.beat_at_seconds(-1.0), BeatTime::zero());
+        assert_eq!(map.bpm_at(beat(3)), 120.0);
+        assert_eq!(TempoMap::default().seconds_at(beat(4)), 2.0);
+    }
+
+    #[test]
+    fn test_tempo_map_transition() {
+        let file = parse_mtxt(
+            "mtxt 1.0\n0.0 tempo 60\n2.0 tempo 120 transition_time=2 transition_interval=100\n",
+        )
+        .unwrap();
+        let records: Vec<MtxtRecord> = file.records.iter().map(|l| l.record.clone()).collect();
+        let map = TempoMap::new(&records);
+        // speeding up from 60 to 120 BPM takes less than the 2 seconds at 60 BPM
+        let ramp_end = map.seconds_at(BeatTime::from_parts(2, 0.0));
+        assert!(ramp_end > 1.0 && ramp_end < 2.0, "{}", ramp_end);
+        assert_eq!(map.bpm_at(BeatTime::from_parts(3, 0.0)), 120.0);
+    }
+
+    #[test]
+    fn test_tempo_map_inverse() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let mut changes = Vec::new();
+            let mut beat = 0.0;
+            for _ in 0..rng.gen_range(0..8) {
+                beat += rng.gen_range(0.0..16.0_f64);
+                let time = BeatTime::from_parts(beat.trunc() as u32, beat.fract() as f32);
+                changes.push((time, rng.gen_range(20.0..300.0)));
+            }
+            let map = TempoMap::from_changes(&changes);
+
+            for _ in 0..50 {
+                let seconds = rng.gen_range(0.0..120.0);
+                let round_trip = map.seconds_at(map.beat_at_seconds(seconds));
+                assert!(
+                    (round_trip - seconds).abs() < 1e-6,
+                    "{} {}",
+                    seconds,
+                    round_trip
+                );
+
+                let beat = map.beat_at_seconds(rng.gen_range(0.0..120.0));
+                let round_trip = map.beat_at_seconds(map.seconds_at(beat));
+                let error = (round_trip.as_f64() - beat.as_f64()).abs() * 60.0 / map.bpm_at(beat);
+                assert!(error < 1e-6, "{} {}", beat, round_trip);
+            }
+        }
+    }
+}
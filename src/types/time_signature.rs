@@ -1,3 +1,5 @@
+use crate::BeatTime;
+use crate::types::record::{MtxtRecord, MtxtRecordLine};
 use anyhow::{Result, bail};
 use std::fmt;
 use std::str::FromStr;
@@ -8,6 +10,124 @@ pub struct TimeSignature {
     pub denominator: u8,
 }
 
+impl TimeSignature {
+    /// Length of a measure in quarter note beats (e.g. 3 for 6/8)
+    pub fn beats_per_measure(&self) -> BeatTime {
+        BeatTime::from_parts(1, 0.0)
+            .mul_ratio(self.numerator as u64 * 4, self.denominator.max(1) as u64)
+    }
+}
+
+/// Bar layout of a piece, following the time signature changes of its records.
+///
+/// Bars are numbered from 1 at the end of the pickup; the pickup (if any) is bar 0, counted as
+/// the last beats of a full bar of the initial signature. A change in the middle of a bar cuts
+/// that bar short and starts a new one. The last signature continues indefinitely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSignatureMap {
+    // (start, bar number at start, signature), sorted by start
+    segments: Vec<(BeatTime, u32, TimeSignature)>,
+    pickup: BeatTime,
+}
+
+impl TimeSignatureMap {
+    pub fn new(initial: &TimeSignature, records: &[MtxtRecordLine], pickup: BeatTime) -> Self {
+        let mut changes: Vec<(BeatTime, &TimeSignature)> = records
+            .iter()
+            .filter_map(|line| match &line.record {
+                MtxtRecord::TimeSignature { time, signature } => Some((*time, signature)),
+                _ => None,
+            })
+            .collect();
+        changes.sort_by_key(|(time, _)| *time);
+
+        let mut segments = vec![(pickup, 1, initial.clone())];
+        for (start, signature) in changes {
+            let (prev_start, prev_bar, prev_signature) = segments.last().unwrap();
+            if start <= *prev_start {
+                segments.last_mut().unwrap().2 = signature.clone();
+                continue;
+            }
+            let length = prev_signature.beats_per_measure().units().max(1);
+            let elapsed = (start - *prev_start).units().div_ceil(length);
+            segments.push((start, prev_bar + elapsed as u32, signature.clone()));
+        }
+
+        Self { segments, pickup }
+    }
+
+    /// Map of a file's own records: the earliest time signature (4/4 if there is none) and
+    /// the `pickup` global meta
+    pub fn from_records(records: &[MtxtRecordLine]) -> Self {
+        let mut initial: Option<(BeatTime, &TimeSignature)> = None;
+        let mut pickup = BeatTime::zero();
+        for line in records {
+            match &line.record {
+                MtxtRecord::TimeSignature { time, signature }
+                    if initial.is_none_or(|(first, _)| *time < first) =>
+                {
+                    initial = Some((*time, signature));
+                }
+                MtxtRecord::GlobalMeta { meta_type, value } if meta_type == "pickup" => {
+                    pickup = value.parse().unwrap_or_default();
+                }
+                _ => {}
+            }
+        }
+        let initial = initial.map(|(_, signature)| signature.clone());
+        Self::new(&initial.unwrap_or_default(), records, pickup)
+    }
+
+    /// Bar containing the time, 0 in the pickup
+    pub fn bar_at(&self, time: BeatTime) -> u32 {
+        match self.segment_at(time) {
+            Some((start, bar, signature)) => {
+                let length = signature.beats_per_measure().units().max(1);
+                bar + ((time - *start).units() / length) as u32
+            }
+            None => 0,
+        }
+    }
+
+    /// Time signature in effect at the time
+    pub fn signature_at(&self, time: BeatTime) -> &TimeSignature {
+        let (_, _, signature) = self.segment_at(time).unwrap_or(&self.segments[0]);
+        signature
+    }
+
+    /// First beat of the bar, 0 for the pickup
+    pub fn bar_start(&self, bar: u32) -> BeatTime {
+        let Some((start, first, signature)) = self
+            .segments
+            .iter()
+            .rev()
+            .find(|(_, first, _)| *first <= bar)
+        else {
+            return BeatTime::zero();
+        };
+        *start
+            + signature
+                .beats_per_measure()
+                .mul_ratio((bar - first) as u64, 1)
+    }
+
+    fn segment_at(&self, time: BeatTime) -> Option<&(BeatTime, u32, TimeSignature)> {
+        self.segments
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= time)
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
 impl fmt::Display for TimeSignature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}/{}", self.numerator, self.denominator)
@@ -37,10 +157,65 @@ impl FromStr for TimeSignature {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_beats_per_measure() {
+        let beats = |s: &str| s.parse::<TimeSignature>().unwrap().beats_per_measure();
+        assert_eq!(beats("4/4").to_string(), "4.0");
+        assert_eq!(beats("6/8").to_string(), "3.0");
+        assert_eq!(beats("7/8").to_string(), "3.5");
+        assert_eq!(beats("3/2").to_string(), "6.0");
+    }
+
+    fn changing_meter() -> TimeSignatureMap {
+        let file =
+            crate::parse_mtxt("mtxt 1.0\n0.0 timesig 4/4\n8.0 timesig 7/8\n11.5 timesig 3/4\n")
+                .unwrap();
+        TimeSignatureMap::from_records(&file.records)
+    }
+
+    #[test]
+    fn test_time_signature_map() {
+        let map = changing_meter();
+        let bars: Vec<u32> = [0.0, 3.9, 4.0, 8.0, 11.0, 11.5, 14.0, 14.5, 20.0]
+            .iter()
+            .map(|beat: &f64| map.bar_at(beat.to_string().parse().unwrap()))
+            .collect();
+        assert_eq!(bars, vec![1, 1, 2, 3, 3, 4, 4, 5, 6]);
+        let starts: Vec<String> = (1..=6).map(|bar| map.bar_start(bar).to_string()).collect();
+        assert_eq!(starts, vec!["0.0", "4.0", "8.0", "11.5", "14.5", "17.5"]);
+        assert_eq!(
+            map.signature_at(BeatTime::from_parts(9, 0.0)).to_string(),
+            "7/8"
+        );
+        assert_eq!(
+            map.signature_at(BeatTime::from_parts(99, 0.0)).to_string(),
+            "3/4"
+        );
+
+        // a change in the middle of a bar cuts it short
+        let file = crate::parse_mtxt("mtxt 1.0\n0.0 timesig 4/4\n6.0 timesig 3/4\n").unwrap();
+        let map = TimeSignatureMap::from_records(&file.records);
+        assert_eq!(map.bar_at(BeatTime::from_parts(5, 0.0)), 2);
+        assert_eq!(map.bar_at(BeatTime::from_parts(6, 0.0)), 3);
+        assert_eq!(map.bar_start(4).to_string(), "9.0");
+    }
+
+    #[test]
+    fn test_time_signature_map_pickup() {
+        let file =
+            crate::parse_mtxt("mtxt 1.0\nmeta global pickup 1.5\n0.0 timesig 3/4\n").unwrap();
+        let map = TimeSignatureMap::from_records(&file.records);
+        assert_eq!(map.bar_at(BeatTime::zero()), 0);
+        assert_eq!(map.bar_at(BeatTime::from_parts(1, 0.5)), 1);
+        assert_eq!(map.bar_start(0).to_string(), "0.0");
+        assert_eq!(map.bar_start(2).to_string(), "4.5");
+    }
+
     #[test]
     fn test_time_signature() {
         let ts: TimeSignature = "4/4".parse().unwrap();
         assert_eq!(ts.numerator, 4);
         assert_eq!(ts.denominator, 4);
+        assert_eq!(TimeSignature::default(), ts);
     }
 }
@@ -9,8 +9,10 @@ pub struct Version {
 }
 
 impl Version {
+    pub const CURRENT: Version = Version { major: 1, minor: 0 };
+
     pub fn latest() -> Self {
-        Version { major: 1, minor: 0 }
+        Self::CURRENT
     }
 
     pub fn fail_if_not_supported(&self) -> Result<()> {
@@ -24,6 +26,12 @@ impl Version {
     }
 }
 
+impl Default for Version {
+    fn default() -> Self {
+        Self::CURRENT
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}.{}", self.major, self.minor)
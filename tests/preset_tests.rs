@@ -0,0 +1,103 @@
+#![cfg(feature = "serde")]
+
+use mtxt::parse_mtxt;
+use mtxt::transforms::{Preset, TransformDescriptor, TransformStep, apply_transforms_from_json};
+use std::fs;
+use std::path::PathBuf;
+
+fn read_preset(name: &str) -> Preset {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("presets");
+    path.push(name);
+    let content = fs::read_to_string(&path).expect("Failed to read preset");
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("toml") => Preset::from_toml(&content).unwrap(),
+        _ => Preset::from_json(&content).unwrap(),
+    }
+}
+
+#[test]
+fn test_preset_round_trip() {
+    for name in ["mastering.toml", "arrangement.json"] {
+        let preset = read_preset(name);
+        assert_eq!(
+            Preset::from_toml(&preset.to_toml().unwrap()).unwrap(),
+            preset,
+            "{}",
+            name
+        );
+        assert_eq!(
+            Preset::from_json(&preset.to_json().unwrap()).unwrap(),
+            preset,
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_preset_values() {
+    let preset = read_preset("mastering.toml");
+    assert!(preset.transforms.apply_directives);
+    assert_eq!(preset.transforms.crop.unwrap().1.to_string(), "12.5");
+    assert_eq!(
+        preset.transforms.transpose_interval.unwrap().semitones(),
+        -2
+    );
+    // keys not in the file keep their defaults
+    assert_eq!(preset.transforms.time_scale, 1.0);
+    assert_eq!(preset.pipeline.len(), 5);
+    assert_eq!(preset.pipeline[3], TransformStep::Offset(0.25));
+
+    let preset = read_preset("arrangement.json");
+    assert_eq!(
+        preset.pipeline.last(),
+        Some(&TransformStep::CompactChannels)
+    );
+}
+
+#[test]
+fn test_preset_unknown_key() {
+    let error = Preset::from_toml("[transforms]\nquantise_grid = 16\n").unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("quantise_grid"),
+        "{:#}",
+        error
+    );
+
+    let error = Preset::from_json(r#"{"pipeline": [{"offset": 1}, "reverse"]}"#).unwrap_err();
+    assert!(format!("{:#}", error).contains("reverse"), "{:#}", error);
+
+    let preset = Preset::from_json("{}").unwrap();
+    assert_eq!(preset.transforms, TransformDescriptor::default());
+    assert!(preset.pipeline.is_empty());
+}
+
+#[test]
+fn test_descriptor_json_round_trip() {
+    let mut descriptor = read_preset("mastering.toml").transforms;
+    descriptor.channel_offset.insert(3, -0.25);
+    let json = descriptor.to_json().unwrap();
+    assert!(json.contains(r#""channel_offset":{"3":-0.25}"#), "{}", json);
+    assert_eq!(TransformDescriptor::from_json(&json).unwrap(), descriptor);
+
+    let descriptor = TransformDescriptor::from_json(r#"{"offset_amount": 2.0}"#).unwrap();
+    assert_eq!(descriptor.offset_amount, 2.0);
+    assert!(descriptor.keep_conductor);
+    assert!(TransformDescriptor::from_json(r#"{"ofset_amount": 2.0}"#).is_err());
+}
+
+#[test]
+fn test_apply_transforms_from_json() {
+    let file = parse_mtxt("mtxt 1.0\n1.1 note C4 dur=1\n0.0 note D4 dur=1\n").unwrap();
+    let json = r#"{
+        "transforms": { "sort_by_time": true },
+        "pipeline": [{ "quantize": { "grid": { "numerator": 1, "denominator": 4 } } }, { "transpose": { "semitones": 12 } }]
+    }"#;
+    let records = apply_transforms_from_json(&file.records, json).unwrap();
+    assert_eq!(
+        mtxt::MtxtFile::from_records(records).to_string(),
+        "mtxt 1.0\n0.0 note D5 dur=1.0\n1.0 note C5 dur=1.0\n"
+    );
+}